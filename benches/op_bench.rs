@@ -8,7 +8,8 @@ use std::{
 };
 
 use splinter_rs::{
-    Cut, Optimizable, PartitionRead, PartitionWrite, Splinter, SplinterRef, testutil::SetGen,
+    CowSplinter, Cut, Optimizable, PartitionRead, PartitionWrite, Splinter, SplinterRef,
+    testutil::SetGen,
 };
 
 const SEED: u64 = 0xDEAD_BEEF;
@@ -147,6 +148,39 @@ fn benchmark_insert(c: &mut Criterion) {
     group.finish();
 }
 
+fn benchmark_cow_insert(c: &mut Criterion) {
+    let cardinalities = [1024u32, 16384, 262144, 1048576];
+
+    let mut group = c.benchmark_group("cow_insert");
+    let mut set_gen = SetGen::new(SEED);
+
+    for &cardinality in &cardinalities {
+        // the edited child is the same size at every cardinality
+        let set = set_gen.random(cardinality as usize);
+        let splinter_ref = mksplinter_ref(
+            set.into_iter()
+                .filter(|v| v >> 24 != 0)
+                .chain((0..64).map(|v| v * 3)),
+        );
+        let value = 1;
+
+        // the first write only decodes the child it touches, so this should
+        // not grow with the cardinality
+        group.bench_function(BenchmarkId::new("splinter ref", cardinality), |b| {
+            b.iter_batched(
+                || CowSplinter::from_ref(splinter_ref.clone()),
+                |mut cow| {
+                    assert!(cow.insert(black_box(value)));
+                    cow
+                },
+                criterion::BatchSize::SmallInput,
+            );
+        });
+    }
+
+    group.finish();
+}
+
 fn benchmark_position(c: &mut Criterion) {
     let cardinalities = [4u32, 16, 64, 256, 1024, 4096, 16384];
 
@@ -614,6 +648,7 @@ criterion_group!(
     benches,
     benchmark_contains,
     benchmark_insert,
+    benchmark_cow_insert,
     benchmark_position,
     benchmark_contains_vs_position,
     benchmark_cardinality,
@@ -1,14 +1,36 @@
+use std::{fmt, io};
+
 use bytes::{BufMut, Bytes, BytesMut};
 use thiserror::Error;
 use zerocopy::{ConvertError, SizeError};
 
-use crate::codec::encoder::Encoder;
+use crate::{
+    codec::{
+        checksum::Checksum,
+        encoder::{Encoder, SliceBuf, WriteBuf},
+        features::Features,
+    },
+    level::Level,
+};
+
+pub use crate::partition_kind::PartitionKind;
 
+pub mod checksum;
+pub mod compressed;
 pub mod encoder;
+pub mod features;
+pub mod legacy_v1;
 
+pub(crate) mod bitmap_ref;
+pub(crate) mod delta_vec_ref;
+pub(crate) mod elias_fano_ref;
 pub(crate) mod footer;
 pub(crate) mod partition_ref;
+pub(crate) mod prefix_ref;
+pub(crate) mod roaring;
 pub(crate) mod runs_ref;
+pub(crate) mod sorted;
+pub(crate) mod sparse_bitmap_ref;
 pub(crate) mod tree_ref;
 
 /// Trait for types that can be encoded into a binary format.
@@ -16,14 +38,92 @@ pub trait Encodable {
     /// Returns the number of bytes required to encode this value.
     ///
     /// This should return the exact number of bytes that [`encode`](Self::encode)
-    /// will write, allowing for efficient buffer pre-allocation.
+    /// will write to an [`Encoder`] with the default settings, allowing for
+    /// efficient buffer pre-allocation.
     ///
     /// Note: This function traverses the entire datastructure which scales with cardinality.
     fn encoded_size(&self) -> usize;
 
+    /// Returns the number of bytes [`encode`](Self::encode) will write to an
+    /// [`Encoder`] created by [`Encoder::with_features`] with the provided
+    /// optional features.
+    ///
+    /// The default implementation returns [`encoded_size`](Self::encoded_size),
+    /// which is correct for values whose encoding doesn't depend on the
+    /// encoder's features.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Encodable, Optimizable, Splinter, codec::features::Features};
+    ///
+    /// let mut splinter = Splinter::from_iter((0..65536).filter(|v| v % 1000 != 0));
+    /// splinter.optimize_with(Features::SUPPORTED);
+    /// assert_eq!(splinter.encoded_size_with(Features::EMPTY), splinter.encoded_size());
+    /// assert!(splinter.encoded_size_with(Features::SUPPORTED) < splinter.encoded_size());
+    /// ```
+    fn encoded_size_with(&self, features: Features) -> usize {
+        let _ = features;
+        self.encoded_size()
+    }
+
+    /// Returns an upper bound on [`encoded_size`](Self::encoded_size) which is
+    /// cheaper to compute, suitable for picking a buffer to pass to
+    /// [`encode_into`](Self::encode_into).
+    ///
+    /// Like `encoded_size`, this assumes the value is encoded by an
+    /// [`Encoder`] with the default settings. The default implementation
+    /// returns the exact encoded size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Splinter, Encodable};
+    ///
+    /// let splinter = Splinter::from_iter((0..65536).step_by(3));
+    /// assert!(splinter.max_encoded_size() >= splinter.encoded_size());
+    /// ```
+    fn max_encoded_size(&self) -> usize {
+        self.encoded_size()
+    }
+
     /// Encodes this value into the provided encoder.
     fn encode<B: BufMut>(&self, encoder: &mut Encoder<B>);
 
+    /// Returns the optional format features which [`encode`](Self::encode)
+    /// uses when the encoder supports every feature.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Splinter, Encodable, codec::features::Features};
+    ///
+    /// let splinter = Splinter::from_iter([8, 42, 16]);
+    /// assert_eq!(splinter.encoded_features(), Features::EMPTY);
+    /// ```
+    fn encoded_features(&self) -> Features {
+        Features::EMPTY
+    }
+
+    /// Returns the optional format features which [`encode`](Self::encode)
+    /// uses when the encoder only supports the provided features.
+    ///
+    /// The default implementation returns the features in
+    /// [`encoded_features`](Self::encoded_features) which are also in
+    /// `features`.
+    fn encoded_features_with(&self, features: Features) -> Features {
+        self.encoded_features().intersection(features)
+    }
+
+    /// Returns the optional format features which every encoding of this
+    /// value depends on, or which it needs to copy parts of an existing
+    /// encoding verbatim. The `encode_to_*` methods enable these features on
+    /// top of the defaults of [`Encoder::new`], and
+    /// [`encoded_size`](Self::encoded_size) includes them.
+    fn required_features(&self) -> Features {
+        Features::EMPTY
+    }
+
     /// Convenience method that encodes this value to a [`Bytes`] buffer.
     ///
     /// This is the easiest way to serialize splinter data. It allocates
@@ -41,17 +141,112 @@ pub trait Encodable {
     /// ```
     fn encode_to_bytes(&self) -> Bytes {
         let size = self.encoded_size();
-        let mut encoder = Encoder::new(BytesMut::with_capacity(size));
+        let mut encoder =
+            Encoder::with_features(BytesMut::with_capacity(size), self.required_features());
         self.encode(&mut encoder);
         encoder.into_inner().freeze()
     }
+
+    /// Encodes this value into an [`io::Write`], returning the number of bytes
+    /// written.
+    ///
+    /// The encoded output is streamed to the writer in chunks rather than
+    /// being buffered in memory, which makes this the preferred way to write
+    /// large splinters to files or sockets. The writer is not flushed.
+    ///
+    /// # Errors
+    ///
+    /// Returns the first I/O error returned by the writer. When this happens
+    /// the writer may have received a prefix of the encoded output.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Splinter, SplinterRef, Encodable};
+    ///
+    /// let splinter = Splinter::from_iter([8, 42, 16]);
+    ///
+    /// let mut out = Vec::new();
+    /// let written = splinter.encode_to_writer(&mut out).unwrap();
+    /// assert_eq!(written, splinter.encoded_size() as u64);
+    /// assert_eq!(SplinterRef::from_bytes(out).unwrap(), splinter);
+    /// ```
+    fn encode_to_writer<W: io::Write>(&self, writer: &mut W) -> io::Result<u64> {
+        let mut encoder = Encoder::with_features(WriteBuf::new(writer), self.required_features());
+        self.encode(&mut encoder);
+        let written = encoder.bytes_written() as u64;
+        encoder.into_inner().finish()?;
+        Ok(written)
+    }
+
+    /// Encodes this value into the start of a caller-provided slice without
+    /// allocating, returning the number of bytes written.
+    ///
+    /// The value is encoded in a single pass, so unlike
+    /// [`encode_to_bytes`](Self::encode_to_bytes) this never calls
+    /// [`encoded_size`](Self::encoded_size). Use
+    /// [`max_encoded_size`](Self::max_encoded_size) to cheaply pick a slice
+    /// which is large enough.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EncodeErr::BufferTooSmall`] if the encoded value doesn't fit
+    /// in `buf`. When this happens `buf` contains a prefix of the encoded
+    /// output.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Splinter, SplinterRef, Encodable, codec::EncodeErr};
+    ///
+    /// let splinter = Splinter::from_iter([8, 42, 16]);
+    ///
+    /// let mut page = [0u8; 4096];
+    /// let written = splinter.encode_into(&mut page).unwrap();
+    /// assert_eq!(SplinterRef::from_bytes(&page[..written]).unwrap(), splinter);
+    ///
+    /// let err = splinter.encode_into(&mut page[..4]).unwrap_err();
+    /// assert!(matches!(err, EncodeErr::BufferTooSmall { needed, .. } if needed == written));
+    /// ```
+    fn encode_into(&self, buf: &mut [u8]) -> Result<usize, EncodeErr> {
+        let mut encoder = Encoder::with_features(SliceBuf::new(buf), self.required_features());
+        self.encode(&mut encoder);
+        encoder.into_inner().finish()
+    }
+
+    /// Encodes this value to a [`Bytes`] buffer, wrapped in an LZ4
+    /// compressed envelope if that makes it smaller.
+    ///
+    /// Compressed splinters can't be queried in place, so this trades query
+    /// performance for size. See [`compressed`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{CowSplinter, Encodable, PartitionRead, Splinter};
+    ///
+    /// let splinter = Splinter::from_iter((0..1_000_000).step_by(256));
+    /// let bytes = splinter.encode_compressed();
+    /// assert!(bytes.len() < splinter.encoded_size());
+    ///
+    /// let cow = CowSplinter::from_bytes(bytes).unwrap();
+    /// assert_eq!(cow.cardinality(), splinter.cardinality());
+    /// ```
+    #[cfg(feature = "lz4")]
+    fn encode_compressed(&self) -> Bytes {
+        compressed::compress(self.encode_to_bytes())
+    }
 }
 
 /// Errors that can occur when deserializing splinter data from bytes.
 ///
 /// These errors indicate various types of corruption or invalid data that can
 /// be encountered when attempting to decode serialized splinter data.
+///
+/// New variants may be added as the format gains features, so matches on
+/// this enum must include a wildcard arm.
 #[derive(Debug, Error)]
+#[non_exhaustive]
 pub enum DecodeErr {
     /// The buffer does not contain enough bytes to decode the expected data.
     ///
@@ -85,10 +280,408 @@ pub enum DecodeErr {
 
     /// The buffer contains data from the incompatible Splinter V1 format.
     ///
-    /// This version of splinter-rs can only decode V2 format data. To decode
-    /// V1 data, use splinter-rs version 0.3.3 or earlier.
-    #[error("buffer contains serialized Splinter V1, decode using splinter-rs:v0.3.3")]
+    /// V1 data can't be accessed in place. Decode it into an owned
+    /// [`Splinter`](crate::Splinter) using [`legacy_v1::decode`] or
+    /// [`CowSplinter::from_bytes_any_version`](crate::CowSplinter::from_bytes_any_version).
+    #[error("buffer contains serialized Splinter V1, decode using codec::legacy_v1")]
     SplinterV1,
+
+    /// The buffer depends on optional format features which this version of
+    /// splinter-rs does not support.
+    ///
+    /// This error indicates the data was written by a newer version of
+    /// splinter-rs. The writer can target an older feature set using
+    /// [`Encoder::with_features`].
+    #[error("buffer uses unsupported format features: {unsupported:?}")]
+    UnsupportedFeature {
+        /// The features which are not supported by this reader.
+        unsupported: Features,
+    },
+
+    /// The buffer uses a checksum whose cargo feature is disabled, so the
+    /// checksum can't be verified.
+    ///
+    /// Enable the `crc32c` or `xxh3` cargo feature to decode the buffer, see
+    /// [`Checksum`].
+    #[error(
+        "buffer uses the {checksum:?} checksum, which is disabled by the crate's cargo features"
+    )]
+    UnsupportedChecksum {
+        /// The checksum used by the buffer.
+        checksum: Checksum,
+    },
+
+    /// The buffer ends with a splinter which doesn't record its length, so it
+    /// can't be split from the data which precedes it.
+    ///
+    /// Splinters record their length when encoded by an [`Encoder`] created
+    /// with [`Encoder::with_encoded_length`].
+    #[error("splinter does not record its length")]
+    MissingLength,
+
+    /// The buffer contains a compressed splinter, which can't be accessed in
+    /// place.
+    ///
+    /// Decompress it using `SplinterRef::from_compressed_bytes` or
+    /// [`CowSplinter::from_bytes`](crate::CowSplinter::from_bytes), which
+    /// require the `lz4` cargo feature.
+    #[error(
+        "buffer contains a compressed splinter, decode using SplinterRef::from_compressed_bytes"
+    )]
+    Compressed,
+
+    /// The buffer contains a [`SplinterPatch`](crate::SplinterPatch) rather
+    /// than a splinter.
+    ///
+    /// Decode it using
+    /// [`SplinterPatch::from_bytes`](crate::SplinterPatch::from_bytes).
+    #[error("buffer contains a splinter patch, decode using SplinterPatch::from_bytes")]
+    Patch,
+
+    /// The buffer contains a splinter rather than a
+    /// [`SplinterPatch`](crate::SplinterPatch).
+    ///
+    /// Decode it using [`SplinterRef::from_bytes`](crate::SplinterRef::from_bytes).
+    #[error("buffer contains a splinter rather than a patch, decode using SplinterRef::from_bytes")]
+    NotPatch,
+
+    /// The splinter exceeds one of the limits configured by
+    /// [`DecodeOptions`].
+    #[error("splinter exceeds the maximum {limit}")]
+    LimitExceeded {
+        /// The limit which was exceeded.
+        limit: DecodeLimit,
+    },
+}
+
+/// The resource limits which can be configured using [`DecodeOptions`].
+///
+/// New limits may be added in the future, so matches on this enum must
+/// include a wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+#[non_exhaustive]
+pub enum DecodeLimit {
+    /// See [`DecodeOptions::with_max_len`].
+    Length,
+    /// See [`DecodeOptions::with_max_fan_out`].
+    FanOut,
+    /// See [`DecodeOptions::with_max_cardinality`].
+    Cardinality,
+}
+
+impl fmt::Display for DecodeLimit {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            DecodeLimit::Length => "buffer length",
+            DecodeLimit::FanOut => "tree fan-out",
+            DecodeLimit::Cardinality => "cardinality",
+        })
+    }
+}
+
+/// The levels of the partition tree which stores a splinter's values, from
+/// the root down.
+///
+/// New levels may be added as the format evolves, so matches on this enum
+/// must include a wildcard arm.
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash)]
+#[non_exhaustive]
+pub enum PartitionLevel {
+    /// The root partition, storing the full 32 bits of each value.
+    High,
+    /// Partitions storing the low 24 bits of each value.
+    Mid,
+    /// Partitions storing the low 16 bits of each value.
+    Low,
+    /// Partitions storing the low 8 bits of each value.
+    Block,
+}
+
+impl fmt::Display for PartitionLevel {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        f.write_str(match self {
+            PartitionLevel::High => "High",
+            PartitionLevel::Mid => "Mid",
+            PartitionLevel::Low => "Low",
+            PartitionLevel::Block => "Block",
+        })
+    }
+}
+
+/// The location of a malformed partition within an encoded splinter.
+///
+/// Partitions are decoded from their last byte, which stores the partition's
+/// kind, so the offset points at that byte.
+///
+/// Locations are only created by splinter-rs while decoding. More fields may
+/// be added in the future, so patterns matching this struct must end in `..`.
+#[derive(Debug, Clone, PartialEq, Eq)]
+#[non_exhaustive]
+pub struct DecodeLocation {
+    /// The offset of the partition's last byte from the start of the buffer.
+    pub offset: usize,
+    /// The level of the partition.
+    pub level: PartitionLevel,
+    /// The segments of the tree partitions leading from the root partition to
+    /// this partition. Empty for the root partition.
+    pub path: Vec<u8>,
+    /// The kind the partition was decoded as, or `None` if its kind couldn't
+    /// be read.
+    pub kind: Option<PartitionKind>,
+}
+
+impl DecodeLocation {
+    pub(crate) fn new<L: Level>(offset: usize, kind: Option<PartitionKind>) -> Self {
+        Self {
+            offset,
+            level: L::LEVEL,
+            path: Vec::new(),
+            kind,
+        }
+    }
+}
+
+impl fmt::Display for DecodeLocation {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match self.kind {
+            Some(kind) => write!(f, "{} {:?} partition", self.level, kind)?,
+            None => write!(f, "{} partition of unknown kind", self.level)?,
+        }
+        write!(f, " at offset {} (path {:?})", self.offset, self.path)
+    }
+}
+
+/// A [`DecodeErr`] along with the location of the partition which caused
+/// it, if any.
+///
+/// Returned by [`SplinterRef::from_bytes_with`] and
+/// [`SplinterRef::validate_deep_located`], and converts into the underlying
+/// [`DecodeErr`] for callers which only care about the category of the error.
+/// A [`DecodeErr`] without a location converts into a `LocatedDecodeErr`
+/// using [`From`]. More fields may be added in the future, so patterns
+/// matching this struct must end in `..`.
+///
+/// [`SplinterRef::from_bytes_with`]: crate::SplinterRef::from_bytes_with
+/// [`SplinterRef::validate_deep_located`]: crate::SplinterRef::validate_deep_located
+#[derive(Debug)]
+#[non_exhaustive]
+pub struct LocatedDecodeErr {
+    /// The category of the error.
+    pub err: DecodeErr,
+    /// The location of the malformed partition, or `None` if the error isn't
+    /// caused by a single partition, such as a checksum mismatch.
+    pub location: Option<DecodeLocation>,
+}
+
+impl LocatedDecodeErr {
+    #[inline]
+    pub(crate) fn new(err: DecodeErr, location: DecodeLocation) -> Self {
+        Self { err, location: Some(location) }
+    }
+
+    /// Moves the error from a child partition into its parent tree, given the
+    /// child's offset within the parent and the child's segment.
+    pub(crate) fn within(mut self, offset: usize, segment: u8) -> Self {
+        if let Some(location) = &mut self.location {
+            location.offset += offset;
+            location.path.insert(0, segment);
+        }
+        self
+    }
+}
+
+impl fmt::Display for LocatedDecodeErr {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        match &self.location {
+            Some(location) => write!(f, "{} in {}", self.err, location),
+            None => write!(f, "{}", self.err),
+        }
+    }
+}
+
+impl std::error::Error for LocatedDecodeErr {}
+
+impl From<DecodeErr> for LocatedDecodeErr {
+    #[inline]
+    fn from(err: DecodeErr) -> Self {
+        Self { err, location: None }
+    }
+}
+
+impl From<LocatedDecodeErr> for DecodeErr {
+    #[inline]
+    fn from(err: LocatedDecodeErr) -> Self {
+        err.err
+    }
+}
+
+/// Controls how much work [`SplinterRef::from_bytes_with`] does to verify a
+/// splinter, and the largest splinter it accepts.
+///
+/// The defaults match [`SplinterRef::from_bytes`]: the checksum is verified,
+/// nested partitions are not validated, and there are no limits. Use
+/// [`DecodeOptions::TRUSTED`] for buffers which never left the process, and
+/// [`DecodeOptions::UNTRUSTED`] combined with limits for data received over
+/// the network.
+///
+/// # Examples
+///
+/// ```
+/// use splinter_rs::{
+///     Splinter, SplinterRef, Encodable,
+///     codec::{DecodeErr, DecodeLimit, DecodeOptions, LocatedDecodeErr},
+/// };
+///
+/// // a full splinter only takes a few bytes to encode
+/// let bytes = Splinter::FULL.encode_to_bytes();
+///
+/// let options = DecodeOptions::UNTRUSTED.with_max_cardinality(1 << 20);
+/// assert!(matches!(
+///     SplinterRef::from_bytes_with(bytes, options),
+///     Err(LocatedDecodeErr {
+///         err: DecodeErr::LimitExceeded { limit: DecodeLimit::Cardinality },
+///         location: None,
+///         ..
+///     })
+/// ));
+/// ```
+///
+/// [`SplinterRef::from_bytes_with`]: crate::SplinterRef::from_bytes_with
+/// [`SplinterRef::from_bytes`]: crate::SplinterRef::from_bytes
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub struct DecodeOptions {
+    verify_checksum: bool,
+    validate_deep: bool,
+    max_len: usize,
+    max_fan_out: usize,
+    max_cardinality: usize,
+}
+
+impl DecodeOptions {
+    /// The options used by [`SplinterRef::from_bytes`](crate::SplinterRef::from_bytes).
+    pub const DEFAULT: Self = Self {
+        verify_checksum: true,
+        validate_deep: false,
+        max_len: usize::MAX,
+        max_fan_out: usize::MAX,
+        max_cardinality: usize::MAX,
+    };
+
+    /// Options for buffers which were encoded in-process and never left it,
+    /// which skip verifying the checksum.
+    pub const TRUSTED: Self = Self::DEFAULT.with_checksum(false);
+
+    /// Options for buffers received from untrusted sources, which verify the
+    /// checksum and recursively validate every partition like
+    /// [`SplinterRef::from_bytes_validated`](crate::SplinterRef::from_bytes_validated).
+    pub const UNTRUSTED: Self = Self::DEFAULT.with_validate_deep(true);
+
+    /// Whether to verify the checksum stored in the splinter's footer.
+    ///
+    /// Skipping the checksum avoids reading the entire buffer, but corrupted
+    /// data may then cause reads to panic or return incorrect results.
+    pub const fn with_checksum(mut self, verify: bool) -> Self {
+        self.verify_checksum = verify;
+        self
+    }
+
+    /// Whether to recursively validate every partition, see
+    /// [`SplinterRef::validate_deep`](crate::SplinterRef::validate_deep).
+    pub const fn with_validate_deep(mut self, validate: bool) -> Self {
+        self.validate_deep = validate;
+        self
+    }
+
+    /// Reject buffers which are longer than `max` bytes, before reading them.
+    ///
+    /// Decoding a splinter using
+    /// [`SplinterRef::decode_to_splinter`](crate::SplinterRef::decode_to_splinter)
+    /// allocates memory proportional to its encoded length, so this bounds the
+    /// memory used by the decoded splinter.
+    /// [`CowSplinter::from_bytes_with`](crate::CowSplinter::from_bytes_with)
+    /// also applies this limit to the uncompressed length of compressed
+    /// splinters before decompressing them.
+    pub const fn with_max_len(mut self, max: usize) -> Self {
+        self.max_len = max;
+        self
+    }
+
+    /// Reject splinters containing a tree partition with more than `max`
+    /// children. Checking this walks every tree partition.
+    pub const fn with_max_fan_out(mut self, max: usize) -> Self {
+        self.max_fan_out = max;
+        self
+    }
+
+    /// Reject splinters containing more than `max` values.
+    ///
+    /// A splinter's cardinality can be far larger than its encoded size: a
+    /// full splinter stores 2^32 values in a few bytes, and remains a few
+    /// bytes once decoded. This limit bounds the number of values yielded
+    /// when iterating over the splinter or collecting it into another
+    /// container, while [`DecodeOptions::with_max_len`] bounds the memory
+    /// used by the decoded splinter. Both are checked before
+    /// [`SplinterRef::from_bytes_with`](crate::SplinterRef::from_bytes_with)
+    /// and [`CowSplinter::from_bytes_with`](crate::CowSplinter::from_bytes_with)
+    /// return, so before anything is decoded.
+    ///
+    /// The cardinality is read from the tree partitions, which can only be
+    /// trusted once they are validated, so setting a limit implies
+    /// [`DecodeOptions::with_validate_deep`].
+    pub const fn with_max_cardinality(mut self, max: usize) -> Self {
+        self.max_cardinality = max;
+        self
+    }
+
+    /// Returns true if the checksum is verified.
+    #[inline]
+    pub const fn verifies_checksum(&self) -> bool {
+        self.verify_checksum
+    }
+
+    /// Returns true if every partition is recursively validated, which is
+    /// always the case when a cardinality limit is set.
+    #[inline]
+    pub const fn validates_deep(&self) -> bool {
+        self.validate_deep || self.max_cardinality < usize::MAX
+    }
+
+    /// The maximum buffer length.
+    #[inline]
+    pub const fn max_len(&self) -> usize {
+        self.max_len
+    }
+
+    /// The maximum number of children of any tree partition.
+    #[inline]
+    pub const fn max_fan_out(&self) -> usize {
+        self.max_fan_out
+    }
+
+    /// The maximum cardinality.
+    #[inline]
+    pub const fn max_cardinality(&self) -> usize {
+        self.max_cardinality
+    }
+}
+
+impl Default for DecodeOptions {
+    fn default() -> Self {
+        Self::DEFAULT
+    }
+}
+
+/// Errors that can occur when serializing splinter data.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+pub enum EncodeErr {
+    /// The provided buffer is too small to hold the encoded value.
+    #[error("buffer too small: needed {needed} bytes, but only {available} are available")]
+    BufferTooSmall {
+        /// The number of bytes required to encode the value.
+        needed: usize,
+        /// The number of bytes in the provided buffer.
+        available: usize,
+    },
 }
 
 impl DecodeErr {
@@ -122,6 +715,9 @@ impl<A, S, V> From<ConvertError<A, S, V>> for DecodeErr {
 
 #[cfg(test)]
 mod tests {
+    use std::io;
+
+    use assert_matches::assert_matches;
     use bytes::BytesMut;
     use itertools::Itertools;
     use proptest::proptest;
@@ -129,18 +725,21 @@ mod tests {
     use crate::{
         Encodable, Splinter, SplinterRef, assert_error,
         codec::{
-            DecodeErr,
-            encoder::Encoder,
+            DecodeErr, DecodeLimit, DecodeLocation, DecodeOptions, EncodeErr, LocatedDecodeErr,
+            PartitionLevel,
+            checksum::Checksum,
+            encoder::{Encoder, WriteBuf},
+            features::Features,
             footer::{Footer, SPLINTER_V2_MAGIC},
             partition_ref::PartitionRef,
         },
-        level::{Block, Level, Low},
+        level::{Block, High, Level, Low},
         partition_kind::PartitionKind,
         testutil::{
-            LevelSetGen, mkpartition, mkpartition_buf, mksplinter_buf, mksplinter_manual,
-            test_partition_read,
+            LevelSetGen, encode_with_features, mkpartition, mkpartition_buf, mksplinter_buf,
+            mksplinter_manual, mksplinter_manual_with_features, test_partition_read,
         },
-        traits::{Optimizable, PartitionRead, TruncateFrom},
+        traits::{Complement, Optimizable, PartitionRead, TruncateFrom},
     };
 
     #[test]
@@ -151,6 +750,11 @@ mod tests {
             PartitionKind::Vec,
             PartitionKind::Run,
             PartitionKind::Tree,
+            PartitionKind::InvertedVec,
+            PartitionKind::InvertedRun,
+            PartitionKind::EliasFano,
+            PartitionKind::DeltaVec,
+            PartitionKind::SparseBitmap,
         ];
         let sets = &[
             vec![0],
@@ -173,16 +777,27 @@ mod tests {
                 println!("Testing partition kind: {kind:?} with set {i}");
 
                 let partition = mkpartition::<Low>(kind, set);
-                let buf = partition.encode_to_bytes();
                 assert_eq!(
                     partition.encoded_size(),
-                    buf.len(),
+                    partition.encode_to_bytes().len(),
                     "encoded_size doesn't match actual size"
                 );
+                let buf = encode_with_features(&partition, Features::SUPPORTED);
+                assert_eq!(
+                    partition.encoded_size_with(Features::SUPPORTED),
+                    buf.len(),
+                    "encoded_size_with doesn't match actual size"
+                );
 
                 let partition_ref = PartitionRef::<'_, Low>::from_suffix(&buf).unwrap();
 
-                assert_eq!(partition_ref.kind(), kind);
+                if kind.is_inverted() && set.len() == Low::MAX_LEN {
+                    // inverted partitions with an empty complement encode as full
+                    assert_eq!(partition_ref.kind(), PartitionKind::Full);
+                } else {
+                    assert_eq!(partition_ref.kind(), kind);
+                }
+                partition_ref.validate().unwrap();
                 test_partition_read(&partition_ref, set);
             }
         }
@@ -203,11 +818,167 @@ mod tests {
                 "encoded_size doesn't match actual size"
             );
             let splinter_ref = SplinterRef::from_bytes(buf).unwrap();
+            test_partition_read(&splinter_ref, &expected);
 
+            let buf = encode_with_features(&splinter, Features::SUPPORTED);
+            assert_eq!(
+                buf.len(),
+                splinter.encoded_size_with(Features::SUPPORTED),
+                "encoded_size_with doesn't match actual size"
+            );
+            let splinter_ref = SplinterRef::from_bytes(buf).unwrap();
             test_partition_read(&splinter_ref, &expected);
         }
     }
 
+    #[test]
+    fn test_encode_to_writer() {
+        let mut setgen = LevelSetGen::<High>::new(0xDEADBEEF);
+        let sets = [
+            vec![],
+            vec![1, 2, 3],
+            setgen.random(4096),
+            setgen.runs(65536, 0.5),
+        ];
+
+        for set in sets {
+            let splinter = Splinter::from_iter(set.iter().copied());
+            let expected = splinter.encode_to_bytes();
+
+            let mut out = Vec::new();
+            let written = splinter.encode_to_writer(&mut out).unwrap();
+            assert_eq!(written, expected.len() as u64);
+            assert_eq!(out, expected);
+
+            // SplinterRef writes its bytes directly
+            let splinter_ref = SplinterRef::from_bytes(expected.clone()).unwrap();
+            let mut out = Vec::new();
+            splinter_ref.encode_to_writer(&mut out).unwrap();
+            assert_eq!(out, expected);
+        }
+    }
+
+    #[test]
+    fn test_encode_into() {
+        let mut setgen = LevelSetGen::<High>::new(0xDEADBEEF);
+        let sets = [
+            vec![],
+            vec![1, 2, 3],
+            setgen.random(4096),
+            setgen.runs(65536, 0.5),
+        ];
+
+        for set in sets {
+            let mut splinter = Splinter::from_iter(set.iter().copied());
+            for optimize in [false, true] {
+                if optimize {
+                    splinter.optimize();
+                }
+                let expected = splinter.encode_to_bytes();
+                assert!(splinter.max_encoded_size() >= expected.len());
+
+                let mut buf = vec![0xFF; splinter.max_encoded_size()];
+                let written = splinter.encode_into(&mut buf).unwrap();
+                assert_eq!(&buf[..written], &expected[..]);
+
+                // the encoded bytes don't fit in a smaller buffer
+                let mut buf = vec![0; expected.len() - 1];
+                assert_eq!(
+                    splinter.encode_into(&mut buf),
+                    Err(EncodeErr::BufferTooSmall {
+                        needed: expected.len(),
+                        available: expected.len() - 1
+                    })
+                );
+                assert_matches!(
+                    splinter.encode_into(&mut []),
+                    Err(EncodeErr::BufferTooSmall { available: 0, .. })
+                );
+
+                // SplinterRef writes its bytes directly
+                let splinter_ref = SplinterRef::from_bytes(expected.clone()).unwrap();
+                assert_eq!(splinter_ref.max_encoded_size(), expected.len());
+                let mut buf = vec![0; expected.len()];
+                assert_eq!(splinter_ref.encode_into(&mut buf), Ok(expected.len()));
+                assert_eq!(buf, expected);
+            }
+        }
+    }
+
+    #[test]
+    fn test_max_encoded_size() {
+        let mut setgen = LevelSetGen::<Low>::new(0xDEADBEEF);
+        let sets = [
+            vec![],
+            vec![0],
+            setgen.random(8),
+            setgen.random(4096),
+            setgen.runs(4096, 0.9),
+            (0..Low::MAX_LEN)
+                .map(|v| v as u16)
+                .filter(|v| v % 251 != 0)
+                .collect_vec(),
+        ];
+
+        // unoptimized partitions of any kind never exceed the bound
+        for set in sets {
+            for kind in [
+                PartitionKind::Bitmap,
+                PartitionKind::Vec,
+                PartitionKind::Run,
+                PartitionKind::Tree,
+                PartitionKind::EliasFano,
+                PartitionKind::DeltaVec,
+                PartitionKind::SparseBitmap,
+                PartitionKind::InvertedVec,
+                PartitionKind::InvertedRun,
+            ] {
+                let mut partition = mkpartition::<Low>(kind, &set);
+                assert!(partition.max_encoded_size() >= partition.encoded_size());
+
+                partition.complement();
+                assert!(partition.max_encoded_size() >= partition.encoded_size());
+            }
+        }
+    }
+
+    #[test]
+    fn test_encode_to_writer_error() {
+        /// A writer which fails once more than `limit` bytes are written
+        struct LimitedWriter {
+            written: usize,
+            limit: usize,
+        }
+
+        impl io::Write for LimitedWriter {
+            fn write(&mut self, buf: &[u8]) -> io::Result<usize> {
+                if self.written + buf.len() > self.limit {
+                    return Err(io::Error::new(io::ErrorKind::StorageFull, "full"));
+                }
+                self.written += buf.len();
+                Ok(buf.len())
+            }
+
+            fn flush(&mut self) -> io::Result<()> {
+                Ok(())
+            }
+        }
+
+        let splinter = Splinter::from_iter((0..1 << 20).step_by(3));
+        let size = splinter.encoded_size();
+        assert!(size > WriteBuf::<LimitedWriter>::CHUNK_SIZE);
+
+        for limit in [0, 1, size / 2, size - 1] {
+            let mut writer = LimitedWriter { written: 0, limit };
+            let err = splinter.encode_to_writer(&mut writer).unwrap_err();
+            assert_eq!(err.kind(), io::ErrorKind::StorageFull);
+        }
+
+        let mut writer = LimitedWriter { written: 0, limit: size };
+        assert_eq!(splinter.encode_to_writer(&mut writer).unwrap(), size as u64);
+        assert_eq!(writer.written, size);
+    }
+
     #[test]
     fn test_dense_splinter_roundtrip_7936_boundary() {
         let encoded = (1u32..=7936).collect::<Splinter>().encode_to_bytes();
@@ -221,7 +992,7 @@ mod tests {
 
     #[test]
     fn test_length_corruption() {
-        for i in 0..Footer::SIZE {
+        for i in 0..Footer::MAGIC_SIZE {
             let truncated = [0].repeat(i);
             assert_error!(
                 SplinterRef::from_bytes(truncated),
@@ -230,6 +1001,12 @@ mod tests {
                 i
             );
         }
+
+        // a valid magic value without the checksum it declares
+        assert_error!(
+            SplinterRef::from_bytes(SPLINTER_V2_MAGIC.to_vec()),
+            DecodeErr::Length
+        );
     }
 
     #[test]
@@ -270,6 +1047,82 @@ mod tests {
         assert_error!(SplinterRef::from_bytes(buf), DecodeErr::Checksum);
     }
 
+    #[test]
+    fn test_decode_options_checksum() {
+        let mut buf = mksplinter_buf(&[1, 2, 3]);
+        let checksum_offset = buf.len() - Footer::SIZE;
+        buf[checksum_offset] = 123;
+        let buf = buf.freeze();
+
+        // trusted buffers skip the checksum
+        let splinter_ref =
+            SplinterRef::from_bytes_with(buf.clone(), DecodeOptions::TRUSTED).unwrap();
+        assert_eq!(splinter_ref.iter().collect_vec(), vec![1, 2, 3]);
+        assert_error!(
+            SplinterRef::from_bytes_with(buf.clone(), DecodeOptions::DEFAULT),
+            DecodeErr::Checksum
+        );
+        assert_error!(
+            SplinterRef::from_bytes_with(buf, DecodeOptions::UNTRUSTED),
+            DecodeErr::Checksum
+        );
+    }
+
+    #[test]
+    fn test_decode_options_limits() {
+        // every other value in 16 blocks produces a low tree with 16 children
+        let splinter = Splinter::from_iter((0..4096).step_by(2));
+        let buf = splinter.encode_to_bytes();
+        let options = DecodeOptions::UNTRUSTED;
+        assert!(SplinterRef::from_bytes_with(buf.clone(), options).is_ok());
+
+        assert_matches!(
+            SplinterRef::from_bytes_with(buf.clone(), options.with_max_len(buf.len() - 1)),
+            Err(LocatedDecodeErr {
+                err: DecodeErr::LimitExceeded { limit: DecodeLimit::Length },
+                location: None
+            })
+        );
+        assert!(SplinterRef::from_bytes_with(buf.clone(), options.with_max_len(buf.len())).is_ok());
+
+        assert_matches!(
+            SplinterRef::from_bytes_with(buf.clone(), options.with_max_fan_out(15)),
+            Err(LocatedDecodeErr {
+                err: DecodeErr::LimitExceeded { limit: DecodeLimit::FanOut },
+                location: None
+            })
+        );
+        assert!(SplinterRef::from_bytes_with(buf.clone(), options.with_max_fan_out(16)).is_ok());
+
+        assert_matches!(
+            SplinterRef::from_bytes_with(buf.clone(), options.with_max_cardinality(2047)),
+            Err(LocatedDecodeErr {
+                err: DecodeErr::LimitExceeded { limit: DecodeLimit::Cardinality },
+                location: None
+            })
+        );
+        assert!(SplinterRef::from_bytes_with(buf, options.with_max_cardinality(2048)).is_ok());
+
+        // a full splinter is tiny both when encoded and when decoded, but
+        // yields 2^32 values when iterated
+        let full = Splinter::FULL.encode_to_bytes();
+        assert!(full.len() < 64);
+        let decoded = SplinterRef::from_bytes(full.clone())
+            .unwrap()
+            .decode_to_splinter();
+        assert!(decoded.encoded_size() < 64);
+        assert_matches!(
+            SplinterRef::from_bytes_with(full, options.with_max_cardinality(1 << 20)),
+            Err(LocatedDecodeErr {
+                err: DecodeErr::LimitExceeded { limit: DecodeLimit::Cardinality },
+                location: None
+            })
+        );
+
+        let err = DecodeErr::LimitExceeded { limit: DecodeLimit::FanOut };
+        assert_eq!(err.to_string(), "splinter exceeds the maximum tree fan-out");
+    }
+
     #[test]
     fn test_corrupted_vec_partition() {
         let mut buf = mkpartition_buf::<Block>(PartitionKind::Vec, &[1, 2, 3]);
@@ -318,6 +1171,78 @@ mod tests {
         assert_error!(PartitionRef::<Block>::from_suffix(&buf), DecodeErr::Length);
     }
 
+    #[test]
+    fn test_validate_located() {
+        let buf = mkpartition_buf::<High>(PartitionKind::Tree, &[1, 2]);
+
+        // corrupt the cumulative cardinality of the root tree
+        let mut corrupted = buf.clone();
+        corrupted[17] = 0x05;
+        let splinter_ref = SplinterRef::from_bytes(mksplinter_manual(&corrupted)).unwrap();
+        let err = splinter_ref.validate_deep_located().unwrap_err();
+        assert_matches!(err.err, DecodeErr::Validity);
+        assert_eq!(
+            err.location,
+            Some(DecodeLocation {
+                offset: buf.len() - 1,
+                level: PartitionLevel::High,
+                path: vec![],
+                kind: Some(PartitionKind::Tree),
+            })
+        );
+
+        // swap the values stored in the child Vec partition
+        let mut corrupted = buf.clone();
+        corrupted[2] = 0x02;
+        corrupted[5] = 0x01;
+        let splinter_ref = SplinterRef::from_bytes(mksplinter_manual(&corrupted)).unwrap();
+        let err = splinter_ref.validate_deep_located().unwrap_err();
+        assert_matches!(err.err, DecodeErr::Validity);
+        assert_eq!(
+            err.location,
+            Some(DecodeLocation {
+                offset: 9,
+                level: PartitionLevel::Mid,
+                path: vec![0],
+                kind: Some(PartitionKind::Vec),
+            })
+        );
+        assert_eq!(
+            err.to_string(),
+            "invalid encoding in Mid Vec partition at offset 9 (path [0])"
+        );
+        assert_error!(splinter_ref.validate_deep(), DecodeErr::Validity);
+
+        // validating while decoding locates errors in the same way
+        let decode_err =
+            SplinterRef::from_bytes_with(mksplinter_manual(&corrupted), DecodeOptions::UNTRUSTED)
+                .expect_err("expected an error");
+        assert_eq!(decode_err.location, err.location);
+
+        // errors in nested trees record every segment on the path
+        let mut splinter = Splinter::from_iter((0..4096).step_by(2).map(|v| v + (3 << 24)));
+        splinter.optimize();
+        let buf = splinter.encode_to_bytes();
+        SplinterRef::from_bytes(buf.clone())
+            .unwrap()
+            .validate_deep_located()
+            .unwrap();
+        let mut corrupted = buf.to_vec();
+        // every other bit is set in the first block's bitmap
+        assert_eq!(corrupted[0], 0b0101_0101);
+        corrupted[0] = 0;
+        let (partitions, footer) = Footer::split_unchecked(&corrupted);
+        let corrupted = mksplinter_manual_with_features(partitions, footer.features);
+        let err = SplinterRef::from_bytes(corrupted)
+            .unwrap()
+            .validate_deep_located()
+            .unwrap_err();
+        let location = err.location.unwrap();
+        assert_eq!(location.level, PartitionLevel::Low);
+        assert_eq!(location.path, vec![3, 0]);
+        assert_eq!(location.kind, Some(PartitionKind::Tree));
+    }
+
     #[test]
     fn test_vec_byteorder() {
         let buf = mkpartition_buf::<Low>(PartitionKind::Vec, &[0x01_00, 0x02_00]);
@@ -355,6 +1280,14 @@ mod tests {
         );
     }
 
+    #[test]
+    #[should_panic(expected = "checksum changed after writing")]
+    fn test_encoder_panics_when_checksum_is_changed_after_writing() {
+        let mut encoder = Encoder::new(BytesMut::new());
+        encoder.put_kind(PartitionKind::Empty);
+        let _ = encoder.with_checksum(Checksum::Crc32c);
+    }
+
     #[test]
     #[should_panic(expected = "footer already present")]
     fn test_encoder_panics_when_footer_is_written_after_splinter_blob() {
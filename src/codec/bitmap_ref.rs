@@ -0,0 +1,161 @@
+use std::{marker::PhantomData, ops::RangeBounds};
+
+use bitvec::{order::Lsb0, slice::BitSlice};
+use either::Either;
+use num::traits::AsPrimitive;
+use zerocopy::IntoBytes;
+
+use crate::{
+    PartitionRead, level::Level, partition::bitmap::BitmapPartition, splinter_mut::Patches,
+    traits::TruncateFrom, util::RangeExt,
+};
+
+/// The storage backing an encoded bitmap.
+#[derive(Debug, Clone, Copy)]
+pub(crate) enum Bits<'a> {
+    Bytes(&'a BitSlice<u8, Lsb0>),
+    /// A bitmap which starts on an 8-byte boundary, which allows it to be
+    /// read as u64 words on little-endian targets
+    #[cfg_attr(target_endian = "big", allow(dead_code))]
+    Words(&'a BitSlice<u64, Lsb0>),
+}
+
+/// Evaluates `$body` with `$bits` bound to the `BitSlice` backing a
+/// [`BitmapRef`], whichever store it uses.
+macro_rules! with_bits {
+    ($bitmap:expr, |$bits:ident| $body:expr) => {
+        match $bitmap.bits {
+            $crate::codec::bitmap_ref::Bits::Bytes($bits) => $body,
+            $crate::codec::bitmap_ref::Bits::Words($bits) => $body,
+        }
+    };
+}
+pub(crate) use with_bits;
+
+/// A zero-copy view of an encoded bitmap partition.
+#[derive(Debug, Clone, Copy)]
+pub struct BitmapRef<'a, L: Level> {
+    pub(crate) bits: Bits<'a>,
+    _marker: PhantomData<L>,
+}
+
+impl<'a, L: Level> BitmapRef<'a, L> {
+    /// Reads an encoded bitmap, using the word-aligned representation if the
+    /// bitmap happens to start on an 8-byte boundary.
+    pub(super) fn from_bytes(data: &'a [u8]) -> Self {
+        // bitmaps are stored as little-endian u64 words
+        #[cfg(target_endian = "little")]
+        if let Ok(words) = <[u64] as zerocopy::FromBytes>::ref_from_bytes(data) {
+            return Self::new(Bits::Words(BitSlice::from_slice(words)));
+        }
+        Self::new(Bits::Bytes(BitSlice::from_slice(data)))
+    }
+
+    fn new(bits: Bits<'a>) -> Self {
+        Self { bits, _marker: PhantomData }
+    }
+
+    /// Returns true if this bitmap is read as u64 words.
+    #[cfg(test)]
+    pub(crate) fn is_aligned(&self) -> bool {
+        matches!(self.bits, Bits::Words(_))
+    }
+
+    /// Returns the offset of this bitmap from the start of `data`, which must
+    /// contain it.
+    pub(crate) fn offset_in(&self, data: &[u8]) -> usize {
+        let start = with_bits!(self, |bits| bits.as_bitptr().pointer() as usize);
+        start - data.as_ptr() as usize
+    }
+
+    /// Plans an in-place edit which toggles `value`.
+    pub(crate) fn plan_edit(&self, value: L::Value, patches: &mut Patches) {
+        // encoded bitmaps always cover whole elements
+        with_bits!(self, |bits| {
+            let (_, elements, _) = bits.domain().region().unwrap();
+            patches.toggle_bit(elements.as_bytes(), value.as_())
+        })
+    }
+
+    pub fn into_iter(self) -> impl Iterator<Item = L::Value> + 'a {
+        match self.bits {
+            Bits::Bytes(bits) => Either::Left(bits.iter_ones()),
+            Bits::Words(bits) => Either::Right(bits.iter_ones()),
+        }
+        .map(L::Value::truncate_from)
+    }
+}
+
+impl<L: Level> PartitionRead<L> for BitmapRef<'_, L> {
+    fn cardinality(&self) -> usize {
+        with_bits!(self, |bits| bits.count_ones())
+    }
+
+    fn is_empty(&self) -> bool {
+        with_bits!(self, |bits| bits.not_any())
+    }
+
+    fn contains(&self, value: L::Value) -> bool {
+        with_bits!(self, |bits| bits[value.as_()])
+    }
+
+    fn position(&self, value: L::Value) -> Option<usize> {
+        with_bits!(self, |bits| {
+            let value: usize = value.as_();
+            bits[value].then(|| bits[..value].count_ones())
+        })
+    }
+
+    fn rank(&self, value: L::Value) -> usize {
+        with_bits!(self, |bits| bits[..=value.as_()].count_ones())
+    }
+
+    fn select(&self, idx: usize) -> Option<L::Value> {
+        with_bits!(self, |bits| bits.iter_ones().nth(idx)).map(L::Value::truncate_from)
+    }
+
+    fn last(&self) -> Option<L::Value> {
+        with_bits!(self, |bits| bits.last_one()).map(L::Value::truncate_from)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = L::Value> {
+        self.into_iter()
+    }
+
+    fn contains_all<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        if let Some(range) = values.try_into_inclusive() {
+            let range = (*range.start()).as_()..=(*range.end()).as_();
+            with_bits!(self, |bits| bits[range].all())
+        } else {
+            // empty range is trivially contained
+            true
+        }
+    }
+
+    fn contains_any<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        if let Some(range) = values.try_into_inclusive() {
+            let range = (*range.start()).as_()..=(*range.end()).as_();
+            with_bits!(self, |bits| bits[range].any())
+        } else {
+            // empty range has no intersection
+            false
+        }
+    }
+}
+
+impl<L: Level> PartialEq for BitmapRef<'_, L> {
+    fn eq(&self, other: &Self) -> bool {
+        with_bits!(self, |l| with_bits!(other, |r| l == r))
+    }
+}
+
+impl<L: Level> Eq for BitmapRef<'_, L> {}
+
+impl<L: Level> From<&BitmapRef<'_, L>> for BitmapPartition<L> {
+    fn from(value: &BitmapRef<'_, L>) -> Self {
+        match value.bits {
+            Bits::Bytes(bits) => BitmapPartition::from_encoded(bits),
+            Bits::Words(bits) => BitmapPartition::from_words(bits),
+        }
+    }
+}
@@ -0,0 +1,145 @@
+use crc64fast_nvme::Digest;
+#[cfg(feature = "xxh3")]
+use xxhash_rust::xxh3::Xxh3Default;
+
+/// The checksum algorithm stored in the footer of a serialized splinter.
+///
+/// Each algorithm is identified by its own magic value, so
+/// [`SplinterRef::from_bytes`] detects the algorithm automatically. Select an
+/// algorithm when encoding using [`Encoder::with_checksum`].
+///
+/// [`Checksum::Crc32c`] and [`Checksum::Xxh3`] require the `crc32c` and
+/// `xxh3` cargo features respectively. Without them, splinters using these
+/// checksums can't be encoded, and decoding them fails with
+/// [`DecodeErr::UnsupportedChecksum`] unless the checksum isn't verified.
+///
+/// [`SplinterRef::from_bytes`]: crate::SplinterRef::from_bytes
+/// [`Encoder::with_checksum`]: crate::codec::encoder::Encoder::with_checksum
+/// [`DecodeErr::UnsupportedChecksum`]: crate::codec::DecodeErr::UnsupportedChecksum
+#[derive(Debug, Clone, Copy, PartialEq, Eq, Hash, Default)]
+#[repr(u8)]
+pub enum Checksum {
+    /// An 8 byte CRC-64/NVME checksum. This is the default, and the only
+    /// checksum understood by splinter-rs 0.12 and earlier.
+    #[default]
+    Crc64Nvme = 0,
+
+    /// A 4 byte CRC-32C (Castagnoli) checksum. Requires the `crc32c` cargo
+    /// feature.
+    Crc32c = 1,
+
+    /// An 8 byte XXH3 hash. Requires the `xxh3` cargo feature.
+    Xxh3 = 2,
+
+    /// No checksum, only the magic value is stored.
+    ///
+    /// Corruption will only be detected if it results in an invalid
+    /// structure, so this should only be used when the splinter is stored in
+    /// a container which already verifies its integrity.
+    None = 3,
+}
+
+impl Checksum {
+    pub(crate) const ALL: [Self; 4] = [Self::Crc64Nvme, Self::Crc32c, Self::Xxh3, Self::None];
+
+    /// The checksums whose cargo features are enabled.
+    #[cfg(test)]
+    pub(crate) fn enabled() -> impl Iterator<Item = Self> + Clone {
+        Self::ALL
+            .into_iter()
+            .filter(|checksum| checksum.is_enabled())
+    }
+
+    /// The number of bytes this checksum occupies in the footer.
+    #[inline]
+    pub const fn size(self) -> usize {
+        match self {
+            Self::Crc64Nvme | Self::Xxh3 => 8,
+            Self::Crc32c => 4,
+            Self::None => 0,
+        }
+    }
+
+    /// Returns true if the cargo feature this checksum requires is enabled.
+    #[inline]
+    pub const fn is_enabled(self) -> bool {
+        match self {
+            Self::Crc64Nvme | Self::None => true,
+            Self::Crc32c => cfg!(feature = "crc32c"),
+            Self::Xxh3 => cfg!(feature = "xxh3"),
+        }
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the checksum's cargo feature is disabled.
+    pub(crate) fn hasher(self) -> Hasher {
+        match self {
+            Self::Crc64Nvme => Hasher::Crc64Nvme(Digest::new()),
+            #[cfg(feature = "crc32c")]
+            Self::Crc32c => Hasher::Crc32c(0),
+            #[cfg(feature = "xxh3")]
+            Self::Xxh3 => Hasher::Xxh3(Box::new(Xxh3Default::new())),
+            Self::None => Hasher::None,
+            #[allow(unreachable_patterns)]
+            _ => panic!(
+                "invalid checksum usage: the {self:?} checksum is disabled by the crate's cargo features"
+            ),
+        }
+    }
+
+    /// Computes this checksum over data, zero-extended to a u64.
+    pub(crate) fn compute(self, data: &[u8]) -> u64 {
+        let mut hasher = self.hasher();
+        hasher.write(data);
+        hasher.sum()
+    }
+}
+
+/// An incremental hasher for a [`Checksum`].
+pub(crate) enum Hasher {
+    Crc64Nvme(Digest),
+    #[cfg(feature = "crc32c")]
+    Crc32c(u32),
+    #[cfg(feature = "xxh3")]
+    Xxh3(Box<Xxh3Default>),
+    None,
+}
+
+impl Hasher {
+    pub fn checksum(&self) -> Checksum {
+        match self {
+            Self::Crc64Nvme(_) => Checksum::Crc64Nvme,
+            #[cfg(feature = "crc32c")]
+            Self::Crc32c(_) => Checksum::Crc32c,
+            #[cfg(feature = "xxh3")]
+            Self::Xxh3(_) => Checksum::Xxh3,
+            Self::None => Checksum::None,
+        }
+    }
+
+    #[inline]
+    pub fn write(&mut self, data: &[u8]) {
+        match self {
+            Self::Crc64Nvme(digest) => digest.write(data),
+            #[cfg(feature = "crc32c")]
+            Self::Crc32c(crc) => *crc = crc32c::crc32c_append(*crc, data),
+            #[cfg(feature = "xxh3")]
+            Self::Xxh3(xxh3) => xxh3.update(data),
+            Self::None => {}
+        }
+    }
+
+    /// Returns the checksum of all data written so far, zero-extended to a
+    /// u64.
+    pub fn sum(&self) -> u64 {
+        match self {
+            Self::Crc64Nvme(digest) => digest.sum64(),
+            #[cfg(feature = "crc32c")]
+            Self::Crc32c(crc) => (*crc).into(),
+            #[cfg(feature = "xxh3")]
+            Self::Xxh3(xxh3) => xxh3.digest(),
+            Self::None => 0,
+        }
+    }
+}
@@ -0,0 +1,324 @@
+//! An optional LZ4 compressed envelope for encoded splinters.
+//!
+//! Some distributions, such as dense sets or sets with one value in every
+//! block, compress very well with a general purpose compressor. The envelope
+//! wraps an LZ4 block containing an entire encoded splinter:
+//!
+//! ```text
+//! [lz4 block][uncompressed length][checksum][COMPRESSED_MAGIC]
+//! ```
+//!
+//! The uncompressed length is a little-endian u32, and the checksum is a
+//! CRC-64/NVME checksum covering the block and the uncompressed length. The
+//! compressed splinter retains its own footer and checksum.
+//!
+//! Compressed splinters can't be queried in place. Decompress them using
+//! `SplinterRef::from_compressed_bytes`, or decode them transparently using
+//! [`CowSplinter::from_bytes`]. Both require the `lz4` cargo feature, while
+//! [`SplinterRef::from_bytes`] always rejects compressed splinters with
+//! [`DecodeErr::Compressed`].
+//!
+//! [`SplinterRef::from_bytes`]: crate::SplinterRef::from_bytes
+//! [`CowSplinter::from_bytes`]: crate::CowSplinter::from_bytes
+//! [`DecodeErr::Compressed`]: crate::codec::DecodeErr::Compressed
+
+#[cfg(feature = "lz4")]
+use {
+    crate::codec::{DecodeErr, DecodeLimit, checksum::Checksum},
+    bytes::{BufMut, Bytes, BytesMut},
+    zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, U32, U64, Unaligned},
+};
+
+/// The last four bytes of a compressed splinter.
+pub const COMPRESSED_MAGIC: [u8; 4] = [0x59, 0x11, 0xA7, 0x9D];
+
+/// LZ4 can't expand data by more than this factor, which bounds the
+/// uncompressed length of a valid block.
+#[cfg(feature = "lz4")]
+const MAX_RATIO: usize = 255;
+
+/// The footer at the end of a compressed splinter.
+#[cfg(feature = "lz4")]
+#[derive(FromBytes, IntoBytes, Immutable, Unaligned, KnownLayout)]
+#[repr(C)]
+struct EnvelopeFooter {
+    /// the length of the uncompressed splinter
+    len: U32<LittleEndian>,
+    /// the checksum of the block and length
+    checksum: U64<LittleEndian>,
+    magic: [u8; 4],
+}
+
+#[cfg(feature = "lz4")]
+impl EnvelopeFooter {
+    /// The number of footer bytes covered by the checksum.
+    const CHECKSUM_OFFSET: usize = size_of::<U32<LittleEndian>>();
+}
+
+/// Returns true if data ends with [`COMPRESSED_MAGIC`].
+pub fn is_compressed(data: &[u8]) -> bool {
+    data.ends_with(&COMPRESSED_MAGIC)
+}
+
+/// Compresses an encoded splinter, but only if the compressed envelope is
+/// smaller than the splinter. Otherwise the splinter is returned unchanged,
+/// so the result may or may not be compressed.
+///
+/// # Examples
+///
+/// ```
+/// use splinter_rs::{Encodable, Splinter, codec::compressed};
+///
+/// // one value in every block compresses well
+/// let dense = Splinter::from_iter((0..1_000_000).step_by(256)).encode_to_bytes();
+/// assert!(compressed::is_compressed(&compressed::compress(dense)));
+///
+/// // while tiny splinters aren't worth compressing
+/// let tiny = Splinter::from_iter([1, 2, 3]).encode_to_bytes();
+/// assert_eq!(compressed::compress(tiny.clone()), tiny);
+/// ```
+#[cfg(feature = "lz4")]
+pub fn compress(splinter: Bytes) -> Bytes {
+    let overhead = size_of::<EnvelopeFooter>();
+    if splinter.len() <= overhead || u32::try_from(splinter.len()).is_err() {
+        return splinter;
+    }
+
+    let Ok(block) = lz4::block::compress(&splinter, None, false) else {
+        return splinter;
+    };
+    if block.len() + overhead >= splinter.len() {
+        return splinter;
+    }
+
+    let mut buf = BytesMut::with_capacity(block.len() + overhead);
+    buf.put_slice(&block);
+    buf.put_u32_le(splinter.len() as u32);
+    let checksum = Checksum::Crc64Nvme.compute(&buf);
+    buf.put_u64_le(checksum);
+    buf.put_slice(&COMPRESSED_MAGIC);
+    buf.freeze()
+}
+
+/// Decompresses a compressed splinter, returning the encoded splinter.
+///
+/// The returned splinter has not been validated; pass it to
+/// [`SplinterRef::from_bytes`](crate::SplinterRef::from_bytes) to do so.
+///
+/// # Errors
+///
+/// - [`DecodeErr::Length`]: Not enough bytes in the buffer
+/// - [`DecodeErr::Magic`]: The buffer doesn't end with [`COMPRESSED_MAGIC`]
+/// - [`DecodeErr::Checksum`]: Data corruption detected
+/// - [`DecodeErr::Validity`]: The LZ4 block is malformed or doesn't match the
+///   uncompressed length
+#[cfg(feature = "lz4")]
+pub fn decompress(data: &[u8]) -> Result<Bytes, DecodeErr> {
+    decompress_with_max_len(data, usize::MAX)
+}
+
+/// Like [`decompress`], but returns [`DecodeErr::LimitExceeded`] rather than
+/// allocating an uncompressed splinter longer than `max_len` bytes.
+#[cfg(feature = "lz4")]
+pub(crate) fn decompress_with_max_len(data: &[u8], max_len: usize) -> Result<Bytes, DecodeErr> {
+    let (block, footer) = EnvelopeFooter::ref_from_suffix(data)?;
+    if footer.magic != COMPRESSED_MAGIC {
+        return Err(DecodeErr::Magic);
+    }
+
+    let mut hasher = Checksum::Crc64Nvme.hasher();
+    hasher.write(block);
+    hasher.write(&footer.as_bytes()[..EnvelopeFooter::CHECKSUM_OFFSET]);
+    if hasher.sum() != footer.checksum.get() {
+        return Err(DecodeErr::Checksum);
+    }
+
+    // reject lengths LZ4 can't produce before allocating the output
+    let len = footer.len.get() as usize;
+    if len > block.len().saturating_mul(MAX_RATIO) {
+        return Err(DecodeErr::Validity);
+    }
+    if len > max_len {
+        return Err(DecodeErr::LimitExceeded { limit: DecodeLimit::Length });
+    }
+    let size = i32::try_from(len).map_err(|_| DecodeErr::Validity)?;
+    let mut buf = vec![0; len];
+    let written = lz4::block::decompress_to_buffer(block, Some(size), &mut buf)
+        .map_err(|_| DecodeErr::Validity)?;
+    if written != len {
+        return Err(DecodeErr::Validity);
+    }
+    Ok(buf.into())
+}
+
+#[cfg(all(test, feature = "lz4"))]
+mod test {
+    use assert_matches::assert_matches;
+    use bytes::Bytes;
+
+    use crate::{
+        CowSplinter, Encodable, PartitionRead, Splinter, SplinterRef, assert_error,
+        codec::{
+            DecodeErr, DecodeLimit, DecodeOptions, LocatedDecodeErr,
+            compressed::{COMPRESSED_MAGIC, compress, decompress, is_compressed},
+        },
+        testutil::SetGen,
+    };
+
+    #[test]
+    fn test_compressed_roundtrip() {
+        let mut set_gen = SetGen::new(0xDEAD_BEEF);
+        let sets = [
+            vec![],
+            vec![1, 2, 3],
+            set_gen.random(1024),
+            set_gen.dense(1, 1, 16, 256),
+            set_gen.distributed(1, 1, 256, 1),
+        ];
+
+        for set in sets {
+            let splinter = Splinter::from_iter(set.iter().copied());
+            let plain = splinter.encode_to_bytes();
+            let encoded = splinter.encode_compressed();
+            assert!(encoded.len() <= plain.len());
+            assert_eq!(is_compressed(&encoded), encoded != plain);
+            if is_compressed(&encoded) {
+                assert_eq!(decompress(&encoded).unwrap(), plain);
+                assert_error!(
+                    SplinterRef::from_bytes(encoded.clone()),
+                    DecodeErr::Compressed
+                );
+            }
+
+            let splinter_ref = SplinterRef::from_compressed_bytes(encoded.clone()).unwrap();
+            assert_eq!(splinter_ref, splinter);
+
+            let cow = CowSplinter::from_bytes(encoded).unwrap();
+            assert_eq!(cow, splinter);
+            assert_eq!(cow.cardinality(), set.len());
+        }
+    }
+
+    #[test]
+    fn test_compressed_adaptive() {
+        // one value in every block compresses well
+        let splinter = Splinter::from_iter((0..(1 << 20)).step_by(256));
+        let plain = splinter.encode_to_bytes();
+        let encoded = splinter.encode_compressed();
+        assert!(is_compressed(&encoded));
+        assert!(encoded.len() < plain.len() / 4, "{} bytes", encoded.len());
+
+        // incompressible splinters are returned unchanged
+        let plain = Bytes::from_static(&[0; 8]);
+        assert_eq!(compress(plain.clone()), plain);
+    }
+
+    #[test]
+    fn test_compressed_corruption() {
+        let splinter = Splinter::from_iter((0..(1 << 20)).step_by(256));
+        let encoded = splinter.encode_compressed();
+        assert!(is_compressed(&encoded));
+
+        assert_error!(decompress(&encoded[..4]), DecodeErr::Length);
+        assert_error!(decompress(&splinter.encode_to_bytes()), DecodeErr::Magic);
+
+        // flip a bit in the block
+        let mut corrupted = encoded.to_vec();
+        corrupted[0] ^= 1;
+        assert_error!(decompress(&corrupted), DecodeErr::Checksum);
+        assert_error!(CowSplinter::from_bytes(corrupted), DecodeErr::Checksum);
+
+        // the magic value alone isn't a compressed splinter
+        assert_matches!(
+            SplinterRef::from_compressed_bytes(Bytes::from_static(&COMPRESSED_MAGIC)),
+            Err(DecodeErr::Length)
+        );
+    }
+
+    #[test]
+    fn test_compressed_limits() {
+        let splinter = Splinter::from_iter((0..(1 << 20)).step_by(256));
+        let plain = splinter.encode_to_bytes();
+        let encoded = splinter.encode_compressed();
+        assert!(is_compressed(&encoded));
+        let options = DecodeOptions::UNTRUSTED;
+
+        let cow = CowSplinter::from_bytes_with(encoded.clone(), options).unwrap();
+        assert_eq!(cow, splinter);
+
+        // the uncompressed length is checked before decompressing
+        let limited = options.with_max_len(plain.len() - 1);
+        assert!(encoded.len() <= limited.max_len());
+        assert_matches!(
+            CowSplinter::from_bytes_with(encoded.clone(), limited),
+            Err(LocatedDecodeErr {
+                err: DecodeErr::LimitExceeded { limit: DecodeLimit::Length },
+                location: None
+            })
+        );
+        assert!(
+            CowSplinter::from_bytes_with(encoded.clone(), options.with_max_len(plain.len()))
+                .is_ok()
+        );
+
+        // the other limits are checked before decoding
+        let limited = options.with_max_cardinality(splinter.cardinality() - 1);
+        assert_matches!(
+            CowSplinter::from_bytes_with(encoded, limited),
+            Err(LocatedDecodeErr {
+                err: DecodeErr::LimitExceeded { limit: DecodeLimit::Cardinality },
+                location: None
+            })
+        );
+    }
+}
+
+#[cfg(all(test, not(feature = "lz4")))]
+mod test_without_lz4 {
+    use assert_matches::assert_matches;
+    use bytes::{BufMut, Bytes, BytesMut};
+
+    use crate::{
+        CowSplinter, Encodable, Splinter, SplinterRef, assert_error,
+        codec::{
+            DecodeErr, DecodeOptions, LocatedDecodeErr,
+            checksum::Checksum,
+            compressed::{COMPRESSED_MAGIC, is_compressed},
+        },
+    };
+
+    /// Wraps an encoded splinter in a compressed envelope, like
+    /// `compress` does when the `lz4` feature is enabled.
+    fn mkcompressed(splinter: &[u8]) -> Bytes {
+        let mut buf = BytesMut::from(&lz4::block::compress(splinter, None, false).unwrap()[..]);
+        buf.put_u32_le(splinter.len() as u32);
+        let checksum = Checksum::Crc64Nvme.compute(&buf);
+        buf.put_u64_le(checksum);
+        buf.put_slice(&COMPRESSED_MAGIC);
+        buf.freeze()
+    }
+
+    #[test]
+    fn test_compressed_without_lz4() {
+        let splinter = Splinter::from_iter((0..(1 << 20)).step_by(256));
+        let encoded = mkcompressed(&splinter.encode_to_bytes());
+        assert!(is_compressed(&encoded));
+
+        // compressed splinters are rejected rather than misread as splinters
+        assert_error!(
+            SplinterRef::from_bytes(encoded.clone()),
+            DecodeErr::Compressed
+        );
+        assert_error!(
+            CowSplinter::from_bytes(encoded.clone()),
+            DecodeErr::Compressed
+        );
+        assert_matches!(
+            CowSplinter::from_bytes_with(encoded, DecodeOptions::UNTRUSTED),
+            Err(LocatedDecodeErr {
+                err: DecodeErr::Compressed,
+                location: None
+            })
+        );
+    }
+}
@@ -0,0 +1,294 @@
+use std::{iter, mem::size_of, ops::RangeBounds};
+
+use num::traits::AsPrimitive;
+use zerocopy::{FromBytes, TryFromBytes};
+
+use crate::{
+    PartitionRead,
+    codec::{DecodeErr, partition_ref::decode_len_from_suffix},
+    level::Level,
+    partition::delta_vec::{SKIP_INTERVAL, get_varint, num_blocks, varint_len},
+    traits::TruncateFrom,
+    util::{IteratorExt, RangeExt},
+};
+
+/// A zero-copy view of a delta-vec partition. See [`DeltaVecPartition`] for a
+/// description of the encoding.
+///
+/// [`DeltaVecPartition`]: crate::partition::delta_vec::DeltaVecPartition
+#[derive(Debug, Clone, Eq)]
+pub struct DeltaVecRef<'a, L: Level> {
+    cardinality: usize,
+    /// the encoded gaps and skip index
+    data: &'a [u8],
+    gaps: &'a [u8],
+    firsts: &'a [L::ValueUnaligned],
+    ends: &'a [L::ValueUnaligned],
+}
+
+impl<'a, L: Level> DeltaVecRef<'a, L> {
+    pub(super) fn from_suffix(data: &'a [u8]) -> Result<Self, DecodeErr> {
+        let (data, cardinality) = decode_len_from_suffix::<L>(data)?;
+        let index_size = Self::index_size(cardinality);
+        DecodeErr::ensure_bytes_available(data, index_size)?;
+
+        // the last end offset is the total size of the gaps
+        let (_, gaps_size) = L::ValueUnaligned::try_read_from_suffix(data)?;
+        let bytes = index_size + gaps_size.into().as_();
+        DecodeErr::ensure_bytes_available(data, bytes)?;
+        Self::new(cardinality, &data[(data.len() - bytes)..])
+    }
+
+    /// Creates a view over the encoded gaps and skip index of a delta-vec
+    /// partition containing `cardinality` values.
+    pub(crate) fn from_parts(cardinality: usize, data: &'a [u8]) -> Self {
+        Self::new(cardinality, data).expect("BUG: encoded delta-vec partition is malformed")
+    }
+
+    fn new(cardinality: usize, data: &'a [u8]) -> Result<Self, DecodeErr> {
+        let blocks = num_blocks(cardinality);
+        let index_size = Self::index_size(cardinality);
+        DecodeErr::ensure_bytes_available(data, index_size)?;
+        let (gaps, index) = data.split_at(data.len() - index_size);
+        let (firsts, ends) = index.split_at(index_size / 2);
+        Ok(Self {
+            cardinality,
+            data,
+            gaps,
+            firsts: <[L::ValueUnaligned]>::ref_from_bytes_with_elems(firsts, blocks)?,
+            ends: <[L::ValueUnaligned]>::ref_from_bytes_with_elems(ends, blocks)?,
+        })
+    }
+
+    #[inline]
+    const fn index_size(cardinality: usize) -> usize {
+        2 * num_blocks(cardinality) * size_of::<L::ValueUnaligned>()
+    }
+
+    /// Verifies that every block decodes to the expected number of values,
+    /// and that the values are sorted, unique, and in range.
+    pub(crate) fn validate(&self) -> Result<(), DecodeErr> {
+        if !L::ALLOW_DELTA_VEC {
+            return Err(DecodeErr::Validity);
+        }
+
+        let mut prev: Option<usize> = None;
+        let mut start = 0;
+        for block in 0..self.firsts.len() {
+            let end: usize = self.ends[block].into().as_();
+            let mut gaps = self.gaps.get(start..end).ok_or(DecodeErr::Validity)?;
+            let mut value: usize = self.firsts[block].into().as_();
+            if prev.is_some_and(|prev| prev >= value) {
+                return Err(DecodeErr::Validity);
+            }
+
+            let mut len = 1;
+            while !gaps.is_empty() {
+                let (gap, size) = get_varint(gaps).ok_or(DecodeErr::Validity)?;
+                // reject overlong varints so every set has one encoding
+                if size != varint_len(gap) {
+                    return Err(DecodeErr::Validity);
+                }
+                value = value
+                    .checked_add(gap)
+                    .and_then(|v| v.checked_add(1))
+                    .ok_or(DecodeErr::Validity)?;
+                gaps = &gaps[size..];
+                len += 1;
+            }
+
+            if value >= L::MAX_LEN || len != self.block_len(block) {
+                return Err(DecodeErr::Validity);
+            }
+            prev = Some(value);
+            start = end;
+        }
+        Ok(())
+    }
+
+    /// Returns a copy of the encoded gaps and skip index.
+    pub(crate) fn to_bytes(&self) -> Box<[u8]> {
+        self.data.into()
+    }
+
+    pub fn into_iter(self) -> impl Iterator<Item = L::Value> {
+        let cardinality = self.cardinality;
+        (0..self.firsts.len())
+            .flat_map(move |block| self.block(block))
+            .map(L::Value::truncate_from)
+            .with_size_hint(cardinality)
+    }
+
+    #[inline]
+    fn block_len(&self, block: usize) -> usize {
+        SKIP_INTERVAL.min(self.cardinality - block * SKIP_INTERVAL)
+    }
+
+    /// Returns an iterator over the values stored in `block`.
+    fn block(&self, block: usize) -> impl Iterator<Item = usize> + use<'a, L> {
+        let start = match block.checked_sub(1) {
+            Some(prev) => self.ends[prev].into().as_(),
+            None => 0,
+        };
+        let end: usize = self.ends[block].into().as_();
+        let mut gaps = self.gaps.get(start..end).unwrap_or_default();
+
+        let first: usize = self.firsts[block].into().as_();
+        let mut value = first;
+        iter::once(first).chain(iter::from_fn(move || {
+            let (gap, size) = get_varint(gaps)?;
+            gaps = &gaps[size..];
+            value = value.checked_add(gap)?.checked_add(1)?;
+            Some(value)
+        }))
+    }
+
+    /// Searches for `value`, returning its index if found or the index where
+    /// it would be inserted otherwise.
+    fn search(&self, value: L::Value) -> Result<usize, usize> {
+        // find the last block which starts at or before `value`
+        let block = self
+            .firsts
+            .partition_point(|&first| Into::<L::Value>::into(first) <= value);
+        let Some(block) = block.checked_sub(1) else {
+            return Err(0);
+        };
+
+        let value: usize = value.as_();
+        let mut idx = block * SKIP_INTERVAL;
+        for candidate in self.block(block) {
+            match candidate.cmp(&value) {
+                std::cmp::Ordering::Less => idx += 1,
+                std::cmp::Ordering::Equal => return Ok(idx),
+                std::cmp::Ordering::Greater => break,
+            }
+        }
+        Err(idx)
+    }
+}
+
+impl<L: Level> PartitionRead<L> for DeltaVecRef<'_, L> {
+    #[inline]
+    fn cardinality(&self) -> usize {
+        self.cardinality
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.cardinality == 0
+    }
+
+    fn contains(&self, value: L::Value) -> bool {
+        self.search(value).is_ok()
+    }
+
+    fn position(&self, value: L::Value) -> Option<usize> {
+        self.search(value).ok()
+    }
+
+    fn rank(&self, value: L::Value) -> usize {
+        match self.search(value) {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        }
+    }
+
+    fn select(&self, idx: usize) -> Option<L::Value> {
+        if idx >= self.cardinality {
+            return None;
+        }
+        self.block(idx / SKIP_INTERVAL)
+            .nth(idx % SKIP_INTERVAL)
+            .map(L::Value::truncate_from)
+    }
+
+    fn last(&self) -> Option<L::Value> {
+        let block = self.firsts.len().checked_sub(1)?;
+        self.block(block).last().map(L::Value::truncate_from)
+    }
+
+    fn iter(&self) -> impl Iterator<Item = L::Value> {
+        self.clone().into_iter()
+    }
+
+    fn contains_all<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        if let Some(range) = values.try_into_inclusive() {
+            let (start, end) = (*range.start(), *range.end());
+            let Ok(start_idx) = self.search(start) else {
+                return false;
+            };
+
+            // values are sorted and unique, so the range is fully present iff
+            // `end` sits exactly `end - start` slots later
+            let offset: usize = (end - start).as_();
+            self.select(start_idx + offset) == Some(end)
+        } else {
+            // empty range is trivially contained
+            true
+        }
+    }
+
+    fn contains_any<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        if let Some(range) = values.try_into_inclusive() {
+            // find the first value >= range.start
+            let idx = self.search(*range.start()).unwrap_or_else(|i| i);
+            self.select(idx).is_some_and(|v| v <= *range.end())
+        } else {
+            // empty range has no intersection
+            false
+        }
+    }
+}
+
+impl<L: Level> PartialEq for DeltaVecRef<'_, L> {
+    fn eq(&self, other: &Self) -> bool {
+        // the encoding is fully determined by the partition's values
+        self.cardinality == other.cardinality && self.data == other.data
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use crate::{
+        assert_error,
+        codec::{DecodeErr, partition_ref::PartitionRef},
+        level::Low,
+        partition_kind::PartitionKind,
+        testutil::mkpartition_buf,
+    };
+
+    #[test]
+    fn test_delta_vec_byteorder() {
+        let buf = mkpartition_buf::<Low>(PartitionKind::DeltaVec, &[0x01_00, 0x02_00]);
+        assert_eq!(
+            buf.as_ref(),
+            &[
+                0xFF, 0x01, // gap of 255 values
+                0x01, 0x00, // first value of the block
+                0x00, 0x02, // end offset of the block
+                0x00, 0x01, // length
+                0x07, // kind
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_delta_vec() {
+        let buf = mkpartition_buf::<Low>(PartitionKind::DeltaVec, &[0x01_00, 0x02_00]);
+        let partition = PartitionRef::<Low>::from_suffix(&buf).unwrap();
+        assert!(partition.validate().is_ok());
+
+        // overlong varint encoding of a zero gap
+        let mut corrupted = buf.to_vec();
+        corrupted[..2].copy_from_slice(&[0x80, 0x00]);
+        let partition = PartitionRef::<Low>::from_suffix(&corrupted).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+
+        // the gap overflows the level
+        let mut corrupted = buf.to_vec();
+        corrupted[2..4].copy_from_slice(&[0xFF, 0x00]);
+        let partition = PartitionRef::<Low>::from_suffix(&corrupted).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+    }
+}
@@ -0,0 +1,383 @@
+use std::{marker::PhantomData, ops::RangeBounds};
+
+use bitvec::{field::BitField, order::Lsb0, slice::BitSlice};
+use num::traits::AsPrimitive;
+use zerocopy::FromBytes;
+
+use crate::{
+    PartitionRead,
+    codec::{DecodeErr, partition_ref::decode_len_from_suffix},
+    level::Level,
+    partition::elias_fano::{EliasFanoLayout, SELECT_INTERVAL, SelectSample, select_samples},
+    traits::TruncateFrom,
+    util::{IteratorExt, RangeExt},
+};
+
+/// A zero-copy view of an Elias-Fano encoded partition. See
+/// [`EliasFanoLayout`] for a description of the encoding.
+#[derive(Debug, Clone, Eq)]
+pub struct EliasFanoRef<'a, L: Level> {
+    cardinality: usize,
+    low_bits: usize,
+    /// the encoded lower and upper bit arrays and select index
+    data: &'a [u8],
+    lower: &'a BitSlice<u8, Lsb0>,
+    upper: &'a BitSlice<u8, Lsb0>,
+    upper_bytes: &'a [u8],
+    one_samples: &'a [SelectSample],
+    zero_samples: &'a [SelectSample],
+    _marker: PhantomData<L>,
+}
+
+impl<'a, L: Level> EliasFanoRef<'a, L> {
+    pub(super) fn from_suffix(data: &'a [u8]) -> Result<Self, DecodeErr> {
+        let (data, cardinality) = decode_len_from_suffix::<L>(data)?;
+        let bytes = EliasFanoLayout::new::<L>(cardinality).bytes();
+        DecodeErr::ensure_bytes_available(data, bytes)?;
+        let range = (data.len() - bytes)..data.len();
+        Ok(Self::from_parts(cardinality, &data[range]))
+    }
+
+    /// Creates a view over the encoded bit arrays and select index of an
+    /// Elias-Fano partition containing `cardinality` values.
+    pub(crate) fn from_parts(cardinality: usize, data: &'a [u8]) -> Self {
+        let layout = EliasFanoLayout::new::<L>(cardinality);
+        assert_eq!(
+            data.len(),
+            layout.bytes(),
+            "BUG: encoded Elias-Fano partition has the wrong length"
+        );
+        let (lower, rest) = data.split_at(layout.lower_bytes());
+        let (upper, samples) = rest.split_at(layout.upper_bytes());
+        let samples = <[SelectSample]>::ref_from_bytes(samples)
+            .expect("BUG: select index is always unaligned and sized to fit");
+        let (one_samples, zero_samples) = samples.split_at(layout.one_samples);
+        Self {
+            cardinality,
+            low_bits: layout.low_bits,
+            data,
+            lower: &BitSlice::from_slice(lower)[..layout.lower_bits],
+            upper: &BitSlice::from_slice(upper)[..layout.upper_bits],
+            upper_bytes: upper,
+            one_samples,
+            zero_samples,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Verifies that the upper bit array contains exactly one bit per value,
+    /// that the values are sorted, unique, and in range, and that the select
+    /// index matches the upper bit array.
+    pub(crate) fn validate(&self) -> Result<(), DecodeErr> {
+        if !L::ALLOW_ELIAS_FANO || self.upper.count_ones() != self.cardinality {
+            return Err(DecodeErr::Validity);
+        }
+        for (bit, samples) in [(true, self.one_samples), (false, self.zero_samples)] {
+            let expected = select_samples(self.upper, bit);
+            if !expected.eq(samples.iter().map(|s| s.get() as usize)) {
+                return Err(DecodeErr::Validity);
+            }
+        }
+        let mut prev: Option<usize> = None;
+        for (idx, pos) in self.upper.iter_ones().enumerate() {
+            let value = self.raw_value(pos - idx, idx);
+            if value >= L::MAX_LEN || prev.is_some_and(|prev| prev >= value) {
+                return Err(DecodeErr::Validity);
+            }
+            prev = Some(value);
+        }
+        Ok(())
+    }
+
+    /// Returns a copy of the encoded bit arrays and select index.
+    pub(crate) fn to_bytes(&self) -> Box<[u8]> {
+        self.data.into()
+    }
+
+    pub fn into_iter(self) -> impl Iterator<Item = L::Value> {
+        let cardinality = self.cardinality;
+        self.upper
+            .iter_ones()
+            .enumerate()
+            .map(move |(idx, pos)| self.value(pos - idx, idx))
+            .with_size_hint(cardinality)
+    }
+
+    #[inline]
+    fn raw_value(&self, high: usize, idx: usize) -> usize {
+        let low = if self.low_bits == 0 {
+            0
+        } else {
+            let start = idx * self.low_bits;
+            self.lower[start..start + self.low_bits].load_le::<usize>()
+        };
+        (high << self.low_bits) | low
+    }
+
+    #[inline]
+    fn value(&self, high: usize, idx: usize) -> L::Value {
+        L::Value::truncate_from(self.raw_value(high, idx))
+    }
+
+    /// Returns the position of the `n`th (zero based) set bit, or unset bit if
+    /// `bit` is false, in the upper bit array. The search starts at the
+    /// closest preceding sample in the select index.
+    #[inline]
+    fn select_upper(&self, bit: bool, n: usize) -> Option<usize> {
+        let samples = if bit {
+            self.one_samples
+        } else {
+            self.zero_samples
+        };
+        let (start, n) = match (n / SELECT_INTERVAL).checked_sub(1) {
+            None => (0, n),
+            Some(sample) => (samples.get(sample)?.get() as usize, n % SELECT_INTERVAL),
+        };
+        select_bit(self.upper_bytes, self.upper.len(), bit, start, n)
+    }
+
+    /// Searches for `value`, returning its index if found or the index where
+    /// it would be inserted otherwise.
+    fn search(&self, value: L::Value) -> Result<usize, usize> {
+        let value: usize = value.as_();
+        let high = value >> self.low_bits;
+
+        // the bucket for `high` starts after the terminating bit of the
+        // previous bucket, and every bit before it belongs to a smaller value
+        let start = if high == 0 {
+            0
+        } else {
+            match self.select_upper(false, high - 1) {
+                Some(pos) => pos + 1,
+                None => return Err(self.cardinality),
+            }
+        };
+        let bucket = self.upper.get(start..).unwrap_or_default();
+        let end = start + bucket.first_zero().unwrap_or(bucket.len());
+
+        // values within a bucket are sorted by their low bits
+        let (mut lo, mut hi) = (start - high, end - high);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            match self.raw_value(high, mid).cmp(&value) {
+                std::cmp::Ordering::Less => lo = mid + 1,
+                std::cmp::Ordering::Equal => return Ok(mid),
+                std::cmp::Ordering::Greater => hi = mid,
+            }
+        }
+        Err(lo)
+    }
+}
+
+/// Returns the position of the `n`th (zero based) set bit, or unset bit if
+/// `bit` is false, at or after position `start` within the first `len` bits
+/// of `bytes`.
+fn select_bit(bytes: &[u8], len: usize, bit: bool, start: usize, mut n: usize) -> Option<usize> {
+    const WORD_BYTES: usize = size_of::<u64>();
+    let first = start / u64::BITS as usize;
+    let bytes = bytes.get(first * WORD_BYTES..)?;
+    for (i, chunk) in bytes.chunks(WORD_BYTES).enumerate() {
+        let mut word = [0; WORD_BYTES];
+        word[..chunk.len()].copy_from_slice(chunk);
+        let mut word = u64::from_le_bytes(word);
+        if !bit {
+            word = !word;
+        }
+        if i == 0 {
+            // ignore the bits before `start`
+            word &= u64::MAX << (start % u64::BITS as usize);
+        }
+        let i = first + i;
+
+        let ones = word.count_ones() as usize;
+        if n < ones {
+            for _ in 0..n {
+                // clear the lowest set bit
+                word &= word - 1;
+            }
+            let pos = i * u64::BITS as usize + word.trailing_zeros() as usize;
+            // ignore the padding bits in the last byte
+            return (pos < len).then_some(pos);
+        }
+        n -= ones;
+    }
+    None
+}
+
+impl<L: Level> PartitionRead<L> for EliasFanoRef<'_, L> {
+    #[inline]
+    fn cardinality(&self) -> usize {
+        self.cardinality
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.cardinality == 0
+    }
+
+    fn contains(&self, value: L::Value) -> bool {
+        self.search(value).is_ok()
+    }
+
+    fn position(&self, value: L::Value) -> Option<usize> {
+        self.search(value).ok()
+    }
+
+    fn rank(&self, value: L::Value) -> usize {
+        match self.search(value) {
+            Ok(index) => index + 1,
+            Err(index) => index,
+        }
+    }
+
+    fn select(&self, idx: usize) -> Option<L::Value> {
+        if idx >= self.cardinality {
+            return None;
+        }
+        let pos = self.select_upper(true, idx)?;
+        Some(self.value(pos - idx, idx))
+    }
+
+    fn last(&self) -> Option<L::Value> {
+        let idx = self.cardinality.checked_sub(1)?;
+        let pos = self.upper.last_one()?;
+        Some(self.value(pos - idx, idx))
+    }
+
+    fn iter(&self) -> impl Iterator<Item = L::Value> {
+        self.clone().into_iter()
+    }
+
+    fn contains_all<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        if let Some(range) = values.try_into_inclusive() {
+            let (start, end) = (*range.start(), *range.end());
+            let Ok(start_idx) = self.search(start) else {
+                return false;
+            };
+
+            // values are sorted and unique, so the range is fully present iff
+            // `end` sits exactly `end - start` slots later
+            let offset: usize = (end - start).as_();
+            self.select(start_idx + offset) == Some(end)
+        } else {
+            // empty range is trivially contained
+            true
+        }
+    }
+
+    fn contains_any<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        if let Some(range) = values.try_into_inclusive() {
+            // find the first value >= range.start
+            let idx = self.search(*range.start()).unwrap_or_else(|i| i);
+            self.select(idx).is_some_and(|v| v <= *range.end())
+        } else {
+            // empty range has no intersection
+            false
+        }
+    }
+}
+
+impl<L: Level> PartialEq for EliasFanoRef<'_, L> {
+    fn eq(&self, other: &Self) -> bool {
+        self.cardinality == other.cardinality
+            && self.lower == other.lower
+            && self.upper == other.upper
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use bitvec::{bitvec, order::Lsb0};
+
+    use crate::{
+        assert_error,
+        codec::{DecodeErr, elias_fano_ref::select_bit, partition_ref::PartitionRef},
+        level::Low,
+        partition_kind::PartitionKind,
+        testutil::mkpartition_buf,
+    };
+
+    #[test]
+    fn test_select_bit() {
+        let mut bits = bitvec![u8, Lsb0; 0; 150];
+        for pos in [0, 7, 8, 64, 65, 149] {
+            bits.set(pos, true);
+        }
+        let select_from =
+            |bit, start, n| select_bit(bits.as_raw_slice(), bits.len(), bit, start, n);
+        let select_bit = |bit, n| select_from(bit, 0, n);
+
+        let ones = (0..7).map(|n| select_bit(true, n)).collect::<Vec<_>>();
+        assert_eq!(
+            ones,
+            [
+                Some(0),
+                Some(7),
+                Some(8),
+                Some(64),
+                Some(65),
+                Some(149),
+                None
+            ]
+        );
+
+        assert_eq!(select_bit(false, 0), Some(1));
+        assert_eq!(select_bit(false, 6), Some(9));
+        assert_eq!(select_bit(false, 143), Some(148));
+        // the padding bits of the last byte are not part of the slice
+        assert_eq!(select_bit(false, 144), None);
+
+        // searches can start in the middle of a word
+        assert_eq!(select_from(true, 8, 0), Some(8));
+        assert_eq!(select_from(true, 9, 0), Some(64));
+        assert_eq!(select_from(true, 9, 2), Some(149));
+        assert_eq!(select_from(true, 9, 3), None);
+        assert_eq!(select_from(false, 64, 0), Some(66));
+        assert_eq!(select_from(false, 149, 0), None);
+    }
+
+    #[test]
+    fn test_elias_fano_byteorder() {
+        let buf = mkpartition_buf::<Low>(PartitionKind::EliasFano, &[0x01_00, 0x02_00]);
+        assert_eq!(
+            buf.as_ref(),
+            &[
+                0x00, 0x01, 0x00, 0x01, // lower bits: two 15 bit values
+                0x03, // upper bits: both values are in the first bucket
+                0x00, 0x01, // length
+                0x06, // kind
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_elias_fano() {
+        let buf = mkpartition_buf::<Low>(PartitionKind::EliasFano, &[0x01_00, 0x02_00]);
+        let partition = PartitionRef::<Low>::from_suffix(&buf).unwrap();
+        assert!(partition.validate().is_ok());
+
+        // swap the low bits of the two values
+        let mut corrupted = buf.to_vec();
+        corrupted[..4].copy_from_slice(&[0x00, 0x02, 0x80, 0x00]);
+        let partition = PartitionRef::<Low>::from_suffix(&corrupted).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+
+        // set an extra bit in the upper bit array
+        let mut corrupted = buf.to_vec();
+        corrupted[4] = 0x07;
+        let partition = PartitionRef::<Low>::from_suffix(&corrupted).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+
+        // move a sample in the select index
+        let values = (0..1024).map(|v| v * 64).collect::<Vec<_>>();
+        let buf = mkpartition_buf::<Low>(PartitionKind::EliasFano, &values);
+        let partition = PartitionRef::<Low>::from_suffix(&buf).unwrap();
+        assert!(partition.validate().is_ok());
+        let mut corrupted = buf.to_vec();
+        // the last zero sample is stored right before the length and kind
+        let sample = corrupted.len() - 7;
+        corrupted[sample] += 1;
+        let partition = PartitionRef::<Low>::from_suffix(&corrupted).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+    }
+}
@@ -1,13 +1,20 @@
-use std::ops::RangeInclusive;
+use std::{io, ops::RangeInclusive};
 
 use bitvec::{boxed::BitBox, order::Lsb0};
-use bytes::BufMut;
-use crc64fast_nvme::Digest;
-use zerocopy::{IntoBytes, transmute_ref};
+use bytes::{BufMut, BytesMut, buf::UninitSlice};
+use zerocopy::IntoBytes;
 
 use crate::{
     PartitionRead,
-    codec::{footer::Footer, runs_ref::EncodedRun, tree_ref::TreeIndexBuilder},
+    codec::{
+        EncodeErr,
+        checksum::{Checksum, Hasher},
+        features::Features,
+        footer::{EncodedFeatures, EncodedLength, Footer},
+        runs_ref::EncodedRun,
+        sorted,
+        tree_ref::TreeIndexBuilder,
+    },
     level::{Block, Level},
     partition::Partition,
     partition_kind::PartitionKind,
@@ -17,25 +24,337 @@ use crate::{
 pub struct Encoder<B: BufMut> {
     buf: B,
     bytes_written: usize,
-    checksum: Digest,
+    checksum: Hasher,
     wrote_footer: bool,
+    /// the optional features this encoder may use
+    features: Features,
+    /// the optional features written to the buffer so far
+    used_features: Features,
+    /// pad the buffer so that bitmap partitions start on an 8-byte boundary
+    align_bitmaps: bool,
+    /// the position of this encoder's first byte within the encoded splinter
+    position: usize,
+    /// the number of padding bytes written to align bitmaps
+    padding: usize,
+    /// record the length of the encoded splinter in the footer
+    encode_length: bool,
 }
 
 impl<B: BufMut> Encoder<B> {
+    /// Create an `Encoder` which doesn't use any optional features, so that
+    /// the encoded splinters can be decoded by every V2 reader. Use
+    /// [`Encoder::with_features`] to opt into optional features.
     pub fn new(buf: B) -> Self {
+        Self::with_features(buf, Features::EMPTY)
+    }
+
+    /// Create an `Encoder` which may use the provided optional features.
+    ///
+    /// Splinters which use optional features are smaller, but can only be
+    /// decoded by readers which support those features. Partitions which
+    /// depend on other features are encoded using an equivalent partition
+    /// kind. Use [`Encodable::encoded_size_with`] to predict the encoded
+    /// size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bytes::BytesMut;
+    /// use splinter_rs::{
+    ///     Encodable, Optimizable, PartitionRead, Splinter, SplinterRef,
+    ///     codec::{encoder::Encoder, features::Features},
+    /// };
+    ///
+    /// let mut splinter = Splinter::from_iter((0..65536).filter(|v| v % 1000 != 0));
+    /// splinter.optimize_with(Features::SUPPORTED);
+    /// let features = SplinterRef::from_bytes(splinter.encode_to_bytes()).unwrap().features();
+    /// assert_eq!(features, Features::EMPTY);
+    ///
+    /// let mut encoder = Encoder::with_features(BytesMut::new(), Features::SUPPORTED);
+    /// splinter.encode(&mut encoder);
+    /// let bytes = encoder.into_inner().freeze();
+    /// assert_eq!(bytes.len(), splinter.encoded_size_with(Features::SUPPORTED));
+    /// let splinter_ref = SplinterRef::from_bytes(bytes).unwrap();
+    /// assert_eq!(
+    ///     splinter_ref.features(),
+    ///     Features::INVERTED_PARTITIONS | Features::PREFIX_PARTITIONS
+    /// );
+    /// assert_eq!(splinter_ref, splinter);
+    /// ```
+    ///
+    /// [`Encodable::encoded_size_with`]: crate::Encodable::encoded_size_with
+    pub fn with_features(buf: B, features: Features) -> Self {
         Self {
             buf,
             bytes_written: 0,
-            checksum: Digest::new(),
+            checksum: Checksum::default().hasher(),
             wrote_footer: false,
+            features,
+            used_features: Features::EMPTY,
+            align_bitmaps: false,
+            position: 0,
+            padding: 0,
+            encode_length: false,
         }
     }
 
+    /// Pad the encoded splinter so that every bitmap partition starts on an
+    /// 8-byte boundary. When the encoded splinter is loaded into a buffer
+    /// which is itself 8-byte aligned, such as a memory mapped file,
+    /// [`SplinterRef`] reads bitmaps as u64 words rather than byte by byte.
+    ///
+    /// The padding is invisible to readers, so aligned splinters can be
+    /// decoded by every version of splinter-rs. Aligned splinters may be up to
+    /// 7 bytes per bitmap partition larger than
+    /// [`Encodable::encoded_size`] predicts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any data has already been written by this encoder.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bytes::BytesMut;
+    /// use splinter_rs::{Encodable, Splinter, SplinterRef, codec::encoder::Encoder};
+    ///
+    /// let splinter = Splinter::from_iter((0..4096).chain((1 << 16)..(1 << 17)).step_by(3));
+    ///
+    /// let mut encoder = Encoder::new(BytesMut::new()).with_aligned_bitmaps();
+    /// splinter.encode(&mut encoder);
+    /// let bytes = encoder.into_inner().freeze();
+    /// assert_eq!(SplinterRef::from_bytes(bytes).unwrap(), splinter);
+    /// ```
+    ///
+    /// [`SplinterRef`]: crate::SplinterRef
+    /// [`Encodable::encoded_size`]: crate::Encodable::encoded_size
+    pub fn with_aligned_bitmaps(mut self) -> Self {
+        assert_eq!(
+            self.bytes_written, 0,
+            "invalid encoder usage: alignment changed after writing"
+        );
+        self.align_bitmaps = true;
+        self
+    }
+
+    /// Returns true if this `Encoder` pads bitmap partitions to start on an
+    /// 8-byte boundary.
+    #[inline]
+    pub fn aligns_bitmaps(&self) -> bool {
+        self.align_bitmaps
+    }
+
+    /// Record the total length of the encoded splinter in its footer, using
+    /// [`Features::ENCODED_LENGTH`]. This allows splinters which are written
+    /// back to back into a single buffer, such as a log segment, to be split
+    /// apart again using [`SplinterRef::split_last`], [`SplinterRef::rsplit`]
+    /// or [`SplinterRef::split_all_vec`].
+    ///
+    /// The length is recorded using the optional [`Features::ENCODED_LENGTH`]
+    /// feature, which this enables. Encoded splinters which record their
+    /// length are 4 bytes larger than [`Encodable::encoded_size`] predicts,
+    /// or 8 bytes larger if they don't otherwise use any optional features.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any data has already been written by this encoder.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bytes::BytesMut;
+    /// use splinter_rs::{Encodable, Splinter, SplinterRef, codec::encoder::Encoder};
+    ///
+    /// let mut buf = BytesMut::new();
+    /// for values in [0..10, 1000..2000] {
+    ///     let mut encoder = Encoder::new(&mut buf).with_encoded_length();
+    ///     Splinter::from_iter(values).encode(&mut encoder);
+    /// }
+    ///
+    /// let (rest, last) = SplinterRef::split_last(&buf[..]).unwrap();
+    /// assert_eq!(last, Splinter::from_iter(1000..2000));
+    /// assert_eq!(SplinterRef::from_bytes(rest).unwrap(), Splinter::from_iter(0..10));
+    /// ```
+    ///
+    /// [`SplinterRef::split_last`]: crate::SplinterRef::split_last
+    /// [`SplinterRef::rsplit`]: crate::SplinterRef::rsplit
+    /// [`SplinterRef::split_all_vec`]: crate::SplinterRef::split_all_vec
+    /// [`Encodable::encoded_size`]: crate::Encodable::encoded_size
+    pub fn with_encoded_length(mut self) -> Self {
+        assert_eq!(
+            self.bytes_written, 0,
+            "invalid encoder usage: length changed after writing"
+        );
+        self.features |= Features::ENCODED_LENGTH;
+        self.encode_length = true;
+        self
+    }
+
+    /// Returns true if this `Encoder` records the length of the encoded
+    /// splinter in its footer.
+    #[inline]
+    pub fn encodes_length(&self) -> bool {
+        self.encode_length
+    }
+
+    /// Run `f` with an empty `Encoder` which has the same settings as this
+    /// one and writes to this encoder's buffer at its current position. The
+    /// nested encoder doesn't compute a checksum, so it can be rewound; its
+    /// output is hashed by this encoder once `f` returns.
+    pub(crate) fn with_rewindable<T>(&mut self, f: impl FnOnce(&mut Encoder<&mut B>) -> T) -> T
+    where
+        B: RewindBuf,
+    {
+        let position = self.position();
+        let mut nested =
+            Encoder::with_features(&mut self.buf, self.features).with_checksum(Checksum::None);
+        nested.align_bitmaps = self.align_bitmaps;
+        nested.position = position;
+        let out = f(&mut nested);
+
+        let (len, padding, features) = (nested.bytes_written, nested.padding, nested.used_features);
+        let written = self.buf.written();
+        self.checksum.write(&written[(written.len() - len)..]);
+        self.bytes_written += len;
+        self.padding += padding;
+        self.use_features(features);
+        out
+    }
+
+    /// The position of the next byte written by this encoder within the
+    /// encoded splinter.
+    #[inline]
+    pub(crate) fn position(&self) -> usize {
+        self.position + self.bytes_written
+    }
+
+    /// Use the provided checksum algorithm in the footer, rather than the
+    /// default [`Checksum::Crc64Nvme`].
+    ///
+    /// Encoded splinters which use a different checksum are
+    /// [`Checksum::size`] bytes smaller or larger than
+    /// [`Encodable::encoded_size`] predicts.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any data has already been written by this encoder, or if the
+    /// checksum's cargo feature is disabled, see [`Checksum::is_enabled`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bytes::BytesMut;
+    /// use splinter_rs::{
+    ///     PartitionRead, SplinterRef,
+    ///     codec::{checksum::Checksum, encoder::Encoder},
+    /// };
+    ///
+    /// let mut encoder = Encoder::new(BytesMut::new()).with_checksum(Checksum::None);
+    /// encoder.encode_sorted([]);
+    /// let bytes = encoder.into_inner().freeze();
+    /// assert_eq!(bytes.len(), 5);
+    ///
+    /// let splinter_ref = SplinterRef::from_bytes(bytes).unwrap();
+    /// assert_eq!(splinter_ref.checksum(), Checksum::None);
+    /// assert!(splinter_ref.is_empty());
+    /// ```
+    ///
+    /// [`Encodable::encoded_size`]: crate::Encodable::encoded_size
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        assert_eq!(
+            self.bytes_written, 0,
+            "invalid encoder usage: checksum changed after writing"
+        );
+        self.checksum = checksum.hasher();
+        self
+    }
+
+    /// The checksum algorithm this `Encoder` writes in the footer.
+    #[inline]
+    pub fn checksum(&self) -> Checksum {
+        self.checksum.checksum()
+    }
+
+    /// The optional features this `Encoder` is allowed to use.
+    #[inline]
+    pub fn features(&self) -> Features {
+        self.features
+    }
+
+    #[inline]
+    pub(crate) fn supports(&self, features: Features) -> bool {
+        self.features.contains(features)
+    }
+
+    /// Record that the buffer uses the provided features, for data written
+    /// directly via [`Encoder::put_slice`].
+    #[inline]
+    pub(crate) fn use_features(&mut self, features: Features) {
+        debug_assert!(self.supports(features), "BUG: unsupported features");
+        self.used_features |= features;
+    }
+
     /// Retrieve the wrapped buffer from the `Encoder`
     pub fn into_inner(self) -> B {
         self.buf
     }
 
+    /// Encode a splinter containing the provided values directly into the
+    /// buffer, including the footer.
+    ///
+    /// The values are never collected into a `Splinter`, and the output is
+    /// byte-identical to encoding a [`Splinter`] containing the same values
+    /// which was optimized with [`Optimizable::optimize_with`] for this
+    /// encoder's features. With [`Encoder::new`], that's the same as
+    /// [`Optimizable::optimize`] followed by [`Encodable::encode_to_bytes`].
+    ///
+    /// At most one Low partition's values (65536 values) are buffered at a
+    /// time. A High or Mid partition which isn't encoded as a tree is built
+    /// in memory before it's written, which takes at most about twice its
+    /// encoded size.
+    ///
+    /// The kind of each High and Mid partition is only known once all of its
+    /// values have been seen, so partitions are encoded as trees while their
+    /// children arrive, and rewound and re-encoded if another kind is
+    /// smaller. Even the root may be re-encoded after the last value, so the
+    /// buffer must implement [`RewindBuf`], such as [`BytesMut`] and
+    /// `Vec<u8>`. Buffers which can't be read back, such as [`WriteBuf`] and
+    /// [`SliceBuf`], are rejected at compile time; encode into a `Vec<u8>`
+    /// and copy it to them instead.
+    ///
+    /// Duplicate values are ignored.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the values are not sorted in ascending order, or if a footer
+    /// has already been written by this encoder.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bytes::BytesMut;
+    /// use splinter_rs::{Encodable, Optimizable, Splinter, codec::encoder::Encoder};
+    ///
+    /// let mut encoder = Encoder::new(BytesMut::new());
+    /// encoder.encode_sorted((0..1_000_000).step_by(7));
+    /// let bytes = encoder.into_inner().freeze();
+    ///
+    /// let mut splinter = Splinter::from_iter((0..1_000_000).step_by(7));
+    /// splinter.optimize();
+    /// assert_eq!(bytes, splinter.encode_to_bytes());
+    /// ```
+    ///
+    /// [`Splinter`]: crate::Splinter
+    /// [`Optimizable::optimize_with`]: crate::Optimizable::optimize_with
+    /// [`Optimizable::optimize`]: crate::Optimizable::optimize
+    /// [`Encodable::encode_to_bytes`]: crate::Encodable::encode_to_bytes
+    pub fn encode_sorted(&mut self, values: impl IntoIterator<Item = u32>)
+    where
+        B: RewindBuf,
+    {
+        sorted::encode_sorted(self, values);
+    }
+
     /// Write an entire encoded splinter to the buffer
     pub(crate) fn write_splinter(&mut self, splinter: &[u8]) {
         self.buf.put_slice(splinter);
@@ -44,15 +363,37 @@ impl<B: BufMut> Encoder<B> {
         self.wrote_footer = true;
     }
 
-    /// Write the checksum and Splinter Magic value to the buffer
+    /// Write the used features, checksum and Splinter Magic value to the
+    /// buffer
     pub(crate) fn write_footer(&mut self) {
         assert!(
             !self.wrote_footer,
             "invalid encoder usage: footer already present"
         );
         self.wrote_footer = true;
-        let footer = Footer::from_checksum(self.checksum.sum64());
-        self.put_slice(footer.as_bytes());
+        if self.encode_length {
+            self.used_features |= Features::ENCODED_LENGTH;
+        }
+        let footer = Footer::new(self.checksum(), self.used_features);
+        if self.encode_length {
+            let length = self.bytes_written + footer.encoded_size();
+            let length = u32::try_from(length).expect("encoded splinter is too large");
+            self.put_slice(EncodedLength::new(length).as_bytes());
+        }
+        if !footer.features.is_empty() {
+            let features = EncodedFeatures::new(footer.features.bits());
+            self.put_slice(features.as_bytes());
+        }
+        let checksum = self.checksum.sum().to_le_bytes();
+        self.put_slice(&checksum[..footer.checksum.size()]);
+        self.put_slice(&footer.magic());
+    }
+
+    /// Write the partitions of an encoded splinter which uses the provided
+    /// features to the buffer, without its footer.
+    pub(crate) fn write_partitions(&mut self, partitions: &[u8], features: Features) {
+        self.put_slice(partitions);
+        self.use_features(features);
     }
 
     /// The total number of bytes written to the buffer since this Encoder was
@@ -61,8 +402,21 @@ impl<B: BufMut> Encoder<B> {
         self.bytes_written
     }
 
+    /// The number of padding bytes included in [`Encoder::bytes_written`].
+    pub(crate) fn padding_written(&self) -> usize {
+        self.padding
+    }
+
     /// Encode a Bitmap partition into the buffer.
     pub(crate) fn put_bitmap_partition(&mut self, bitmap: &BitBox<u64, Lsb0>) {
+        if self.align_bitmaps {
+            // partitions are decoded from their suffix, so readers never see
+            // the padding in front of the bitmap
+            let position = self.position();
+            let padding = position.next_multiple_of(size_of::<u64>()) - position;
+            self.put_slice(&[0; size_of::<u64>()][..padding]);
+            self.padding += padding;
+        }
         self.put_bitmap_raw(bitmap);
     }
 
@@ -72,6 +426,23 @@ impl<B: BufMut> Encoder<B> {
         self.put_length::<L>(values.len());
     }
 
+    /// Encode the bit arrays of an Elias-Fano partition into the buffer.
+    pub(crate) fn put_elias_fano_partition<L: Level>(&mut self, bits: &[u8], cardinality: usize) {
+        self.put_slice(bits);
+        self.put_length::<L>(cardinality);
+    }
+
+    /// Encode the gaps and skip index of a delta-vec partition into the buffer.
+    pub(crate) fn put_delta_vec_partition<L: Level>(&mut self, data: &[u8], cardinality: usize) {
+        self.put_slice(data);
+        self.put_length::<L>(cardinality);
+    }
+
+    /// Encode the words and mask of a sparse bitmap partition into the buffer.
+    pub(crate) fn put_sparse_bitmap_partition(&mut self, data: &[u8]) {
+        self.put_slice(data);
+    }
+
     /// Encode a Run partition into the buffer.
     pub(crate) fn put_run_partition<L: Level>(
         &mut self,
@@ -102,7 +473,13 @@ impl<B: BufMut> Encoder<B> {
             Partition::Full => {}
             Partition::Bitmap(p) => self.put_bitmap_raw(p.as_bitbox()),
             Partition::Vec(p) => self.put_iter::<Block>(p.iter()),
-            Partition::Run(_) | Partition::Tree(_) => unreachable!(),
+            Partition::Run(_)
+            | Partition::Tree(_)
+            | Partition::Prefix(_)
+            | Partition::Inverted(_)
+            | Partition::EliasFano(_)
+            | Partition::DeltaVec(_)
+            | Partition::SparseBitmap(_) => unreachable!(),
         }
 
         self.put_length::<Block>(num_children);
@@ -115,6 +492,7 @@ impl<B: BufMut> Encoder<B> {
     }
 
     pub(crate) fn put_kind(&mut self, k: PartitionKind) {
+        self.use_features(k.features());
         let d = [k as u8];
         self.put_slice(&d)
     }
@@ -131,16 +509,420 @@ impl<B: BufMut> Encoder<B> {
         self.put_slice(L::ValueUnaligned::from(v).as_bytes());
     }
 
+    /// Writes a bitmap as a sequence of little-endian u64 words, which stores
+    /// bit `i` in bit `i % 8` of byte `i / 8` regardless of the target's byte
+    /// order.
     fn put_bitmap_raw(&mut self, bitmap: &BitBox<u64, Lsb0>) {
-        let raw = bitmap.as_raw_slice();
-        static_assertions::assert_cfg!(target_endian = "little");
-        let raw: &[zerocopy::U64<zerocopy::LittleEndian>] = transmute_ref!(raw);
-        self.put_slice(raw.as_bytes());
+        const CHUNK_WORDS: usize = 32;
+        let mut chunk = [0u8; CHUNK_WORDS * size_of::<u64>()];
+        for words in bitmap.as_raw_slice().chunks(CHUNK_WORDS) {
+            let bytes = &mut chunk[..size_of_val(words)];
+            for (dst, word) in bytes.chunks_exact_mut(size_of::<u64>()).zip(words) {
+                dst.copy_from_slice(&word.to_le_bytes());
+            }
+            self.put_slice(bytes);
+        }
     }
 
-    fn put_slice(&mut self, data: &[u8]) {
+    pub(crate) fn put_slice(&mut self, data: &[u8]) {
         self.checksum.write(data);
         self.buf.put_slice(data);
         self.bytes_written += data.len();
     }
 }
+
+/// A position in an [`Encoder`]'s output, which the encoder can later be
+/// rewound to.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct Checkpoint {
+    bytes_written: usize,
+    padding: usize,
+    used_features: Features,
+}
+
+impl Checkpoint {
+    /// The number of bytes written before this checkpoint.
+    #[inline]
+    pub(crate) fn bytes_written(&self) -> usize {
+        self.bytes_written
+    }
+
+    /// The number of padding bytes written before this checkpoint.
+    #[inline]
+    pub(crate) fn padding_written(&self) -> usize {
+        self.padding
+    }
+}
+
+impl<B: RewindBuf> Encoder<B> {
+    /// Records the current position in the output.
+    pub(crate) fn checkpoint(&self) -> Checkpoint {
+        Checkpoint {
+            bytes_written: self.bytes_written,
+            padding: self.padding,
+            used_features: self.used_features,
+        }
+    }
+
+    /// Returns the bytes written since `checkpoint`.
+    pub(crate) fn written_since(&self, checkpoint: &Checkpoint) -> &[u8] {
+        let len = self.bytes_written - checkpoint.bytes_written;
+        let written = self.buf.written();
+        &written[(written.len() - len)..]
+    }
+
+    /// Discards everything written since `checkpoint`.
+    ///
+    /// # Panics
+    ///
+    /// Panics if this encoder computes a checksum, as the checksum can't be
+    /// rewound.
+    pub(crate) fn rewind(&mut self, checkpoint: Checkpoint) {
+        assert_eq!(
+            self.checksum(),
+            Checksum::None,
+            "invalid encoder usage: can't rewind a checksum"
+        );
+        let len = self.bytes_written - checkpoint.bytes_written;
+        let written = self.buf.written().len();
+        self.buf.truncate_written(written - len);
+        self.bytes_written = checkpoint.bytes_written;
+        self.padding = checkpoint.padding;
+        self.used_features = checkpoint.used_features;
+    }
+}
+
+/// A [`BufMut`] whose contents can be read back and truncated, which lets
+/// [`Encoder::encode_sorted`] rewind and re-encode partitions in place.
+pub trait RewindBuf: BufMut {
+    /// Returns every byte written to this buffer.
+    fn written(&self) -> &[u8];
+
+    /// Discards everything but the first `len` bytes written to this buffer.
+    fn truncate_written(&mut self, len: usize);
+}
+
+impl RewindBuf for BytesMut {
+    #[inline]
+    fn written(&self) -> &[u8] {
+        self
+    }
+
+    #[inline]
+    fn truncate_written(&mut self, len: usize) {
+        self.truncate(len);
+    }
+}
+
+impl RewindBuf for Vec<u8> {
+    #[inline]
+    fn written(&self) -> &[u8] {
+        self
+    }
+
+    #[inline]
+    fn truncate_written(&mut self, len: usize) {
+        self.truncate(len);
+    }
+}
+
+impl<T: RewindBuf + ?Sized> RewindBuf for &mut T {
+    #[inline]
+    fn written(&self) -> &[u8] {
+        (**self).written()
+    }
+
+    #[inline]
+    fn truncate_written(&mut self, len: usize) {
+        (**self).truncate_written(len);
+    }
+}
+
+/// A [`BufMut`] which streams everything written to it into an
+/// [`io::Write`], allowing an [`Encoder`] to write directly to a file,
+/// socket, or hashing writer.
+///
+/// Writes are buffered in chunks of [`WriteBuf::CHUNK_SIZE`] bytes. The first
+/// I/O error is recorded and all subsequent writes are discarded; the error
+/// is returned by [`WriteBuf::finish`], which must be called to write out the
+/// final chunk.
+///
+/// # Examples
+///
+/// ```
+/// use splinter_rs::{
+///     Encodable, PartitionRead, Splinter, SplinterRef,
+///     codec::encoder::{Encoder, WriteBuf},
+/// };
+///
+/// let mut out = Vec::new();
+/// let mut encoder = Encoder::new(WriteBuf::new(&mut out));
+/// Splinter::from_iter([1, 2, 3]).encode(&mut encoder);
+/// encoder.into_inner().finish().unwrap();
+///
+/// let splinter_ref = SplinterRef::from_bytes(out).unwrap();
+/// assert_eq!(splinter_ref.iter().collect::<Vec<_>>(), [1, 2, 3]);
+/// ```
+pub struct WriteBuf<W: io::Write> {
+    writer: W,
+    buf: Vec<u8>,
+    err: Option<io::Error>,
+}
+
+impl<W: io::Write> WriteBuf<W> {
+    /// The number of bytes buffered before they are written to the writer.
+    pub const CHUNK_SIZE: usize = 8192;
+
+    pub fn new(writer: W) -> Self {
+        Self {
+            writer,
+            buf: Vec::with_capacity(Self::CHUNK_SIZE),
+            err: None,
+        }
+    }
+
+    /// Writes any buffered bytes to the writer and returns it, or the first
+    /// I/O error encountered while writing.
+    ///
+    /// This does not call [`io::Write::flush`] on the writer.
+    pub fn finish(mut self) -> io::Result<W> {
+        self.write_buffered();
+        match self.err {
+            Some(err) => Err(err),
+            None => Ok(self.writer),
+        }
+    }
+
+    fn write_buffered(&mut self) {
+        self.write_all_or_record(None);
+    }
+
+    /// Writes the buffer followed by `data` (if any) to the writer, recording
+    /// the first error.
+    fn write_all_or_record(&mut self, data: Option<&[u8]>) {
+        if self.err.is_none() {
+            let result = self
+                .writer
+                .write_all(&self.buf)
+                .and_then(|()| data.map_or(Ok(()), |data| self.writer.write_all(data)));
+            self.err = result.err();
+        }
+        self.buf.clear();
+    }
+}
+
+// SAFETY: all of the unsafe BufMut requirements are delegated to the inner
+// Vec, which we only ever clear after writing out its initialized bytes.
+unsafe impl<W: io::Write> BufMut for WriteBuf<W> {
+    #[inline]
+    fn remaining_mut(&self) -> usize {
+        self.buf.remaining_mut()
+    }
+
+    #[inline]
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        // SAFETY: the caller guarantees that `cnt` bytes have been initialized
+        unsafe { self.buf.advance_mut(cnt) };
+        if self.buf.len() >= Self::CHUNK_SIZE {
+            self.write_buffered();
+        }
+    }
+
+    #[inline]
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        self.buf.chunk_mut()
+    }
+
+    fn put_slice(&mut self, src: &[u8]) {
+        if self.buf.len() + src.len() < Self::CHUNK_SIZE {
+            self.buf.extend_from_slice(src);
+        } else {
+            // large writes bypass the buffer
+            self.write_all_or_record(Some(src));
+        }
+    }
+}
+
+/// A [`BufMut`] which writes into a fixed, caller-provided slice, allowing
+/// an [`Encoder`] to write into pre-allocated buffers without allocating.
+///
+/// Writes which don't fit in the slice are discarded rather than panicking,
+/// but are still counted so that [`SliceBuf::finish`] can report the number
+/// of bytes which would have been needed.
+///
+/// # Examples
+///
+/// ```
+/// use splinter_rs::{
+///     Encodable, PartitionRead, Splinter, SplinterRef,
+///     codec::encoder::{Encoder, SliceBuf},
+/// };
+///
+/// let mut page = [0u8; 64];
+/// let mut encoder = Encoder::new(SliceBuf::new(&mut page));
+/// Splinter::from_iter([1, 2, 3]).encode(&mut encoder);
+/// let written = encoder.into_inner().finish().unwrap();
+///
+/// let splinter_ref = SplinterRef::from_bytes(&page[..written]).unwrap();
+/// assert_eq!(splinter_ref.iter().collect::<Vec<_>>(), [1, 2, 3]);
+/// ```
+pub struct SliceBuf<'a> {
+    buf: &'a mut [u8],
+    /// the number of bytes written, including any which didn't fit
+    len: usize,
+}
+
+impl<'a> SliceBuf<'a> {
+    pub fn new(buf: &'a mut [u8]) -> Self {
+        Self { buf, len: 0 }
+    }
+
+    /// Returns the number of bytes written to the slice, or
+    /// [`EncodeErr::BufferTooSmall`] if any writes didn't fit.
+    pub fn finish(self) -> Result<usize, EncodeErr> {
+        if self.len <= self.buf.len() {
+            Ok(self.len)
+        } else {
+            Err(EncodeErr::BufferTooSmall {
+                needed: self.len,
+                available: self.buf.len(),
+            })
+        }
+    }
+}
+
+// SAFETY: chunk_mut only ever returns the unwritten tail of the slice, and
+// advance_mut is only called with the number of bytes initialized in it.
+unsafe impl BufMut for SliceBuf<'_> {
+    #[inline]
+    fn remaining_mut(&self) -> usize {
+        self.buf.len().saturating_sub(self.len)
+    }
+
+    #[inline]
+    unsafe fn advance_mut(&mut self, cnt: usize) {
+        assert!(cnt <= self.remaining_mut(), "advance out of bounds");
+        self.len += cnt;
+    }
+
+    #[inline]
+    fn chunk_mut(&mut self) -> &mut UninitSlice {
+        let start = self.len.min(self.buf.len());
+        UninitSlice::new(&mut self.buf[start..])
+    }
+
+    #[inline]
+    fn put_slice(&mut self, src: &[u8]) {
+        // once a write doesn't fit, every later write is discarded too
+        if let Some(dst) = self.buf.get_mut(self.len..self.len + src.len()) {
+            dst.copy_from_slice(src);
+        }
+        self.len += src.len();
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use bytes::BytesMut;
+    use itertools::Itertools;
+    use zerocopy::IntoBytes;
+
+    use crate::{
+        Encodable, Splinter, SplinterRef,
+        codec::{
+            encoder::Encoder,
+            partition_ref::{NonRecursivePartitionRef, PartitionRef},
+        },
+        level::Low,
+        partition::Partition,
+        partition_kind::PartitionKind,
+        testutil::{SetGen, test_partition_read},
+        traits::Optimizable,
+    };
+
+    #[test]
+    fn test_aligned_bitmaps() {
+        // alternate between Vec and Bitmap blocks so the bitmaps need padding
+        let values = (0..8u16)
+            .flat_map(|block| {
+                let count = if block % 2 == 0 { 3 } else { 100 };
+                (0..count).map(move |v| block * 256 + v * 2)
+            })
+            .collect_vec();
+        let mut partition = Partition::<Low>::from_iter(values.iter().copied());
+        partition.optimize();
+        assert_eq!(partition.kind(), PartitionKind::Tree);
+
+        let mut encoder = Encoder::new(BytesMut::new()).with_aligned_bitmaps();
+        partition.encode(&mut encoder);
+        let encoded = encoder.into_inner();
+        assert!(encoded.len() > partition.encoded_size());
+
+        // copy the encoded partition into an aligned buffer, and also one byte
+        // past the aligned buffer
+        let mut words = vec![0u64; encoded.len().div_ceil(8) + 1];
+        for offset in [0, 1] {
+            let buf = &mut words.as_mut_bytes()[offset..(offset + encoded.len())];
+            buf.copy_from_slice(&encoded);
+            let PartitionRef::Tree(tree) = PartitionRef::<Low>::from_suffix(buf).unwrap() else {
+                panic!("expected a tree partition");
+            };
+
+            let mut aligned = 0;
+            for child in tree.children() {
+                if let PartitionRef::NonRecursive(NonRecursivePartitionRef::Bitmap { bitmap }) =
+                    child
+                {
+                    if bitmap.is_aligned() {
+                        aligned += 1;
+                    } else {
+                        assert_eq!(offset, 1, "bitmap is not aligned");
+                    }
+                }
+            }
+            assert_eq!(aligned, if offset == 0 { 4 } else { 0 });
+
+            let tree = PartitionRef::Tree(tree);
+            tree.validate().unwrap();
+            test_partition_read(&tree, &values);
+            assert_eq!(partition, tree);
+        }
+    }
+
+    #[test]
+    fn test_aligned_bitmaps_splinter() {
+        let mut set_gen = SetGen::new(0xDEAD_BEEF);
+        let mut splinter = Splinter::from_iter(set_gen.distributed(4, 8, 8, 64));
+        splinter.optimize();
+
+        let mut encoder = Encoder::new(BytesMut::new()).with_aligned_bitmaps();
+        splinter.encode(&mut encoder);
+        let aligned = encoder.into_inner().freeze();
+        assert_ne!(aligned, splinter.encode_to_bytes());
+        let splinter_ref = SplinterRef::from_bytes_validated(aligned.clone()).unwrap();
+        assert_eq!(splinter_ref, splinter);
+
+        // re-encoding a splinter ref aligns its bitmaps
+        let unaligned_bytes = splinter.encode_to_bytes();
+        let unaligned = SplinterRef::from_bytes(unaligned_bytes.clone()).unwrap();
+        assert!(
+            !unaligned
+                .load_unchecked()
+                .bitmaps_aligned(&unaligned_bytes, 0)
+        );
+        let mut encoder = Encoder::new(BytesMut::new()).with_aligned_bitmaps();
+        unaligned.encode(&mut encoder);
+        assert_eq!(encoder.into_inner().freeze(), aligned);
+
+        // splinter refs which are already aligned are copied as is, unless
+        // they are written at a misaligned position
+        let root = splinter_ref.load_unchecked();
+        assert!(root.bitmaps_aligned(&aligned, 0));
+        assert!(root.bitmaps_aligned(&aligned, 8));
+        assert!(!root.bitmaps_aligned(&aligned, 4));
+        let mut encoder = Encoder::new(BytesMut::new()).with_aligned_bitmaps();
+        splinter_ref.encode(&mut encoder);
+        assert_eq!(encoder.into_inner().freeze(), aligned);
+    }
+}
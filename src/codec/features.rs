@@ -0,0 +1,313 @@
+use std::{
+    fmt::{self, Debug},
+    ops::{BitOr, BitOrAssign},
+};
+
+/// A set of optional encodings used by a serialized splinter.
+///
+/// Every encoded splinter records the optional features it depends on, which
+/// allows a reader to fail with [`DecodeErr::UnsupportedFeature`] rather than
+/// misinterpreting data written by a newer version of this crate.
+///
+/// Splinters which don't use any optional features are encoded in the
+/// original V2 format, which all V2 readers can decode.
+///
+/// [`DecodeErr::UnsupportedFeature`]: crate::codec::DecodeErr::UnsupportedFeature
+#[derive(Clone, Copy, PartialEq, Eq, Hash, Default)]
+pub struct Features(u32);
+
+impl Features {
+    /// No optional features, i.e. the original V2 format.
+    pub const EMPTY: Self = Self(0);
+
+    /// Partitions which store the complement of their values.
+    pub const INVERTED_PARTITIONS: Self = Self(1 << 0);
+
+    /// Partitions which store their values using Elias-Fano encoding.
+    pub const ELIAS_FANO_PARTITIONS: Self = Self(1 << 1);
+
+    /// Partitions which store the deltas between their values as varints.
+    pub const DELTA_VEC_PARTITIONS: Self = Self(1 << 2);
+
+    /// A footer which records the total length of the encoded splinter, which
+    /// allows splinters to be split from a buffer of concatenated splinters.
+    /// See [`Encoder::with_encoded_length`].
+    ///
+    /// [`Encoder::with_encoded_length`]: crate::codec::encoder::Encoder::with_encoded_length
+    pub const ENCODED_LENGTH: Self = Self(1 << 3);
+
+    /// A patch between two splinters rather than a splinter, see
+    /// [`SplinterPatch`].
+    ///
+    /// [`SplinterPatch`]: crate::SplinterPatch
+    pub const PATCH: Self = Self(1 << 4);
+
+    /// Tree partitions with a single child, which store the child's segment
+    /// rather than a tree index.
+    pub const PREFIX_PARTITIONS: Self = Self(1 << 5);
+
+    /// Bitmap partitions which only store their nonzero 64 bit words.
+    pub const SPARSE_BITMAP_PARTITIONS: Self = Self(1 << 6);
+
+    /// Every feature this version of splinter-rs can read and write.
+    pub const SUPPORTED: Self = Self::INVERTED_PARTITIONS
+        .union(Self::ELIAS_FANO_PARTITIONS)
+        .union(Self::DELTA_VEC_PARTITIONS)
+        .union(Self::ENCODED_LENGTH)
+        .union(Self::PATCH)
+        .union(Self::PREFIX_PARTITIONS)
+        .union(Self::SPARSE_BITMAP_PARTITIONS);
+
+    const NAMES: [(Self, &'static str); 7] = [
+        (Self::INVERTED_PARTITIONS, "INVERTED_PARTITIONS"),
+        (Self::ELIAS_FANO_PARTITIONS, "ELIAS_FANO_PARTITIONS"),
+        (Self::DELTA_VEC_PARTITIONS, "DELTA_VEC_PARTITIONS"),
+        (Self::ENCODED_LENGTH, "ENCODED_LENGTH"),
+        (Self::PATCH, "PATCH"),
+        (Self::PREFIX_PARTITIONS, "PREFIX_PARTITIONS"),
+        (Self::SPARSE_BITMAP_PARTITIONS, "SPARSE_BITMAP_PARTITIONS"),
+    ];
+
+    /// Creates a feature set from its raw bits, retaining unknown bits.
+    #[inline]
+    pub const fn from_bits(bits: u32) -> Self {
+        Self(bits)
+    }
+
+    #[inline]
+    pub const fn bits(self) -> u32 {
+        self.0
+    }
+
+    #[inline]
+    pub const fn is_empty(self) -> bool {
+        self.0 == 0
+    }
+
+    /// Returns true if every feature in `other` is also in `self`.
+    #[inline]
+    pub const fn contains(self, other: Self) -> bool {
+        self.0 & other.0 == other.0
+    }
+
+    #[inline]
+    pub const fn union(self, other: Self) -> Self {
+        Self(self.0 | other.0)
+    }
+
+    /// Returns the features in both `self` and `other`.
+    #[inline]
+    pub const fn intersection(self, other: Self) -> Self {
+        Self(self.0 & other.0)
+    }
+
+    /// Returns the features in `self` which are not in `other`.
+    #[inline]
+    pub const fn difference(self, other: Self) -> Self {
+        Self(self.0 & !other.0)
+    }
+
+    /// Returns the features in `self` which this version of splinter-rs does
+    /// not support.
+    #[inline]
+    pub const fn unsupported(self) -> Self {
+        self.difference(Self::SUPPORTED)
+    }
+}
+
+impl BitOr for Features {
+    type Output = Self;
+
+    #[inline]
+    fn bitor(self, rhs: Self) -> Self {
+        self.union(rhs)
+    }
+}
+
+impl BitOrAssign for Features {
+    #[inline]
+    fn bitor_assign(&mut self, rhs: Self) {
+        *self = self.union(rhs);
+    }
+}
+
+impl Debug for Features {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut set = f.debug_set();
+        for (feature, name) in Self::NAMES {
+            if self.contains(feature) {
+                set.entry(&format_args!("{name}"));
+            }
+        }
+        let unknown = self.unsupported();
+        if !unknown.is_empty() {
+            set.entry(&format_args!("{:#x}", unknown.0));
+        }
+        set.finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use itertools::Itertools;
+
+    use crate::{
+        Encodable, Splinter, SplinterRef,
+        codec::{
+            features::Features,
+            footer::{SPLINTER_V2_FEATURES_MAGIC, SPLINTER_V2_MAGIC},
+        },
+        partition_kind::PartitionKind,
+        testutil::{encode_with_features, mksplinter_buf, test_partition_read},
+        traits::Optimizable,
+    };
+
+    #[test]
+    fn test_encoded_features() {
+        // splinters without optional features use the original V2 footer
+        let buf = mksplinter_buf(&[1, 2, 3]);
+        assert!(buf.ends_with(&SPLINTER_V2_MAGIC));
+        let splinter_ref = SplinterRef::from_bytes(buf).unwrap();
+        assert_eq!(splinter_ref.features(), Features::EMPTY);
+
+        let values = (0..65536).filter(|v| v % 1000 != 0).collect_vec();
+        let mut splinter = Splinter::from_iter(values.iter().copied());
+        splinter.optimize_with(Features::SUPPORTED);
+        assert_eq!(
+            splinter.encoded_features(),
+            Features::INVERTED_PARTITIONS | Features::PREFIX_PARTITIONS
+        );
+        // optional features are only used when the encoder opts into them
+        let buf = splinter.encode_to_bytes();
+        assert_eq!(buf.len(), splinter.encoded_size());
+        assert!(buf.ends_with(&SPLINTER_V2_MAGIC));
+        test_partition_read(&SplinterRef::from_bytes(buf).unwrap(), &values);
+
+        let buf = encode_with_features(&splinter, Features::SUPPORTED);
+        assert_eq!(buf.len(), splinter.encoded_size_with(Features::SUPPORTED));
+        assert!(buf.ends_with(&SPLINTER_V2_FEATURES_MAGIC));
+        let splinter_ref = SplinterRef::from_bytes(buf).unwrap();
+        assert_eq!(
+            splinter_ref.features(),
+            Features::INVERTED_PARTITIONS | Features::PREFIX_PARTITIONS
+        );
+        test_partition_read(&splinter_ref, &values);
+
+        // targeting the original feature set falls back to V2 partitions,
+        // both when encoding a Splinter and when re-encoding a SplinterRef
+        for encoded in [
+            encode_with_features(&splinter, Features::EMPTY),
+            encode_with_features(&splinter_ref, Features::EMPTY),
+        ] {
+            assert!(encoded.ends_with(&SPLINTER_V2_MAGIC));
+            let legacy = SplinterRef::from_bytes_validated(encoded).unwrap();
+            assert_eq!(legacy.features(), Features::EMPTY);
+            test_partition_read(&legacy, &values);
+        }
+    }
+
+    #[test]
+    fn test_prefix_features() {
+        // optimized single-segment splinters use prefix partitions
+        let values = (0..4096).step_by(7).map(|v| v + (5 << 24)).collect_vec();
+        let mut splinter = Splinter::from_iter(values.iter().copied());
+        assert_eq!(splinter.encoded_features(), Features::EMPTY);
+        splinter.optimize_with(Features::SUPPORTED);
+        assert_eq!(splinter.encoded_features(), Features::PREFIX_PARTITIONS);
+        let splinter_ref =
+            SplinterRef::from_bytes_validated(encode_with_features(&splinter, Features::SUPPORTED))
+                .unwrap();
+        assert_eq!(splinter_ref.features(), Features::PREFIX_PARTITIONS);
+        assert_eq!(splinter_ref.load_unchecked().kind(), PartitionKind::Prefix);
+        test_partition_read(&splinter_ref, &values);
+
+        // without the feature, prefix partitions fall back to trees
+        for encoded in [
+            encode_with_features(&splinter, Features::EMPTY),
+            encode_with_features(&splinter_ref, Features::EMPTY),
+        ] {
+            assert!(encoded.ends_with(&SPLINTER_V2_MAGIC));
+            let legacy = SplinterRef::from_bytes_validated(encoded).unwrap();
+            assert_eq!(legacy.features(), Features::EMPTY);
+            assert_eq!(legacy.load_unchecked().kind(), PartitionKind::Tree);
+            test_partition_read(&legacy, &values);
+        }
+    }
+
+    #[test]
+    fn test_elias_fano_features() {
+        let values = (0..65536).step_by(16).collect_vec();
+        let mut splinter = Splinter::from_iter(values.iter().copied());
+        splinter.optimize_with(Features::SUPPORTED);
+        assert_eq!(
+            splinter.encoded_features(),
+            Features::ELIAS_FANO_PARTITIONS | Features::PREFIX_PARTITIONS
+        );
+        let splinter_ref =
+            SplinterRef::from_bytes(encode_with_features(&splinter, Features::SUPPORTED)).unwrap();
+        assert_eq!(
+            splinter_ref.features(),
+            Features::ELIAS_FANO_PARTITIONS | Features::PREFIX_PARTITIONS
+        );
+        test_partition_read(&splinter_ref, &values);
+
+        // without the feature, Elias-Fano partitions fall back to bitmaps
+        let encoded = encode_with_features(&splinter_ref, Features::EMPTY);
+        assert!(encoded.ends_with(&SPLINTER_V2_MAGIC));
+        let legacy = SplinterRef::from_bytes_validated(encoded).unwrap();
+        assert_eq!(legacy.features(), Features::EMPTY);
+        test_partition_read(&legacy, &values);
+    }
+
+    #[test]
+    fn test_delta_vec_features() {
+        let values = (0..1024).step_by(37).collect_vec();
+        let mut splinter = Splinter::from_iter(values.iter().copied());
+        splinter.optimize_with(Features::SUPPORTED);
+        assert_eq!(
+            splinter.encoded_features(),
+            Features::DELTA_VEC_PARTITIONS | Features::PREFIX_PARTITIONS
+        );
+        let splinter_ref =
+            SplinterRef::from_bytes(encode_with_features(&splinter, Features::SUPPORTED)).unwrap();
+        assert_eq!(
+            splinter_ref.features(),
+            Features::DELTA_VEC_PARTITIONS | Features::PREFIX_PARTITIONS
+        );
+        test_partition_read(&splinter_ref, &values);
+
+        // without the feature, delta-vec partitions fall back to Vec partitions
+        let encoded = encode_with_features(&splinter_ref, Features::EMPTY);
+        assert!(encoded.ends_with(&SPLINTER_V2_MAGIC));
+        let legacy = SplinterRef::from_bytes_validated(encoded).unwrap();
+        assert_eq!(legacy.features(), Features::EMPTY);
+        test_partition_read(&legacy, &values);
+    }
+
+    #[test]
+    fn test_sparse_bitmap_features() {
+        // clustered values in every eighth word
+        let values = (0..65536)
+            .step_by(3)
+            .filter(|v| (v / 64) % 8 == 0)
+            .collect_vec();
+        let mut splinter = Splinter::from_iter(values.iter().copied());
+        splinter.optimize_with(Features::SUPPORTED);
+        let features = Features::SPARSE_BITMAP_PARTITIONS | Features::PREFIX_PARTITIONS;
+        assert_eq!(splinter.encoded_features(), features);
+        let splinter_ref =
+            SplinterRef::from_bytes_validated(encode_with_features(&splinter, Features::SUPPORTED))
+                .unwrap();
+        assert_eq!(splinter_ref.features(), features);
+        test_partition_read(&splinter_ref, &values);
+
+        // without the feature, sparse bitmaps fall back to Vec or Bitmap
+        // partitions
+        let encoded = encode_with_features(&splinter_ref, Features::EMPTY);
+        assert!(encoded.ends_with(&SPLINTER_V2_MAGIC));
+        let legacy = SplinterRef::from_bytes_validated(encoded).unwrap();
+        assert_eq!(legacy.features(), Features::EMPTY);
+        test_partition_read(&legacy, &values);
+    }
+}
@@ -1,44 +1,369 @@
-use crc64fast_nvme::Digest;
-use zerocopy::{
-    ByteEq, ByteHash, FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, U64, Unaligned,
-};
+use zerocopy::{FromBytes, LittleEndian, U32};
 
-use crate::codec::DecodeErr;
+use crate::codec::{DecodeErr, checksum::Checksum, features::Features};
+
+/// The first three bytes of every Splinter version 2 magic value. The last
+/// byte records the footer layout, see [`Footer::magic`].
+const MAGIC_PREFIX: [u8; 3] = [0x59, 0x11, 0xA7];
+
+/// The smallest valid value of the last magic byte.
+const MAGIC_BASE: u8 = 0xE2;
 
 /// The last four bytes of an encoded Splinter version 2
-pub const SPLINTER_V2_MAGIC: [u8; 4] = [0x59, 0x11, 0xA7, 0xE2];
+#[cfg(test)]
+pub const SPLINTER_V2_MAGIC: [u8; 4] = Footer::new(Checksum::Crc64Nvme, Features::EMPTY).magic();
+
+/// The last four bytes of an encoded Splinter version 2 which uses optional
+/// features. The feature bits are stored as a little-endian u32 immediately
+/// before the checksum, and are covered by the checksum.
+#[cfg(test)]
+pub const SPLINTER_V2_FEATURES_MAGIC: [u8; 4] =
+    Footer::new(Checksum::Crc64Nvme, Features::INVERTED_PARTITIONS).magic();
+
+/// The encoded feature bits which precede the checksum of a footer with
+/// [`SPLINTER_V2_FEATURES_MAGIC`].
+pub type EncodedFeatures = U32<LittleEndian>;
+
+/// The total length of an encoded splinter, including its footer, which
+/// precedes the feature bits of a footer using [`Features::ENCODED_LENGTH`].
+pub type EncodedLength = U32<LittleEndian>;
 
-#[derive(FromBytes, IntoBytes, Immutable, Unaligned, KnownLayout, ByteHash, ByteEq)]
-#[repr(C)]
+/// Describes the footer at the end of an encoded splinter.
+///
+/// Encoding order: [length][features][checksum][magic]
+///
+/// The features are only present if non-empty, and the length is only
+/// present if the features include [`Features::ENCODED_LENGTH`]. The checksum
+/// is [`Checksum::size`] bytes long and covers everything before it.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
 pub struct Footer {
-    checksum: U64<LittleEndian>,
-    magic: [u8; 4],
+    pub checksum: Checksum,
+    pub features: Features,
 }
 
 impl Footer {
-    pub const SIZE: usize = std::mem::size_of::<Self>();
+    /// The size of the default footer, which has a [`Checksum::Crc64Nvme`]
+    /// checksum and no features.
+    pub const SIZE: usize = Self::new(Checksum::Crc64Nvme, Features::EMPTY).encoded_size();
 
-    pub fn from_checksum(checksum: u64) -> Self {
-        Self {
-            checksum: checksum.into(),
-            magic: SPLINTER_V2_MAGIC,
+    pub const MAGIC_SIZE: usize = MAGIC_PREFIX.len() + 1;
+
+    pub const fn new(checksum: Checksum, features: Features) -> Self {
+        Self { checksum, features }
+    }
+
+    /// The number of bytes needed to encode this footer.
+    pub const fn encoded_size(&self) -> usize {
+        let features = if self.features.is_empty() {
+            0
+        } else {
+            size_of::<EncodedFeatures>()
+        };
+        let length = if self.features.contains(Features::ENCODED_LENGTH) {
+            size_of::<EncodedLength>()
+        } else {
+            0
+        };
+        length + features + self.checksum.size() + Self::MAGIC_SIZE
+    }
+
+    /// The magic value which ends this footer. The last byte stores the
+    /// checksum algorithm in bits 1-2 and whether features are present in
+    /// bit 0, offset by [`MAGIC_BASE`].
+    pub const fn magic(&self) -> [u8; 4] {
+        let layout = ((self.checksum as u8) << 1) | !self.features.is_empty() as u8;
+        let [a, b, c] = MAGIC_PREFIX;
+        [a, b, c, MAGIC_BASE + layout]
+    }
+
+    /// Parses a magic value, returning the checksum and whether features are
+    /// present.
+    fn parse_magic(magic: &[u8; 4]) -> Option<(Checksum, bool)> {
+        let [a, b, c, last] = *magic;
+        if [a, b, c] != MAGIC_PREFIX {
+            return None;
         }
+        let layout = last.checked_sub(MAGIC_BASE)?;
+        let checksum = *Checksum::ALL.get(usize::from(layout >> 1))?;
+        Some((checksum, layout & 1 == 1))
+    }
+
+    /// Returns true if data ends with any Splinter version 2 magic value.
+    pub fn has_magic(data: &[u8]) -> bool {
+        data.last_chunk().and_then(Self::parse_magic).is_some()
     }
 
-    pub fn validate(&self, data: &[u8]) -> Result<(), DecodeErr> {
-        if self.magic != SPLINTER_V2_MAGIC {
-            return Err(DecodeErr::Magic);
+    /// Splits an encoded splinter into its partitions and footer, validating
+    /// the footer and checksum.
+    pub fn split(data: &[u8]) -> Result<(&[u8], Self), DecodeErr> {
+        let (data, magic) = data.split_last_chunk().ok_or(DecodeErr::Length)?;
+        let (checksum, has_features) = Self::parse_magic(magic).ok_or(DecodeErr::Magic)?;
+        if !checksum.is_enabled() {
+            return Err(DecodeErr::UnsupportedChecksum { checksum });
         }
 
-        let checksum = {
-            let mut c = Digest::new();
-            c.write(data);
-            c.sum64()
-        };
-        if checksum != self.checksum.get() {
+        let checksum_offset = data
+            .len()
+            .checked_sub(checksum.size())
+            .ok_or(DecodeErr::Length)?;
+        let (covered, stored) = data.split_at(checksum_offset);
+        let expected = checksum.compute(covered).to_le_bytes();
+        if stored != &expected[..checksum.size()] {
             return Err(DecodeErr::Checksum);
         }
 
-        Ok(())
+        if !has_features {
+            return Ok((covered, Self::new(checksum, Features::EMPTY)));
+        }
+        let (partitions, features) = EncodedFeatures::read_from_suffix(covered)?;
+        let features = Features::from_bits(features.get());
+        let unsupported = features.unsupported();
+        if !unsupported.is_empty() {
+            return Err(DecodeErr::UnsupportedFeature { unsupported });
+        }
+        if !features.contains(Features::ENCODED_LENGTH) {
+            return Ok((partitions, Self::new(checksum, features)));
+        }
+        let (partitions, length) = EncodedLength::read_from_suffix(partitions)?;
+        if length.get() as usize != data.len() + Self::MAGIC_SIZE {
+            return Err(DecodeErr::Length);
+        }
+        Ok((partitions, Self::new(checksum, features)))
+    }
+
+    /// Splits the last splinter from the end of a buffer containing
+    /// concatenated splinters, returning the remaining buffer and the last
+    /// splinter. The last splinter must record its length, but the checksum
+    /// is not validated.
+    pub fn split_last(data: &[u8]) -> Result<(&[u8], &[u8]), DecodeErr> {
+        let (rest, magic) = data.split_last_chunk().ok_or(DecodeErr::Length)?;
+        let (checksum, has_features) = Self::parse_magic(magic).ok_or(DecodeErr::Magic)?;
+        if !has_features {
+            return Err(DecodeErr::MissingLength);
+        }
+
+        let checksum_offset = rest
+            .len()
+            .checked_sub(checksum.size())
+            .ok_or(DecodeErr::Length)?;
+        let (rest, features) = EncodedFeatures::read_from_suffix(&rest[..checksum_offset])?;
+        if !Features::from_bits(features.get()).contains(Features::ENCODED_LENGTH) {
+            return Err(DecodeErr::MissingLength);
+        }
+        let (_, length) = EncodedLength::read_from_suffix(rest)?;
+        let offset = data
+            .len()
+            .checked_sub(length.get() as usize)
+            .ok_or(DecodeErr::Length)?;
+        Ok(data.split_at(offset))
+    }
+
+    /// Recomputes the checksum of a previously validated splinter after its
+    /// partitions have been modified in place.
+    pub fn update_checksum(data: &mut [u8]) {
+        let (_, footer) = Self::split_unchecked(data);
+        let checksum_offset = data.len() - Self::MAGIC_SIZE - footer.checksum.size();
+        let (covered, stored) =
+            data[..checksum_offset + footer.checksum.size()].split_at_mut(checksum_offset);
+        let checksum = footer.checksum.compute(covered).to_le_bytes();
+        stored.copy_from_slice(&checksum[..footer.checksum.size()]);
+    }
+
+    /// Splits a previously validated splinter into its partitions and footer.
+    pub fn split_unchecked(data: &[u8]) -> (&[u8], Self) {
+        Self::split_unverified(data).unwrap()
+    }
+
+    /// Splits the end of an encoded splinter into its partitions and footer,
+    /// without validating the checksum or the encoded length. This allows the
+    /// footer to be parsed without reading the entire splinter.
+    pub fn split_unverified(data: &[u8]) -> Result<(&[u8], Self), DecodeErr> {
+        let (data, magic) = data.split_last_chunk().ok_or(DecodeErr::Length)?;
+        let (checksum, has_features) = Self::parse_magic(magic).ok_or(DecodeErr::Magic)?;
+        let checksum_offset = data
+            .len()
+            .checked_sub(checksum.size())
+            .ok_or(DecodeErr::Length)?;
+        let data = &data[..checksum_offset];
+        if !has_features {
+            return Ok((data, Self::new(checksum, Features::EMPTY)));
+        }
+
+        let (mut partitions, features) = EncodedFeatures::read_from_suffix(data)?;
+        let features = Features::from_bits(features.get());
+        let unsupported = features.unsupported();
+        if !unsupported.is_empty() {
+            return Err(DecodeErr::UnsupportedFeature { unsupported });
+        }
+        if features.contains(Features::ENCODED_LENGTH) {
+            (partitions, _) = EncodedLength::read_from_suffix(partitions)?;
+        }
+        Ok((partitions, Self::new(checksum, features)))
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use bytes::{Bytes, BytesMut};
+    use itertools::Itertools;
+
+    use crate::{
+        Encodable, Splinter, SplinterRef, assert_error,
+        codec::{
+            DecodeErr, DecodeOptions, checksum::Checksum, encoder::Encoder, features::Features,
+            footer::Footer,
+        },
+        level::High,
+        partition_kind::PartitionKind,
+        testutil::{
+            encode_with_checksum, mkpartition_buf, mksplinter_manual_with_features,
+            mksplinter_manual_with_footer, test_partition_read,
+        },
+        traits::{Optimizable, PartitionRead},
+    };
+
+    #[test]
+    fn test_checksums() {
+        let values = (0..1000).step_by(3).collect_vec();
+        let splinter = Splinter::from_iter(values.iter().copied());
+        let default = splinter.encode_to_splinter_ref();
+
+        for checksum in Checksum::enabled() {
+            let mut encoder = Encoder::new(BytesMut::new()).with_checksum(checksum);
+            splinter.encode(&mut encoder);
+            let buf = encoder.into_inner().freeze();
+            assert_eq!(
+                buf.len(),
+                splinter.encoded_size() - Checksum::default().size() + checksum.size()
+            );
+            assert_eq!(
+                buf,
+                mksplinter_manual_with_footer(
+                    &buf[..buf.len() - checksum.size() - Footer::MAGIC_SIZE],
+                    Footer::new(checksum, Features::EMPTY)
+                )
+            );
+
+            let splinter_ref = SplinterRef::from_bytes(buf.clone()).unwrap();
+            assert_eq!(splinter_ref.checksum(), checksum);
+            test_partition_read(&splinter_ref, &values);
+
+            // re-encoding a SplinterRef only replaces the footer
+            let mut encoder = Encoder::new(BytesMut::new()).with_checksum(checksum);
+            default.encode(&mut encoder);
+            assert_eq!(encoder.into_inner().freeze(), buf);
+            assert_eq!(
+                encode_with_checksum(&splinter_ref, Checksum::default()),
+                default.encode_to_bytes()
+            );
+
+            // corruption is detected by every checksum except None
+            let mut corrupted = buf.to_vec();
+            corrupted[0] ^= 1;
+            let result = SplinterRef::from_bytes(corrupted);
+            if checksum == Checksum::None {
+                assert!(result.is_ok());
+            } else {
+                assert_error!(result, DecodeErr::Checksum);
+            }
+        }
+
+        // the smallest possible splinter is an empty partition and a magic
+        // value
+        let empty = encode_with_checksum(&Splinter::EMPTY, Checksum::None);
+        assert_eq!(empty.len(), 1 + Footer::MAGIC_SIZE);
+        assert!(SplinterRef::from_bytes(empty).unwrap().is_empty());
+    }
+
+    #[test]
+    fn test_checksums_with_features() {
+        let values = (0..65536).filter(|v| v % 1000 != 0).collect_vec();
+        let mut splinter = Splinter::from_iter(values.iter().copied());
+        splinter.optimize_with(Features::SUPPORTED);
+
+        for checksum in Checksum::enabled() {
+            let mut encoder = Encoder::with_features(BytesMut::new(), Features::SUPPORTED)
+                .with_checksum(checksum);
+            splinter.encode(&mut encoder);
+            let splinter_ref = SplinterRef::from_bytes(encoder.into_inner().freeze()).unwrap();
+            assert_eq!(splinter_ref.checksum(), checksum);
+            assert_eq!(
+                splinter_ref.features(),
+                Features::INVERTED_PARTITIONS | Features::PREFIX_PARTITIONS
+            );
+            test_partition_read(&splinter_ref, &values);
+        }
+    }
+
+    #[test]
+    fn test_disabled_checksums() {
+        let empty = encode_with_checksum(&Splinter::EMPTY, Checksum::None);
+        let partitions = &empty[..empty.len() - Footer::MAGIC_SIZE];
+
+        for checksum in Checksum::ALL {
+            // the stored checksum is never verified if its cargo feature is
+            // disabled, so any value will do
+            let mut buf = partitions.to_vec();
+            buf.resize(buf.len() + checksum.size(), 0);
+            buf.extend_from_slice(&Footer::new(checksum, Features::EMPTY).magic());
+            let buf = Bytes::from(buf);
+
+            let result = SplinterRef::from_bytes(buf.clone());
+            if checksum.is_enabled() {
+                assert!(!matches!(
+                    result,
+                    Err(DecodeErr::UnsupportedChecksum { .. })
+                ));
+            } else {
+                assert_matches!(
+                    result,
+                    Err(DecodeErr::UnsupportedChecksum { checksum: c }) if c == checksum
+                );
+            }
+
+            // trusted buffers don't need the checksum
+            let splinter_ref = SplinterRef::from_bytes_with(buf, DecodeOptions::TRUSTED).unwrap();
+            assert!(splinter_ref.is_empty());
+        }
+    }
+
+    #[test]
+    #[cfg(not(all(feature = "crc32c", feature = "xxh3")))]
+    #[should_panic(expected = "disabled by the crate's cargo features")]
+    fn test_disabled_checksum_encoder() {
+        let checksum = Checksum::ALL.into_iter().find(|c| !c.is_enabled()).unwrap();
+        let _ = Encoder::new(BytesMut::new()).with_checksum(checksum);
+    }
+
+    #[test]
+    fn test_unsupported_features() {
+        let partition = mkpartition_buf::<High>(PartitionKind::Vec, &[1, 2, 3]);
+        let unknown = Features::from_bits(1 << 31);
+
+        let buf = mksplinter_manual_with_features(&partition, unknown);
+        assert_matches!(
+            SplinterRef::from_bytes(buf),
+            Err(DecodeErr::UnsupportedFeature { unsupported }) if unsupported == unknown
+        );
+
+        let buf = mksplinter_manual_with_features(&partition, unknown | Features::SUPPORTED);
+        assert_matches!(
+            SplinterRef::from_bytes(buf),
+            Err(DecodeErr::UnsupportedFeature { unsupported }) if unsupported == unknown
+        );
+
+        // every feature except PATCH can be used by a splinter
+        let features = Features::SUPPORTED.difference(Features::PATCH);
+        let buf = mksplinter_manual_with_features(&partition, features);
+        let splinter_ref = SplinterRef::from_bytes(buf).unwrap();
+        assert_eq!(splinter_ref.features(), features);
+        assert_eq!(splinter_ref.iter().collect_vec(), [1, 2, 3]);
+
+        // the feature bits are covered by the checksum
+        let mut buf = mksplinter_manual_with_features(&partition, unknown).to_vec();
+        let features_offset = buf.len() - Footer::new(Checksum::default(), unknown).encoded_size();
+        buf[features_offset] = 0xFF;
+        assert_error!(SplinterRef::from_bytes(buf), DecodeErr::Checksum);
     }
 }
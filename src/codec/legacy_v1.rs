@@ -0,0 +1,298 @@
+//! Decoding support for the legacy Splinter V1 format.
+//!
+//! Splinter V1 was written by splinter-rs 0.3.3 and earlier. A V1 buffer
+//! starts with [`SPLINTER_V1_MAGIC`] and ends with a four byte footer storing
+//! the number of top-level partitions. Each partition stores the encoded
+//! values of its children followed by an index:
+//!
+//! ```text
+//! [children...][keys][cardinalities][offsets]
+//! ```
+//!
+//! - `keys` is a block storing the segment of each child.
+//! - `cardinalities` stores the number of entries in each child, minus one.
+//! - `offsets` stores the distance from the end of each child to the end of
+//!   the children. Offsets are u32 in the high and mid levels and u16 in the
+//!   low level, all little-endian.
+//!
+//! A block of `n` segments is stored as `n` sorted bytes if `n < 32`, a 32
+//! byte bitmap if `n >= 32`, or omitted entirely if `n == 256`.
+//!
+//! V1 buffers can only be decoded into an owned [`Splinter`]. Use
+//! [`CowSplinter::from_bytes_any_version`] to accept both formats.
+//!
+//! [`CowSplinter::from_bytes_any_version`]: crate::CowSplinter::from_bytes_any_version
+
+use std::collections::BTreeMap;
+
+use bitvec::{order::Lsb0, slice::BitSlice};
+use itertools::Itertools;
+use zerocopy::{FromBytes, Immutable, LittleEndian, U16, U32};
+
+use crate::{
+    PartitionRead, Splinter,
+    codec::{DecodeErr, footer::Footer},
+    level::{Block, High, Level, Low, Mid},
+    partition::{Partition, bitmap::BitmapPartition, tree::TreePartition, vec::VecPartition},
+};
+
+/// The first four bytes of an encoded Splinter V1.
+pub const SPLINTER_V1_MAGIC: [u8; 4] = [0xDA, 0xAE, 0x12, 0xDF];
+
+/// The size of the footer at the end of an encoded Splinter V1.
+const FOOTER_SIZE: usize = 4;
+
+/// The size of a block stored as a bitmap.
+const BITMAP_SIZE: usize = 32;
+
+/// Returns true if data looks like a Splinter V1 rather than a Splinter V2
+/// which happens to start with [`SPLINTER_V1_MAGIC`].
+pub(crate) fn is_v1(data: &[u8]) -> bool {
+    data.starts_with(&SPLINTER_V1_MAGIC) && !Footer::has_magic(data)
+}
+
+/// Decodes a Splinter V1 buffer into a [`Splinter`].
+///
+/// The returned splinter mirrors the V1 structure and is not optimized. Call
+/// [`Optimizable::optimize`](crate::Optimizable::optimize) before re-encoding
+/// it to get the most compact V2 encoding.
+///
+/// # Errors
+///
+/// - [`DecodeErr::Length`]: The buffer is truncated
+/// - [`DecodeErr::Magic`]: The buffer does not start with [`SPLINTER_V1_MAGIC`]
+/// - [`DecodeErr::Validity`]: The buffer contains an invalid V1 structure
+///
+/// # Examples
+///
+/// ```
+/// use splinter_rs::{PartitionRead, codec::legacy_v1};
+///
+/// // the encoding of a V1 splinter containing 42
+/// let data = b"\xda\xae\x12\xdf\x2a\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x01\0\0\0";
+/// let splinter = legacy_v1::decode(data).unwrap();
+/// assert_eq!(splinter.iter().collect::<Vec<_>>(), vec![42]);
+/// ```
+pub fn decode(data: &[u8]) -> Result<Splinter, DecodeErr> {
+    DecodeErr::ensure_bytes_available(data, SPLINTER_V1_MAGIC.len() + FOOTER_SIZE)?;
+    if !data.starts_with(&SPLINTER_V1_MAGIC) {
+        return Err(DecodeErr::Magic);
+    }
+    let (data, footer) = data.split_at(data.len() - FOOTER_SIZE);
+    let len = usize::from(U16::<LittleEndian>::read_from_prefix(footer)?.0.get());
+    let data = &data[SPLINTER_V1_MAGIC.len()..];
+
+    if len == 0 {
+        return Ok(Splinter::EMPTY);
+    }
+    let partition = decode_tree::<High, U32<LittleEndian>>(data, len, |data, len| {
+        decode_tree::<Mid, U32<LittleEndian>>(data, len, |data, len| {
+            decode_tree::<Low, U16<LittleEndian>>(data, len, decode_block)
+        })
+    })?;
+    Ok(Splinter::new(partition))
+}
+
+/// Decodes a partition with len children from the suffix of data, decoding
+/// each child with `decode_child`.
+fn decode_tree<L: Level, O>(
+    data: &[u8],
+    len: usize,
+    decode_child: impl Fn(&[u8], usize) -> Result<L::Down, DecodeErr>,
+) -> Result<Partition<L>, DecodeErr>
+where
+    O: FromBytes + Immutable + Copy + Into<u32>,
+{
+    let index_size = block_size(len)?
+        .checked_add(len * (1 + size_of::<O>()))
+        .ok_or(DecodeErr::Validity)?;
+    let values_len = data
+        .len()
+        .checked_sub(index_size)
+        .ok_or(DecodeErr::Length)?;
+    let (values, index) = data.split_at(values_len);
+
+    let (keys, index) = index.split_at(block_size(len)?);
+    let (cardinalities, offsets) = index.split_at(len);
+    let offsets = <[O]>::ref_from_bytes_with_elems(offsets, len)?;
+    let keys = decode_block(keys, len)?;
+
+    let mut children = BTreeMap::new();
+    for (segment, &cardinality, &offset) in itertools::izip!(keys.iter(), cardinalities, offsets) {
+        let offset = usize::try_from(offset.into()).map_err(|_| DecodeErr::Validity)?;
+        let end = values.len().checked_sub(offset).ok_or(DecodeErr::Length)?;
+        let child = decode_child(&values[..end], usize::from(cardinality) + 1)?;
+        children.insert(segment, child);
+    }
+    Ok(Partition::Tree(TreePartition::from(children)))
+}
+
+/// Decodes a block of len segments from the suffix of data.
+fn decode_block(data: &[u8], len: usize) -> Result<Partition<Block>, DecodeErr> {
+    let size = block_size(len)?;
+    DecodeErr::ensure_bytes_available(data, size)?;
+    let data = &data[data.len() - size..];
+
+    let partition = match len {
+        Block::MAX_LEN => Partition::Full,
+        BITMAP_SIZE.. => {
+            let bits = BitSlice::<u8, Lsb0>::from_slice(data);
+            Partition::Bitmap(BitmapPartition::from_encoded(bits))
+        }
+        _ => {
+            if !data.iter().tuple_windows().all(|(a, b)| a < b) {
+                return Err(DecodeErr::Validity);
+            }
+            let segments = data.iter().copied();
+            Partition::Vec(VecPartition::from_sorted_unique_unchecked(segments))
+        }
+    };
+    if partition.cardinality() != len {
+        return Err(DecodeErr::Validity);
+    }
+    Ok(partition)
+}
+
+/// Returns the encoded size of a block of len segments.
+fn block_size(len: usize) -> Result<usize, DecodeErr> {
+    match len {
+        0 => Err(DecodeErr::Validity),
+        Block::MAX_LEN => Ok(0),
+        BITMAP_SIZE..Block::MAX_LEN => Ok(BITMAP_SIZE),
+        1..BITMAP_SIZE => Ok(len),
+        _ => Err(DecodeErr::Validity),
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use itertools::Itertools;
+
+    use crate::{PartitionRead, codec::DecodeErr};
+
+    use super::decode;
+
+    const EMPTY: &[u8] = b"\xda\xae\x12\xdf\0\0\0\0";
+
+    const ONE: &[u8] = &[
+        0xda, 0xae, 0x12, 0xdf, 0x2a, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+    ];
+
+    /// Contains `[1, 2, 3, 256, 70000, 1 << 24, u32::MAX]`
+    const MIXED: &[u8] = &[
+        0xda, 0xae, 0x12, 0xdf, 0x01, 0x02, 0x03, 0x00, 0x00, 0x01, 0x02, 0x00, 0x01, 0x00, 0x00,
+        0x00, 0x70, 0x11, 0x00, 0x00, 0x00, 0x00, 0x01, 0x01, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff,
+        0xff, 0x00, 0x00, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xff, 0x01, 0x00,
+        0x00, 0x16, 0x00, 0x00, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00,
+        0x00, 0x00,
+    ];
+
+    /// Contains every third value in 0..120, stored as a bitmap block
+    const DENSE_BLOCK: &[u8] = &[
+        0xda, 0xae, 0x12, 0xdf, 0x49, 0x92, 0x24, 0x49, 0x92, 0x24, 0x49, 0x92, 0x24, 0x49, 0x92,
+        0x24, 0x49, 0x92, 0x24, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x27, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+    ];
+
+    /// Contains 0..256, stored as a full block
+    const FULL_BLOCK: &[u8] = &[
+        0xda, 0xae, 0x12, 0xdf, 0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x00,
+    ];
+
+    /// Encoded by a real splinter-rs 0.3.3 build, rather than by hand, with:
+    ///
+    /// ```ignore
+    /// let values = [0u32, 1, 2, 300, 65535]
+    ///     .into_iter()
+    ///     .chain((65536..65600).step_by(2))
+    ///     .chain(131072..131328)
+    ///     .chain([1 << 24, (1 << 24) + 70000, u32::MAX]);
+    /// Splinter::from_iter(values).serialize_to_bytes()
+    /// ```
+    const SPLINTER_0_3_3: &[u8] = &[
+        0xda, 0xae, 0x12, 0xdf, 0x00, 0x01, 0x02, 0x2c, 0xff, 0x00, 0x01, 0xff, 0x02, 0x00, 0x00,
+        0x02, 0x00, 0x01, 0x00, 0x00, 0x00, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x55, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x1f, 0x00, 0x00, 0x00, 0xff, 0x00,
+        0x00, 0x00, 0x01, 0x02, 0x02, 0x00, 0x00, 0x28, 0x00, 0x00, 0x00, 0x04, 0x00, 0x00, 0x00,
+        0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x70, 0x11, 0x00, 0x00, 0x00, 0x00,
+        0x01, 0x00, 0x00, 0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0xff, 0xff, 0x00, 0x00,
+        0x00, 0xff, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0xff, 0x02, 0x01, 0x00, 0x21, 0x00,
+        0x00, 0x00, 0x0b, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x03, 0x00, 0x00, 0x00,
+    ];
+
+    fn decode_values(data: &[u8]) -> Vec<u32> {
+        decode(data).unwrap().iter().collect_vec()
+    }
+
+    #[test]
+    fn test_decode_v1() {
+        assert_eq!(decode_values(EMPTY), Vec::<u32>::new());
+        assert_eq!(decode_values(ONE), vec![42]);
+        assert_eq!(
+            decode_values(MIXED),
+            vec![1, 2, 3, 256, 70000, 1 << 24, u32::MAX]
+        );
+        assert_eq!(
+            decode_values(DENSE_BLOCK),
+            (0..120).step_by(3).collect_vec()
+        );
+        assert_eq!(decode_values(FULL_BLOCK), (0..256).collect_vec());
+        assert_eq!(
+            decode_values(SPLINTER_0_3_3),
+            [0, 1, 2, 300, 65535]
+                .into_iter()
+                .chain((65536..65600).step_by(2))
+                .chain(131072..131328)
+                .chain([1 << 24, (1 << 24) + 70000, u32::MAX])
+                .collect_vec()
+        );
+    }
+
+    #[test]
+    fn test_decode_v1_errors() {
+        // too short
+        assert_matches!(decode(&EMPTY[..7]), Err(DecodeErr::Length));
+
+        // bad magic
+        let mut data = ONE.to_vec();
+        data[0] = 0;
+        assert_matches!(decode(&data), Err(DecodeErr::Magic));
+
+        // too many top-level partitions
+        let mut data = EMPTY.to_vec();
+        data[4..6].copy_from_slice(&257u16.to_le_bytes());
+        assert_matches!(decode(&data), Err(DecodeErr::Validity));
+
+        // partition count exceeds the data
+        let mut data = MIXED.to_vec();
+        let footer = data.len() - 4;
+        data[footer] = 200;
+        assert_matches!(decode(&data), Err(DecodeErr::Length));
+
+        // out of bounds offset
+        let mut data = ONE.to_vec();
+        data[17] = 0xff;
+        assert_matches!(decode(&data), Err(DecodeErr::Length));
+
+        // unsorted block
+        let mut data = MIXED.to_vec();
+        data.swap(4, 6);
+        assert_matches!(decode(&data), Err(DecodeErr::Validity));
+
+        // bitmap block with the wrong cardinality
+        let mut data = DENSE_BLOCK.to_vec();
+        data[4] = 0;
+        assert_matches!(decode(&data), Err(DecodeErr::Validity));
+
+        // missing values
+        let mut data = ONE.to_vec();
+        data.remove(4);
+        assert_matches!(decode(&data), Err(DecodeErr::Length));
+    }
+}
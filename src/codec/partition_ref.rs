@@ -1,16 +1,23 @@
-use bitvec::{order::Lsb0, slice::BitSlice};
-use either::Either;
 use num::traits::{AsPrimitive, Bounded};
+use range_set_blaze::SortedDisjoint;
 use zerocopy::{FromBytes, TryFromBytes};
 
 use crate::{
     MultiIter, PartitionRead,
-    codec::{DecodeErr, runs_ref::RunsRef, tree_ref::TreeRef},
+    codec::{
+        DecodeErr, DecodeLocation, LocatedDecodeErr, bitmap_ref::BitmapRef,
+        delta_vec_ref::DeltaVecRef, elias_fano_ref::EliasFanoRef, prefix_ref::PrefixRef,
+        runs_ref::RunsRef, sparse_bitmap_ref::SparseBitmapRef, tree_ref::TreeRef,
+    },
     level::{Block, Level},
-    partition::{Partition, bitmap::BitmapPartition, vec::VecPartition},
+    partition::{
+        Partition, bitmap::BitmapPartition, inverted::InvertedPartition, run::MergeRuns,
+        vec::VecPartition,
+    },
     partition_kind::PartitionKind,
+    splinter_mut::{NeedsReencode, Patches},
     traits::TruncateFrom,
-    util::{RangeExt, RangeIter},
+    util::{IteratorExt, RangeExt, RangeIter},
 };
 
 pub(super) fn decode_len_from_suffix<L: Level>(data: &[u8]) -> Result<(&[u8], usize), DecodeErr> {
@@ -24,9 +31,12 @@ pub(super) fn decode_len_from_suffix<L: Level>(data: &[u8]) -> Result<(&[u8], us
 pub enum NonRecursivePartitionRef<'a, L: Level> {
     Empty,
     Full,
-    Bitmap { bitmap: &'a BitSlice<u8, Lsb0> },
+    Bitmap { bitmap: BitmapRef<'a, L> },
     Vec { values: &'a [L::ValueUnaligned] },
     Run { runs: RunsRef<'a, L> },
+    EliasFano { values: EliasFanoRef<'a, L> },
+    DeltaVec { values: DeltaVecRef<'a, L> },
+    SparseBitmap { bitmap: SparseBitmapRef<'a, L> },
 }
 
 impl<'a, L: Level> NonRecursivePartitionRef<'a, L> {
@@ -39,7 +49,7 @@ impl<'a, L: Level> NonRecursivePartitionRef<'a, L> {
                 DecodeErr::ensure_bytes_available(data, bytes)?;
                 let range = (data.len() - bytes)..data.len();
                 Ok(Self::Bitmap {
-                    bitmap: BitSlice::from_slice(&data[range]),
+                    bitmap: BitmapRef::from_bytes(&data[range]),
                 })
             }
             PartitionKind::Vec => {
@@ -52,11 +62,60 @@ impl<'a, L: Level> NonRecursivePartitionRef<'a, L> {
                 })
             }
             PartitionKind::Run => Ok(Self::Run { runs: RunsRef::from_suffix(data)? }),
-            PartitionKind::Tree => unreachable!("non-recursive"),
+            PartitionKind::EliasFano => {
+                Ok(Self::EliasFano { values: EliasFanoRef::from_suffix(data)? })
+            }
+            PartitionKind::DeltaVec => {
+                Ok(Self::DeltaVec { values: DeltaVecRef::from_suffix(data)? })
+            }
+            PartitionKind::SparseBitmap => Ok(Self::SparseBitmap {
+                bitmap: SparseBitmapRef::from_suffix(data)?,
+            }),
+            PartitionKind::Tree | PartitionKind::Prefix => unreachable!("non-recursive"),
+            PartitionKind::InvertedVec | PartitionKind::InvertedRun => {
+                unreachable!("inverted partitions are decoded by PartitionRef")
+            }
         }
     }
 
-    #[cfg(test)]
+    /// Verifies the internal invariants of this partition which are assumed
+    /// by the read path but not checked by [`Self::from_suffix_with_kind`].
+    pub(crate) fn validate(&self) -> Result<(), DecodeErr> {
+        match self {
+            Self::Empty | Self::Full | Self::Bitmap { .. } => Ok(()),
+            Self::Vec { values } => {
+                // values must be sorted and unique
+                if values.windows(2).all(|w| w[0] < w[1]) {
+                    Ok(())
+                } else {
+                    Err(DecodeErr::Validity)
+                }
+            }
+            Self::Run { runs } => runs.validate(),
+            Self::EliasFano { values } => values.validate(),
+            Self::DeltaVec { values } => values.validate(),
+            Self::SparseBitmap { bitmap } => bitmap.validate(),
+        }
+    }
+
+    /// Plans an in-place edit which inserts or removes `value`, which must
+    /// respectively be missing from or present in this partition.
+    pub(crate) fn plan_edit(
+        &self,
+        value: L::Value,
+        insert: bool,
+        patches: &mut Patches,
+    ) -> Result<(), NeedsReencode> {
+        match self {
+            Self::Bitmap { bitmap } => bitmap.plan_edit(value, patches),
+            Self::Run { runs } => runs.plan_edit(value, insert, patches)?,
+            Self::SparseBitmap { bitmap } => bitmap.plan_edit(value, patches)?,
+            // the remaining kinds must be resized or rebuilt
+            _ => return Err(NeedsReencode),
+        }
+        Ok(())
+    }
+
     pub fn kind(&self) -> PartitionKind {
         match self {
             Self::Empty => PartitionKind::default(),
@@ -64,6 +123,9 @@ impl<'a, L: Level> NonRecursivePartitionRef<'a, L> {
             Self::Bitmap { .. } => PartitionKind::Bitmap,
             Self::Vec { .. } => PartitionKind::Vec,
             Self::Run { .. } => PartitionKind::Run,
+            Self::EliasFano { .. } => PartitionKind::EliasFano,
+            Self::DeltaVec { .. } => PartitionKind::DeltaVec,
+            Self::SparseBitmap { .. } => PartitionKind::SparseBitmap,
         }
     }
 }
@@ -85,7 +147,7 @@ impl<'a> NonRecursivePartitionRef<'a, Block> {
                 DecodeErr::ensure_bytes_available(data, bytes)?;
                 let range = (data.len() - bytes)..data.len();
                 Ok(Self::Bitmap {
-                    bitmap: BitSlice::from_slice(&data[range]),
+                    bitmap: BitmapRef::from_bytes(&data[range]),
                 })
             }
             PartitionKind::Vec => {
@@ -109,9 +171,12 @@ impl<'a, L: Level> PartitionRead<L> for NonRecursivePartitionRef<'a, L> {
         match self {
             Self::Empty => 0,
             Self::Full => L::MAX_LEN,
-            Self::Bitmap { bitmap } => bitmap.count_ones(),
+            Self::Bitmap { bitmap } => bitmap.cardinality(),
             Self::Vec { values } => values.len(),
             Self::Run { runs } => runs.cardinality(),
+            Self::EliasFano { values } => values.cardinality(),
+            Self::DeltaVec { values } => values.cardinality(),
+            Self::SparseBitmap { bitmap } => bitmap.cardinality(),
         }
     }
 
@@ -119,9 +184,12 @@ impl<'a, L: Level> PartitionRead<L> for NonRecursivePartitionRef<'a, L> {
         match self {
             Self::Empty => true,
             Self::Full => false,
-            Self::Bitmap { bitmap } => bitmap.not_any(),
+            Self::Bitmap { bitmap } => bitmap.is_empty(),
             Self::Vec { values } => values.is_empty(),
             Self::Run { runs } => runs.is_empty(),
+            Self::EliasFano { values } => values.is_empty(),
+            Self::DeltaVec { values } => values.is_empty(),
+            Self::SparseBitmap { bitmap } => bitmap.is_empty(),
         }
     }
 
@@ -129,9 +197,12 @@ impl<'a, L: Level> PartitionRead<L> for NonRecursivePartitionRef<'a, L> {
         match self {
             Self::Empty => false,
             Self::Full => true,
-            Self::Bitmap { bitmap } => *bitmap.get(value.as_()).unwrap(),
+            Self::Bitmap { bitmap } => bitmap.contains(value),
             Self::Vec { values } => values.binary_search(&value.into()).is_ok(),
             Self::Run { runs } => runs.contains(value),
+            Self::EliasFano { values } => values.contains(value),
+            Self::DeltaVec { values } => values.contains(value),
+            Self::SparseBitmap { bitmap } => bitmap.contains(value),
         }
     }
 
@@ -139,15 +210,12 @@ impl<'a, L: Level> PartitionRead<L> for NonRecursivePartitionRef<'a, L> {
         match self {
             Self::Empty => None,
             Self::Full => Some(value.as_()),
-            Self::Bitmap { bitmap } => {
-                let contains = *bitmap.get(value.as_()).unwrap();
-                contains.then(|| {
-                    let prefix = bitmap.get(0..value.as_());
-                    prefix.unwrap().count_ones()
-                })
-            }
+            Self::Bitmap { bitmap } => bitmap.position(value),
             Self::Vec { values } => values.binary_search(&value.into()).ok(),
             Self::Run { runs } => runs.position(value),
+            Self::EliasFano { values } => values.position(value),
+            Self::DeltaVec { values } => values.position(value),
+            Self::SparseBitmap { bitmap } => bitmap.position(value),
         }
     }
 
@@ -155,15 +223,15 @@ impl<'a, L: Level> PartitionRead<L> for NonRecursivePartitionRef<'a, L> {
         match self {
             Self::Empty => 0,
             Self::Full => value.as_() + 1,
-            Self::Bitmap { bitmap } => {
-                let prefix = bitmap.get(0..=value.as_());
-                prefix.unwrap().count_ones()
-            }
+            Self::Bitmap { bitmap } => bitmap.rank(value),
             Self::Vec { values } => match values.binary_search(&value.into()) {
                 Ok(index) => index + 1,
                 Err(index) => index,
             },
             Self::Run { runs } => runs.rank(value),
+            Self::EliasFano { values } => values.rank(value),
+            Self::DeltaVec { values } => values.rank(value),
+            Self::SparseBitmap { bitmap } => bitmap.rank(value),
         }
     }
 
@@ -171,9 +239,12 @@ impl<'a, L: Level> PartitionRead<L> for NonRecursivePartitionRef<'a, L> {
         match self {
             Self::Empty => None,
             Self::Full => (idx < L::MAX_LEN).then(|| L::Value::truncate_from(idx)),
-            Self::Bitmap { bitmap } => bitmap.iter_ones().nth(idx).map(L::Value::truncate_from),
+            Self::Bitmap { bitmap } => bitmap.select(idx),
             Self::Vec { values } => values.get(idx).map(|&v| v.into()),
             Self::Run { runs } => runs.select(idx),
+            Self::EliasFano { values } => values.select(idx),
+            Self::DeltaVec { values } => values.select(idx),
+            Self::SparseBitmap { bitmap } => bitmap.select(idx),
         }
     }
 
@@ -181,9 +252,12 @@ impl<'a, L: Level> PartitionRead<L> for NonRecursivePartitionRef<'a, L> {
         match self {
             Self::Empty => None,
             Self::Full => Some(L::Value::max_value()),
-            Self::Bitmap { bitmap } => bitmap.last_one().map(L::Value::truncate_from),
+            Self::Bitmap { bitmap } => bitmap.last(),
             Self::Vec { values } => values.last().map(|&v| v.into()),
             Self::Run { runs } => runs.last(),
+            Self::EliasFano { values } => values.last(),
+            Self::DeltaVec { values } => values.last(),
+            Self::SparseBitmap { bitmap } => bitmap.last(),
         }
     }
 
@@ -191,11 +265,12 @@ impl<'a, L: Level> PartitionRead<L> for NonRecursivePartitionRef<'a, L> {
         match self {
             Self::Empty => Iter::Empty(std::iter::empty()),
             Self::Full => Iter::Full((0..L::MAX_LEN).map(L::Value::truncate_from)),
-            Self::Bitmap { bitmap } => {
-                Iter::Bitmap(bitmap.iter_ones().map(L::Value::truncate_from))
-            }
+            Self::Bitmap { bitmap } => Iter::Bitmap(bitmap.iter()),
             Self::Vec { values } => Iter::Vec(values.iter().map(|&v| v.into())),
             Self::Run { runs } => Iter::Run(runs.iter()),
+            Self::EliasFano { values } => Iter::EliasFano(values.iter()),
+            Self::DeltaVec { values } => Iter::DeltaVec(values.iter()),
+            Self::SparseBitmap { bitmap } => Iter::SparseBitmap(bitmap.iter()),
         }
     }
 
@@ -210,15 +285,7 @@ impl<'a, L: Level> PartitionRead<L> for NonRecursivePartitionRef<'a, L> {
                 // Full partition contains every possible range (empty or not)
                 true
             }
-            Self::Bitmap { bitmap } => {
-                if let Some(range) = values.try_into_inclusive() {
-                    let range = (*range.start()).as_()..=(*range.end()).as_();
-                    let slice = bitmap.get(range).unwrap();
-                    slice.all()
-                } else {
-                    true
-                }
-            }
+            Self::Bitmap { bitmap } => bitmap.contains_all(values),
             Self::Vec { values: vec_values } => {
                 if let Some(range) = values.try_into_inclusive() {
                     let (start, end) = (*range.start(), *range.end());
@@ -227,15 +294,20 @@ impl<'a, L: Level> PartitionRead<L> for NonRecursivePartitionRef<'a, L> {
                         Err(_) => return false,
                     };
 
-                    // Check if all values in the range are present by comparing iterators
-                    let expected = RangeIter::new(range);
-                    let actual = vec_values[start_idx..].iter().map(|&v| v.into());
-                    itertools::equal(expected, actual.take_while(|&v| v <= end))
+                    // values are sorted and unique, so the range is fully
+                    // present iff `end` sits exactly `end - start` slots later
+                    let offset: usize = (end - start).as_();
+                    vec_values
+                        .get(start_idx + offset)
+                        .is_some_and(|&v| v.into() == end)
                 } else {
                     true
                 }
             }
             Self::Run { runs } => runs.contains_all(values),
+            Self::EliasFano { values: ef_values } => ef_values.contains_all(values),
+            Self::DeltaVec { values: dv_values } => dv_values.contains_all(values),
+            Self::SparseBitmap { bitmap } => bitmap.contains_all(values),
         }
     }
 
@@ -246,15 +318,7 @@ impl<'a, L: Level> PartitionRead<L> for NonRecursivePartitionRef<'a, L> {
                 // Full partition has intersection with any non-empty range
                 !RangeExt::is_empty(&values)
             }
-            Self::Bitmap { bitmap } => {
-                if let Some(range) = values.try_into_inclusive() {
-                    let range = (*range.start()).as_()..=(*range.end()).as_();
-                    let slice = bitmap.get(range).unwrap();
-                    slice.any()
-                } else {
-                    false
-                }
-            }
+            Self::Bitmap { bitmap } => bitmap.contains_any(values),
             Self::Vec { values: vec_values } => {
                 if let Some(range) = values.try_into_inclusive() {
                     // Binary search for the start of the range
@@ -270,6 +334,9 @@ impl<'a, L: Level> PartitionRead<L> for NonRecursivePartitionRef<'a, L> {
                 }
             }
             Self::Run { runs } => runs.contains_any(values),
+            Self::EliasFano { values: ef_values } => ef_values.contains_any(values),
+            Self::DeltaVec { values: dv_values } => dv_values.contains_any(values),
+            Self::SparseBitmap { bitmap } => bitmap.contains_any(values),
         }
     }
 }
@@ -282,6 +349,9 @@ impl<'a, L: Level> PartialEq for NonRecursivePartitionRef<'a, L> {
             (Bitmap { bitmap: l }, Bitmap { bitmap: r }) => l == r,
             (Vec { values: l }, Vec { values: r }) => l == r,
             (Run { runs: l }, Run { runs: r }) => l == r,
+            (EliasFano { values: l }, EliasFano { values: r }) => l == r,
+            (DeltaVec { values: l }, DeltaVec { values: r }) => l == r,
+            (SparseBitmap { bitmap: l }, SparseBitmap { bitmap: r }) => l == r,
             (Empty, Empty) => true,
             (Full, Full) => true,
 
@@ -301,11 +371,14 @@ impl<'a, L: Level> From<&NonRecursivePartitionRef<'a, L>> for Partition<L> {
         match value {
             Empty => Partition::EMPTY,
             Full => Partition::Full,
-            Bitmap { bitmap } => Partition::Bitmap((*bitmap).into()),
+            Bitmap { bitmap } => Partition::Bitmap(bitmap.into()),
             Vec { values } => Partition::Vec(VecPartition::from_sorted_unique_unchecked(
                 values.iter().map(|&v| v.into()),
             )),
             Run { runs } => Partition::Run(runs.into()),
+            EliasFano { values } => Partition::EliasFano(values.into()),
+            DeltaVec { values } => Partition::DeltaVec(values.into()),
+            SparseBitmap { bitmap } => Partition::SparseBitmap(bitmap.into()),
         }
     }
 }
@@ -315,6 +388,8 @@ impl<'a, L: Level> From<&NonRecursivePartitionRef<'a, L>> for Partition<L> {
 pub enum PartitionRef<'a, L: Level> {
     NonRecursive(NonRecursivePartitionRef<'a, L>),
     Tree(TreeRef<'a, L>),
+    Prefix(PrefixRef<'a, L>),
+    Inverted(InvertedPartition<NonRecursivePartitionRef<'a, L>>),
 }
 
 impl<'a, L: Level> PartitionRef<'a, L> {
@@ -322,17 +397,109 @@ impl<'a, L: Level> PartitionRef<'a, L> {
         let (data, kind) = PartitionKind::try_read_from_suffix(data)?;
         match kind {
             PartitionKind::Tree => Ok(Self::Tree(TreeRef::from_suffix(data)?)),
+            PartitionKind::Prefix => Ok(Self::Prefix(PrefixRef::from_suffix(data)?)),
+            kind if kind.is_inverted() => Ok(Self::Inverted(InvertedPartition::new(
+                NonRecursivePartitionRef::from_suffix_with_kind(kind.invert(), data)?,
+            ))),
             kind => Ok(Self::NonRecursive(
                 NonRecursivePartitionRef::from_suffix_with_kind(kind, data)?,
             )),
         }
     }
 
-    #[cfg(test)]
+    /// Recursively verifies this partition and all of its children, returning
+    /// an error rather than panicking if the encoding is malformed.
+    pub(crate) fn validate(&self) -> Result<(), DecodeErr> {
+        // without the surrounding buffer the location isn't meaningful
+        self.validate_located(0).map_err(DecodeErr::from)
+    }
+
+    /// Like [`Self::validate`], but locates the first malformed partition,
+    /// given the offset of this partition's last byte.
+    pub(crate) fn validate_located(&self, offset: usize) -> Result<(), LocatedDecodeErr> {
+        let locate =
+            |err| LocatedDecodeErr::new(err, DecodeLocation::new::<L>(offset, Some(self.kind())));
+        match self {
+            Self::NonRecursive(p) => p.validate().map_err(locate),
+            Self::Tree(p) => p.validate_located(locate),
+            Self::Prefix(p) => p.validate_located(locate),
+            Self::Inverted(p) => p.inner().validate().map_err(locate),
+        }
+    }
+
+    /// Decodes the partition at the end of `data` and recursively verifies
+    /// it, locating errors relative to the start of `data`.
+    pub(crate) fn validate_suffix(data: &'a [u8]) -> Result<Self, LocatedDecodeErr> {
+        let offset = data.len().saturating_sub(1);
+        let partition = Self::from_suffix(data).map_err(|err| {
+            let kind = PartitionKind::try_read_from_suffix(data)
+                .ok()
+                .map(|(_, kind)| kind);
+            LocatedDecodeErr::new(err, DecodeLocation::new::<L>(offset, kind))
+        })?;
+        partition.validate_located(offset)?;
+        Ok(partition)
+    }
+
+    /// Verifies the partitions read by an edit of `value`: the index of every
+    /// tree on the path to `value`, and the partition which stores it.
+    pub(crate) fn validate_path(&self, value: L::Value) -> Result<(), DecodeErr> {
+        match self {
+            Self::NonRecursive(p) => p.validate(),
+            Self::Tree(p) => p.validate_path(value),
+            Self::Prefix(p) => p.validate_path(value),
+            Self::Inverted(p) => p.inner().validate(),
+        }
+    }
+
+    /// Plans an in-place edit which inserts or removes `value`, which must
+    /// respectively be missing from or present in this partition.
+    pub(crate) fn plan_edit(
+        &self,
+        value: L::Value,
+        insert: bool,
+        patches: &mut Patches,
+    ) -> Result<(), NeedsReencode> {
+        match self {
+            Self::NonRecursive(p) => p.plan_edit(value, insert, patches),
+            Self::Tree(p) => p.plan_edit(value, insert, patches),
+            Self::Prefix(p) => p.plan_edit(value, insert, patches),
+            // inserting a value removes it from the stored complement
+            Self::Inverted(p) => p.inner().plan_edit(value, !insert, patches),
+        }
+    }
+
+    /// Recursively verifies that no tree partition has more than `max`
+    /// children.
+    pub(crate) fn check_fan_out(&self, max: usize) -> Result<(), DecodeErr> {
+        match self {
+            Self::Tree(p) => p.check_fan_out(max),
+            Self::Prefix(p) => p.check_fan_out(max),
+            Self::NonRecursive(_) | Self::Inverted(_) => Ok(()),
+        }
+    }
+
+    /// Recursively verifies that every bitmap partition would start on an
+    /// 8-byte boundary if the encoded `splinter` containing this partition
+    /// was written at `position`.
+    pub(crate) fn bitmaps_aligned(&self, splinter: &[u8], position: usize) -> bool {
+        match self {
+            Self::NonRecursive(NonRecursivePartitionRef::Bitmap { bitmap }) => {
+                (position + bitmap.offset_in(splinter)).is_multiple_of(size_of::<u64>())
+            }
+            Self::NonRecursive(_) | Self::Inverted(_) => true,
+            Self::Tree(p) => (0..p.num_children())
+                .all(|idx| p.load_child(idx).bitmaps_aligned(splinter, position)),
+            Self::Prefix(p) => p.child().bitmaps_aligned(splinter, position),
+        }
+    }
+
     pub fn kind(&self) -> PartitionKind {
         match self {
             Self::NonRecursive(p) => p.kind(),
             Self::Tree(_) => PartitionKind::Tree,
+            Self::Prefix(_) => PartitionKind::Prefix,
+            Self::Inverted(p) => p.inner().kind().invert(),
         }
     }
 }
@@ -342,6 +509,8 @@ impl<'a, L: Level> PartitionRead<L> for PartitionRef<'a, L> {
         match self {
             Self::NonRecursive(p) => p.cardinality(),
             Self::Tree(p) => p.cardinality(),
+            Self::Prefix(p) => p.cardinality(),
+            Self::Inverted(p) => p.cardinality(),
         }
     }
 
@@ -349,6 +518,8 @@ impl<'a, L: Level> PartitionRead<L> for PartitionRef<'a, L> {
         match self {
             Self::NonRecursive(p) => p.is_empty(),
             Self::Tree(p) => p.is_empty(),
+            Self::Prefix(p) => p.is_empty(),
+            Self::Inverted(p) => p.is_empty(),
         }
     }
 
@@ -356,6 +527,8 @@ impl<'a, L: Level> PartitionRead<L> for PartitionRef<'a, L> {
         match self {
             Self::NonRecursive(p) => p.contains(value),
             Self::Tree(p) => p.contains(value),
+            Self::Prefix(p) => p.contains(value),
+            Self::Inverted(p) => p.contains(value),
         }
     }
 
@@ -363,6 +536,8 @@ impl<'a, L: Level> PartitionRead<L> for PartitionRef<'a, L> {
         match self {
             Self::NonRecursive(p) => p.position(value),
             Self::Tree(p) => p.position(value),
+            Self::Prefix(p) => p.position(value),
+            Self::Inverted(p) => p.position(value),
         }
     }
 
@@ -370,6 +545,8 @@ impl<'a, L: Level> PartitionRead<L> for PartitionRef<'a, L> {
         match self {
             Self::NonRecursive(p) => p.rank(value),
             Self::Tree(p) => p.rank(value),
+            Self::Prefix(p) => p.rank(value),
+            Self::Inverted(p) => p.rank(value),
         }
     }
 
@@ -377,6 +554,8 @@ impl<'a, L: Level> PartitionRead<L> for PartitionRef<'a, L> {
         match self {
             Self::NonRecursive(p) => p.select(idx),
             Self::Tree(p) => p.select(idx),
+            Self::Prefix(p) => p.select(idx),
+            Self::Inverted(p) => p.select(idx),
         }
     }
 
@@ -384,13 +563,17 @@ impl<'a, L: Level> PartitionRead<L> for PartitionRef<'a, L> {
         match self {
             Self::NonRecursive(p) => p.last(),
             Self::Tree(p) => p.last(),
+            Self::Prefix(p) => p.last(),
+            Self::Inverted(p) => p.last(),
         }
     }
 
     fn iter(&self) -> impl Iterator<Item = L::Value> {
         match self {
-            Self::NonRecursive(p) => Either::Left(p.iter()),
-            Self::Tree(p) => Either::Right(p.iter()),
+            Self::NonRecursive(p) => RefIter::NonRecursive(p.iter()),
+            Self::Tree(p) => RefIter::Tree(p.iter()),
+            Self::Prefix(p) => RefIter::Prefix(p.iter()),
+            Self::Inverted(p) => RefIter::Inverted(p.iter()),
         }
     }
 
@@ -398,6 +581,8 @@ impl<'a, L: Level> PartitionRead<L> for PartitionRef<'a, L> {
         match self {
             Self::NonRecursive(p) => p.contains_all(values),
             Self::Tree(p) => p.contains_all(values),
+            Self::Prefix(p) => p.contains_all(values),
+            Self::Inverted(p) => p.contains_all(values),
         }
     }
 
@@ -405,6 +590,8 @@ impl<'a, L: Level> PartitionRead<L> for PartitionRef<'a, L> {
         match self {
             Self::NonRecursive(p) => p.contains_any(values),
             Self::Tree(p) => p.contains_any(values),
+            Self::Prefix(p) => p.contains_any(values),
+            Self::Inverted(p) => p.contains_any(values),
         }
     }
 }
@@ -415,6 +602,8 @@ impl<'a, L: Level> PartialEq for PartitionRef<'a, L> {
             // use fast physical ops if both partitions share storage
             (Self::NonRecursive(l0), Self::NonRecursive(r0)) => l0 == r0,
             (Self::Tree(l0), Self::Tree(r0)) => l0 == r0,
+            (Self::Prefix(l0), Self::Prefix(r0)) => l0 == r0,
+            (Self::Inverted(l0), Self::Inverted(r0)) => l0 == r0,
 
             // otherwise fall back to logical ops
             (a, b) => itertools::equal(a.iter(), b.iter()),
@@ -422,7 +611,18 @@ impl<'a, L: Level> PartialEq for PartitionRef<'a, L> {
     }
 }
 
-MultiIter!(Iter, Empty, Full, Bitmap, Vec, Run);
+MultiIter!(
+    Iter,
+    Empty,
+    Full,
+    Bitmap,
+    Vec,
+    Run,
+    EliasFano,
+    DeltaVec,
+    SparseBitmap
+);
+MultiIter!(RefIter, NonRecursive, Tree, Prefix, Inverted);
 
 impl<'a, L: Level> IntoIterator for NonRecursivePartitionRef<'a, L> {
     type Item = L::Value;
@@ -433,9 +633,12 @@ impl<'a, L: Level> IntoIterator for NonRecursivePartitionRef<'a, L> {
         match self {
             Self::Empty => Box::new(std::iter::empty()),
             Self::Full => Box::new((0..L::MAX_LEN).map(L::Value::truncate_from)),
-            Self::Bitmap { bitmap } => Box::new(bitmap.iter_ones().map(L::Value::truncate_from)),
+            Self::Bitmap { bitmap } => Box::new(bitmap.into_iter()),
             Self::Vec { values } => Box::new(values.iter().map(|&v| v.into())),
             Self::Run { runs } => Box::new(runs.into_iter()),
+            Self::EliasFano { values } => Box::new(values.into_iter()),
+            Self::DeltaVec { values } => Box::new(values.into_iter()),
+            Self::SparseBitmap { bitmap } => Box::new(bitmap.into_iter()),
         }
     }
 }
@@ -449,6 +652,16 @@ impl<'a, L: Level> IntoIterator for PartitionRef<'a, L> {
         match self {
             Self::NonRecursive(p) => p.into_iter(),
             Self::Tree(tree_ref) => tree_ref.into_iter(),
+            Self::Prefix(prefix_ref) => prefix_ref.into_iter(),
+            Self::Inverted(p) => {
+                let cardinality = p.cardinality();
+                Box::new(
+                    MergeRuns::new(p.into_inner().into_iter())
+                        .complement()
+                        .flat_map(RangeIter::new)
+                        .with_size_hint(cardinality),
+                )
+            }
         }
     }
 }
@@ -458,9 +671,14 @@ mod test {
     use itertools::Itertools;
 
     use crate::{
-        codec::partition_ref::{NonRecursivePartitionRef, PartitionRef},
-        level::Block,
-        testutil::test_partition_read,
+        SplinterRef, assert_error,
+        codec::{
+            DecodeErr,
+            partition_ref::{NonRecursivePartitionRef, PartitionRef},
+        },
+        level::{Block, High},
+        partition_kind::PartitionKind,
+        testutil::{mkpartition_buf, mksplinter_manual, test_partition_read},
     };
 
     #[test]
@@ -468,4 +686,20 @@ mod test {
         let block = PartitionRef::<'_, Block>::NonRecursive(NonRecursivePartitionRef::Full);
         test_partition_read(&block, &(0..=255).collect_vec());
     }
+
+    #[test]
+    fn test_validate_unsorted_vec() {
+        let mut buf = mkpartition_buf::<High>(PartitionKind::Tree, &[1, 2]);
+
+        // swap the two child values
+        buf[2] = 0x02;
+        buf[5] = 0x01;
+        let corrupted = mksplinter_manual(&buf);
+
+        assert!(SplinterRef::from_bytes(corrupted.clone()).is_ok());
+        assert_error!(
+            SplinterRef::from_bytes_validated(corrupted.clone()),
+            DecodeErr::Validity
+        );
+    }
 }
@@ -0,0 +1,313 @@
+use std::{borrow::Borrow, fmt::Debug};
+
+use num::traits::Bounded;
+
+use crate::{
+    PartitionRead,
+    codec::{DecodeErr, LocatedDecodeErr, partition_ref::PartitionRef},
+    level::Level,
+    segment::{Segment, SplitSegment},
+    splinter_mut::{NeedsReencode, Patches},
+    util::{IteratorExt, RangeExt},
+};
+
+/// A path-compressed tree partition, which stores its only child followed by
+/// the child's segment rather than a tree index.
+#[derive(Clone)]
+pub struct PrefixRef<'a, L: Level> {
+    segment: Segment,
+    /// the number of bytes before the segment, which end with the child
+    child_len: usize,
+    child: Box<L::PrefixChild<'a>>,
+}
+
+impl<'a, L: Level> PrefixRef<'a, L> {
+    /// Decodes the segment and the child partition which precedes it.
+    pub(super) fn from_suffix(data: &'a [u8]) -> Result<Self, DecodeErr> {
+        // only levels which may contain trees can be path-compressed, which
+        // also bounds the recursion into nested prefix partitions
+        if !L::ALLOW_TREE {
+            return Err(DecodeErr::Validity);
+        }
+        let (&segment, child) = data.split_last().ok_or(DecodeErr::Length)?;
+        Ok(Self {
+            segment,
+            child_len: child.len(),
+            child: Box::new(PartitionRef::from_suffix(child)?.into()),
+        })
+    }
+
+    /// Recursively verifies the child partition. Errors in the prefix itself
+    /// are located by `locate`, while errors in the child are located within
+    /// the child's segment.
+    pub(crate) fn validate_located(
+        &self,
+        locate: impl Fn(DecodeErr) -> LocatedDecodeErr,
+    ) -> Result<(), LocatedDecodeErr> {
+        self.child()
+            .validate_located(self.child_len.saturating_sub(1))
+            .map_err(|err| err.within(0, self.segment))?;
+        if self.child().is_empty() {
+            return Err(locate(DecodeErr::Validity));
+        }
+        Ok(())
+    }
+
+    /// Verifies the path to `value` through the child, if it shares the
+    /// prefix's segment.
+    pub(crate) fn validate_path(&self, value: L::Value) -> Result<(), DecodeErr> {
+        let (segment, value) = value.split();
+        if segment != self.segment {
+            return Ok(());
+        }
+        self.child().validate_path(value)?;
+        if self.child().is_empty() {
+            return Err(DecodeErr::Validity);
+        }
+        Ok(())
+    }
+
+    /// Recursively verifies that no nested tree has more than `max` children.
+    pub(crate) fn check_fan_out(&self, max: usize) -> Result<(), DecodeErr> {
+        self.child().check_fan_out(max)
+    }
+
+    /// Plans an in-place edit which inserts or removes `value`, which must
+    /// respectively be missing from or present in this partition.
+    pub(crate) fn plan_edit(
+        &self,
+        value: L::Value,
+        insert: bool,
+        patches: &mut Patches,
+    ) -> Result<(), NeedsReencode> {
+        let (segment, value) = value.split();
+        // adding a second child or removing the only one changes the kind
+        if segment != self.segment {
+            return Err(NeedsReencode);
+        }
+        if !insert && self.child().cardinality() == 1 {
+            return Err(NeedsReencode);
+        }
+        self.child().plan_edit(value, insert, patches)
+    }
+
+    /// The segment shared by every value in this partition.
+    #[inline]
+    pub(crate) fn segment(&self) -> Segment {
+        self.segment
+    }
+
+    /// The number of bytes used by the child partition, which starts at the
+    /// beginning of the data this partition was decoded from.
+    #[inline]
+    pub(crate) fn child_len(&self) -> usize {
+        self.child_len
+    }
+
+    #[inline]
+    pub(crate) fn child(&self) -> &PartitionRef<'a, L::LevelDown> {
+        (*self.child).borrow()
+    }
+
+    #[inline]
+    pub(crate) fn into_child(self) -> PartitionRef<'a, L::LevelDown> {
+        (*self.child).into()
+    }
+}
+
+impl<'a, L: Level> PartitionRead<L> for PrefixRef<'a, L> {
+    fn cardinality(&self) -> usize {
+        self.child().cardinality()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.child().is_empty()
+    }
+
+    fn contains(&self, value: L::Value) -> bool {
+        let (segment, value) = value.split();
+        segment == self.segment && self.child().contains(value)
+    }
+
+    fn position(&self, value: L::Value) -> Option<usize> {
+        let (segment, value) = value.split();
+        if segment == self.segment {
+            self.child().position(value)
+        } else {
+            None
+        }
+    }
+
+    fn rank(&self, value: L::Value) -> usize {
+        let (segment, value) = value.split();
+        match segment.cmp(&self.segment) {
+            std::cmp::Ordering::Less => 0,
+            std::cmp::Ordering::Equal => self.child().rank(value),
+            std::cmp::Ordering::Greater => self.cardinality(),
+        }
+    }
+
+    fn select(&self, n: usize) -> Option<L::Value> {
+        let segment = self.segment;
+        self.child()
+            .select(n)
+            .map(|v| L::Value::unsplit(segment, v))
+    }
+
+    fn last(&self) -> Option<L::Value> {
+        let segment = self.segment;
+        self.child().last().map(|v| L::Value::unsplit(segment, v))
+    }
+
+    fn iter(&self) -> impl Iterator<Item = L::Value> {
+        self.clone().into_iter()
+    }
+
+    fn contains_all<R: std::ops::RangeBounds<L::Value>>(&self, values: R) -> bool {
+        if let Some(values) = values.try_into_inclusive() {
+            let (start, end) = (*values.start(), *values.end());
+            // every value must share this partition's segment
+            start.segment() == self.segment
+                && end.segment() == self.segment
+                && self.child().contains_all(start.rest()..=end.rest())
+        } else {
+            // empty range is trivially contained
+            true
+        }
+    }
+
+    fn contains_any<R: std::ops::RangeBounds<L::Value>>(&self, values: R) -> bool {
+        if let Some(values) = values.try_into_inclusive() {
+            let (start, end) = (*values.start(), *values.end());
+            if start.segment() > self.segment || end.segment() < self.segment {
+                return false;
+            }
+
+            // clamp the range to this partition's segment
+            let start = if start.segment() == self.segment {
+                start.rest()
+            } else {
+                Bounded::min_value()
+            };
+            let end = if end.segment() == self.segment {
+                end.rest()
+            } else {
+                Bounded::max_value()
+            };
+            self.child().contains_any(start..=end)
+        } else {
+            // empty range has no intersection
+            false
+        }
+    }
+}
+
+impl<'a, L: Level + 'a> IntoIterator for PrefixRef<'a, L> {
+    type Item = L::Value;
+
+    type IntoIter = Box<dyn Iterator<Item = L::Value> + 'a>;
+
+    fn into_iter(self) -> Self::IntoIter {
+        let segment = self.segment;
+        let child = self.into_child();
+        let cardinality = child.cardinality();
+        Box::new(
+            child
+                .into_iter()
+                .map(move |v| L::Value::unsplit(segment, v))
+                .with_size_hint(cardinality),
+        )
+    }
+}
+
+impl<'a, L: Level> PartialEq for PrefixRef<'a, L> {
+    fn eq(&self, other: &Self) -> bool {
+        self.segment == other.segment && self.child() == other.child()
+    }
+}
+
+impl<'a, L: Level> Eq for PrefixRef<'a, L> {}
+
+impl<'a, L: Level> Debug for PrefixRef<'a, L> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PrefixRef")
+            .field("segment", &self.segment)
+            .field("child", self.child())
+            .finish()
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+
+    use crate::{
+        SplinterRef, assert_error,
+        codec::{DecodeErr, PartitionLevel, partition_ref::PartitionRef},
+        level::{Block, Low},
+        partition_kind::PartitionKind,
+        testutil::{mkpartition_buf, test_partition_read},
+    };
+
+    #[test]
+    fn test_decode_malformed_prefix_root() {
+        // a checksummed prefix root whose child is malformed
+        let buf = [
+            168, 187, 238, 66, 0, 0, 4, 3, 85, 0, 21, 32, 0, 0, 0, 89, 17, 167, 233,
+        ];
+        assert_error!(SplinterRef::from_bytes(&buf[..]), DecodeErr::Validity);
+        assert_error!(
+            SplinterRef::from_bytes_validated(&buf[..]),
+            DecodeErr::Validity
+        );
+    }
+
+    #[test]
+    fn test_prefix_byteorder() {
+        let buf = mkpartition_buf::<Low>(PartitionKind::Prefix, &[0x01_00, 0x01_02]);
+        assert_eq!(
+            buf.as_ref(),
+            &[
+                0x00, 0x02, // child values
+                0x01, // child length
+                0x03, // child kind
+                0x01, // segment
+                0x15, // kind
+            ]
+        );
+    }
+
+    #[test]
+    fn test_validate_prefix() {
+        let buf = mkpartition_buf::<Low>(PartitionKind::Prefix, &[0x01_00, 0x01_02]);
+        let partition = PartitionRef::<Low>::from_suffix(&buf).unwrap();
+        assert!(partition.validate().is_ok());
+        test_partition_read(&partition, &[0x01_00, 0x01_02]);
+
+        // Block partitions may never be encoded as prefixes
+        assert_error!(
+            PartitionRef::<Block>::from_suffix(&buf),
+            DecodeErr::Validity
+        );
+
+        // the child is decoded along with the prefix
+        assert_error!(
+            PartitionRef::<Low>::from_suffix(&buf[1..]),
+            DecodeErr::Length
+        );
+
+        // the child may not be empty
+        let partition = PartitionRef::<Low>::from_suffix(&[0x00, 0x01, 0x15]).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+
+        // errors in the child are located within the prefix's segment
+        let mut corrupted = buf.to_vec();
+        corrupted[..2].copy_from_slice(&[0x02, 0x00]);
+        let err = PartitionRef::<Low>::validate_suffix(&corrupted).unwrap_err();
+        assert_matches!(err.err, DecodeErr::Validity);
+        let location = err.location.unwrap();
+        assert_eq!(location.level, PartitionLevel::Block);
+        assert_eq!(location.path, vec![1]);
+        assert_eq!(location.kind, Some(PartitionKind::Vec));
+    }
+}
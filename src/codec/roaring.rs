@@ -0,0 +1,510 @@
+//! Conversion to and from the [Roaring portable serialization format].
+//!
+//! A Roaring bitmap stores the low 16 bits of each value in a container keyed
+//! by the high 16 bits. The high and mid segments of a Splinter value are
+//! exactly the container key, so each container maps directly onto a
+//! `Partition<Low>`: array containers become Vec partitions, bitmap containers
+//! become Bitmap partitions and run containers become Run partitions.
+//!
+//! [Roaring portable serialization format]: https://github.com/RoaringBitmap/RoaringFormatSpec
+
+use std::{collections::BTreeMap, iter, ops::RangeInclusive};
+
+use bitvec::{order::Lsb0, slice::BitSlice};
+use bytes::BufMut;
+use either::Either;
+use itertools::Itertools;
+use range_set_blaze::CheckSortedDisjoint;
+use zerocopy::{FromBytes, Immutable, KnownLayout, LittleEndian, U16, U32, U64, Unaligned};
+
+use crate::{
+    PartitionRead,
+    codec::DecodeErr,
+    level::{High, Level, Low, Mid},
+    partition::{
+        Partition,
+        bitmap::BitmapPartition,
+        run::{MergeRuns, RunPartition},
+        tree::TreePartition,
+        vec::VecPartition,
+    },
+    segment::Segment,
+};
+
+/// The cookie of a bitmap containing run containers. The upper 16 bits of
+/// the cookie store the number of containers minus one.
+const SERIAL_COOKIE: u32 = 12347;
+
+/// The cookie of a bitmap without run containers, followed by a u32
+/// container count.
+const SERIAL_COOKIE_NO_RUNCONTAINER: u32 = 12346;
+
+/// Bitmaps with run containers only include container offsets when they
+/// have at least this many containers.
+const NO_OFFSET_THRESHOLD: usize = 4;
+
+/// The maximum cardinality of an array container.
+const ARRAY_MAX_CARDINALITY: usize = 4096;
+
+/// The number of u64 words in a bitmap container.
+const BITMAP_WORDS: usize = Low::MAX_LEN / 64;
+
+#[derive(FromBytes, Immutable, Unaligned, KnownLayout)]
+#[repr(C)]
+struct Descriptor {
+    key: U16<LittleEndian>,
+    /// cardinality minus one
+    cardinality: U16<LittleEndian>,
+}
+
+#[derive(FromBytes, Immutable, Unaligned, KnownLayout)]
+#[repr(C)]
+struct Interval {
+    start: U16<LittleEndian>,
+    /// length minus one
+    length: U16<LittleEndian>,
+}
+
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum ContainerKind {
+    Array,
+    Bitmap,
+    Run,
+}
+
+impl ContainerKind {
+    /// Returns the array or bitmap kind, which Roaring readers infer from the
+    /// cardinality of non-run containers.
+    fn from_cardinality(cardinality: usize) -> Self {
+        if cardinality <= ARRAY_MAX_CARDINALITY {
+            Self::Array
+        } else {
+            Self::Bitmap
+        }
+    }
+
+    /// Selects the container kind used to store a Low partition.
+    fn select(partition: &Partition<Low>) -> Self {
+        let inferred = Self::from_cardinality(partition.cardinality());
+        match partition {
+            Partition::Full | Partition::Run(_) => Self::Run,
+            Partition::Vec(_)
+            | Partition::Bitmap(_)
+            | Partition::EliasFano(_)
+            | Partition::DeltaVec(_)
+            | Partition::SparseBitmap(_) => inferred,
+            Partition::Tree(_) | Partition::Prefix(_) | Partition::Inverted(_) => {
+                if Self::Run.encoded_size(partition) < inferred.encoded_size(partition) {
+                    Self::Run
+                } else {
+                    inferred
+                }
+            }
+        }
+    }
+
+    fn encoded_size(self, partition: &Partition<Low>) -> usize {
+        match self {
+            Self::Array => partition.cardinality() * size_of::<u16>(),
+            Self::Bitmap => BITMAP_WORDS * size_of::<u64>(),
+            Self::Run => size_of::<u16>() + partition.count_runs() * size_of::<Interval>(),
+        }
+    }
+}
+
+/// Calls `f` with the key and partition of every non-empty container in
+/// ascending key order.
+fn for_each_container(partition: &Partition<High>, mut f: impl FnMut(u16, &Partition<Low>)) {
+    for (high, mid) in partition.to_tree().iter_children() {
+        for (low, container) in mid.to_tree().iter_children() {
+            if !container.is_empty() {
+                f(u16::from_be_bytes([high, low]), container);
+            }
+        }
+    }
+}
+
+fn iter_runs(partition: &Partition<Low>) -> impl Iterator<Item = RangeInclusive<u16>> {
+    match partition {
+        Partition::Full => Either::Left(Either::Left(iter::once(0..=u16::MAX))),
+        Partition::Run(runs) => Either::Left(Either::Right(runs.iter_runs())),
+        other => Either::Right(MergeRuns::new(other.iter())),
+    }
+}
+
+/// Encodes a partition in the Roaring portable serialization format.
+pub(crate) fn encode<B: BufMut>(partition: &Partition<High>, buf: &mut B) {
+    let mut descriptors = Vec::new();
+    for_each_container(partition, |key, container| {
+        descriptors.push((
+            key,
+            container.cardinality(),
+            ContainerKind::select(container),
+        ));
+    });
+
+    // Encoding order: [cookie][descriptors][offsets][containers]
+    let count = descriptors.len();
+    let has_runs = descriptors
+        .iter()
+        .any(|&(_, _, kind)| kind == ContainerKind::Run);
+    let mut offset = count * size_of::<Descriptor>();
+    if has_runs {
+        let mut run_flags = vec![0u8; count.div_ceil(8)];
+        for (i, &(_, _, kind)) in descriptors.iter().enumerate() {
+            if kind == ContainerKind::Run {
+                run_flags[i / 8] |= 1 << (i % 8);
+            }
+        }
+        buf.put_u32_le(SERIAL_COOKIE | ((count as u32 - 1) << 16));
+        buf.put_slice(&run_flags);
+        offset += size_of::<u32>() + run_flags.len();
+    } else {
+        buf.put_u32_le(SERIAL_COOKIE_NO_RUNCONTAINER);
+        buf.put_u32_le(count as u32);
+        offset += size_of::<u32>() * 2;
+    }
+
+    for &(key, cardinality, _) in &descriptors {
+        buf.put_u16_le(key);
+        buf.put_u16_le((cardinality - 1) as u16);
+    }
+
+    let kinds = || descriptors.iter().map(|&(_, _, kind)| kind);
+    if !has_runs || count >= NO_OFFSET_THRESHOLD {
+        offset += count * size_of::<u32>();
+        let mut kinds = kinds();
+        for_each_container(partition, |_, container| {
+            buf.put_u32_le(offset as u32);
+            offset += kinds.next().unwrap().encoded_size(container);
+        });
+    }
+
+    let mut kinds = kinds();
+    for_each_container(partition, |_, container| match kinds.next().unwrap() {
+        ContainerKind::Array => {
+            for value in container.iter() {
+                buf.put_u16_le(value);
+            }
+        }
+        ContainerKind::Bitmap => {
+            if let Partition::Bitmap(bitmap) = container {
+                for &word in bitmap.as_bitbox().as_raw_slice() {
+                    buf.put_u64_le(word);
+                }
+            } else {
+                let mut words = [0u64; BITMAP_WORDS];
+                for value in container.iter() {
+                    words[usize::from(value / 64)] |= 1 << (value % 64);
+                }
+                for word in words {
+                    buf.put_u64_le(word);
+                }
+            }
+        }
+        ContainerKind::Run => {
+            buf.put_u16_le(container.count_runs() as u16);
+            for run in iter_runs(container) {
+                buf.put_u16_le(*run.start());
+                buf.put_u16_le(run.end() - run.start());
+            }
+        }
+    });
+}
+
+/// Decodes a partition from the Roaring portable serialization format.
+pub(crate) fn decode(data: &[u8]) -> Result<Partition<High>, DecodeErr> {
+    let (cookie, data) = U32::<LittleEndian>::read_from_prefix(data)?;
+    let cookie = cookie.get();
+    let (count, run_flags, data) = if cookie & 0xFFFF == SERIAL_COOKIE {
+        let count = (cookie >> 16) as usize + 1;
+        let (run_flags, data) = <[u8]>::ref_from_prefix_with_elems(data, count.div_ceil(8))?;
+        (count, Some(run_flags), data)
+    } else if cookie == SERIAL_COOKIE_NO_RUNCONTAINER {
+        let (count, data) = U32::<LittleEndian>::read_from_prefix(data)?;
+        (count.get() as usize, None, data)
+    } else {
+        return Err(DecodeErr::Magic);
+    };
+    if count > Low::MAX_LEN {
+        return Err(DecodeErr::Validity);
+    }
+
+    let (descriptors, mut data) = <[Descriptor]>::ref_from_prefix_with_elems(data, count)?;
+    if run_flags.is_none() || count >= NO_OFFSET_THRESHOLD {
+        // containers are stored in order, so the offsets aren't needed
+        (_, data) = <[U32<LittleEndian>]>::ref_from_prefix_with_elems(data, count)?;
+    }
+
+    let mut children = BTreeMap::<Segment, BTreeMap<Segment, Partition<Low>>>::new();
+    let mut last_key = None;
+    for (i, descriptor) in descriptors.iter().enumerate() {
+        let key = descriptor.key.get();
+        if last_key.is_some_and(|last_key| last_key >= key) {
+            return Err(DecodeErr::Validity);
+        }
+        last_key = Some(key);
+
+        let cardinality = usize::from(descriptor.cardinality.get()) + 1;
+        let is_run = run_flags.is_some_and(|flags| flags[i / 8] & (1 << (i % 8)) != 0);
+        let kind = if is_run {
+            ContainerKind::Run
+        } else {
+            ContainerKind::from_cardinality(cardinality)
+        };
+        let (container, rest) = decode_container(kind, cardinality, data)?;
+        if container.cardinality() != cardinality {
+            return Err(DecodeErr::Validity);
+        }
+        data = rest;
+
+        let [high, low] = key.to_be_bytes();
+        children.entry(high).or_default().insert(low, container);
+    }
+    if !data.is_empty() {
+        return Err(DecodeErr::Validity);
+    }
+
+    if children.is_empty() {
+        return Ok(Partition::EMPTY);
+    }
+    let children = children
+        .into_iter()
+        .map(|(segment, children)| {
+            let child = Partition::Tree(TreePartition::<Mid>::from(children));
+            (segment, child)
+        })
+        .collect::<BTreeMap<_, _>>();
+    Ok(Partition::Tree(TreePartition::from(children)))
+}
+
+/// Decodes a single container from the start of data, returning the
+/// remaining data.
+fn decode_container(
+    kind: ContainerKind,
+    cardinality: usize,
+    data: &[u8],
+) -> Result<(Partition<Low>, &[u8]), DecodeErr> {
+    match kind {
+        ContainerKind::Array => {
+            let (values, data) =
+                <[U16<LittleEndian>]>::ref_from_prefix_with_elems(data, cardinality)?;
+            if !values
+                .iter()
+                .tuple_windows()
+                .all(|(a, b)| a.get() < b.get())
+            {
+                return Err(DecodeErr::Validity);
+            }
+            let values = values.iter().map(|v| v.get());
+            let partition = VecPartition::from_sorted_unique_unchecked(values);
+            Ok((Partition::Vec(partition), data))
+        }
+        ContainerKind::Bitmap => {
+            let (words, data) =
+                <[U64<LittleEndian>]>::ref_from_prefix_with_elems(data, BITMAP_WORDS)?;
+            let words = words.iter().map(|w| w.get()).collect_vec();
+            let bits = BitSlice::<u64, Lsb0>::from_slice(&words);
+            Ok((Partition::Bitmap(BitmapPartition::from(bits)), data))
+        }
+        ContainerKind::Run => {
+            let (num_runs, data) = U16::<LittleEndian>::read_from_prefix(data)?;
+            let (intervals, data) =
+                <[Interval]>::ref_from_prefix_with_elems(data, num_runs.get().into())?;
+
+            let mut runs = Vec::with_capacity(intervals.len());
+            for interval in intervals {
+                let start = interval.start.get();
+                let end = start
+                    .checked_add(interval.length.get())
+                    .ok_or(DecodeErr::Validity)?;
+                if runs
+                    .last()
+                    .is_some_and(|run: &RangeInclusive<u16>| start <= *run.end())
+                {
+                    return Err(DecodeErr::Validity);
+                }
+                runs.push(start..=end);
+            }
+            // Roaring permits adjacent runs, which we merge
+            let runs = runs.into_iter().coalesce(|a, b| {
+                if a.end() + 1 == *b.start() {
+                    Ok(*a.start()..=*b.end())
+                } else {
+                    Err((a, b))
+                }
+            });
+            let runs = RunPartition::from_sorted_disjoint(CheckSortedDisjoint::new(runs));
+            Ok((Partition::Run(runs), data))
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use assert_matches::assert_matches;
+    use itertools::Itertools;
+    use proptest::{collection::vec, proptest};
+    use roaring::RoaringBitmap;
+
+    use crate::{
+        Optimizable, PartitionRead, Splinter,
+        codec::DecodeErr,
+        testutil::{LevelSetGen, SetGen},
+    };
+
+    fn encode_roaring(bitmap: &RoaringBitmap) -> Vec<u8> {
+        let mut buf = Vec::new();
+        bitmap.serialize_into(&mut buf).unwrap();
+        buf
+    }
+
+    fn assert_roaring_roundtrip(values: &[u32]) {
+        let expected = values.iter().copied().sorted().dedup().collect_vec();
+
+        // Roaring -> Splinter, both with and without run containers
+        let mut bitmap = RoaringBitmap::from_iter(values.iter().copied());
+        for _ in 0..2 {
+            let splinter = Splinter::from_roaring_bytes(&encode_roaring(&bitmap)).unwrap();
+            assert_eq!(splinter.iter().collect_vec(), expected);
+            bitmap.optimize();
+        }
+
+        // Splinter -> Roaring, both before and after optimizing
+        let mut splinter = Splinter::from_iter(values.iter().copied());
+        for _ in 0..2 {
+            let mut buf = Vec::new();
+            splinter.encode_roaring(&mut buf);
+            let decoded = RoaringBitmap::deserialize_from(&buf[..]).unwrap();
+            assert_eq!(decoded.iter().collect_vec(), expected);
+            assert_eq!(
+                Splinter::from_roaring_bytes(&buf)
+                    .unwrap()
+                    .iter()
+                    .collect_vec(),
+                expected
+            );
+            splinter.optimize();
+        }
+    }
+
+    #[test]
+    fn test_roaring_edge_cases() {
+        assert_roaring_roundtrip(&[]);
+        assert_roaring_roundtrip(&[0]);
+        assert_roaring_roundtrip(&[u32::MAX]);
+        assert_roaring_roundtrip(&[0, u32::MAX]);
+        assert_roaring_roundtrip(&(0..=65535).collect_vec());
+        assert_roaring_roundtrip(&(0..4096).collect_vec());
+        assert_roaring_roundtrip(&(0..4097).step_by(2).collect_vec());
+        assert_roaring_roundtrip(&(0..8194).step_by(2).collect_vec());
+        assert_roaring_roundtrip(&(65535..=(1 << 20)).step_by(3).collect_vec());
+        assert_roaring_roundtrip(&(0..(1 << 18)).filter(|v| v % 1024 != 7).collect_vec());
+    }
+
+    #[test]
+    fn test_roaring_distributions() {
+        let mut set_gen = SetGen::new(0xDEAD_BEEF);
+        assert_roaring_roundtrip(&set_gen.distributed(1, 1, 16, 256));
+        assert_roaring_roundtrip(&set_gen.distributed(4, 8, 8, 128));
+        assert_roaring_roundtrip(&set_gen.distributed(256, 16, 1, 1));
+        assert_roaring_roundtrip(&set_gen.dense(8, 8, 8, 8));
+        assert_roaring_roundtrip(&set_gen.random(4096));
+        assert_roaring_roundtrip(&set_gen.random_max(60000, 65536));
+
+        let mut set_gen = LevelSetGen::<crate::level::High>::new(0xDEAD_BEEF);
+        assert_roaring_roundtrip(&set_gen.runs(4096, 0.01));
+        assert_roaring_roundtrip(&set_gen.runs(4096, 0.9));
+    }
+
+    #[test]
+    fn test_roaring_full_splinter() {
+        let mut buf = Vec::new();
+        Splinter::FULL.encode_roaring(&mut buf);
+        let decoded = RoaringBitmap::deserialize_from(&buf[..]).unwrap();
+        assert!(decoded.is_full());
+        assert_eq!(
+            Splinter::from_roaring_bytes(&buf).unwrap().cardinality(),
+            1 << 32
+        );
+    }
+
+    #[test]
+    fn test_roaring_adjacent_runs() {
+        // cookie, run flags, descriptor, [num_runs, (start, len-1) x 2]
+        let buf = [
+            0x3B, 0x30, 0x00, 0x00, 0x01, 0x00, 0x00, 0x03, 0x00, 0x02, 0x00, 0x00, 0x00, 0x01,
+            0x00, 0x02, 0x00, 0x01, 0x00,
+        ];
+        let splinter = Splinter::from_roaring_bytes(&buf).unwrap();
+        assert_eq!(splinter.iter().collect_vec(), [0, 1, 2, 3]);
+    }
+
+    #[test]
+    fn test_roaring_decode_errors() {
+        let buf = encode_roaring(&RoaringBitmap::from_iter([1, 2, 3, 1 << 20]));
+
+        assert_matches!(Splinter::from_roaring_bytes(&[]), Err(DecodeErr::Length));
+        assert_matches!(
+            Splinter::from_roaring_bytes(&[0, 0, 0, 0]),
+            Err(DecodeErr::Magic)
+        );
+        for len in 0..buf.len() {
+            assert_matches!(
+                Splinter::from_roaring_bytes(&buf[..len]),
+                Err(DecodeErr::Length | DecodeErr::Magic)
+            );
+        }
+
+        // trailing bytes
+        let mut trailing = buf.clone();
+        trailing.push(0);
+        assert_matches!(
+            Splinter::from_roaring_bytes(&trailing),
+            Err(DecodeErr::Validity)
+        );
+
+        // header: cookie, count, descriptors (key, cardinality-1), offsets
+        let key_offset = 8;
+        let card_offset = 10;
+        let values_offset = 8 + 2 * 4 + 2 * 4;
+
+        // keys out of order
+        let mut unsorted = buf.clone();
+        unsorted[key_offset..key_offset + 2].copy_from_slice(&100u16.to_le_bytes());
+        assert_matches!(
+            Splinter::from_roaring_bytes(&unsorted),
+            Err(DecodeErr::Validity)
+        );
+
+        // array values out of order
+        let mut unsorted = buf.clone();
+        unsorted[values_offset] = 5;
+        assert_matches!(
+            Splinter::from_roaring_bytes(&unsorted),
+            Err(DecodeErr::Validity)
+        );
+
+        // cardinality doesn't match the container
+        let mut bitmap = RoaringBitmap::from_iter(0..100);
+        bitmap.optimize();
+        let mut mismatch = encode_roaring(&bitmap);
+        mismatch[card_offset - 3] = 0;
+        assert_matches!(
+            Splinter::from_roaring_bytes(&mismatch),
+            Err(DecodeErr::Validity)
+        );
+    }
+
+    proptest! {
+        #[test]
+        fn test_roaring_proptest(values in vec(0u32..(1 << 20), 0..1024)) {
+            assert_roaring_roundtrip(&values);
+        }
+
+        #[test]
+        fn test_roaring_sparse_proptest(values in vec(proptest::num::u32::ANY, 0..512)) {
+            assert_roaring_roundtrip(&values);
+        }
+    }
+}
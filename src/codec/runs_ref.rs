@@ -1,5 +1,6 @@
 use std::{iter::FusedIterator, ops::RangeInclusive};
 
+use num::traits::ConstOne;
 use range_set_blaze::{SortedDisjoint, SortedStarts};
 use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, Unaligned};
 
@@ -8,6 +9,7 @@ use crate::{
     codec::{DecodeErr, partition_ref::decode_len_from_suffix},
     level::Level,
     partition::run::{Run, run_position, run_rank, run_select},
+    splinter_mut::{NeedsReencode, Patches},
     util::IteratorExt,
 };
 
@@ -27,6 +29,64 @@ impl<'a, L: Level> RunsRef<'a, L> {
         })
     }
 
+    /// Verifies that every run is well formed and that runs are sorted,
+    /// disjoint, and non-adjacent.
+    pub(crate) fn validate(&self) -> Result<(), DecodeErr> {
+        let mut prev_end: Option<L::Value> = None;
+        for run in self.ranges() {
+            let (start, end) = (*run.start(), *run.end());
+            if start > end {
+                return Err(DecodeErr::Validity);
+            }
+            if let Some(prev_end) = prev_end {
+                if start <= prev_end || start - prev_end == L::Value::ONE {
+                    return Err(DecodeErr::Validity);
+                }
+            }
+            prev_end = Some(end);
+        }
+        Ok(())
+    }
+
+    /// Plans an in-place edit which inserts or removes `value`, which must
+    /// respectively be missing from or present in these runs. Only edits
+    /// which grow or shrink a single run by one value fit in place.
+    pub(crate) fn plan_edit(
+        &self,
+        value: L::Value,
+        insert: bool,
+        patches: &mut Patches,
+    ) -> Result<(), NeedsReencode> {
+        let idx = self.runs.partition_point(|run| run.end.into() < value);
+        if insert {
+            let prev = idx.checked_sub(1).map(|idx| &self.runs[idx]);
+            let next = self.runs.get(idx);
+            let extends_prev = prev.is_some_and(|run| run.end.into() + L::Value::ONE == value);
+            let extends_next = next.is_some_and(|run| value + L::Value::ONE == run.start.into());
+            match (prev, next) {
+                // merging two runs would remove a run
+                _ if extends_prev && extends_next => return Err(NeedsReencode),
+                (Some(run), _) if extends_prev => patches.add(run.end.as_bytes(), 1),
+                (_, Some(run)) if extends_next => patches.add(run.start.as_bytes(), -1),
+                _ => return Err(NeedsReencode),
+            }
+        } else {
+            let run = &self.runs[idx];
+            let (start, end) = (run.start.into(), run.end.into());
+            if start == end {
+                return Err(NeedsReencode);
+            } else if value == start {
+                patches.add(run.start.as_bytes(), 1);
+            } else if value == end {
+                patches.add(run.end.as_bytes(), -1);
+            } else {
+                // removing a value from the middle splits the run
+                return Err(NeedsReencode);
+            }
+        }
+        Ok(())
+    }
+
     pub fn ranges(&self) -> RangesIter<'_, L> {
         RangesIter { inner: self.runs.iter() }
     }
@@ -178,3 +238,43 @@ impl<L: Level> FusedIterator for RangesIter<'_, L> {}
 impl<L: Level> SortedStarts<L::Value> for RangesIter<'_, L> {}
 impl<L: Level> SortedDisjoint<L::Value> for RangesIter<'_, L> {}
 impl<L: Level> ExactSizeIterator for RangesIter<'_, L> {}
+
+#[cfg(test)]
+mod test {
+
+    use crate::{
+        assert_error,
+        codec::{DecodeErr, partition_ref::PartitionRef},
+        level::Low,
+        partition_kind::PartitionKind,
+        testutil::mkpartition_buf,
+    };
+
+    #[test]
+    fn test_validate_overlapping_runs() {
+        let mut buf = mkpartition_buf::<Low>(PartitionKind::Run, &[1, 2, 3, 5]);
+
+        //                          1 ..= 3                 5 ..= 5           len  kind
+        assert_eq!(
+            buf.as_ref(),
+            &[
+                0x00, 0x01, 0x00, 0x03, 0x00, 0x05, 0x00, 0x05, 0x00, 0x01, 0x04
+            ]
+        );
+
+        // make the second run adjacent to the first
+        buf[5] = 0x04;
+        let partition = PartitionRef::<Low>::from_suffix(&buf).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+
+        // make the second run overlap the first
+        buf[5] = 0x02;
+        let partition = PartitionRef::<Low>::from_suffix(&buf).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+
+        // make the second run inverted
+        buf[5] = 0x06;
+        let partition = PartitionRef::<Low>::from_suffix(&buf).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+    }
+}
@@ -0,0 +1,478 @@
+use num::traits::{AsPrimitive, Bounded, ConstOne, ConstZero};
+
+use crate::{
+    PartitionRead,
+    codec::{
+        Encodable,
+        encoder::{Checkpoint, Encoder, RewindBuf},
+        features::Features,
+        partition_ref::PartitionRef,
+        tree_ref::TreeIndexBuilder,
+    },
+    level::{High, Level, Low, Mid},
+    partition::{
+        KindSizes, Partition,
+        delta_vec::{DeltaVecPartition, SKIP_INTERVAL, varint_len},
+        elias_fano::EliasFanoPartition,
+        run::RunPartition,
+        sparse_bitmap::{SparseBitmapPartition, WORD_BITS},
+        tree::TreePartition,
+        vec::VecPartition,
+    },
+    partition_kind::PartitionKind,
+    segment::{Segment, SplitSegment},
+    traits::Optimizable,
+};
+
+/// Encodes sorted values into the same bytes as a `Splinter` containing those
+/// values which was optimized with `Optimizable::optimize_with` for the
+/// encoder's features, without materializing the Splinter.
+///
+/// Values are buffered one Low partition at a time. Every High and Mid
+/// partition is speculatively encoded as a tree while its children arrive,
+/// with every child appended to the output. Once a partition is
+/// complete we make the same choice as `Partition::optimize` and only
+/// re-encode the partition if a non-tree kind is smaller, by rewinding the
+/// buffer and building the smaller kind directly from the tree's values. A
+/// tree with a single child becomes a prefix partition by replacing its index
+/// with the child's segment, so the children never need to be re-encoded.
+///
+/// The smaller kind is built in memory before it replaces the tree, so
+/// besides the output this needs memory for the largest partition which
+/// isn't a tree. Leaf partitions are built straight from their values
+/// without collecting them first, and take at most about twice their
+/// encoded size, which in turn is never larger than the speculative tree
+/// they replace.
+pub(crate) fn encode_sorted<B: RewindBuf>(
+    encoder: &mut Encoder<B>,
+    values: impl IntoIterator<Item = u32>,
+) {
+    // checksums can't be rewound, so the partitions are hashed once the
+    // root is complete
+    encoder.with_rewindable(|buf| encode_partitions(buf, values));
+    encoder.write_footer();
+}
+
+fn encode_partitions<B: RewindBuf>(buf: &mut Encoder<B>, values: impl IntoIterator<Item = u32>) {
+    let mut high = NodeEncoder::<High>::new(buf);
+    let mut mid: Option<(Segment, NodeEncoder<Mid>)> = None;
+    // low partitions are keyed by their high and mid segments
+    let mut low: Option<((Segment, Segment), Vec<u16>)> = None;
+    let mut last: Option<u32> = None;
+
+    for value in values {
+        if let Some(last) = last {
+            assert!(last <= value, "values must be sorted: {value} < {last}");
+            if last == value {
+                continue;
+            }
+        }
+        last = Some(value);
+
+        let (high_segment, mid_value) = value.split();
+        let (mid_segment, low_value) = mid_value.split();
+
+        // close the open partitions once the value moves past their segment
+        if let Some(((_, segment), mut values)) =
+            low.take_if(|(key, _)| *key != (high_segment, mid_segment))
+        {
+            let (_, node) = mid
+                .as_mut()
+                .expect("BUG: low partition without a mid partition");
+            finish_low(buf, node, segment, &mut values);
+        }
+        if let Some((segment, node)) = mid.take_if(|(segment, _)| *segment != high_segment) {
+            let cardinality = node.cardinality;
+            node.finish(buf);
+            high.push_child(buf, segment, cardinality);
+        }
+
+        high.push(value);
+        let (_, mid_node) = mid.get_or_insert_with(|| (high_segment, NodeEncoder::new(buf)));
+        mid_node.push(mid_value);
+        let (_, low_values) = low.get_or_insert_with(|| ((high_segment, mid_segment), Vec::new()));
+        low_values.push(low_value);
+    }
+
+    if let Some(((_, segment), mut values)) = low {
+        let (_, node) = mid
+            .as_mut()
+            .expect("BUG: low partition without a mid partition");
+        finish_low(buf, node, segment, &mut values);
+    }
+    if let Some((segment, node)) = mid {
+        let cardinality = node.cardinality;
+        node.finish(buf);
+        high.push_child(buf, segment, cardinality);
+    }
+
+    high.finish(buf);
+}
+
+/// Optimize and encode a complete Low partition as a child of `parent`.
+fn finish_low<B: RewindBuf>(
+    buf: &mut Encoder<B>,
+    parent: &mut NodeEncoder<Mid>,
+    segment: Segment,
+    values: &mut Vec<u16>,
+) {
+    // this mirrors `Partition::from_iter` followed by `Partition::optimize`
+    let mut partition =
+        Partition::<Low>::Vec(VecPartition::from_sorted_unique_unchecked(values.drain(..)));
+    partition.optimize_fast();
+    partition.optimize_with(buf.features());
+    partition.encode(buf);
+    parent.push_child(buf, segment, partition.cardinality());
+}
+
+/// Build a non-tree partition of the provided kind from a sorted iterator
+/// of unique values, without building a tree first.
+fn build_leaf<L: Level>(
+    kind: PartitionKind,
+    cardinality: usize,
+    values: impl Iterator<Item = L::Value>,
+) -> Partition<L> {
+    match kind {
+        PartitionKind::Bitmap => Partition::Bitmap(values.collect()),
+        PartitionKind::Vec => Partition::Vec(VecPartition::from_sorted_unique_unchecked(values)),
+        PartitionKind::EliasFano => Partition::EliasFano(
+            EliasFanoPartition::from_sorted_unique_unchecked(cardinality, values),
+        ),
+        PartitionKind::DeltaVec => {
+            Partition::DeltaVec(DeltaVecPartition::from_sorted_unique_unchecked(values))
+        }
+        PartitionKind::SparseBitmap => {
+            Partition::SparseBitmap(SparseBitmapPartition::from_sorted_unique_unchecked(values))
+        }
+        PartitionKind::Run | PartitionKind::InvertedVec | PartitionKind::InvertedRun => {
+            // inverted partitions are built from the runs of their complement
+            let mut partition = Partition::Run(RunPartition::from_sorted_unique_unchecked(values));
+            partition.switch_kind(kind);
+            partition
+        }
+        PartitionKind::Empty
+        | PartitionKind::Full
+        | PartitionKind::Tree
+        | PartitionKind::Prefix => {
+            unreachable!("BUG: {kind:?} is not a leaf partition kind")
+        }
+    }
+}
+
+/// Tracks a partially encoded tree partition along with the statistics
+/// `Partition::optimize_kind` needs to pick the partition's final kind.
+struct NodeEncoder<L: Level> {
+    /// the position in the shared buffer where this partition starts
+    start: Checkpoint,
+    /// (segment, offset, cardinality) for each encoded child
+    children: Vec<(Segment, usize, usize)>,
+    cardinality: usize,
+    runs: usize,
+    /// see `DeltaVecPartition::gap_bytes`
+    gap_bytes: usize,
+    /// see `Partition::count_words`
+    words: usize,
+    first: Option<L::Value>,
+    last: Option<L::Value>,
+}
+
+impl<L: Level> NodeEncoder<L> {
+    /// Create a `NodeEncoder` whose children will be written to `buf` at its
+    /// current position.
+    fn new<B: RewindBuf>(buf: &Encoder<B>) -> Self {
+        Self {
+            start: buf.checkpoint(),
+            children: Vec::new(),
+            cardinality: 0,
+            runs: 0,
+            gap_bytes: 0,
+            words: 0,
+            first: None,
+            last: None,
+        }
+    }
+
+    fn push(&mut self, value: L::Value) {
+        if let Some(last) = self.last
+            && !self.cardinality.is_multiple_of(SKIP_INTERVAL)
+        {
+            let gap: usize = (value - last).as_();
+            self.gap_bytes += varint_len(gap - 1);
+        }
+        self.cardinality += 1;
+        if self.last.is_none_or(|last| value - last != L::Value::ONE) {
+            self.runs += 1;
+        }
+        let word = |value: L::Value| value.as_() / WORD_BITS;
+        if self.last.is_none_or(|last| word(last) != word(value)) {
+            self.words += 1;
+        }
+        self.first.get_or_insert(value);
+        self.last = Some(value);
+    }
+
+    /// Record a child which has just been written to `buf`.
+    fn push_child<B: RewindBuf>(&mut self, buf: &Encoder<B>, segment: Segment, cardinality: usize) {
+        self.children
+            .push((segment, buf.bytes_written(), cardinality));
+    }
+
+    /// Write the partition's index to `buf` following its children, or
+    /// replace the children with a smaller partition kind.
+    fn finish<B: RewindBuf>(self, buf: &mut Encoder<B>) {
+        let cardinality = self.cardinality;
+        if cardinality == 0 {
+            Partition::<L>::EMPTY.encode(buf);
+            return;
+        } else if cardinality == L::MAX_LEN {
+            buf.rewind(self.start);
+            Partition::<L>::Full.encode(buf);
+            return;
+        }
+
+        let segments = self.children.len();
+        let estimate =
+            TreePartition::<L>::estimate_encoded_size(segments, cardinality, buf.features()) + 1;
+
+        // Partition::from_iter only becomes a tree if the estimate is the
+        // smallest option, after which optimize compares the actual size
+        let (initial, _) = Partition::<L>::cheapest_kind(
+            cardinality,
+            KindSizes { tree: estimate, ..KindSizes::default() },
+            buf.features(),
+        );
+        // `Partition::encoded_size` doesn't include bitmap alignment
+        let children = (buf.bytes_written() - self.start.bytes_written())
+            - (buf.padding_written() - self.start.padding_written());
+        let tree = if initial == PartitionKind::Tree {
+            children + TreeIndexBuilder::<L>::encoded_size(segments) + 1
+        } else {
+            estimate
+        };
+        let prefix = if segments != 1 {
+            usize::MAX
+        } else if initial == PartitionKind::Tree {
+            // child + segment + kind
+            children + 2
+        } else {
+            TreePartition::<L>::estimate_prefix_encoded_size(cardinality, buf.features()) + 1
+        };
+
+        let run = RunPartition::<L>::encoded_size(self.runs) + 1;
+        let inverted_run = if cardinality > L::MAX_LEN / 2 {
+            // see Partition::count_complement_runs
+            let at_start = (self.first == Some(L::Value::ZERO)) as usize;
+            let at_end = (self.last == Some(L::Value::max_value())) as usize;
+            RunPartition::<L>::encoded_size(self.runs + 1 - at_start - at_end) + 1
+        } else {
+            usize::MAX
+        };
+
+        let elias_fano = Partition::<L>::elias_fano_size(cardinality);
+        let delta_vec = if L::ALLOW_DELTA_VEC {
+            DeltaVecPartition::<L>::encoded_size(cardinality, self.gap_bytes) + 1
+        } else {
+            usize::MAX
+        };
+        let (kind, _) = Partition::<L>::cheapest_kind(
+            cardinality,
+            KindSizes {
+                tree,
+                prefix,
+                run,
+                inverted_run,
+                elias_fano,
+                delta_vec,
+                sparse_bitmap: Partition::<L>::sparse_bitmap_size(self.words),
+            },
+            buf.features(),
+        );
+
+        if kind == PartitionKind::Prefix && buf.supports(Features::PREFIX_PARTITIONS) {
+            let (segment, _, _) = self.children[0];
+            buf.put_slice(&[segment]);
+            buf.put_kind(PartitionKind::Prefix);
+        } else {
+            // without prefix partition support, prefix partitions fall back
+            // to trees
+            let mut index = TreeIndexBuilder::<L>::new(segments);
+            for (segment, offset, cardinality) in self.children {
+                index.push(segment, offset, cardinality);
+            }
+            buf.put_tree_index(index);
+            buf.put_kind(PartitionKind::Tree);
+        }
+
+        if !matches!(kind, PartitionKind::Tree | PartitionKind::Prefix) {
+            // replace the speculative tree with the smaller kind, which is
+            // built straight from the tree's values
+            let tree = PartitionRef::<L>::from_suffix(buf.written_since(&self.start))
+                .expect("BUG: speculative tree is invalid");
+            let partition = build_leaf::<L>(kind, cardinality, tree.into_iter());
+            buf.rewind(self.start);
+            partition.encode(buf);
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use bytes::BytesMut;
+    use itertools::Itertools;
+    use proptest::{collection::vec, proptest};
+
+    use crate::{
+        Encodable, Optimizable, PartitionRead, Splinter, SplinterRef,
+        codec::{checksum::Checksum, encoder::Encoder, features::Features},
+        testutil::{LevelSetGen, SetGen, encode_with_features},
+    };
+
+    fn assert_encodes_like_splinter(values: &[u32]) {
+        let splinter = Splinter::from_iter(values.iter().copied());
+
+        // the default encoder matches a splinter optimized for the V2 format
+        let mut optimized = splinter.clone();
+        optimized.optimize();
+        let mut encoder = Encoder::new(BytesMut::new());
+        encoder.encode_sorted(values.iter().copied().sorted());
+        assert_eq!(encoder.into_inner().freeze(), optimized.encode_to_bytes());
+
+        let mut optimized = splinter;
+        optimized.optimize_with(Features::SUPPORTED);
+        let mut encoder = Encoder::with_features(BytesMut::new(), Features::SUPPORTED);
+        encoder.encode_sorted(values.iter().copied().sorted());
+        assert_eq!(
+            encoder.into_inner().freeze(),
+            encode_with_features(&optimized, Features::SUPPORTED)
+        );
+    }
+
+    fn assert_encodes_like_aligned_splinter(values: &[u32]) {
+        let mut splinter = Splinter::from_iter(values.iter().copied());
+        splinter.optimize_with(Features::SUPPORTED);
+        let mut encoder =
+            Encoder::with_features(BytesMut::new(), Features::SUPPORTED).with_aligned_bitmaps();
+        splinter.encode(&mut encoder);
+        let expected = encoder.into_inner().freeze();
+
+        let mut encoder =
+            Encoder::with_features(BytesMut::new(), Features::SUPPORTED).with_aligned_bitmaps();
+        encoder.encode_sorted(values.iter().copied().sorted());
+        assert_eq!(encoder.into_inner().freeze(), expected);
+    }
+
+    #[test]
+    fn test_encode_sorted_edge_cases() {
+        assert_encodes_like_splinter(&[]);
+        assert_encodes_like_splinter(&[0]);
+        assert_encodes_like_splinter(&[u32::MAX]);
+        assert_encodes_like_splinter(&[0, 0, 1, 1, 2]);
+        assert_encodes_like_splinter(&[0, u32::MAX]);
+        assert_encodes_like_splinter(&(0..=255).collect_vec());
+        assert_encodes_like_splinter(&(0..=65535).collect_vec());
+        assert_encodes_like_splinter(&(1..=65535).collect_vec());
+        assert_encodes_like_splinter(&(65535..=(1 << 20)).step_by(3).collect_vec());
+        assert_encodes_like_splinter(&(0..(1 << 18)).filter(|v| v % 1024 != 7).collect_vec());
+        // the first mid partition is re-encoded as a run after its children
+        // have been written, followed by partitions which remain trees
+        assert_encodes_like_splinter(
+            &(0..(1 << 17))
+                .chain(((1 << 24)..(1 << 25)).step_by(4099))
+                .collect_vec(),
+        );
+    }
+
+    #[test]
+    fn test_encode_sorted_distributions() {
+        let mut set_gen = SetGen::new(0xDEAD_BEEF);
+        assert_encodes_like_splinter(&set_gen.distributed(1, 1, 16, 256));
+        assert_encodes_like_splinter(&set_gen.distributed(4, 8, 8, 128));
+        assert_encodes_like_splinter(&set_gen.distributed(256, 16, 1, 1));
+        assert_encodes_like_splinter(&set_gen.dense(8, 8, 8, 8));
+        assert_encodes_like_splinter(&set_gen.dense(1, 32, 16, 8));
+        assert_encodes_like_splinter(&set_gen.random(4096));
+        assert_encodes_like_splinter(&set_gen.random_max(60000, 65536));
+
+        let mut set_gen = LevelSetGen::<crate::level::High>::new(0xDEAD_BEEF);
+        assert_encodes_like_splinter(&set_gen.runs(4096, 0.01));
+        assert_encodes_like_splinter(&set_gen.runs(4096, 0.9));
+
+        // scattered values whose high partitions are small enough for their
+        // kind to depend on the available features
+        assert_encodes_like_splinter(
+            &(0..2000u32)
+                .map(|i| i.wrapping_mul(2654435761) >> 8)
+                .collect_vec(),
+        );
+    }
+
+    #[test]
+    fn test_encode_sorted_dense_mid() {
+        // a single mid partition with millions of values, whose speculative
+        // tree of 256 low bitmaps is rewound and rebuilt as a mid bitmap
+        let values = ((1 << 24)..(2 << 24)).step_by(8);
+        let mut encoder = Encoder::new(BytesMut::new());
+        encoder.encode_sorted(values.clone());
+        let splinter = SplinterRef::from_bytes(encoder.into_inner().freeze()).unwrap();
+        assert!(splinter.encoded_size() < (1 << 21) + 64);
+        assert_eq!(splinter.cardinality(), values.len());
+        assert!(splinter.iter().eq(values));
+    }
+
+    #[test]
+    fn test_encode_sorted_aligned() {
+        let mut set_gen = SetGen::new(0xDEAD_BEEF);
+        assert_encodes_like_aligned_splinter(&(0..=65535).step_by(2).collect_vec());
+        assert_encodes_like_aligned_splinter(&set_gen.distributed(4, 8, 8, 128));
+        assert_encodes_like_aligned_splinter(&set_gen.distributed(4, 8, 8, 64));
+        assert_encodes_like_aligned_splinter(&set_gen.dense(8, 8, 8, 8));
+        assert_encodes_like_aligned_splinter(&set_gen.random_max(60000, 65536));
+    }
+
+    #[test]
+    fn test_encode_sorted_buffers() {
+        // the first mid partition is rewound and re-encoded as a run
+        let values = (0..(1 << 17))
+            .chain(((1 << 24)..(1 << 25)).step_by(4099))
+            .collect_vec();
+        let mut splinter = Splinter::from_iter(values.iter().copied());
+        splinter.optimize_with(Features::SUPPORTED);
+        let mut encoder = Encoder::with_features(BytesMut::new(), Features::SUPPORTED)
+            .with_checksum(Checksum::None);
+        splinter.encode(&mut encoder);
+        let expected = encoder.into_inner().freeze();
+
+        // rewindable buffers are written to directly, after existing contents
+        let mut out = b"prefix".to_vec();
+        let mut encoder =
+            Encoder::with_features(&mut out, Features::SUPPORTED).with_checksum(Checksum::None);
+        encoder.encode_sorted(values.iter().copied());
+        assert_eq!(&out[..6], b"prefix");
+        assert_eq!(out[6..], expected);
+
+        let mut out = BytesMut::new();
+        let mut encoder =
+            Encoder::with_features(&mut out, Features::SUPPORTED).with_checksum(Checksum::None);
+        encoder.encode_sorted(values.iter().copied());
+        assert_eq!(out, expected);
+    }
+
+    #[test]
+    #[should_panic(expected = "values must be sorted")]
+    fn test_encode_sorted_unsorted() {
+        let mut encoder = Encoder::new(bytes::BytesMut::new());
+        encoder.encode_sorted([2, 1]);
+    }
+
+    proptest! {
+        #[test]
+        fn test_encode_sorted_proptest(values in vec(0u32..(1 << 20), 0..1024)) {
+            assert_encodes_like_splinter(&values);
+        }
+
+        #[test]
+        fn test_encode_sorted_sparse_proptest(values in vec(proptest::num::u32::ANY, 0..512)) {
+            assert_encodes_like_splinter(&values);
+        }
+    }
+}
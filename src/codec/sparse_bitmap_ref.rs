@@ -0,0 +1,267 @@
+use std::{marker::PhantomData, ops::RangeBounds};
+
+use bitvec::{order::Lsb0, slice::BitSlice};
+use num::traits::AsPrimitive;
+use zerocopy::{FromBytes, IntoBytes, LE, U64};
+
+use crate::{
+    PartitionRead,
+    codec::DecodeErr,
+    level::Level,
+    partition::sparse_bitmap::{SparseBitmapPartition, WORD_BITS, word_values},
+    splinter_mut::{NeedsReencode, Patches},
+    traits::TruncateFrom,
+    util::{IteratorExt, RangeExt},
+};
+
+/// A zero-copy view of a sparse bitmap partition. See
+/// [`SparseBitmapPartition`] for a description of the encoding.
+#[derive(Debug, Clone, Eq)]
+pub struct SparseBitmapRef<'a, L: Level> {
+    /// the encoded words and mask
+    data: &'a [u8],
+    words: &'a [U64<LE>],
+    mask: &'a BitSlice<u8, Lsb0>,
+    _marker: PhantomData<L>,
+}
+
+impl<'a, L: Level> SparseBitmapRef<'a, L> {
+    pub(super) fn from_suffix(data: &'a [u8]) -> Result<Self, DecodeErr> {
+        let mask_size = SparseBitmapPartition::<L>::MASK_SIZE;
+        DecodeErr::ensure_bytes_available(data, mask_size)?;
+        let mask = BitSlice::<u8, Lsb0>::from_slice(&data[(data.len() - mask_size)..]);
+        let mask = &mask[..SparseBitmapPartition::<L>::WORDS];
+
+        // the mask has one bit for every stored word
+        let words = mask.count_ones();
+        let bytes = SparseBitmapPartition::<L>::encoded_size(words);
+        DecodeErr::ensure_bytes_available(data, bytes)?;
+        Ok(Self::from_parts(&data[(data.len() - bytes)..]))
+    }
+
+    /// Creates a view over the encoded words and mask of a sparse bitmap
+    /// partition.
+    pub(crate) fn from_parts(data: &'a [u8]) -> Self {
+        let mask_size = SparseBitmapPartition::<L>::MASK_SIZE;
+        let (words, mask) = data.split_at(data.len() - mask_size);
+        let words = <[U64<LE>]>::ref_from_bytes(words)
+            .expect("BUG: encoded sparse bitmap has the wrong length");
+        let mask = &BitSlice::from_slice(mask)[..SparseBitmapPartition::<L>::WORDS];
+        assert_eq!(
+            words.len(),
+            mask.count_ones(),
+            "BUG: encoded sparse bitmap has the wrong length"
+        );
+        Self { data, words, mask, _marker: PhantomData }
+    }
+
+    /// Verifies that every stored word is nonzero, so that every set has
+    /// exactly one encoding.
+    pub(crate) fn validate(&self) -> Result<(), DecodeErr> {
+        if !L::ALLOW_SPARSE_BITMAP || self.words.iter().any(|word| word.get() == 0) {
+            return Err(DecodeErr::Validity);
+        }
+        Ok(())
+    }
+
+    /// Plans an in-place edit which toggles `value`. Only values whose word
+    /// is stored and remains nonzero can be edited in place.
+    pub(crate) fn plan_edit(
+        &self,
+        value: L::Value,
+        patches: &mut Patches,
+    ) -> Result<(), NeedsReencode> {
+        let value: usize = value.as_();
+        let bit = value % WORD_BITS;
+        let slot = self.slot(value).ok_or(NeedsReencode)?;
+        let word = &self.words[slot];
+        if word.get() == 1 << bit {
+            // removing the last value in a word removes the word
+            return Err(NeedsReencode);
+        }
+        patches.toggle_bit(word.as_bytes(), bit);
+        Ok(())
+    }
+
+    /// Returns a copy of the encoded words and mask.
+    pub(crate) fn to_bytes(&self) -> Box<[u8]> {
+        self.data.into()
+    }
+
+    /// The number of nonzero words stored by this partition.
+    #[inline]
+    pub(crate) fn count_words(&self) -> usize {
+        self.words.len()
+    }
+
+    /// Returns the index of the stored word containing `value`, or `None`
+    /// if the word is zero.
+    #[inline]
+    fn slot(&self, value: usize) -> Option<usize> {
+        let word = value / WORD_BITS;
+        self.mask[word].then(|| self.mask[..word].count_ones())
+    }
+
+    /// Returns the word index and value of every stored word.
+    pub(crate) fn words(&self) -> impl Iterator<Item = (usize, u64)> + use<'a, L> {
+        self.mask
+            .iter_ones()
+            .zip(self.words.iter().map(|word| word.get()))
+    }
+
+    pub fn into_iter(self) -> impl Iterator<Item = L::Value> {
+        let cardinality = self.cardinality();
+        word_values(self.words())
+            .map(L::Value::truncate_from)
+            .with_size_hint(cardinality)
+    }
+}
+
+impl<L: Level> PartitionRead<L> for SparseBitmapRef<'_, L> {
+    fn cardinality(&self) -> usize {
+        self.words
+            .iter()
+            .map(|word| word.get().count_ones() as usize)
+            .sum()
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        // stored words are nonzero
+        self.words.is_empty()
+    }
+
+    fn contains(&self, value: L::Value) -> bool {
+        let value: usize = value.as_();
+        self.slot(value)
+            .is_some_and(|slot| self.words[slot].get() & (1 << (value % WORD_BITS)) != 0)
+    }
+
+    fn position(&self, value: L::Value) -> Option<usize> {
+        self.contains(value).then(|| self.rank(value) - 1)
+    }
+
+    fn rank(&self, value: L::Value) -> usize {
+        let value: usize = value.as_();
+        let word = value / WORD_BITS;
+
+        // count the values in every stored word before `value`'s word
+        let slots = self.mask[..word].count_ones();
+        let before: usize = self.words[..slots]
+            .iter()
+            .map(|word| word.get().count_ones() as usize)
+            .sum();
+
+        if self.mask[word] {
+            // then the values up to and including `value` in its word
+            let bits = u64::MAX >> (WORD_BITS - 1 - value % WORD_BITS);
+            before + (self.words[slots].get() & bits).count_ones() as usize
+        } else {
+            before
+        }
+    }
+
+    fn select(&self, mut idx: usize) -> Option<L::Value> {
+        for (word, mut bits) in self.words() {
+            let count = bits.count_ones() as usize;
+            if idx < count {
+                // clear the lowest `idx` bits to find the selected bit
+                for _ in 0..idx {
+                    bits &= bits - 1;
+                }
+                let value = word * WORD_BITS + bits.trailing_zeros() as usize;
+                return Some(L::Value::truncate_from(value));
+            }
+            idx -= count;
+        }
+        None
+    }
+
+    fn last(&self) -> Option<L::Value> {
+        let word = self.mask.last_one()?;
+        let bits = self.words.last()?.get();
+        let value = (word + 1) * WORD_BITS - 1 - bits.leading_zeros() as usize;
+        Some(L::Value::truncate_from(value))
+    }
+
+    fn iter(&self) -> impl Iterator<Item = L::Value> {
+        self.clone().into_iter()
+    }
+
+    fn contains_all<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        if let Some(range) = values.try_into_inclusive() {
+            let (start, end) = (*range.start(), *range.end());
+            // every value in the range is present iff the range contains
+            // `end - start + 1` values
+            let len: usize = (end - start).as_();
+            self.contains(start) && self.rank(end) - self.rank(start) == len
+        } else {
+            // empty range is trivially contained
+            true
+        }
+    }
+
+    fn contains_any<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        if let Some(range) = values.try_into_inclusive() {
+            let (start, end) = (*range.start(), *range.end());
+            self.contains(start) || self.rank(end) > self.rank(start)
+        } else {
+            // empty range has no intersection
+            false
+        }
+    }
+}
+
+impl<L: Level> PartialEq for SparseBitmapRef<'_, L> {
+    fn eq(&self, other: &Self) -> bool {
+        // the encoding is fully determined by the partition's values
+        self.data == other.data
+    }
+}
+
+#[cfg(test)]
+mod test {
+
+    use crate::{
+        assert_error,
+        codec::{DecodeErr, partition_ref::PartitionRef},
+        level::{Block, Low},
+        partition_kind::PartitionKind,
+        testutil::mkpartition_buf,
+    };
+
+    #[test]
+    fn test_sparse_bitmap_byteorder() {
+        let buf = mkpartition_buf::<Low>(PartitionKind::SparseBitmap, &[0x01_00, 0x01_02]);
+        let mut expected = vec![
+            0x05, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, // the fifth word
+            0x10, // mask: only the fifth word is stored
+        ];
+        expected.extend([0x00; 127]); // the rest of the mask
+        expected.push(0x12); // kind
+        assert_eq!(buf.as_ref(), expected);
+    }
+
+    #[test]
+    fn test_validate_sparse_bitmap() {
+        let buf = mkpartition_buf::<Low>(PartitionKind::SparseBitmap, &[0x01_00, 0x01_02]);
+        let partition = PartitionRef::<Low>::from_suffix(&buf).unwrap();
+        assert!(partition.validate().is_ok());
+
+        // stored words may not be zero
+        let mut corrupted = buf.to_vec();
+        corrupted[0] = 0x00;
+        let partition = PartitionRef::<Low>::from_suffix(&corrupted).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+
+        // the mask must be preceded by every stored word
+        assert_error!(
+            PartitionRef::<Low>::from_suffix(&buf[4..]),
+            DecodeErr::Length
+        );
+
+        // Block partitions may never be encoded as sparse bitmaps
+        let partition = PartitionRef::<Block>::from_suffix(&buf).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+    }
+}
@@ -1,17 +1,18 @@
 use num::traits::AsPrimitive;
-use std::{marker::PhantomData, mem::size_of};
-use zerocopy::FromBytes;
+use std::{marker::PhantomData, mem::size_of, ops::Range};
+use zerocopy::{FromBytes, IntoBytes};
 
 use crate::{
     PartitionRead,
     codec::{
-        DecodeErr,
+        DecodeErr, DecodeLimit, LocatedDecodeErr,
         partition_ref::{NonRecursivePartitionRef, PartitionRef, decode_len_from_suffix},
     },
     level::{Block, Level},
     partition::{Partition, bitmap::BitmapPartition},
     partition_kind::PartitionKind,
     segment::{Segment, SplitSegment},
+    splinter_mut::{NeedsReencode, Patches},
     traits::TruncateFrom,
     util::{IteratorExt, RangeExt},
 };
@@ -69,7 +70,158 @@ impl<'a, L: Level> TreeRef<'a, L> {
         })
     }
 
-    fn load_child(&self, idx: usize) -> PartitionRef<'a, L::LevelDown> {
+    /// Recursively verifies the tree index and every child partition. Errors
+    /// in the tree itself are located by `locate`, while errors in a child
+    /// are moved from the child's byte range into the tree's.
+    ///
+    /// Each child is decoded from the exact byte range between its offset and
+    /// the previous child's offset, so a valid tree never reads outside of its
+    /// own children.
+    pub(crate) fn validate_located(
+        &self,
+        locate: impl Fn(DecodeErr) -> LocatedDecodeErr,
+    ) -> Result<(), LocatedDecodeErr> {
+        self.validate_index(self.children.len()).map_err(&locate)?;
+
+        let mut cardinality = 0;
+        let children = self.try_children().zip(self.segments());
+        for (idx, (range, segment)) in children.enumerate() {
+            let range = range.map_err(&locate)?;
+            let child =
+                PartitionRef::<L::LevelDown>::validate_suffix(&self.children[range.clone()])
+                    .map_err(|err| err.within(range.start, segment))?;
+
+            let child_cardinality = child.cardinality();
+            if child_cardinality == 0 {
+                return Err(locate(DecodeErr::Validity));
+            }
+            cardinality += child_cardinality;
+            if self.prefix_cardinality(idx + 1) != cardinality {
+                return Err(locate(DecodeErr::Validity));
+            }
+        }
+
+        Ok(())
+    }
+
+    /// Verifies the tree index without decoding any children, given the
+    /// number of bytes between the start of the first child and the index.
+    pub(crate) fn validate_index(&self, children_len: usize) -> Result<(), DecodeErr> {
+        if !L::ALLOW_TREE {
+            return Err(DecodeErr::Validity);
+        }
+
+        // the segment store must contain exactly num_children unique segments
+        self.segments.validate()?;
+        if self.segments.cardinality() != self.num_children {
+            return Err(DecodeErr::Validity);
+        }
+
+        // the first child must start within the children, every child must
+        // end after the previous one, and the last child must end
+        // immediately before the tree index
+        if self.child_end_offset(0) >= children_len {
+            return Err(DecodeErr::Length);
+        }
+        let ordered = self.offsets.windows(2).all(|w| w[0] > w[1]);
+        if !ordered || self.child_end_offset(self.num_children - 1) != 0 {
+            return Err(DecodeErr::Validity);
+        }
+
+        // children are never empty, so cumulative cardinalities increase
+        if !self
+            .cumulative_cardinalities
+            .windows(2)
+            .all(|w| w[0] < w[1])
+        {
+            return Err(DecodeErr::Validity);
+        }
+        Ok(())
+    }
+
+    /// Verifies the tree index and the path to `value` through the child
+    /// which stores it, without decoding any other children.
+    pub(crate) fn validate_path(&self, value: L::Value) -> Result<(), DecodeErr> {
+        self.validate_index(self.children.len())?;
+        let (segment, value) = value.split();
+        let Ok(idx) = self.search_segment(segment) else {
+            return Ok(());
+        };
+        let child = PartitionRef::<L::LevelDown>::from_suffix(self.child_bytes(idx))?;
+        child.validate_path(value)?;
+        // edits patch the cumulative cardinalities based on the child's
+        let expected = self.prefix_cardinality(idx + 1) - self.prefix_cardinality(idx);
+        if child.cardinality() != expected {
+            return Err(DecodeErr::Validity);
+        }
+        Ok(())
+    }
+
+    /// Recursively verifies that neither this tree nor any nested tree has
+    /// more than `max` children.
+    pub(crate) fn check_fan_out(&self, max: usize) -> Result<(), DecodeErr> {
+        if self.num_children > max {
+            return Err(DecodeErr::LimitExceeded { limit: DecodeLimit::FanOut });
+        }
+        self.try_children().try_for_each(|range| {
+            PartitionRef::<L::LevelDown>::from_suffix(&self.children[range?])?.check_fan_out(max)
+        })
+    }
+
+    /// Returns the exact byte range of every child, between its offset and
+    /// the previous child's offset, returning an error rather than panicking
+    /// if the offsets are malformed.
+    fn try_children(&self) -> impl Iterator<Item = Result<Range<usize>, DecodeErr>> + '_ {
+        let mut start = 0;
+        (0..self.num_children).map(move |idx| {
+            let relative_offset: usize = self.offsets[idx].into().as_();
+            let end = self
+                .children
+                .len()
+                .checked_sub(relative_offset)
+                .ok_or(DecodeErr::Length)?;
+
+            // children are stored in order and are never empty
+            if idx > 0 && end <= start {
+                return Err(DecodeErr::Validity);
+            }
+            // the last child must end immediately before the tree index
+            if idx == self.num_children - 1 && relative_offset != 0 {
+                return Err(DecodeErr::Validity);
+            }
+
+            let range = start..end;
+            start = end;
+            Ok(range)
+        })
+    }
+
+    /// Plans an in-place edit which inserts or removes `value`, which must
+    /// respectively be missing from or present in this tree, and updates the
+    /// cumulative cardinalities which follow the edited child.
+    pub(crate) fn plan_edit(
+        &self,
+        value: L::Value,
+        insert: bool,
+        patches: &mut Patches,
+    ) -> Result<(), NeedsReencode> {
+        let (segment, value) = value.split();
+        // adding or removing a child would resize the tree index
+        let idx = self.search_segment(segment).map_err(|_| NeedsReencode)?;
+        let child = self.load_child(idx);
+        if !insert && child.cardinality() == 1 {
+            return Err(NeedsReencode);
+        }
+        child.plan_edit(value, insert, patches)?;
+
+        let delta = if insert { 1 } else { -1 };
+        for cardinality in &self.cumulative_cardinalities[idx..] {
+            patches.add(cardinality.as_bytes(), delta);
+        }
+        Ok(())
+    }
+
+    pub(crate) fn load_child(&self, idx: usize) -> PartitionRef<'a, L::LevelDown> {
         let relative_offset: usize = self.offsets[idx].into().as_();
         let offset = self.children.len() - relative_offset;
         PartitionRef::from_suffix(&self.children[..offset]).unwrap()
@@ -77,7 +229,7 @@ impl<'a, L: Level> TreeRef<'a, L> {
 
     /// Returns the cumulative cardinality before the given index (sum of all children < idx)
     #[inline]
-    fn prefix_cardinality(&self, idx: usize) -> usize {
+    pub(crate) fn prefix_cardinality(&self, idx: usize) -> usize {
         if idx == 0 {
             0
         } else {
@@ -87,6 +239,56 @@ impl<'a, L: Level> TreeRef<'a, L> {
         }
     }
 
+    /// Searches for the child storing `segment`, returning its index if
+    /// found, or the number of children with a smaller segment otherwise.
+    pub(crate) fn search_segment(&self, segment: Segment) -> Result<usize, usize> {
+        self.segments
+            .position(segment)
+            .ok_or_else(|| self.segments.rank(segment))
+    }
+
+    /// Returns the index and segment of the child containing the value at
+    /// position `n`. `n` must be less than the tree's cardinality.
+    pub(crate) fn child_at(&self, n: usize) -> Option<(usize, Segment)> {
+        // Binary search to find the child containing position n
+        // We're looking for the first index where cumulative_cardinalities[idx] > n
+        // Since we store cumulative - 1, we compare encoded < n (equivalent to cumulative - 1 < n, i.e., cumulative <= n)
+        let idx = self.cumulative_cardinalities.partition_point(|c| {
+            let c: usize = (*c).into().as_();
+            c < n
+        });
+        Some((idx, self.segments.select(idx)?))
+    }
+
+    /// The number of bytes between the end of the child at `idx` and the end
+    /// of the last child.
+    #[inline]
+    pub(crate) fn child_end_offset(&self, idx: usize) -> usize {
+        self.offsets[idx].into().as_()
+    }
+
+    #[inline]
+    pub(crate) fn num_children(&self) -> usize {
+        self.num_children
+    }
+
+    /// The number of bytes available before the tree index, which ends with
+    /// the children.
+    #[inline]
+    pub(crate) fn children_len(&self) -> usize {
+        self.children.len()
+    }
+
+    /// Returns the encoded bytes of the child at `idx`. Children are
+    /// self-contained, so these bytes can be copied verbatim into another tree.
+    pub(crate) fn child_bytes(&self, idx: usize) -> &'a [u8] {
+        let start = match idx {
+            0 => 0,
+            idx => self.children.len() - self.child_end_offset(idx - 1),
+        };
+        &self.children[start..self.children.len() - self.child_end_offset(idx)]
+    }
+
     pub(crate) fn load_child_at_segment(
         &self,
         segment: Segment,
@@ -143,15 +345,14 @@ impl<'a, L: Level> PartitionRead<L> for TreeRef<'a, L> {
 
     fn rank(&self, value: L::Value) -> usize {
         let (segment, value) = value.split();
-        match self.segments.position(segment) {
-            Some(idx) => {
+        match self.search_segment(segment) {
+            Ok(idx) => {
                 // Segment exists: O(1) prefix cardinality + rank within child
                 let child = self.load_child(idx);
                 self.prefix_cardinality(idx) + child.rank(value)
             }
-            None => {
+            Err(count_less) => {
                 // Segment doesn't exist: return cardinality of all segments < target
-                let count_less = self.segments.rank(segment);
                 self.prefix_cardinality(count_less)
             }
         }
@@ -162,16 +363,8 @@ impl<'a, L: Level> PartitionRead<L> for TreeRef<'a, L> {
             return None;
         }
 
-        // Binary search to find the child containing position n
-        // We're looking for the first index where cumulative_cardinalities[idx] > n
-        // Since we store cumulative - 1, we compare encoded < n (equivalent to cumulative - 1 < n, i.e., cumulative <= n)
-        let idx = self.cumulative_cardinalities.partition_point(|c| {
-            let c: usize = (*c).into().as_();
-            c < n
-        });
-
+        let (idx, segment) = self.child_at(n)?;
         let prefix = self.prefix_cardinality(idx);
-        let segment = self.segments.select(idx)?;
         let child = self.load_child(idx);
         child
             .select(n - prefix)
@@ -406,11 +599,12 @@ mod tests {
     use bytes::BytesMut;
 
     use crate::{
-        Encodable, PartitionRead,
-        codec::{encoder::Encoder, partition_ref::PartitionRef},
-        level::{Block, Low},
+        Encodable, PartitionRead, SplinterRef, assert_error,
+        codec::{DecodeErr, DecodeOptions, encoder::Encoder, partition_ref::PartitionRef},
+        level::{Block, High, Low},
         partition::{Partition, tree::TreePartition, vec::VecPartition},
         partition_kind::PartitionKind,
+        testutil::{mkpartition_buf, mksplinter_manual},
     };
 
     #[test]
@@ -460,4 +654,95 @@ mod tests {
         // Encoding must reject this invalid representation instead of silently truncating offsets.
         let _ = PartitionRef::<Low>::from_suffix(&buf);
     }
+
+    #[test]
+    fn test_validate_tree_offset_out_of_bounds() {
+        let mut buf = mkpartition_buf::<High>(PartitionKind::Tree, &[1, 2]);
+
+        assert_eq!(
+            buf.as_ref(),
+            &[
+                // Vec partition (child)
+                // 1              2             len         kind
+                0x00, 0x00, 0x01, 0x00, 0x00, 0x02, 0x00, 0x00, 0x01, 0x03,
+                // Tree partition
+                // offsets (u32), cumulative_cardinalities-1 (u32), segments, len, kind
+                0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x00, 0x05
+            ]
+        );
+
+        // point the child offset past the start of the buffer
+        buf[13] = 0x20;
+        let corrupted = mksplinter_manual(&buf);
+
+        // the shallow check passes, but reading would panic
+        assert!(SplinterRef::from_bytes(corrupted.clone()).is_ok());
+        assert_error!(
+            SplinterRef::from_bytes_validated(corrupted),
+            DecodeErr::Length
+        );
+    }
+
+    #[test]
+    fn test_validate_tree_cardinality() {
+        let mut buf = mkpartition_buf::<High>(PartitionKind::Tree, &[1, 2]);
+
+        // corrupt the cumulative cardinality
+        buf[17] = 0x05;
+        let corrupted = mksplinter_manual(&buf);
+
+        assert!(SplinterRef::from_bytes(corrupted.clone()).is_ok());
+        assert_error!(
+            SplinterRef::from_bytes_validated(corrupted.clone()),
+            DecodeErr::Validity
+        );
+
+        // the cardinality limit can't be checked against unvalidated trees
+        let options = DecodeOptions::DEFAULT.with_max_cardinality(1 << 20);
+        assert!(options.validates_deep());
+        assert_error!(
+            SplinterRef::from_bytes_with(corrupted, options),
+            DecodeErr::Validity
+        );
+    }
+
+    #[test]
+    fn test_validate_tree_segments() {
+        let mut buf = mkpartition_buf::<Low>(PartitionKind::Tree, &[0x00_01, 0x02_01]);
+
+        assert_eq!(
+            buf.as_ref(),
+            &[
+                // Vec partitions (children)
+                0x01, 0x00, 0x03, 0x01, 0x00, 0x03,
+                // Tree partition
+                // offsets (u16), cumulative_cardinalities-1 (u16), segments, len, kind
+                0x00, 0x03, 0x00, 0x00, 0x00, 0x00, 0x00, 0x01, 0x00, 0x02, 0x01, 0x05
+            ]
+        );
+        PartitionRef::<Low>::from_suffix(&buf)
+            .unwrap()
+            .validate()
+            .unwrap();
+
+        // duplicate segment
+        buf[15] = 0x00;
+        let partition = PartitionRef::<Low>::from_suffix(&buf).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+
+        // children out of order
+        buf[15] = 0x02;
+        buf[7] = 0x05;
+        let partition = PartitionRef::<Low>::from_suffix(&buf).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+    }
+
+    #[test]
+    fn test_validate_tree_at_block_level() {
+        let buf = mkpartition_buf::<Low>(PartitionKind::Tree, &[1, 2]);
+
+        // Block partitions may never be encoded as trees
+        let partition = PartitionRef::<Block>::from_suffix(&buf).unwrap();
+        assert_error!(partition.validate(), DecodeErr::Validity);
+    }
 }
@@ -1,5 +1,5 @@
 use bitvec::{boxed::BitBox, order::BitOrder, slice::BitSlice, store::BitStore};
-use num::{PrimInt, traits::ConstOne};
+use num::{Saturating, Zero, traits::ConstOne};
 
 /// Counts the number of unique values present in `iter`. Requires that the
 /// iterator is sorted.
@@ -7,7 +7,7 @@ use num::{PrimInt, traits::ConstOne};
 pub fn count_unique_sorted<I, T>(iter: I) -> usize
 where
     I: IntoIterator<Item = T>,
-    T: PrimInt,
+    T: Copy + Ord + Zero,
 {
     let mut iter = iter.into_iter().peekable();
     let mut count = 0;
@@ -29,7 +29,7 @@ where
 pub fn count_runs_sorted<I, T>(iter: I) -> usize
 where
     I: IntoIterator<Item = T>,
-    T: PrimInt + ConstOne,
+    T: Copy + Ord + Saturating + ConstOne,
 {
     let mut iter = iter.into_iter().peekable();
     let mut count = 0;
@@ -1,4 +1,5 @@
 use std::fmt::Debug;
+use std::mem;
 use std::ops::{
     BitAnd, BitAndAssign, BitOr, BitOrAssign, BitXor, BitXorAssign, Deref, RangeBounds, Sub,
     SubAssign,
@@ -7,12 +8,20 @@ use std::ops::{
 use bytes::{BufMut, Bytes};
 use either::Either;
 
+#[cfg(feature = "lz4")]
+use crate::codec::{DecodeLimit, compressed};
 use crate::{
     Encodable, PartitionRead, PartitionWrite, Splinter, SplinterRef,
-    codec::{DecodeErr, encoder::Encoder},
+    codec::{
+        DecodeErr, DecodeOptions, LocatedDecodeErr, encoder::Encoder, features::Features, legacy_v1,
+    },
     level::High,
 };
 
+mod patched;
+
+pub use patched::PatchedSplinter;
+
 /// A clone-on-write splinter that can hold either a reference or an owned value.
 ///
 /// `CowSplinter` is an enum that can contain either a [`SplinterRef<B>`] (for zero-copy
@@ -46,6 +55,29 @@ use crate::{
 /// assert_eq!(cow.cardinality(), 3);
 /// ```
 ///
+/// Splinters whose root is a tree, or a prefix over a tree, are only
+/// partially decoded when modified. Writes decode the children they touch into
+/// a [`PatchedSplinter`], and encoding copies every other child verbatim from
+/// the original buffer. Writes only validate the tree index and the children
+/// they decode, so their cost doesn't depend on the size of the splinter:
+///
+/// ```
+/// use splinter_rs::{
+///     CowSplinter, Encodable, Optimizable, PartitionRead, PartitionWrite, Splinter,
+/// };
+///
+/// let mut original = Splinter::from_iter((0..1_000_000).step_by(7));
+/// original.optimize();
+/// let mut cow = CowSplinter::from_ref(original.encode_to_splinter_ref());
+///
+/// cow.insert(1);
+/// assert!(matches!(cow, CowSplinter::Patched(_)));
+/// assert_eq!(cow.cardinality(), original.cardinality() + 1);
+///
+/// let encoded = CowSplinter::from_bytes(cow.encode_to_bytes()).unwrap();
+/// assert_eq!(encoded, cow);
+/// ```
+///
 /// Creating from different sources:
 ///
 /// ```
@@ -63,12 +95,19 @@ use crate::{
 /// let bytes = cow2.encode_to_bytes();
 /// let cow3 = CowSplinter::from_bytes(bytes).unwrap();
 /// ```
+///
+/// New variants may be added as other partially decoded representations are
+/// introduced, so matches on this enum must include a wildcard arm.
 #[derive(Clone)]
+#[non_exhaustive]
 pub enum CowSplinter<B> {
     /// Contains a zero-copy reference to serialized data
     Ref(SplinterRef<B>),
     /// Contains an owned, mutable splinter
     Owned(Splinter),
+    /// Contains a zero-copy reference to serialized data along with decoded
+    /// copies of the children which have been modified
+    Patched(PatchedSplinter<B>),
 }
 
 impl<B> Default for CowSplinter<B> {
@@ -87,6 +126,10 @@ impl<B: Deref<Target = [u8]>> Debug for CowSplinter<B> {
             CowSplinter::Owned(splinter) => {
                 f.debug_tuple("CowSplinter::Owned").field(splinter).finish()
             }
+            CowSplinter::Patched(patched) => f
+                .debug_tuple("CowSplinter::Patched")
+                .field(patched)
+                .finish(),
         }
     }
 }
@@ -123,6 +166,7 @@ impl From<CowSplinter<Bytes>> for SplinterRef<Bytes> {
         match cow {
             CowSplinter::Ref(splinter_ref) => splinter_ref,
             CowSplinter::Owned(splinter) => splinter.encode_to_splinter_ref(),
+            CowSplinter::Patched(patched) => SplinterRef { data: patched.encode_to_bytes() },
         }
     }
 }
@@ -168,9 +212,16 @@ impl<B: Deref<Target = [u8]>> CowSplinter<B> {
     /// This is equivalent to creating a `SplinterRef` from the bytes and wrapping
     /// it in a `CowSplinter::Ref`. All the same validation rules apply.
     ///
+    /// With the `lz4` cargo feature enabled, splinters wrapped in a
+    /// [compressed envelope](crate::codec::compressed) are also accepted.
+    /// They can't be accessed in place, so they are decompressed and decoded
+    /// into a `CowSplinter::Owned`.
+    ///
     /// # Errors
     ///
-    /// Returns the same errors as [`SplinterRef::from_bytes`].
+    /// Returns the same errors as [`SplinterRef::from_bytes`], or when the
+    /// `lz4` feature is enabled the errors returned by
+    /// `codec::compressed::decompress` instead of [`DecodeErr::Compressed`].
     ///
     /// # Examples
     ///
@@ -185,9 +236,107 @@ impl<B: Deref<Target = [u8]>> CowSplinter<B> {
     /// assert!(cow.contains(42));
     /// ```
     pub fn from_bytes(data: B) -> Result<Self, DecodeErr> {
+        #[cfg(feature = "lz4")]
+        if compressed::is_compressed(&data) {
+            let splinter_ref = SplinterRef::from_bytes(compressed::decompress(&data)?)?;
+            return Ok(Self::Owned(splinter_ref.decode_to_splinter()));
+        }
         Ok(Self::Ref(SplinterRef::from_bytes(data)?))
     }
 
+    /// Creates a `CowSplinter` from raw bytes using [`DecodeOptions`], like
+    /// [`SplinterRef::from_bytes_with`].
+    ///
+    /// The limits are checked before any memory is allocated. With the `lz4`
+    /// cargo feature enabled, compressed splinters are accepted like
+    /// [`CowSplinter::from_bytes`]; their uncompressed length is checked
+    /// against [`DecodeOptions::with_max_len`] before they are decompressed,
+    /// and every limit is checked before they are decoded into a
+    /// `CowSplinter::Owned`.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`SplinterRef::from_bytes_with`], or when
+    /// the `lz4` feature is enabled the errors returned by
+    /// `codec::compressed::decompress` instead of [`DecodeErr::Compressed`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{
+    ///     CowSplinter, Encodable, PartitionRead, Splinter,
+    ///     codec::{DecodeErr, DecodeLimit, DecodeOptions, LocatedDecodeErr},
+    /// };
+    ///
+    /// let options = DecodeOptions::UNTRUSTED.with_max_cardinality(1000);
+    ///
+    /// let bytes = Splinter::from_iter(0..1000).encode_to_bytes();
+    /// let cow = CowSplinter::from_bytes_with(bytes, options).unwrap();
+    /// assert_eq!(cow.cardinality(), 1000);
+    ///
+    /// let bytes = Splinter::from_iter(0..1001).encode_to_bytes();
+    /// assert!(matches!(
+    ///     CowSplinter::from_bytes_with(bytes, options),
+    ///     Err(LocatedDecodeErr {
+    ///         err: DecodeErr::LimitExceeded { limit: DecodeLimit::Cardinality },
+    ///         location: None,
+    ///         ..
+    ///     })
+    /// ));
+    /// ```
+    pub fn from_bytes_with(data: B, options: DecodeOptions) -> Result<Self, LocatedDecodeErr> {
+        #[cfg(feature = "lz4")]
+        if compressed::is_compressed(&data) {
+            if data.len() > options.max_len() {
+                return Err(DecodeErr::LimitExceeded { limit: DecodeLimit::Length }.into());
+            }
+            let data = compressed::decompress_with_max_len(&data, options.max_len())?;
+            let splinter_ref = SplinterRef::from_bytes_with(data, options)?;
+            return Ok(Self::Owned(splinter_ref.decode_to_splinter()));
+        }
+        Ok(Self::Ref(SplinterRef::from_bytes_with(data, options)?))
+    }
+
+    /// Creates a `CowSplinter` from raw bytes in either the current or the
+    /// legacy V1 format.
+    ///
+    /// Current format data is wrapped in a `CowSplinter::Ref` exactly like
+    /// [`CowSplinter::from_bytes`]. V1 data can't be accessed in place, so it
+    /// is decoded into a `CowSplinter::Owned` using [`legacy_v1::decode`].
+    /// Encoding the result writes the current format, which makes this a
+    /// convenient way to migrate V1 data.
+    ///
+    /// # Errors
+    ///
+    /// Returns the same errors as [`SplinterRef::from_bytes`] or
+    /// [`legacy_v1::decode`], except that [`DecodeErr::SplinterV1`] is never
+    /// returned.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{CowSplinter, Encodable, PartitionRead};
+    ///
+    /// // the encoding of a V1 splinter containing 42
+    /// let v1 = b"\xda\xae\x12\xdf\x2a\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\0\x01\0\0\0";
+    /// let cow = CowSplinter::from_bytes_any_version(v1.as_slice()).unwrap();
+    /// assert!(matches!(cow, CowSplinter::Owned(_)));
+    /// assert!(cow.contains(42));
+    ///
+    /// // re-encode in the current format
+    /// let v2 = cow.encode_to_bytes();
+    /// let cow = CowSplinter::from_bytes_any_version(v2).unwrap();
+    /// assert!(matches!(cow, CowSplinter::Ref(_)));
+    /// assert!(cow.contains(42));
+    /// ```
+    pub fn from_bytes_any_version(data: B) -> Result<Self, DecodeErr> {
+        if legacy_v1::is_v1(&data) {
+            Ok(Self::Owned(legacy_v1::decode(&data)?))
+        } else {
+            Self::from_bytes(data)
+        }
+    }
+
     /// Converts this `CowSplinter` into an owned [`Splinter`].
     ///
     /// If this is already an owned splinter, it returns it directly.
@@ -211,13 +360,15 @@ impl<B: Deref<Target = [u8]>> CowSplinter<B> {
         match self {
             Self::Ref(splinter_ref) => splinter_ref.decode_to_splinter(),
             Self::Owned(splinter) => splinter,
+            Self::Patched(patched) => patched.to_splinter(),
         }
     }
 
     /// Returns a mutable reference to the underlying [`Splinter`].
     ///
     /// This method implements the "clone-on-write" behavior: if the current value
-    /// is a `Ref`, it will be converted to `Owned` by deserializing the data.
+    /// is a `Ref` or `Patched`, it will be converted to `Owned` by deserializing
+    /// the data.
     /// Subsequent calls will return the same mutable reference without additional
     /// conversions.
     ///
@@ -241,12 +392,29 @@ impl<B: Deref<Target = [u8]>> CowSplinter<B> {
         match *self {
             Self::Ref(ref splinter_ref) => {
                 *self = Self::Owned(splinter_ref.decode_to_splinter());
-                match *self {
-                    Self::Ref(..) => unreachable!(),
-                    Self::Owned(ref mut owned) => owned,
-                }
             }
+            Self::Patched(ref patched) => {
+                *self = Self::Owned(patched.to_splinter());
+            }
+            Self::Owned(_) => (),
+        }
+        match *self {
             Self::Owned(ref mut owned) => owned,
+            _ => unreachable!(),
+        }
+    }
+
+    /// Converts a `Ref` whose root is a tree, or a prefix over a tree, into a
+    /// `Patched` splinter, which only decodes the children modified by
+    /// subsequent writes.
+    fn patch(&mut self) {
+        if matches!(self, Self::Ref(_))
+            && let Self::Ref(splinter_ref) = mem::take(self)
+        {
+            *self = match PatchedSplinter::new(splinter_ref) {
+                Ok(patched) => Self::Patched(patched),
+                Err(splinter_ref) => Self::Ref(splinter_ref),
+            };
         }
     }
 }
@@ -256,7 +424,8 @@ impl CowSplinter<Bytes> {
     ///
     /// For the `Ref` variant, this clones the underlying `Bytes` (which is efficient
     /// due to reference counting). For the `Owned` variant, this encodes the splinter
-    /// to bytes.
+    /// to bytes. For the `Patched` variant, this re-encodes the modified children
+    /// and copies the rest from the underlying `Bytes`.
     ///
     /// # Examples
     ///
@@ -274,6 +443,7 @@ impl CowSplinter<Bytes> {
         match self {
             CowSplinter::Ref(splinter_ref) => splinter_ref.encode_to_bytes(),
             CowSplinter::Owned(splinter) => splinter.encode_to_bytes(),
+            CowSplinter::Patched(patched) => patched.encode_to_bytes(),
         }
     }
 }
@@ -283,6 +453,23 @@ impl<B: Deref<Target = [u8]>> Encodable for CowSplinter<B> {
         match self {
             CowSplinter::Ref(splinter_ref) => splinter_ref.encoded_size(),
             CowSplinter::Owned(splinter) => splinter.encoded_size(),
+            CowSplinter::Patched(patched) => patched.encoded_size(),
+        }
+    }
+
+    fn encoded_size_with(&self, features: Features) -> usize {
+        match self {
+            CowSplinter::Ref(splinter_ref) => splinter_ref.encoded_size_with(features),
+            CowSplinter::Owned(splinter) => splinter.encoded_size_with(features),
+            CowSplinter::Patched(patched) => patched.encoded_size_with(features),
+        }
+    }
+
+    fn max_encoded_size(&self) -> usize {
+        match self {
+            CowSplinter::Ref(splinter_ref) => splinter_ref.max_encoded_size(),
+            CowSplinter::Owned(splinter) => splinter.max_encoded_size(),
+            CowSplinter::Patched(patched) => patched.max_encoded_size(),
         }
     }
 
@@ -290,6 +477,31 @@ impl<B: Deref<Target = [u8]>> Encodable for CowSplinter<B> {
         match self {
             CowSplinter::Ref(splinter_ref) => splinter_ref.encode(encoder),
             CowSplinter::Owned(splinter) => splinter.encode(encoder),
+            CowSplinter::Patched(patched) => patched.encode(encoder),
+        }
+    }
+
+    fn encoded_features(&self) -> Features {
+        match self {
+            CowSplinter::Ref(splinter_ref) => splinter_ref.encoded_features(),
+            CowSplinter::Owned(splinter) => splinter.encoded_features(),
+            CowSplinter::Patched(patched) => patched.encoded_features(),
+        }
+    }
+
+    fn encoded_features_with(&self, features: Features) -> Features {
+        match self {
+            CowSplinter::Ref(splinter_ref) => splinter_ref.encoded_features_with(features),
+            CowSplinter::Owned(splinter) => splinter.encoded_features_with(features),
+            CowSplinter::Patched(patched) => patched.encoded_features_with(features),
+        }
+    }
+
+    fn required_features(&self) -> Features {
+        match self {
+            CowSplinter::Ref(splinter_ref) => splinter_ref.required_features(),
+            CowSplinter::Owned(splinter) => splinter.required_features(),
+            CowSplinter::Patched(patched) => patched.required_features(),
         }
     }
 }
@@ -299,6 +511,7 @@ impl<B: Deref<Target = [u8]>> PartitionRead<High> for CowSplinter<B> {
         match self {
             CowSplinter::Ref(splinter_ref) => splinter_ref.cardinality(),
             CowSplinter::Owned(splinter) => splinter.cardinality(),
+            CowSplinter::Patched(patched) => patched.cardinality(),
         }
     }
 
@@ -306,6 +519,7 @@ impl<B: Deref<Target = [u8]>> PartitionRead<High> for CowSplinter<B> {
         match self {
             CowSplinter::Ref(splinter_ref) => splinter_ref.is_empty(),
             CowSplinter::Owned(splinter) => splinter.is_empty(),
+            CowSplinter::Patched(patched) => patched.is_empty(),
         }
     }
 
@@ -313,6 +527,7 @@ impl<B: Deref<Target = [u8]>> PartitionRead<High> for CowSplinter<B> {
         match self {
             CowSplinter::Ref(splinter_ref) => splinter_ref.contains(value),
             CowSplinter::Owned(splinter) => splinter.contains(value),
+            CowSplinter::Patched(patched) => patched.contains(value),
         }
     }
 
@@ -320,6 +535,7 @@ impl<B: Deref<Target = [u8]>> PartitionRead<High> for CowSplinter<B> {
         match self {
             CowSplinter::Ref(splinter_ref) => splinter_ref.position(value),
             CowSplinter::Owned(splinter) => splinter.position(value),
+            CowSplinter::Patched(patched) => patched.position(value),
         }
     }
 
@@ -327,6 +543,7 @@ impl<B: Deref<Target = [u8]>> PartitionRead<High> for CowSplinter<B> {
         match self {
             CowSplinter::Ref(splinter_ref) => splinter_ref.rank(value),
             CowSplinter::Owned(splinter) => splinter.rank(value),
+            CowSplinter::Patched(patched) => patched.rank(value),
         }
     }
 
@@ -334,6 +551,7 @@ impl<B: Deref<Target = [u8]>> PartitionRead<High> for CowSplinter<B> {
         match self {
             CowSplinter::Ref(splinter_ref) => splinter_ref.select(idx),
             CowSplinter::Owned(splinter) => splinter.select(idx),
+            CowSplinter::Patched(patched) => patched.select(idx),
         }
     }
 
@@ -341,13 +559,15 @@ impl<B: Deref<Target = [u8]>> PartitionRead<High> for CowSplinter<B> {
         match self {
             CowSplinter::Ref(splinter_ref) => splinter_ref.last(),
             CowSplinter::Owned(splinter) => splinter.last(),
+            CowSplinter::Patched(patched) => patched.last(),
         }
     }
 
     fn iter(&self) -> impl Iterator<Item = u32> {
         match self {
             CowSplinter::Ref(splinter_ref) => Either::Left(splinter_ref.iter()),
-            CowSplinter::Owned(splinter) => Either::Right(splinter.iter()),
+            CowSplinter::Owned(splinter) => Either::Right(Either::Left(splinter.iter())),
+            CowSplinter::Patched(patched) => Either::Right(Either::Right(patched.iter())),
         }
     }
 
@@ -355,6 +575,7 @@ impl<B: Deref<Target = [u8]>> PartitionRead<High> for CowSplinter<B> {
         match self {
             CowSplinter::Ref(splinter_ref) => splinter_ref.contains_all(values),
             CowSplinter::Owned(splinter) => splinter.contains_all(values),
+            CowSplinter::Patched(patched) => patched.contains_all(values),
         }
     }
 
@@ -362,6 +583,7 @@ impl<B: Deref<Target = [u8]>> PartitionRead<High> for CowSplinter<B> {
         match self {
             CowSplinter::Ref(splinter_ref) => splinter_ref.contains_any(values),
             CowSplinter::Owned(splinter) => splinter.contains_any(values),
+            CowSplinter::Patched(patched) => patched.contains_any(values),
         }
     }
 }
@@ -369,17 +591,29 @@ impl<B: Deref<Target = [u8]>> PartitionRead<High> for CowSplinter<B> {
 impl<B: Deref<Target = [u8]>> PartitionWrite<High> for CowSplinter<B> {
     #[inline]
     fn insert(&mut self, value: u32) -> bool {
-        self.to_mut().insert(value)
+        self.patch();
+        match self {
+            Self::Patched(patched) => patched.insert(value),
+            _ => self.to_mut().insert(value),
+        }
     }
 
     #[inline]
     fn remove(&mut self, value: u32) -> bool {
-        self.to_mut().remove(value)
+        self.patch();
+        match self {
+            Self::Patched(patched) => patched.remove(value),
+            _ => self.to_mut().remove(value),
+        }
     }
 
     #[inline]
     fn remove_range<R: RangeBounds<u32>>(&mut self, values: R) {
-        self.to_mut().remove_range(values)
+        self.patch();
+        match self {
+            Self::Patched(patched) => patched.remove_range(values),
+            _ => self.to_mut().remove_range(values),
+        }
     }
 }
 
@@ -393,6 +627,8 @@ impl<B: Deref<Target = [u8]>, B2: Deref<Target = [u8]>> PartialEq<CowSplinter<B2
             (Ref(l), Owned(r)) => l == r,
             (Owned(l), Ref(r)) => l == r,
             (Owned(l), Owned(r)) => l == r,
+            (Patched(l), r) => l.eq_values(r),
+            (l, Patched(r)) => r.eq_values(l),
         }
     }
 }
@@ -412,6 +648,7 @@ impl<B: Deref<Target = [u8]>, B2: Deref<Target = [u8]>> PartialEq<SplinterRef<B2
         match self {
             CowSplinter::Ref(splinter_ref) => splinter_ref == other,
             CowSplinter::Owned(splinter) => splinter == other,
+            CowSplinter::Patched(patched) => patched.eq_values(other),
         }
     }
 }
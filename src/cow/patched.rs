@@ -0,0 +1,1481 @@
+use std::{
+    collections::BTreeMap,
+    fmt::{self, Debug},
+    ops::{BitAndAssign, BitOrAssign, BitXorAssign, Deref, RangeBounds, RangeInclusive, SubAssign},
+};
+
+use bytes::BufMut;
+use either::Either;
+use itertools::Itertools;
+use num::traits::Bounded;
+
+use crate::{
+    Cut, Encodable, PartitionRead, PartitionWrite, Splinter, SplinterRef,
+    codec::{
+        checksum::Checksum,
+        encoder::Encoder,
+        features::Features,
+        footer::Footer,
+        partition_ref::PartitionRef,
+        tree_ref::{TreeIndexBuilder, TreeRef},
+    },
+    level::{High, Level, Low, Mid},
+    partition::{Partition, tree::TreePartition},
+    partition_kind::PartitionKind,
+    segment::{Segment, SplitSegment},
+    util::{IteratorExt, RangeExt},
+};
+
+/// A [`SplinterRef`] with a set of modified children.
+///
+/// Every child of a tree partition is a self-contained encoded partition, so
+/// a small edit only needs to decode the child it touches. `PatchedSplinter`
+/// tracks the modified (dirty) children of the splinter's root tree by
+/// segment and reads every other child directly from the original buffer.
+/// When every value shares a single high segment the root is a prefix
+/// partition instead, and the children of the tree below the prefix are
+/// tracked. Encoding copies the clean children verbatim and only re-encodes
+/// the dirty children and the tree index, so the cost of an edit is
+/// proportional to the size of the children it modified rather than the size
+/// of the set.
+///
+/// `PatchedSplinter` is created by [`CowSplinter`] when a splinter with a tree
+/// root, or a prefix root over a tree, is modified. Only the index of the
+/// patched tree is validated up front, and splinters with a malformed index
+/// are decoded into an owned [`Splinter`] instead. Each child is validated
+/// against the index when an edit first decodes it, and if they disagree
+/// every child is decoded. Clean children are never validated, as they are
+/// copied verbatim.
+///
+/// [`CowSplinter`]: crate::CowSplinter
+#[derive(Clone)]
+pub struct PatchedSplinter<B> {
+    base: SplinterRef<B>,
+    dirty: Dirty,
+    cardinality: usize,
+}
+
+/// Decoded copies of the modified children, which may be empty.
+#[derive(Clone)]
+enum Dirty {
+    /// Children of the root tree. If the base splinter's root isn't a tree,
+    /// every child is dirty.
+    Tree(BTreeMap<Segment, Partition<Mid>>),
+    /// Children of the tree below a prefix root with the given segment.
+    Prefix(Segment, BTreeMap<Segment, Partition<Low>>),
+}
+
+/// The tree being patched, which reads its clean children from the base
+/// splinter.
+#[derive(Clone)]
+struct PatchedTree<'a, L: Level> {
+    /// the tree in the base splinter, or `None` if every child is dirty
+    base: Option<TreeRef<'a, L>>,
+    dirty: &'a BTreeMap<Segment, L::Down>,
+    cardinality: usize,
+}
+
+/// A [`PatchedTree`] which can modify its children.
+struct PatchedTreeMut<'a, L: Level> {
+    base: Option<TreeRef<'a, L>>,
+    dirty: &'a mut BTreeMap<Segment, L::Down>,
+    cardinality: &'a mut usize,
+}
+
+/// The root of a [`PatchedSplinter`].
+enum Root<T, U> {
+    Tree(T),
+    /// A prefix partition with the given segment over a tree
+    Prefix(Segment, U),
+}
+
+/// A child of a [`PatchedTree`].
+enum Child<'a, L: Level> {
+    Clean(CleanChild<'a, L>),
+    Dirty(&'a L::Down),
+}
+
+/// An unmodified child of the base tree. Encoding copies its bytes verbatim
+/// and takes its cardinality from the base tree's index, so it is only
+/// decoded when it is read.
+struct CleanChild<'a, L: Level> {
+    tree: TreeRef<'a, L>,
+    idx: usize,
+}
+
+impl<'a, L: Level> CleanChild<'a, L> {
+    fn bytes(&self) -> &'a [u8] {
+        self.tree.child_bytes(self.idx)
+    }
+
+    fn cardinality(&self) -> usize {
+        self.tree.prefix_cardinality(self.idx + 1) - self.tree.prefix_cardinality(self.idx)
+    }
+
+    fn load(&self) -> PartitionRef<'a, L::LevelDown> {
+        self.tree.load_child(self.idx)
+    }
+}
+
+/// Evaluates `$body` with `$partition` bound to a reference to the partition
+/// backing a [`Child`], whichever variant it is.
+macro_rules! with_child {
+    ($child:expr, |$partition:ident| $body:expr) => {
+        match $child {
+            Child::Clean(ref clean) => {
+                let $partition = &clean.load();
+                $body
+            }
+            Child::Dirty($partition) => $body,
+        }
+    };
+}
+
+fn root_tree<B: Deref<Target = [u8]>>(base: &SplinterRef<B>) -> Option<TreeRef<'_, High>> {
+    match base.load_unchecked() {
+        PartitionRef::Tree(tree) => Some(tree),
+        _ => None,
+    }
+}
+
+/// Returns the segment and child of `base`'s root if it is a prefix over a
+/// tree.
+fn prefix_tree<B: Deref<Target = [u8]>>(
+    base: &SplinterRef<B>,
+) -> Option<(Segment, TreeRef<'_, Mid>)> {
+    match base.load_unchecked() {
+        PartitionRef::Prefix(prefix) => {
+            let segment = prefix.segment();
+            match prefix.into_child() {
+                PartitionRef::Tree(tree) => Some((segment, tree)),
+                _ => None,
+            }
+        }
+        _ => None,
+    }
+}
+
+/// Clamps `values` to the values in `segment`, or returns `None` if they
+/// don't overlap.
+fn clamp_to_segment(
+    values: &RangeInclusive<u32>,
+    segment: Segment,
+) -> Option<RangeInclusive<<Mid as Level>::Value>> {
+    let (start, end) = (*values.start(), *values.end());
+    if start.segment() > segment || end.segment() < segment {
+        return None;
+    }
+    let start = if start.segment() == segment {
+        start.rest()
+    } else {
+        Bounded::min_value()
+    };
+    let end = if end.segment() == segment {
+        end.rest()
+    } else {
+        Bounded::max_value()
+    };
+    Some(start..=end)
+}
+
+impl<B: Deref<Target = [u8]>> PatchedSplinter<B> {
+    /// Wraps `base` with an empty set of modifications, or returns it
+    /// unchanged if its root is neither a tree nor a prefix over a tree, or
+    /// if the index of the patched tree is malformed.
+    pub(crate) fn new(base: SplinterRef<B>) -> Result<Self, SplinterRef<B>> {
+        let (dirty, valid) = if let Some(tree) = root_tree(&base) {
+            let valid = tree.validate_index(tree.children_len()).is_ok();
+            (Dirty::Tree(BTreeMap::new()), valid)
+        } else if let Some((segment, tree)) = prefix_tree(&base) {
+            let valid = tree.validate_index(tree.children_len()).is_ok();
+            (Dirty::Prefix(segment, BTreeMap::new()), valid)
+        } else {
+            return Err(base);
+        };
+        if !valid {
+            return Err(base);
+        }
+        let cardinality = base.cardinality();
+        Ok(Self { base, dirty, cardinality })
+    }
+
+    /// The number of children which have been modified since this splinter
+    /// was decoded. For splinters with a prefix root these are children of
+    /// the tree below the prefix.
+    pub fn dirty_segments(&self) -> usize {
+        match &self.dirty {
+            Dirty::Tree(dirty) => dirty.len(),
+            Dirty::Prefix(_, dirty) => dirty.len(),
+        }
+    }
+
+    /// Decodes this splinter into an owned [`Splinter`].
+    pub fn to_splinter(&self) -> Splinter {
+        if self.cardinality == 0 {
+            return Splinter::EMPTY;
+        }
+        match self.root() {
+            Root::Tree(tree) => Splinter::new(tree.to_partition()),
+            Root::Prefix(segment, tree) => {
+                let children = BTreeMap::from([(segment, tree.to_partition())]);
+                Splinter::new(Partition::Prefix(TreePartition::from(children)))
+            }
+        }
+    }
+
+    /// Returns true if this splinter contains exactly the same values as
+    /// `other`, without decoding the clean children.
+    pub(crate) fn eq_values(&self, other: &impl PartitionRead<High>) -> bool {
+        self.cardinality == other.cardinality() && self.iter().eq(other.iter())
+    }
+
+    fn root(&self) -> Root<PatchedTree<'_, High>, PatchedTree<'_, Mid>> {
+        match &self.dirty {
+            Dirty::Tree(dirty) => Root::Tree(PatchedTree {
+                base: root_tree(&self.base),
+                dirty,
+                cardinality: self.cardinality,
+            }),
+            Dirty::Prefix(segment, dirty) => Root::Prefix(
+                *segment,
+                PatchedTree {
+                    base: prefix_tree(&self.base).map(|(_, tree)| tree),
+                    dirty,
+                    cardinality: self.cardinality,
+                },
+            ),
+        }
+    }
+
+    fn root_mut(&mut self) -> Root<PatchedTreeMut<'_, High>, PatchedTreeMut<'_, Mid>> {
+        match &mut self.dirty {
+            Dirty::Tree(dirty) => Root::Tree(PatchedTreeMut {
+                base: root_tree(&self.base),
+                dirty,
+                cardinality: &mut self.cardinality,
+            }),
+            Dirty::Prefix(segment, dirty) => Root::Prefix(
+                *segment,
+                PatchedTreeMut {
+                    base: prefix_tree(&self.base).map(|(_, tree)| tree),
+                    dirty,
+                    cardinality: &mut self.cardinality,
+                },
+            ),
+        }
+    }
+
+    /// Switches from patching the tree below a prefix root to patching the
+    /// root itself, which decodes the prefix's child. The root then has no
+    /// clean children.
+    fn unprefix(&mut self) {
+        let (segment, child) = match self.root() {
+            Root::Prefix(segment, tree) => (segment, tree.to_partition()),
+            Root::Tree(_) => return,
+        };
+        // the cardinality is no longer read from the base tree's index
+        self.cardinality = child.cardinality();
+        self.dirty = Dirty::Tree(BTreeMap::from([(segment, child)]));
+    }
+
+    /// The features used by the clean children, which are copied verbatim.
+    fn base_features(&self) -> Features {
+        if matches!(self.dirty, Dirty::Tree(_)) && root_tree(&self.base).is_none() {
+            return Features::EMPTY;
+        }
+        // the encoded length is stored in the footer rather than the partitions
+        self.base.features().difference(Features::ENCODED_LENGTH)
+    }
+}
+
+impl<'a, L: Level + 'a> PatchedTree<'a, L> {
+    /// Returns the non-empty children in segment order.
+    fn children(&self) -> Vec<(Segment, Child<'a, L>)> {
+        let dirty = self.dirty;
+        let clean = self.base.iter().flat_map(|tree| {
+            tree.segments()
+                .enumerate()
+                .filter(|(_, segment)| !dirty.contains_key(segment))
+                .map(|(idx, segment)| {
+                    let tree = tree.clone();
+                    (segment, Child::Clean(CleanChild { tree, idx }))
+                })
+        });
+        let dirty = dirty
+            .iter()
+            .filter(|(_, partition)| !partition.is_empty())
+            .map(|(&segment, partition)| (segment, Child::Dirty(partition)));
+        clean.merge_by(dirty, |a, b| a.0 < b.0).collect()
+    }
+
+    fn child(&self, segment: Segment) -> Option<Child<'a, L>> {
+        match self.dirty.get(&segment) {
+            Some(partition) => (!partition.is_empty()).then_some(Child::Dirty(partition)),
+            None => {
+                let tree = self.base.as_ref()?;
+                let idx = tree.search_segment(segment).ok()?;
+                let tree = tree.clone();
+                Some(Child::Clean(CleanChild { tree, idx }))
+            }
+        }
+    }
+
+    /// The number of values stored in children with a smaller segment.
+    fn prefix_cardinality(&self, segment: Segment) -> usize {
+        let base_cardinality = |segment| match &self.base {
+            Some(tree) => match tree.search_segment(segment) {
+                Ok(idx) => tree.prefix_cardinality(idx + 1) - tree.prefix_cardinality(idx),
+                Err(_) => 0,
+            },
+            None => 0,
+        };
+        let base_prefix = self.base.as_ref().map_or(0, |tree| {
+            tree.prefix_cardinality(tree.search_segment(segment).unwrap_or_else(|idx| idx))
+        });
+        self.dirty
+            .range(..segment)
+            .fold(base_prefix, |acc, (&segment, child)| {
+                acc + child.cardinality() - base_cardinality(segment)
+            })
+    }
+
+    fn to_partition(&self) -> Partition<L> {
+        let children: BTreeMap<Segment, L::Down> = self
+            .children()
+            .into_iter()
+            .map(|(segment, child)| {
+                let partition = match child {
+                    Child::Clean(clean) => L::Down::from(&clean.load()),
+                    Child::Dirty(partition) => partition.clone(),
+                };
+                (segment, partition)
+            })
+            .collect();
+        if children.is_empty() {
+            Partition::EMPTY
+        } else {
+            Partition::Tree(TreePartition::from(children))
+        }
+    }
+
+    fn into_values(self) -> impl Iterator<Item = L::Value> + 'a {
+        let cardinality = self.cardinality;
+        self.children()
+            .into_iter()
+            .flat_map(|(segment, child)| {
+                child
+                    .into_values()
+                    .map(move |v| L::Value::unsplit(segment, v))
+            })
+            .with_size_hint(cardinality)
+    }
+
+    /// The encoded size of this tree, including its kind, when encoded with
+    /// the provided optional features.
+    fn encoded_size(&self, features: Features) -> usize {
+        let children = self.children();
+        let values: usize = children
+            .iter()
+            .map(|(_, child)| match child {
+                Child::Clean(clean) => clean.bytes().len(),
+                Child::Dirty(partition) => partition.encoded_size_with(features),
+            })
+            .sum();
+        // values + index + kind
+        values + TreeIndexBuilder::<L>::encoded_size(children.len()) + 1
+    }
+
+    /// Encodes this tree, which must not be empty, copying the clean children
+    /// verbatim.
+    fn encode<T: BufMut>(&self, encoder: &mut Encoder<T>) {
+        let children = self.children();
+        let mut index = TreeIndexBuilder::<L>::new(children.len());
+        for (segment, child) in &children {
+            match child {
+                Child::Clean(clean) => encoder.put_slice(clean.bytes()),
+                Child::Dirty(partition) => partition.encode(encoder),
+            }
+            index.push(*segment, encoder.bytes_written(), child.cardinality());
+        }
+        encoder.put_tree_index(index);
+        encoder.put_kind(PartitionKind::Tree);
+    }
+
+    /// The features used by the dirty children when encoded with the
+    /// provided optional features.
+    fn encoded_features(&self, features: Features) -> Features {
+        self.dirty.values().fold(Features::EMPTY, |used, child| {
+            used | child.encoded_features_with(features)
+        })
+    }
+}
+
+impl<'a, L: Level + 'a> PatchedTreeMut<'a, L> {
+    fn as_ref(&self) -> PatchedTree<'_, L> {
+        PatchedTree {
+            base: self.base.clone(),
+            dirty: self.dirty,
+            cardinality: *self.cardinality,
+        }
+    }
+
+    /// Validates the clean child at `segment`, if any, before it is read or
+    /// decoded, and checks its cardinality against the base tree's index.
+    /// If either is malformed the index can't be trusted, so every clean
+    /// child is decoded instead.
+    fn check_child(&mut self, segment: Segment) {
+        let Some(tree) = &self.base else { return };
+        if self.dirty.contains_key(&segment) {
+            return;
+        }
+        let Ok(idx) = tree.search_segment(segment) else {
+            return;
+        };
+        let expected = tree.prefix_cardinality(idx + 1) - tree.prefix_cardinality(idx);
+        let valid = PartitionRef::<L::LevelDown>::validate_suffix(tree.child_bytes(idx))
+            .is_ok_and(|child| child.cardinality() == expected);
+        if !valid {
+            self.detach();
+        }
+    }
+
+    /// Decodes every clean child, and recomputes the cardinality from the
+    /// decoded children rather than the base tree's index.
+    fn detach(&mut self) {
+        if let Some(tree) = &self.base {
+            for (idx, segment) in tree.segments().enumerate() {
+                self.dirty
+                    .entry(segment)
+                    .or_insert_with(|| L::Down::from(&tree.load_child(idx)));
+            }
+        }
+        *self.cardinality = self.dirty.values().map(|child| child.cardinality()).sum();
+    }
+
+    /// Returns the dirty copy of the child at `segment`, decoding it from the
+    /// base tree if needed. Clean children must be checked with
+    /// [`Self::check_child`] first.
+    fn child_mut(&mut self, segment: Segment) -> &mut L::Down {
+        let base = &self.base;
+        self.dirty.entry(segment).or_insert_with(|| {
+            base.as_ref()
+                .and_then(|tree| tree.load_child_at_segment(segment))
+                .map(|child| L::Down::from(&child))
+                .unwrap_or_default()
+        })
+    }
+
+    fn insert(&mut self, value: L::Value) -> bool {
+        self.check_child(value.segment());
+        // avoid decoding the child if the value is already present
+        if self.as_ref().contains(value) {
+            return false;
+        }
+        let (segment, value) = value.split();
+        self.child_mut(segment).insert(value);
+        *self.cardinality += 1;
+        true
+    }
+
+    fn remove(&mut self, value: L::Value) -> bool {
+        self.check_child(value.segment());
+        if !self.as_ref().contains(value) {
+            return false;
+        }
+        let (segment, value) = value.split();
+        self.child_mut(segment).remove(value);
+        *self.cardinality -= 1;
+        true
+    }
+
+    fn remove_range(&mut self, values: RangeInclusive<L::Value>) {
+        let p1 = (*values.start()).segment_end().min(*values.end());
+        let p2 = (*values.end()).segment_start().max(*values.start());
+        let segments = values.start().segment()..=values.end().segment();
+
+        let covered = self
+            .as_ref()
+            .children()
+            .into_iter()
+            .map(|(segment, _)| segment)
+            .filter(|segment| segments.contains(segment))
+            .collect_vec();
+        for segment in covered {
+            // special case first and last segment
+            let range = if segment == *segments.start() {
+                values.start().rest()..=p1.rest()
+            } else if segment == *segments.end() {
+                p2.rest()..=values.end().rest()
+            } else {
+                self.dirty.insert(segment, L::Down::default());
+                continue;
+            };
+            self.check_child(segment);
+            if self
+                .as_ref()
+                .child(segment)
+                .is_some_and(|child| child.contains_any(range.clone()))
+            {
+                self.child_mut(segment).remove_range(range);
+            }
+        }
+
+        let cardinality = self
+            .as_ref()
+            .children()
+            .iter()
+            .map(|(_, child)| child.cardinality())
+            .sum();
+        *self.cardinality = cardinality;
+    }
+}
+
+impl<B: Deref<Target = [u8]>> Debug for PatchedSplinter<B> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        let mut f = f.debug_struct("PatchedSplinter");
+        f.field("base", &self.base);
+        match &self.dirty {
+            Dirty::Tree(dirty) => f.field("dirty", &dirty.keys()),
+            Dirty::Prefix(segment, dirty) => {
+                f.field("prefix", segment).field("dirty", &dirty.keys())
+            }
+        };
+        f.field("cardinality", &self.cardinality).finish()
+    }
+}
+
+impl<B: Deref<Target = [u8]>> Encodable for PatchedSplinter<B> {
+    fn encoded_size(&self) -> usize {
+        self.encoded_size_with(self.required_features())
+    }
+
+    fn encoded_size_with(&self, features: Features) -> usize {
+        if self.cardinality == 0 {
+            return Splinter::EMPTY.encoded_size();
+        }
+        if !features.contains(self.base_features()) {
+            return self.to_splinter().encoded_size_with(features);
+        }
+        let root = match self.root() {
+            Root::Tree(tree) => tree.encoded_size(features),
+            // child + segment + kind
+            Root::Prefix(_, tree) => tree.encoded_size(features) + 2,
+        };
+        let footer = Footer::new(Checksum::default(), self.encoded_features_with(features));
+        root + footer.encoded_size()
+    }
+
+    fn max_encoded_size(&self) -> usize {
+        if self.cardinality == 0 {
+            return Splinter::EMPTY.encoded_size();
+        }
+        // the clean children, root and tree index take up no more space than
+        // in the base splinter, so only the dirty children and their index
+        // entries are added to it
+        let dirty = match &self.dirty {
+            Dirty::Tree(dirty) => dirty_max_encoded_size::<High>(dirty),
+            Dirty::Prefix(_, dirty) => dirty_max_encoded_size::<Mid>(dirty),
+        };
+        let footer = Footer::new(Checksum::default(), Features::SUPPORTED);
+        self.base.inner().len() + dirty + footer.encoded_size()
+    }
+
+    fn encode<T: BufMut>(&self, encoder: &mut Encoder<T>) {
+        let features = self.base_features();
+        if self.cardinality == 0 || !encoder.supports(features) || encoder.aligns_bitmaps() {
+            // the clean children can't be copied into this encoding
+            return self.to_splinter().encode(encoder);
+        }
+
+        encoder.use_features(features);
+        match self.root() {
+            Root::Tree(tree) => tree.encode(encoder),
+            Root::Prefix(segment, tree) => {
+                tree.encode(encoder);
+                encoder.put_slice(&[segment]);
+                encoder.put_kind(PartitionKind::Prefix);
+            }
+        }
+        encoder.write_footer();
+    }
+
+    fn encoded_features(&self) -> Features {
+        self.encoded_features_with(Features::SUPPORTED)
+    }
+
+    fn encoded_features_with(&self, features: Features) -> Features {
+        if self.cardinality == 0 {
+            return Features::EMPTY;
+        }
+        if !features.contains(self.base_features()) {
+            return self.to_splinter().encoded_features_with(features);
+        }
+        let dirty = match self.root() {
+            Root::Tree(tree) => tree.encoded_features(features),
+            Root::Prefix(_, tree) => tree.encoded_features(features),
+        };
+        self.base_features() | dirty
+    }
+
+    /// The features used by the base splinter, which the clean children can
+    /// only be copied into encoders supporting.
+    fn required_features(&self) -> Features {
+        self.base_features()
+    }
+}
+
+/// An upper bound on the space the dirty children of a tree and their index
+/// entries add to its encoding.
+fn dirty_max_encoded_size<L: Level>(dirty: &BTreeMap<Segment, L::Down>) -> usize {
+    let values: usize = dirty.values().map(|child| child.max_encoded_size()).sum();
+    values + TreeIndexBuilder::<L>::encoded_size(dirty.len())
+}
+
+impl<B: Deref<Target = [u8]>> PartitionRead<High> for PatchedSplinter<B> {
+    fn cardinality(&self) -> usize {
+        self.cardinality
+    }
+
+    fn is_empty(&self) -> bool {
+        self.cardinality == 0
+    }
+
+    fn contains(&self, value: u32) -> bool {
+        match self.root() {
+            Root::Tree(tree) => tree.contains(value),
+            Root::Prefix(segment, tree) => {
+                let (value_segment, value) = value.split();
+                value_segment == segment && tree.contains(value)
+            }
+        }
+    }
+
+    fn position(&self, value: u32) -> Option<usize> {
+        match self.root() {
+            Root::Tree(tree) => tree.position(value),
+            Root::Prefix(segment, tree) => {
+                let (value_segment, value) = value.split();
+                if value_segment == segment {
+                    tree.position(value)
+                } else {
+                    None
+                }
+            }
+        }
+    }
+
+    fn rank(&self, value: u32) -> usize {
+        match self.root() {
+            Root::Tree(tree) => tree.rank(value),
+            Root::Prefix(segment, tree) => {
+                let (value_segment, value) = value.split();
+                match value_segment.cmp(&segment) {
+                    std::cmp::Ordering::Less => 0,
+                    std::cmp::Ordering::Equal => tree.rank(value),
+                    std::cmp::Ordering::Greater => self.cardinality,
+                }
+            }
+        }
+    }
+
+    fn select(&self, n: usize) -> Option<u32> {
+        match self.root() {
+            Root::Tree(tree) => tree.select(n),
+            Root::Prefix(segment, tree) => tree.select(n).map(|v| u32::unsplit(segment, v)),
+        }
+    }
+
+    fn last(&self) -> Option<u32> {
+        match self.root() {
+            Root::Tree(tree) => tree.last(),
+            Root::Prefix(segment, tree) => tree.last().map(|v| u32::unsplit(segment, v)),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = u32> {
+        match self.root() {
+            Root::Tree(tree) => Either::Left(tree.into_values()),
+            Root::Prefix(segment, tree) => {
+                Either::Right(tree.into_values().map(move |v| u32::unsplit(segment, v)))
+            }
+        }
+    }
+
+    fn contains_all<R: RangeBounds<u32>>(&self, values: R) -> bool {
+        match self.root() {
+            Root::Tree(tree) => tree.contains_all(values),
+            Root::Prefix(segment, tree) => {
+                if let Some(values) = values.try_into_inclusive() {
+                    let (start, end) = (*values.start(), *values.end());
+                    // every value must share the prefix's segment
+                    start.segment() == segment
+                        && end.segment() == segment
+                        && tree.contains_all(start.rest()..=end.rest())
+                } else {
+                    // empty range is trivially contained
+                    true
+                }
+            }
+        }
+    }
+
+    fn contains_any<R: RangeBounds<u32>>(&self, values: R) -> bool {
+        match self.root() {
+            Root::Tree(tree) => tree.contains_any(values),
+            Root::Prefix(segment, tree) => values
+                .try_into_inclusive()
+                .and_then(|values| clamp_to_segment(&values, segment))
+                .is_some_and(|values| tree.contains_any(values)),
+        }
+    }
+}
+
+impl<B: Deref<Target = [u8]>> PartitionWrite<High> for PatchedSplinter<B> {
+    fn insert(&mut self, value: u32) -> bool {
+        if let Dirty::Prefix(segment, _) = &self.dirty
+            && value.segment() != *segment
+        {
+            // the root gains a second child, so it must become a tree
+            self.unprefix();
+        }
+        match self.root_mut() {
+            Root::Tree(mut tree) => tree.insert(value),
+            Root::Prefix(_, mut tree) => tree.insert(value.rest()),
+        }
+    }
+
+    fn remove(&mut self, value: u32) -> bool {
+        match self.root_mut() {
+            Root::Tree(mut tree) => tree.remove(value),
+            Root::Prefix(segment, mut tree) => {
+                let (value_segment, value) = value.split();
+                value_segment == segment && tree.remove(value)
+            }
+        }
+    }
+
+    fn remove_range<R: RangeBounds<u32>>(&mut self, values: R) {
+        let Some(values) = values.try_into_inclusive() else {
+            return;
+        };
+        match self.root_mut() {
+            Root::Tree(mut tree) => tree.remove_range(values),
+            Root::Prefix(segment, mut tree) => {
+                if let Some(values) = clamp_to_segment(&values, segment) {
+                    tree.remove_range(values);
+                }
+            }
+        }
+    }
+}
+
+impl<'a, L: Level + 'a> PartitionRead<L> for PatchedTree<'a, L> {
+    fn cardinality(&self) -> usize {
+        self.cardinality
+    }
+
+    fn is_empty(&self) -> bool {
+        self.cardinality == 0
+    }
+
+    fn contains(&self, value: L::Value) -> bool {
+        let (segment, value) = value.split();
+        self.child(segment)
+            .is_some_and(|child| child.contains(value))
+    }
+
+    fn position(&self, value: L::Value) -> Option<usize> {
+        let (segment, value) = value.split();
+        let child_pos = self.child(segment)?.position(value)?;
+        Some(self.prefix_cardinality(segment) + child_pos)
+    }
+
+    fn rank(&self, value: L::Value) -> usize {
+        let (segment, value) = value.split();
+        let child_rank = self.child(segment).map_or(0, |child| child.rank(value));
+        self.prefix_cardinality(segment) + child_rank
+    }
+
+    fn select(&self, mut n: usize) -> Option<L::Value> {
+        for (segment, child) in self.children() {
+            let len = child.cardinality();
+            if n < len {
+                return child.select(n).map(|v| L::Value::unsplit(segment, v));
+            }
+            n -= len;
+        }
+        None
+    }
+
+    fn last(&self) -> Option<L::Value> {
+        let (segment, child) = self.children().pop()?;
+        child.last().map(|v| L::Value::unsplit(segment, v))
+    }
+
+    fn iter(&self) -> impl Iterator<Item = L::Value> {
+        self.clone().into_values()
+    }
+
+    fn contains_all<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        if let Some(values) = values.try_into_inclusive() {
+            let p1 = (*values.start()).segment_end().min(*values.end());
+            let p2 = (*values.end()).segment_start().max(*values.start());
+            let segments = values.start().segment()..=values.end().segment();
+
+            segments.clone().all(|segment| {
+                let Some(child) = self.child(segment) else {
+                    return false;
+                };
+                if segment == *segments.start() {
+                    child.contains_all(values.start().rest()..=p1.rest())
+                } else if segment == *segments.end() {
+                    child.contains_all(p2.rest()..=values.end().rest())
+                } else {
+                    // middle segments must be full
+                    child.cardinality() == L::LevelDown::MAX_LEN
+                }
+            })
+        } else {
+            // empty range is trivially contained
+            true
+        }
+    }
+
+    fn contains_any<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        if let Some(values) = values.try_into_inclusive() {
+            let p1 = (*values.start()).segment_end().min(*values.end());
+            let p2 = (*values.end()).segment_start().max(*values.start());
+            let segments = values.start().segment()..=values.end().segment();
+
+            segments.clone().any(|segment| {
+                let Some(child) = self.child(segment) else {
+                    return false;
+                };
+                if segment == *segments.start() {
+                    child.contains_any(values.start().rest()..=p1.rest())
+                } else if segment == *segments.end() {
+                    child.contains_any(p2.rest()..=values.end().rest())
+                } else {
+                    // children are never empty
+                    true
+                }
+            })
+        } else {
+            // empty range has no intersection
+            false
+        }
+    }
+}
+
+/// Converts `root` into a tree, so that it can be combined with a
+/// [`PatchedTree`] child by child.
+fn as_tree<L: Level>(root: &mut Partition<L>) -> &mut TreePartition<L> {
+    if !matches!(root, Partition::Tree(_) | Partition::Prefix(_)) {
+        *root = Partition::Tree(root.to_tree().into_owned());
+    }
+    match root {
+        Partition::Tree(tree) | Partition::Prefix(tree) => tree,
+        _ => unreachable!(),
+    }
+}
+
+/// Builds an optimized tree from the non-empty `children`.
+fn tree_from<L: Level>(children: BTreeMap<Segment, L::Down>) -> Partition<L> {
+    let mut tree = Partition::Tree(TreePartition::from(children));
+    tree.optimize_fast();
+    tree
+}
+
+// The following ops read the clean children directly from the base splinter,
+// so only the children which end up in the result are decoded. A prefix root
+// only affects the child at its segment.
+
+impl<'a, L: Level + 'a> BitOrAssign<&PatchedTree<'a, L>> for Partition<L> {
+    fn bitor_assign(&mut self, rhs: &PatchedTree<'a, L>) {
+        as_tree(self).update_children(|children| {
+            for (segment, child) in rhs.children() {
+                let lhs = children.entry(segment).or_default();
+                with_child!(child, |child| lhs.bitor_assign(child));
+            }
+        });
+        self.optimize_fast();
+    }
+}
+
+impl<'a, L: Level + 'a> BitAndAssign<&PatchedTree<'a, L>> for Partition<L> {
+    fn bitand_assign(&mut self, rhs: &PatchedTree<'a, L>) {
+        as_tree(self).update_children(|children| {
+            children.retain(|&segment, lhs| match rhs.child(segment) {
+                Some(child) => {
+                    with_child!(child, |child| lhs.bitand_assign(child));
+                    true
+                }
+                None => false,
+            });
+        });
+        self.optimize_fast();
+    }
+}
+
+impl<'a, L: Level + 'a> BitXorAssign<&PatchedTree<'a, L>> for Partition<L> {
+    fn bitxor_assign(&mut self, rhs: &PatchedTree<'a, L>) {
+        as_tree(self).update_children(|children| {
+            for (segment, child) in rhs.children() {
+                let lhs = children.entry(segment).or_default();
+                with_child!(child, |child| lhs.bitxor_assign(child));
+            }
+        });
+        self.optimize_fast();
+    }
+}
+
+impl<'a, L: Level + 'a> SubAssign<&PatchedTree<'a, L>> for Partition<L> {
+    fn sub_assign(&mut self, rhs: &PatchedTree<'a, L>) {
+        as_tree(self).update_children(|children| {
+            for (segment, lhs) in children.iter_mut() {
+                if let Some(child) = rhs.child(*segment) {
+                    with_child!(child, |child| lhs.sub_assign(child));
+                }
+            }
+        });
+        self.optimize_fast();
+    }
+}
+
+impl<'a, L: Level + 'a> Cut<PatchedTree<'a, L>> for Partition<L> {
+    type Out = Self;
+
+    fn cut(&mut self, rhs: &PatchedTree<'a, L>) -> Self::Out {
+        let mut intersection = BTreeMap::new();
+        as_tree(self).update_children(|children| {
+            for (segment, child) in rhs.children() {
+                if let Some(lhs) = children.get_mut(&segment) {
+                    let cut = with_child!(child, |child| lhs.cut(child));
+                    if !cut.is_empty() {
+                        intersection.insert(segment, cut);
+                    }
+                }
+            }
+        });
+        self.optimize_fast();
+        tree_from(intersection)
+    }
+}
+
+impl<B: Deref<Target = [u8]>> BitOrAssign<&PatchedSplinter<B>> for Partition<High> {
+    fn bitor_assign(&mut self, rhs: &PatchedSplinter<B>) {
+        match rhs.root() {
+            Root::Tree(tree) => self.bitor_assign(&tree),
+            Root::Prefix(segment, tree) => {
+                as_tree(self).update_children(|children| {
+                    children.entry(segment).or_default().bitor_assign(&tree);
+                });
+                self.optimize_fast();
+            }
+        }
+    }
+}
+
+impl<B: Deref<Target = [u8]>> BitAndAssign<&PatchedSplinter<B>> for Partition<High> {
+    fn bitand_assign(&mut self, rhs: &PatchedSplinter<B>) {
+        match rhs.root() {
+            Root::Tree(tree) => self.bitand_assign(&tree),
+            Root::Prefix(segment, tree) => {
+                as_tree(self).update_children(|children| {
+                    children.retain(|&child_segment, _| child_segment == segment);
+                    if let Some(lhs) = children.get_mut(&segment) {
+                        lhs.bitand_assign(&tree);
+                    }
+                });
+                self.optimize_fast();
+            }
+        }
+    }
+}
+
+impl<B: Deref<Target = [u8]>> BitXorAssign<&PatchedSplinter<B>> for Partition<High> {
+    fn bitxor_assign(&mut self, rhs: &PatchedSplinter<B>) {
+        match rhs.root() {
+            Root::Tree(tree) => self.bitxor_assign(&tree),
+            Root::Prefix(segment, tree) => {
+                as_tree(self).update_children(|children| {
+                    children.entry(segment).or_default().bitxor_assign(&tree);
+                });
+                self.optimize_fast();
+            }
+        }
+    }
+}
+
+impl<B: Deref<Target = [u8]>> SubAssign<&PatchedSplinter<B>> for Partition<High> {
+    fn sub_assign(&mut self, rhs: &PatchedSplinter<B>) {
+        match rhs.root() {
+            Root::Tree(tree) => self.sub_assign(&tree),
+            Root::Prefix(segment, tree) => {
+                as_tree(self).update_children(|children| {
+                    if let Some(lhs) = children.get_mut(&segment) {
+                        lhs.sub_assign(&tree);
+                    }
+                });
+                self.optimize_fast();
+            }
+        }
+    }
+}
+
+impl<B: Deref<Target = [u8]>> Cut<PatchedSplinter<B>> for Partition<High> {
+    type Out = Self;
+
+    fn cut(&mut self, rhs: &PatchedSplinter<B>) -> Self::Out {
+        match rhs.root() {
+            Root::Tree(tree) => self.cut(&tree),
+            Root::Prefix(segment, tree) => {
+                let mut intersection = BTreeMap::new();
+                as_tree(self).update_children(|children| {
+                    if let Some(lhs) = children.get_mut(&segment) {
+                        let cut = lhs.cut(&tree);
+                        if !cut.is_empty() {
+                            intersection.insert(segment, cut);
+                        }
+                    }
+                });
+                self.optimize_fast();
+                tree_from(intersection)
+            }
+        }
+    }
+}
+
+impl<'a, L: Level + 'a> Child<'a, L> {
+    fn into_values(self) -> impl Iterator<Item = <L::LevelDown as Level>::Value> + 'a {
+        match self {
+            Child::Clean(clean) => Either::Left(clean.load().into_iter()),
+            Child::Dirty(partition) => Either::Right(partition.iter()),
+        }
+    }
+}
+
+impl<L: Level> PartitionRead<L::LevelDown> for Child<'_, L> {
+    fn cardinality(&self) -> usize {
+        match self {
+            Child::Clean(clean) => clean.cardinality(),
+            Child::Dirty(partition) => partition.cardinality(),
+        }
+    }
+
+    fn is_empty(&self) -> bool {
+        match self {
+            // children are never empty
+            Child::Clean(_) => false,
+            Child::Dirty(partition) => partition.is_empty(),
+        }
+    }
+
+    fn contains(&self, value: <L::LevelDown as Level>::Value) -> bool {
+        match self {
+            Child::Clean(clean) => clean.load().contains(value),
+            Child::Dirty(partition) => partition.contains(value),
+        }
+    }
+
+    fn position(&self, value: <L::LevelDown as Level>::Value) -> Option<usize> {
+        match self {
+            Child::Clean(clean) => clean.load().position(value),
+            Child::Dirty(partition) => partition.position(value),
+        }
+    }
+
+    fn rank(&self, value: <L::LevelDown as Level>::Value) -> usize {
+        match self {
+            Child::Clean(clean) => clean.load().rank(value),
+            Child::Dirty(partition) => partition.rank(value),
+        }
+    }
+
+    fn select(&self, idx: usize) -> Option<<L::LevelDown as Level>::Value> {
+        match self {
+            Child::Clean(clean) => clean.load().select(idx),
+            Child::Dirty(partition) => partition.select(idx),
+        }
+    }
+
+    fn last(&self) -> Option<<L::LevelDown as Level>::Value> {
+        match self {
+            Child::Clean(clean) => clean.load().last(),
+            Child::Dirty(partition) => partition.last(),
+        }
+    }
+
+    fn iter(&self) -> impl Iterator<Item = <L::LevelDown as Level>::Value> {
+        match self {
+            Child::Clean(clean) => Either::Left(clean.load().into_iter()),
+            Child::Dirty(partition) => Either::Right(partition.iter()),
+        }
+    }
+
+    fn contains_all<R: RangeBounds<<L::LevelDown as Level>::Value>>(&self, values: R) -> bool {
+        match self {
+            Child::Clean(clean) => clean.load().contains_all(values),
+            Child::Dirty(partition) => partition.contains_all(values),
+        }
+    }
+
+    fn contains_any<R: RangeBounds<<L::LevelDown as Level>::Value>>(&self, values: R) -> bool {
+        match self {
+            Child::Clean(clean) => clean.load().contains_any(values),
+            Child::Dirty(partition) => partition.contains_any(values),
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+    use proptest::{collection::vec, proptest};
+
+    use crate::{
+        CowSplinter, Cut, Encodable, Optimizable, PartitionRead, PartitionWrite, Splinter,
+        SplinterRef,
+        codec::{features::Features, partition_ref::PartitionRef},
+        cow::patched::root_tree,
+        level::High,
+        partition_kind::PartitionKind,
+        testutil::{
+            encode_with_features, mkpartition_buf, mksplinter_manual,
+            mksplinter_manual_with_features, test_partition_read,
+        },
+    };
+
+    /// 100 values in each of the first 64 high segments
+    fn values() -> impl Iterator<Item = u32> {
+        (0..64).flat_map(|high| (0..100).map(move |i| (high << 24) | (i * 3)))
+    }
+
+    #[test]
+    fn test_patched_reuses_clean_children() {
+        let base = Splinter::from_iter(values()).encode_to_splinter_ref();
+        let mut cow = CowSplinter::from_ref(base.clone());
+        let mut expected = Splinter::from_iter(values());
+
+        for (value, insert) in [(5 << 24 | 1, true), (9 << 24 | 3, false), (200 << 24, true)] {
+            if insert {
+                assert!(cow.insert(value) && expected.insert(value));
+            } else {
+                assert!(cow.remove(value) && expected.remove(value));
+            }
+        }
+        // existing values don't dirty their children
+        assert!(!cow.insert(0));
+        assert!(!cow.remove(1));
+
+        let CowSplinter::Patched(patched) = &cow else {
+            panic!("expected a patched splinter: {cow:?}");
+        };
+        assert_eq!(patched.dirty_segments(), 3);
+        assert_eq!(cow, expected);
+        test_partition_read(patched, &expected.iter().collect_vec());
+
+        let encoded = cow.encode_to_bytes();
+        assert_eq!(encoded.len(), cow.encoded_size());
+        let splinter_ref = SplinterRef::from_bytes_validated(encoded.clone()).unwrap();
+        assert_eq!(splinter_ref, expected);
+
+        // the children before the first dirty segment are copied verbatim
+        let tree = root_tree(&base).unwrap();
+        let clean = tree.children_len() - tree.child_end_offset(4);
+        assert_eq!(encoded[..clean], base.inner()[..clean]);
+    }
+
+    #[test]
+    fn test_patched_remove() {
+        let mut cow = CowSplinter::from_ref(Splinter::from_iter(values()).encode_to_splinter_ref());
+        let mut expected = Splinter::from_iter(values());
+
+        // remove an entire child, and part of two more
+        for range in [(3 << 24)..(4 << 24), (10 << 24 | 150)..(12 << 24 | 150)] {
+            cow.remove_range(range.clone());
+            expected.remove_range(range);
+        }
+        assert!(matches!(cow, CowSplinter::Patched(_)));
+        assert_eq!(cow, expected);
+        test_partition_read(&cow, &expected.iter().collect_vec());
+        let encoded = cow.encode_to_bytes();
+        assert_eq!(encoded.len(), cow.encoded_size());
+        assert_eq!(
+            SplinterRef::from_bytes_validated(encoded).unwrap(),
+            expected
+        );
+
+        // emptying the splinter encodes an empty splinter
+        cow.remove_range(..);
+        assert!(cow.is_empty());
+        assert_eq!(cow.encode_to_bytes(), Splinter::EMPTY.encode_to_bytes());
+        assert_eq!(cow.into_owned(), Splinter::EMPTY);
+    }
+
+    #[test]
+    fn test_patched_requires_tree_root() {
+        // small splinters don't have a tree root, so they are decoded entirely
+        let mut cow =
+            CowSplinter::from_ref(Splinter::from_iter([1, 2, 3]).encode_to_splinter_ref());
+        assert!(cow.insert(4));
+        assert!(matches!(cow, CowSplinter::Owned(_)));
+        assert_eq!(cow, Splinter::from_iter(1..=4));
+    }
+
+    #[test]
+    fn test_patched_requires_valid_base() {
+        let mut buf = mkpartition_buf::<High>(PartitionKind::Tree, &[1, 2]);
+        // corrupt the cumulative cardinality, which the checksum doesn't
+        // detect
+        buf[17] = 0x05;
+        let corrupted = mksplinter_manual(&buf);
+        let mut cow = CowSplinter::from_bytes(corrupted).unwrap();
+        assert!(matches!(cow, CowSplinter::Ref(_)));
+
+        // the edited child doesn't match the tree index, so the index isn't
+        // trusted and every child is decoded
+        assert!(cow.insert(3));
+        let CowSplinter::Patched(patched) = &cow else {
+            panic!("expected a patched splinter: {cow:?}");
+        };
+        assert_eq!(patched.dirty_segments(), 1);
+        assert_eq!(cow.cardinality(), 3);
+        assert_eq!(cow, Splinter::from_iter(1..=3));
+        assert_eq!(
+            SplinterRef::from_bytes_validated(cow.encode_to_bytes()).unwrap(),
+            Splinter::from_iter(1..=3)
+        );
+    }
+
+    #[test]
+    fn test_patched_skips_clean_children() {
+        let values = values().collect_vec();
+        let mut buf = mkpartition_buf::<High>(PartitionKind::Tree, &values);
+        let PartitionRef::Tree(tree) = PartitionRef::<High>::from_suffix(&buf).unwrap() else {
+            panic!("expected a tree root");
+        };
+        // corrupt the kind of the first child, which the checksum doesn't
+        // detect
+        let end = tree.children_len() - tree.child_end_offset(0);
+        buf[end - 1] = 0xFF;
+        let corrupted = mksplinter_manual(&buf);
+        let mut cow = CowSplinter::from_bytes(corrupted.clone()).unwrap();
+
+        // edits only validate and decode the children they touch, so the
+        // cost of an edit doesn't depend on the size of the set, and the
+        // corrupted child is never read
+        assert!(cow.insert(5 << 24 | 1));
+        assert!(cow.remove(9 << 24));
+        cow.remove_range((20 << 24)..(30 << 24 | 150));
+        let CowSplinter::Patched(patched) = &cow else {
+            panic!("expected a patched splinter: {cow:?}");
+        };
+        assert_eq!(patched.dirty_segments(), 13);
+        assert_eq!(cow.cardinality(), values.len() + 1 - 1 - 10 * 100 - 50);
+
+        // the corrupted child is copied verbatim
+        let encoded = cow.encode_to_bytes();
+        assert_eq!(encoded.len(), cow.encoded_size());
+        assert_eq!(encoded[..end], corrupted[..end]);
+        assert!(SplinterRef::from_bytes_validated(encoded).is_err());
+    }
+
+    #[test]
+    fn test_patched_copies_clean_children_with_features() {
+        // every child is stored as an inverted partition, which depends on
+        // an optional feature
+        let mut splinter = Splinter::from_iter((0..8).flat_map(|high| {
+            (0..65536)
+                .filter(|v| v % 1000 != 0)
+                .map(move |v| (high << 24) | v)
+        }));
+        splinter.optimize_with(Features::SUPPORTED);
+        let features = splinter.encoded_features();
+        assert!(!features.is_empty());
+
+        let mut buf = encode_with_features(splinter.inner(), Features::SUPPORTED).to_vec();
+        let PartitionRef::Tree(tree) = PartitionRef::<High>::from_suffix(&buf).unwrap() else {
+            panic!("expected a tree root");
+        };
+        // corrupt the kind of the first child, which the checksum doesn't
+        // detect
+        let end = tree.children_len() - tree.child_end_offset(0);
+        buf[end - 1] = 0xFF;
+        let corrupted = mksplinter_manual_with_features(&buf, features);
+        let mut cow = CowSplinter::from_bytes(corrupted.clone()).unwrap();
+        assert!(cow.insert(5 << 24));
+        let CowSplinter::Patched(patched) = &cow else {
+            panic!("expected a patched splinter: {cow:?}");
+        };
+        assert_eq!(patched.dirty_segments(), 1);
+        assert_eq!(cow.required_features(), features);
+
+        // the default encoders support the base splinter's features, so the
+        // clean children are copied verbatim rather than decoded
+        let encoded = cow.encode_to_bytes();
+        assert_eq!(encoded.len(), cow.encoded_size());
+        assert!(encoded.len() <= cow.max_encoded_size());
+        assert_eq!(encoded[..end], corrupted[..end]);
+        let mut written = Vec::new();
+        cow.encode_to_writer(&mut written).unwrap();
+        assert_eq!(written, encoded);
+        assert_eq!(
+            SplinterRef::from_bytes(encoded).unwrap().features(),
+            features
+        );
+    }
+
+    #[test]
+    fn test_patched_prefix_root() {
+        // every value shares the first high segment, so optimizing the
+        // splinter gives it a prefix root over a tree
+        let mut expected = Splinter::from_iter((0..300_000).step_by(61));
+        expected.optimize_with(Features::SUPPORTED);
+        let base =
+            SplinterRef::from_bytes(encode_with_features(&expected, Features::SUPPORTED)).unwrap();
+        assert!(matches!(base.load_unchecked(), PartitionRef::Prefix(_)));
+        let mut cow = CowSplinter::from_ref(base);
+
+        assert!(cow.insert(1) && expected.insert(1));
+        assert!(cow.remove(61) && expected.remove(61));
+        let CowSplinter::Patched(patched) = &cow else {
+            panic!("expected a patched splinter: {cow:?}");
+        };
+        assert_eq!(patched.dirty_segments(), 1);
+        assert_eq!(cow.cardinality(), expected.cardinality());
+        assert_eq!(cow, expected);
+        test_partition_read(&cow, &expected.iter().collect_vec());
+
+        cow.remove_range(100_000..200_000);
+        expected.remove_range(100_000..200_000);
+        assert_eq!(cow, expected);
+        test_partition_read(&cow, &expected.iter().collect_vec());
+
+        let encoded = encode_with_features(&cow, Features::SUPPORTED);
+        assert_eq!(encoded.len(), cow.encoded_size_with(Features::SUPPORTED));
+        let splinter_ref = SplinterRef::from_bytes_validated(encoded).unwrap();
+        assert!(matches!(
+            splinter_ref.load_unchecked(),
+            PartitionRef::Prefix(_)
+        ));
+        assert_eq!(splinter_ref, expected);
+
+        for lhs in [
+            Splinter::from_iter((0..500_000).step_by(3)),
+            Splinter::from_iter([5, 6, 7, 1 << 24]),
+        ] {
+            assert_eq!(&lhs | &cow, &lhs | &expected);
+            assert_eq!(&lhs & &cow, &lhs & &expected);
+            assert_eq!(&lhs ^ &cow, &lhs ^ &expected);
+            assert_eq!(&lhs - &cow, &lhs - &expected);
+
+            let mut remaining = lhs.clone();
+            let mut expected_remaining = lhs;
+            assert_eq!(remaining.cut(&cow), expected_remaining.cut(&expected));
+            assert_eq!(remaining, expected_remaining);
+        }
+
+        // inserting outside of the prefix's segment gives the root a second
+        // child
+        assert!(cow.insert(5 << 24) && expected.insert(5 << 24));
+        assert!(matches!(cow, CowSplinter::Patched(_)));
+        assert_eq!(cow, expected);
+        test_partition_read(&cow, &expected.iter().collect_vec());
+        let encoded = cow.encode_to_bytes();
+        assert_eq!(encoded.len(), cow.encoded_size());
+        assert_eq!(
+            SplinterRef::from_bytes_validated(encoded).unwrap(),
+            expected
+        );
+    }
+
+    #[test]
+    fn test_patched_ops() {
+        let mut cow = CowSplinter::from_ref(Splinter::from_iter(values()).encode_to_splinter_ref());
+        let mut expected = Splinter::from_iter(values());
+        assert!(cow.insert(7 << 24 | 1) && expected.insert(7 << 24 | 1));
+        assert!(cow.remove(8 << 24) && expected.remove(8 << 24));
+        assert!(matches!(cow, CowSplinter::Patched(_)));
+
+        for lhs in [
+            Splinter::EMPTY,
+            Splinter::FULL,
+            Splinter::from_iter(0..1000),
+        ] {
+            assert_eq!(&lhs | &cow, &lhs | &expected);
+            assert_eq!(&lhs & &cow, &lhs & &expected);
+            assert_eq!(&lhs ^ &cow, &lhs ^ &expected);
+            assert_eq!(&lhs - &cow, &lhs - &expected);
+
+            let mut remaining = lhs.clone();
+            let mut expected_remaining = lhs;
+            assert_eq!(remaining.cut(&cow), expected_remaining.cut(&expected));
+            assert_eq!(remaining, expected_remaining);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_patched_ops_proptest(
+            prefix: bool,
+            set in vec(0u32..(1 << 28), 0..1024),
+            edits in vec(0u32..(1 << 28), 0..16),
+            other in vec(0u32..(1 << 28), 0..1024),
+        ) {
+            // optimized splinters within a single high segment have a prefix root
+            let mask = if prefix { (1 << 24) - 1 } else { u32::MAX };
+            let mut expected = Splinter::from_iter(set.into_iter().map(|v| v & mask));
+            if prefix {
+                expected.optimize();
+            }
+            let edits = edits.into_iter().map(|v| v & mask);
+            let mut cow = CowSplinter::from_ref(expected.encode_to_splinter_ref());
+            for value in edits {
+                assert_eq!(cow.insert(value), expected.insert(value));
+            }
+            let other = Splinter::from_iter(other);
+
+            assert_eq!(other == cow, other == expected);
+            assert_eq!(&other | &cow, &other | &expected);
+            assert_eq!(&other & &cow, &other & &expected);
+            assert_eq!(&other ^ &cow, &other ^ &expected);
+            assert_eq!(&other - &cow, &other - &expected);
+
+            let mut remaining = other.clone();
+            let mut expected_remaining = other;
+            assert_eq!(remaining.cut(&cow), expected_remaining.cut(&expected));
+            assert_eq!(remaining, expected_remaining);
+        }
+
+        #[test]
+        fn test_patched_proptest(
+            prefix: bool,
+            set in vec(0u32..(1 << 28), 0..1024),
+            edits in vec((0u32..(1 << 28), proptest::bool::ANY), 0..64),
+        ) {
+            // optimized splinters within a single high segment have a prefix root
+            let mask = if prefix { (1 << 24) - 1 } else { u32::MAX };
+            let mut expected = Splinter::from_iter(set.into_iter().map(|v| v & mask));
+            if prefix {
+                expected.optimize();
+            }
+            let mut cow = CowSplinter::from_ref(expected.encode_to_splinter_ref());
+            for (value, insert) in edits {
+                let value = value & mask;
+                if insert {
+                    assert_eq!(cow.insert(value), expected.insert(value));
+                } else {
+                    assert_eq!(cow.remove(value), expected.remove(value));
+                }
+            }
+
+            assert_eq!(&cow, &expected);
+            test_partition_read(&cow, &expected.iter().collect_vec());
+            let encoded = cow.encode_to_bytes();
+            assert_eq!(encoded.len(), cow.encoded_size());
+            assert!(encoded.len() <= cow.max_encoded_size());
+            assert_eq!(SplinterRef::from_bytes_validated(encoded).unwrap(), expected);
+        }
+    }
+}
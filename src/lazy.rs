@@ -0,0 +1,765 @@
+//! Lazily query splinters stored in a random-access byte source, such as a
+//! file or an object store, without reading the entire splinter.
+//!
+//! Encoded splinters are decoded from their end, and every tree partition
+//! records the offset of each of its children, while prefix partitions store
+//! their only child immediately before its segment. [`LazySplinterRef`] uses this
+//! to answer a query by fetching the footer, then the root tree index, and
+//! then only the child partitions along the path to the queried value.
+//! Fetched data is kept in a small cache of fixed size blocks, so repeated
+//! queries usually don't touch the source.
+
+use std::{cmp::Ordering, collections::VecDeque, fmt::Debug, io, ops::Range, sync::Mutex};
+
+use bytes::Bytes;
+use thiserror::Error;
+
+use crate::{
+    PartitionRead,
+    codec::{
+        DecodeErr,
+        features::Features,
+        footer::{EncodedFeatures, EncodedLength, Footer},
+        partition_ref::PartitionRef,
+        prefix_ref::PrefixRef,
+        tree_ref::TreeRef,
+    },
+    level::{High, Level},
+    segment::SplitSegment,
+};
+
+/// The default size of the blocks fetched from the source.
+pub const DEFAULT_BLOCK_SIZE: usize = 4096;
+
+/// The default number of blocks cached by a [`LazySplinterRef`].
+pub const DEFAULT_CACHE_BLOCKS: usize = 64;
+
+/// The largest possible footer: an encoded length, features, an 8 byte
+/// checksum and the magic value.
+const MAX_FOOTER_SIZE: usize =
+    size_of::<EncodedLength>() + size_of::<EncodedFeatures>() + 8 + Footer::MAGIC_SIZE;
+
+/// A byte source which supports reads at arbitrary offsets.
+pub trait ReadAt {
+    /// Returns the total number of bytes in the source.
+    fn size(&self) -> io::Result<u64>;
+
+    /// Reads exactly `buf.len()` bytes starting at `offset` into `buf`.
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()>;
+}
+
+impl ReadAt for [u8] {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.len() as u64)
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        let data = usize::try_from(offset)
+            .ok()
+            .and_then(|offset| self.get(offset..)?.get(..buf.len()))
+            .ok_or(io::ErrorKind::UnexpectedEof)?;
+        buf.copy_from_slice(data);
+        Ok(())
+    }
+}
+
+impl ReadAt for Vec<u8> {
+    fn size(&self) -> io::Result<u64> {
+        self.as_slice().size()
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.as_slice().read_exact_at(buf, offset)
+    }
+}
+
+impl ReadAt for Bytes {
+    fn size(&self) -> io::Result<u64> {
+        self.as_ref().size()
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        self.as_ref().read_exact_at(buf, offset)
+    }
+}
+
+impl<T: ReadAt + ?Sized> ReadAt for &T {
+    fn size(&self) -> io::Result<u64> {
+        (**self).size()
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        (**self).read_exact_at(buf, offset)
+    }
+}
+
+#[cfg(unix)]
+impl ReadAt for std::fs::File {
+    fn size(&self) -> io::Result<u64> {
+        Ok(self.metadata()?.len())
+    }
+
+    fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+        std::os::unix::fs::FileExt::read_exact_at(self, buf, offset)
+    }
+}
+
+/// Errors that can occur when lazily querying a splinter.
+#[derive(Debug, Error)]
+pub enum ReadErr {
+    /// The source failed to read the requested bytes.
+    #[error("failed to read from source: {0}")]
+    Io(#[from] io::Error),
+
+    /// The fetched bytes do not contain a valid splinter.
+    #[error(transparent)]
+    Decode(#[from] DecodeErr),
+}
+
+/// A least recently used cache of blocks fetched from the source.
+struct BlockCache {
+    capacity: usize,
+    /// cached blocks and their index, from least to most recently used
+    blocks: VecDeque<(u64, Box<[u8]>)>,
+}
+
+impl BlockCache {
+    fn get(&mut self, block: u64) -> Option<&[u8]> {
+        let idx = self.blocks.iter().position(|(b, _)| *b == block)?;
+        let entry = self.blocks.remove(idx)?;
+        self.blocks.push_back(entry);
+        self.blocks.back().map(|(_, data)| &data[..])
+    }
+
+    fn contains(&self, block: u64) -> bool {
+        self.blocks.iter().any(|(b, _)| *b == block)
+    }
+
+    fn insert(&mut self, block: u64, data: &[u8]) {
+        if self.blocks.len() == self.capacity {
+            self.blocks.pop_front();
+        }
+        self.blocks.push_back((block, data.into()));
+    }
+}
+
+/// A reference to a serialized splinter stored in a [`ReadAt`] source, which
+/// only reads the parts of the splinter needed to answer each query.
+///
+/// The checksum can't be verified without reading the entire splinter, so
+/// each partition is validated as it's fetched instead. Queries return
+/// [`ReadErr`] rather than panicking if the source is corrupted.
+///
+/// # Examples
+///
+/// ```
+/// use splinter_rs::{Encodable, LazySplinterRef, Splinter};
+///
+/// let splinter = Splinter::from_iter((0..1_000_000).step_by(7));
+/// let bytes = splinter.encode_to_bytes();
+///
+/// let lazy = LazySplinterRef::open(bytes).unwrap();
+/// assert_eq!(lazy.cardinality().unwrap(), 142858);
+/// assert!(lazy.contains(700).unwrap());
+/// assert_eq!(lazy.rank(700).unwrap(), 101);
+/// assert_eq!(lazy.select(100).unwrap(), Some(700));
+/// ```
+pub struct LazySplinterRef<R> {
+    source: R,
+    size: u64,
+    /// the offset of the footer, which immediately follows the root partition
+    partitions_end: u64,
+    features: Features,
+    block_size: u64,
+    cache: Mutex<BlockCache>,
+}
+
+impl<R> Debug for LazySplinterRef<R> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("LazySplinterRef")
+            .field("size", &self.size)
+            .field("features", &self.features)
+            .finish_non_exhaustive()
+    }
+}
+
+impl<R: ReadAt> LazySplinterRef<R> {
+    /// Opens a splinter stored in `source` by fetching its footer, using
+    /// [`DEFAULT_BLOCK_SIZE`] byte blocks and caching up to
+    /// [`DEFAULT_CACHE_BLOCKS`] of them.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`ReadErr::Io`] if the source fails, or [`ReadErr::Decode`] if
+    /// the footer is invalid.
+    pub fn open(source: R) -> Result<Self, ReadErr> {
+        Self::open_with_cache(source, DEFAULT_BLOCK_SIZE, DEFAULT_CACHE_BLOCKS)
+    }
+
+    /// Opens a splinter stored in `source`, fetching it in blocks of
+    /// `block_size` bytes and caching up to `cache_blocks` of them. Larger
+    /// blocks reduce the number of reads at the cost of reading more unused
+    /// data, which is worthwhile for sources with high latency.
+    ///
+    /// # Panics
+    ///
+    /// Panics if `block_size` or `cache_blocks` is zero.
+    pub fn open_with_cache(
+        source: R,
+        block_size: usize,
+        cache_blocks: usize,
+    ) -> Result<Self, ReadErr> {
+        assert!(block_size > 0, "block size must be positive");
+        assert!(cache_blocks > 0, "cache must hold at least one block");
+
+        let size = source.size()?;
+        let mut lazy = Self {
+            source,
+            size,
+            partitions_end: 0,
+            features: Features::EMPTY,
+            block_size: block_size as u64,
+            cache: Mutex::new(BlockCache {
+                capacity: cache_blocks,
+                blocks: VecDeque::with_capacity(cache_blocks),
+            }),
+        };
+
+        let tail = lazy.read(size.saturating_sub(MAX_FOOTER_SIZE as u64)..size)?;
+        let (partitions, footer) = Footer::split_unverified(&tail)?;
+        if footer.features.contains(Features::PATCH) {
+            return Err(DecodeErr::Patch.into());
+        }
+        lazy.partitions_end = size - (tail.len() - partitions.len()) as u64;
+        lazy.features = footer.features;
+        Ok(lazy)
+    }
+
+    /// Returns a reference to the underlying source.
+    #[inline]
+    pub fn inner(&self) -> &R {
+        &self.source
+    }
+
+    /// Consumes the `LazySplinterRef` and returns the underlying source.
+    #[inline]
+    pub fn into_inner(self) -> R {
+        self.source
+    }
+
+    /// Returns the optional format features used by this splinter.
+    #[inline]
+    pub fn features(&self) -> Features {
+        self.features
+    }
+
+    /// Returns the number of values in the splinter. Only the root partition
+    /// index is fetched, or the index of the first tree below a chain of
+    /// prefix partitions.
+    pub fn cardinality(&self) -> Result<usize, ReadErr> {
+        self.cardinality_in::<High>(self.root())
+    }
+
+    /// Returns true if the splinter contains no values.
+    pub fn is_empty(&self) -> Result<bool, ReadErr> {
+        Ok(self.cardinality()? == 0)
+    }
+
+    /// Returns true if the splinter contains `value`.
+    pub fn contains(&self, value: u32) -> Result<bool, ReadErr> {
+        self.contains_in::<High>(self.root(), value)
+    }
+
+    /// Returns the number of values in the splinter which are less than or
+    /// equal to `value`.
+    pub fn rank(&self, value: u32) -> Result<usize, ReadErr> {
+        self.rank_in::<High>(self.root(), value)
+    }
+
+    /// Returns the value at position `idx` in sorted order, or `None` if
+    /// `idx` is out of bounds.
+    pub fn select(&self, idx: usize) -> Result<Option<u32>, ReadErr> {
+        self.select_in::<High>(self.root(), idx)
+    }
+
+    #[inline]
+    fn root(&self) -> Range<u64> {
+        0..self.partitions_end
+    }
+
+    fn cardinality_in<L: Level>(&self, bounds: Range<u64>) -> Result<usize, ReadErr> {
+        let (offset, data) = self.fetch::<L>(bounds.clone())?;
+        match decode::<L>(&data, offset, bounds.start)? {
+            PartitionRef::Prefix(prefix) => {
+                let child = prefix_child_bounds(&prefix, offset, bounds.start)?;
+                self.cardinality_in::<L::LevelDown>(child)
+            }
+            partition => Ok(partition.cardinality()),
+        }
+    }
+
+    fn contains_in<L: Level>(&self, bounds: Range<u64>, value: L::Value) -> Result<bool, ReadErr> {
+        let (offset, data) = self.fetch::<L>(bounds.clone())?;
+        match decode::<L>(&data, offset, bounds.start)? {
+            PartitionRef::Tree(tree) => {
+                let (segment, value) = value.split();
+                match tree.search_segment(segment) {
+                    Ok(idx) => {
+                        let child = child_bounds(&tree, idx, offset, bounds.start)?;
+                        self.contains_in::<L::LevelDown>(child, value)
+                    }
+                    Err(_) => Ok(false),
+                }
+            }
+            PartitionRef::Prefix(prefix) => {
+                let (segment, value) = value.split();
+                if segment != prefix.segment() {
+                    return Ok(false);
+                }
+                let child = prefix_child_bounds(&prefix, offset, bounds.start)?;
+                self.contains_in::<L::LevelDown>(child, value)
+            }
+            partition => Ok(partition.contains(value)),
+        }
+    }
+
+    fn rank_in<L: Level>(&self, bounds: Range<u64>, value: L::Value) -> Result<usize, ReadErr> {
+        let (offset, data) = self.fetch::<L>(bounds.clone())?;
+        match decode::<L>(&data, offset, bounds.start)? {
+            PartitionRef::Tree(tree) => {
+                let (segment, value) = value.split();
+                match tree.search_segment(segment) {
+                    Ok(idx) => {
+                        let child = child_bounds(&tree, idx, offset, bounds.start)?;
+                        let rank = self.rank_in::<L::LevelDown>(child, value)?;
+                        Ok(tree.prefix_cardinality(idx) + rank)
+                    }
+                    Err(count_less) => Ok(tree.prefix_cardinality(count_less)),
+                }
+            }
+            PartitionRef::Prefix(prefix) => {
+                let (segment, value) = value.split();
+                match segment.cmp(&prefix.segment()) {
+                    Ordering::Less => Ok(0),
+                    Ordering::Equal => {
+                        let child = prefix_child_bounds(&prefix, offset, bounds.start)?;
+                        self.rank_in::<L::LevelDown>(child, value)
+                    }
+                    Ordering::Greater => {
+                        // every value in the partition is smaller
+                        let child = prefix_child_bounds(&prefix, offset, bounds.start)?;
+                        self.cardinality_in::<L::LevelDown>(child)
+                    }
+                }
+            }
+            partition => Ok(partition.rank(value)),
+        }
+    }
+
+    fn select_in<L: Level>(
+        &self,
+        bounds: Range<u64>,
+        n: usize,
+    ) -> Result<Option<L::Value>, ReadErr> {
+        let (offset, data) = self.fetch::<L>(bounds.clone())?;
+        match decode::<L>(&data, offset, bounds.start)? {
+            PartitionRef::Tree(tree) => {
+                if n >= tree.cardinality() {
+                    return Ok(None);
+                }
+                let Some((idx, segment)) = tree.child_at(n) else {
+                    return Ok(None);
+                };
+                let child = child_bounds(&tree, idx, offset, bounds.start)?;
+                let value =
+                    self.select_in::<L::LevelDown>(child, n - tree.prefix_cardinality(idx))?;
+                Ok(value.map(|v| L::Value::unsplit(segment, v)))
+            }
+            PartitionRef::Prefix(prefix) => {
+                let segment = prefix.segment();
+                let child = prefix_child_bounds(&prefix, offset, bounds.start)?;
+                let value = self.select_in::<L::LevelDown>(child, n)?;
+                Ok(value.map(|v| L::Value::unsplit(segment, v)))
+            }
+            partition => Ok(partition.select(n)),
+        }
+    }
+
+    /// Fetches the partition which ends at `bounds.end`, returning the
+    /// fetched bytes and their offset in the source. The fetched bytes end
+    /// with the partition, but only include the index of tree partitions.
+    /// Prefix partitions decode their child along with their segment, so
+    /// their fetched bytes extend to the child's tree index, or to the first
+    /// partition below them which isn't a tree.
+    ///
+    /// Partitions are decoded from their end, so their size isn't known in
+    /// advance. Starting with the block containing the end of the partition,
+    /// this doubles the fetched range until the partition can be decoded or
+    /// the fetched range covers `bounds`.
+    fn fetch<L: Level>(&self, bounds: Range<u64>) -> Result<(u64, Vec<u8>), ReadErr> {
+        let end = bounds.end;
+        let mut offset =
+            (end.saturating_sub(1) / self.block_size * self.block_size).max(bounds.start);
+        loop {
+            let data = self.read(offset..end)?;
+            match PartitionRef::<L>::from_suffix(&data).map(|_| ()) {
+                Ok(()) => return Ok((offset, data)),
+                Err(DecodeErr::Length) if offset > bounds.start => {
+                    let len = (end - offset) * 2;
+                    offset = end.saturating_sub(len).max(bounds.start);
+                }
+                Err(err) => return Err(err.into()),
+            }
+        }
+    }
+
+    /// Reads a range of bytes from the source through the block cache.
+    /// Consecutive blocks which aren't cached are fetched in a single read.
+    fn read(&self, range: Range<u64>) -> Result<Vec<u8>, ReadErr> {
+        let mut out = Vec::with_capacity((range.end - range.start) as usize);
+        if range.is_empty() {
+            return Ok(out);
+        }
+
+        let bs = self.block_size;
+        let copy_block = |out: &mut Vec<u8>, block: u64, data: &[u8]| {
+            let start = range.start.saturating_sub(block * bs) as usize;
+            let end = (range.end - block * bs).min(data.len() as u64) as usize;
+            out.extend_from_slice(&data[start..end]);
+        };
+
+        let mut cache = self.cache.lock().unwrap_or_else(|err| err.into_inner());
+        let last = (range.end - 1) / bs;
+        let mut block = range.start / bs;
+        while block <= last {
+            if let Some(data) = cache.get(block) {
+                copy_block(&mut out, block, data);
+                block += 1;
+                continue;
+            }
+
+            let mut end = block + 1;
+            while end <= last && !cache.contains(end) {
+                end += 1;
+            }
+            let start_offset = block * bs;
+            let mut buf = vec![0; ((end * bs).min(self.size) - start_offset) as usize];
+            self.source.read_exact_at(&mut buf, start_offset)?;
+            for data in buf.chunks(bs as usize) {
+                copy_block(&mut out, block, data);
+                cache.insert(block, data);
+                block += 1;
+            }
+        }
+        Ok(out)
+    }
+}
+
+/// Decodes a fetched partition, given the offset of the fetched data and the
+/// start of the partition's bounds. Tree partitions only validate their
+/// index, as their children haven't been fetched, while prefix partitions
+/// leave their child to be validated when it's fetched.
+fn decode<L: Level>(
+    data: &[u8],
+    offset: u64,
+    start: u64,
+) -> Result<PartitionRef<'_, L>, DecodeErr> {
+    let partition = PartitionRef::<L>::from_suffix(data)?;
+    match &partition {
+        PartitionRef::Tree(tree) => {
+            let children_len = (offset + tree.children_len() as u64)
+                .checked_sub(start)
+                .ok_or(DecodeErr::Validity)?;
+            tree.validate_index(children_len as usize)?;
+        }
+        PartitionRef::Prefix(_) => {}
+        partition => partition.validate()?,
+    }
+    Ok(partition)
+}
+
+/// Returns the range of the source which contains the child at `idx`, given
+/// the offset of the data the tree was decoded from, and the start of the
+/// tree's bounds.
+fn child_bounds<L: Level>(
+    tree: &TreeRef<'_, L>,
+    idx: usize,
+    offset: u64,
+    start: u64,
+) -> Result<Range<u64>, DecodeErr> {
+    let children_end = offset + tree.children_len() as u64;
+    let end_of = |idx: usize| {
+        children_end
+            .checked_sub(tree.child_end_offset(idx) as u64)
+            .filter(|&end| end >= start)
+            .ok_or(DecodeErr::Validity)
+    };
+    let child_start = match idx.checked_sub(1) {
+        Some(prev) => end_of(prev)?,
+        None => start,
+    };
+    let child_end = end_of(idx)?;
+    if child_start >= child_end {
+        return Err(DecodeErr::Validity);
+    }
+    Ok(child_start..child_end)
+}
+
+/// Returns the range of the source which contains the only child of a
+/// prefix partition, given the offset of the data the prefix partition was
+/// decoded from, and the start of the partition's bounds.
+fn prefix_child_bounds<L: Level>(
+    prefix: &PrefixRef<'_, L>,
+    offset: u64,
+    start: u64,
+) -> Result<Range<u64>, DecodeErr> {
+    let child_end = offset + prefix.child_len() as u64;
+    if start >= child_end {
+        return Err(DecodeErr::Validity);
+    }
+    Ok(start..child_end)
+}
+
+#[cfg(test)]
+mod tests {
+    use std::{
+        io,
+        sync::atomic::{AtomicUsize, Ordering},
+    };
+
+    use assert_matches::assert_matches;
+    use bytes::{Bytes, BytesMut};
+    use itertools::Itertools;
+
+    use crate::{
+        Encodable, Optimizable, PartitionRead, Splinter, SplinterRef,
+        codec::{DecodeErr, encoder::Encoder, features::Features, footer::Footer},
+        lazy::{LazySplinterRef, ReadAt, ReadErr},
+        partition_kind::PartitionKind,
+        testutil::SetGen,
+    };
+
+    /// An in-memory source which counts the reads it serves.
+    struct CountingSource {
+        data: Bytes,
+        reads: AtomicUsize,
+        bytes: AtomicUsize,
+    }
+
+    impl CountingSource {
+        fn new(data: Bytes) -> Self {
+            Self {
+                data,
+                reads: AtomicUsize::new(0),
+                bytes: AtomicUsize::new(0),
+            }
+        }
+
+        /// Returns and resets the number of reads and bytes read.
+        fn take(&self) -> (usize, usize) {
+            (
+                self.reads.swap(0, Ordering::Relaxed),
+                self.bytes.swap(0, Ordering::Relaxed),
+            )
+        }
+    }
+
+    impl ReadAt for CountingSource {
+        fn size(&self) -> io::Result<u64> {
+            self.data.size()
+        }
+
+        fn read_exact_at(&self, buf: &mut [u8], offset: u64) -> io::Result<()> {
+            self.reads.fetch_add(1, Ordering::Relaxed);
+            self.bytes.fetch_add(buf.len(), Ordering::Relaxed);
+            self.data.read_exact_at(buf, offset)
+        }
+    }
+
+    fn mksplinter(values: &[u32]) -> Splinter {
+        let mut splinter = Splinter::from_iter(values.iter().copied());
+        splinter.optimize();
+        splinter
+    }
+
+    #[test]
+    fn test_lazy_matches_splinter() {
+        let mut set_gen = SetGen::new(0xDEAD_BEEF);
+        let sets = [
+            vec![],
+            vec![0],
+            vec![u32::MAX],
+            (0..100).collect_vec(),
+            set_gen.distributed(4, 8, 8, 64),
+            set_gen.distributed(64, 4, 4, 8),
+            set_gen.dense(2, 2, 8, 256),
+            set_gen.random(10_000),
+        ];
+
+        for values in sets {
+            let splinter = mksplinter(&values);
+            let encoded = splinter.encode_to_bytes();
+
+            // small blocks exercise fetching partitions across blocks
+            for block_size in [5, 64, 4096] {
+                let lazy = LazySplinterRef::open_with_cache(&encoded[..], block_size, 4).unwrap();
+                assert_eq!(lazy.cardinality().unwrap(), values.len());
+                assert_eq!(lazy.is_empty().unwrap(), values.is_empty());
+
+                let probes = values
+                    .iter()
+                    .step_by(values.len() / 64 + 1)
+                    .flat_map(|&v| [v, v.wrapping_add(1), v.wrapping_sub(1)])
+                    .chain([0, 1 << 16, u32::MAX]);
+                for value in probes {
+                    assert_eq!(lazy.contains(value).unwrap(), splinter.contains(value));
+                    assert_eq!(lazy.rank(value).unwrap(), splinter.rank(value));
+                }
+                for idx in (0..=values.len()).step_by(values.len() / 64 + 1) {
+                    assert_eq!(lazy.select(idx).unwrap(), splinter.select(idx));
+                }
+            }
+        }
+    }
+
+    #[test]
+    fn test_lazy_fetches_only_needed_partitions() {
+        // many high partitions, each containing a single large low partition
+        let values = (0..256u32)
+            .flat_map(|high| (0..2000).map(move |v| (high << 24) | (v * 3)))
+            .collect_vec();
+        let splinter = mksplinter(&values);
+        let source = CountingSource::new(splinter.encode_to_bytes());
+        let size = source.data.len();
+
+        let lazy = LazySplinterRef::open_with_cache(&source, 1024, 16).unwrap();
+        let (reads, _) = source.take();
+        assert_eq!(reads, 1, "opening only fetches the footer");
+
+        assert!(lazy.contains(200 << 24 | 3000).unwrap());
+        let (reads, bytes) = source.take();
+        assert!(reads <= 10, "contains took {reads} reads");
+        assert!(bytes * 20 < size, "contains read {bytes} of {size} bytes");
+
+        // the path to the value is cached
+        assert!(!lazy.contains(200 << 24 | 3001).unwrap());
+        assert_eq!(source.take(), (0, 0));
+
+        assert_eq!(lazy.rank(100 << 24).unwrap(), 100 * 2000 + 1);
+        assert_eq!(lazy.select(100 * 2000).unwrap(), Some(100 << 24));
+        let (_, bytes) = source.take();
+        assert!(
+            bytes * 20 < size,
+            "rank and select read {bytes} of {size} bytes"
+        );
+
+        // the root index is enough to compute the cardinality
+        assert_eq!(lazy.cardinality().unwrap(), values.len());
+        assert_eq!(source.take().0, 0);
+    }
+
+    #[test]
+    fn test_lazy_features_and_aligned_bitmaps() {
+        let values = (0..65536).filter(|v| v % 1000 != 0).collect_vec();
+        let mut splinter = Splinter::from_iter(values.iter().copied());
+        splinter.optimize_with(Features::SUPPORTED);
+
+        let mut encoder = Encoder::with_features(BytesMut::new(), Features::SUPPORTED)
+            .with_aligned_bitmaps()
+            .with_encoded_length();
+        splinter.encode(&mut encoder);
+        let encoded = encoder.into_inner().freeze();
+
+        let lazy = LazySplinterRef::open_with_cache(encoded, 16, 2).unwrap();
+        assert_eq!(
+            lazy.features(),
+            splinter.encoded_features() | Features::ENCODED_LENGTH
+        );
+        assert!(lazy.features().contains(Features::INVERTED_PARTITIONS));
+        assert_eq!(lazy.cardinality().unwrap(), values.len());
+        assert!(lazy.contains(1001).unwrap());
+        assert!(!lazy.contains(1000).unwrap());
+        assert_eq!(lazy.rank(2000).unwrap(), 1998);
+        assert_eq!(lazy.select(1998).unwrap(), Some(2001));
+    }
+
+    #[test]
+    fn test_lazy_corrupted_tree_index() {
+        let values = (0..4u32)
+            .flat_map(|high| (0..4096).step_by(2).map(move |v| (high << 24) | v))
+            .collect_vec();
+        let encoded = mksplinter(&values).encode_to_bytes();
+        let root = SplinterRef::from_bytes(encoded.clone()).unwrap();
+        assert_eq!(root.load_unchecked().kind(), PartitionKind::Tree);
+
+        // the root tree index ends with 4 u32 offsets, 4 u32 cumulative
+        // cardinalities, 4 segments, the length and the kind
+        let end = Footer::split(&encoded).unwrap().0.len();
+        let segments = end - 6;
+        let cardinalities = segments - 16;
+        let offsets = cardinalities - 16;
+
+        let corruptions: [(usize, &[u8]); 4] = [
+            // a duplicate segment
+            (segments + 2, &[1]),
+            // a cumulative cardinality which decreases
+            (cardinalities + 4, &[0xFF, 0xFF, 0xFF, 0xFF]),
+            // the first child starts before the splinter
+            (offsets, &[0xFF, 0xFF, 0xFF, 0xFF]),
+            // the last child doesn't end at the index
+            (offsets + 15, &[1]),
+        ];
+        for (at, bytes) in corruptions {
+            let mut corrupted = encoded.to_vec();
+            corrupted[at..at + bytes.len()].copy_from_slice(bytes);
+            let lazy = LazySplinterRef::open(&corrupted[..]).unwrap();
+            for value in [0, 2 << 24, 3 << 24 | 4094] {
+                assert_matches!(lazy.contains(value), Err(ReadErr::Decode(_)));
+                assert_matches!(lazy.rank(value), Err(ReadErr::Decode(_)));
+            }
+            for idx in [0, 4096, values.len() - 1] {
+                assert_matches!(lazy.select(idx), Err(ReadErr::Decode(_)));
+            }
+        }
+    }
+
+    #[test]
+    fn test_lazy_errors() {
+        assert_matches!(
+            LazySplinterRef::open(&b"not a splinter"[..]),
+            Err(ReadErr::Decode(DecodeErr::Magic))
+        );
+        assert_matches!(
+            LazySplinterRef::open(&b""[..]),
+            Err(ReadErr::Decode(DecodeErr::Length))
+        );
+
+        // corruption is detected when the corrupted partition is fetched
+        let values = (1..4u32)
+            .flat_map(|high| (0..4096).step_by(2).map(move |v| (high << 24) | v))
+            .chain([10, 20, 30])
+            .collect_vec();
+        let mut encoded = mksplinter(&values).encode_to_bytes().to_vec();
+        // the first partition stores 10, 20 and 30
+        encoded[0] ^= 0xFF;
+        let lazy = LazySplinterRef::open(&encoded[..]).unwrap();
+        assert!(lazy.contains(3 << 24).unwrap());
+        assert_matches!(lazy.contains(20), Err(ReadErr::Decode(DecodeErr::Validity)));
+
+        // io errors are returned
+        struct FailingSource;
+        impl ReadAt for FailingSource {
+            fn size(&self) -> io::Result<u64> {
+                Ok(100)
+            }
+
+            fn read_exact_at(&self, _: &mut [u8], _: u64) -> io::Result<()> {
+                Err(io::ErrorKind::TimedOut.into())
+            }
+        }
+        assert_matches!(
+            LazySplinterRef::open(FailingSource),
+            Err(ReadErr::Io(err)) if err.kind() == io::ErrorKind::TimedOut
+        );
+    }
+}
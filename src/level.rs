@@ -1,29 +1,30 @@
 use std::{
+    borrow::Borrow,
     fmt::{Debug, Display},
     ops::{BitAndAssign, BitOrAssign, BitXorAssign, SubAssign},
 };
 
-use ::u24::U24;
 use num::{
     cast::AsPrimitive,
     traits::{ConstOne, ConstZero},
 };
-use u24::u24;
 use zerocopy::{BE, FromBytes, Immutable, IntoBytes, KnownLayout, U16, U32, Unaligned};
 
 use crate::{
-    codec::{Encodable, partition_ref::PartitionRef},
+    codec::{Encodable, PartitionLevel, partition_ref::PartitionRef},
     never::Never,
     partition::Partition,
     segment::SplitSegment,
     traits::{
-        Complement, Cut, DefaultFull, Optimizable, PartitionRead, PartitionWrite, TruncateFrom,
+        Complement, Cut, DefaultFull, Int, Optimizable, PartitionRead, PartitionWrite, TruncateFrom,
     },
+    u24::{U24, u24},
 };
 
 #[doc(hidden)]
-pub trait Level: Sized + Clone + Copy {
+pub trait Level: Sized + Clone + Copy + Debug + 'static {
     const DEBUG_NAME: &'static str;
+    const LEVEL: PartitionLevel;
 
     type LevelDown: Level;
 
@@ -44,16 +45,25 @@ pub trait Level: Sized + Clone + Copy {
         + for<'a> Cut<PartitionRef<'a, Self::LevelDown>, Out = Self::Down>
         + for<'a> PartialEq<PartitionRef<'a, Self::LevelDown>>
         + for<'a> BitOrAssign<&'a Self::Down>
-        + for<'a> BitOrAssign<&'a PartitionRef<'a, Self::LevelDown>>
+        + for<'a, 'b> BitOrAssign<&'b PartitionRef<'a, Self::LevelDown>>
         + for<'a> BitAndAssign<&'a Self::Down>
-        + for<'a> BitAndAssign<&'a PartitionRef<'a, Self::LevelDown>>
+        + for<'a, 'b> BitAndAssign<&'b PartitionRef<'a, Self::LevelDown>>
         + for<'a> BitXorAssign<&'a Self::Down>
-        + for<'a> BitXorAssign<&'a PartitionRef<'a, Self::LevelDown>>
+        + for<'a, 'b> BitXorAssign<&'b PartitionRef<'a, Self::LevelDown>>
         + for<'a> SubAssign<&'a Self::Down>
-        + for<'a> SubAssign<&'a PartitionRef<'a, Self::LevelDown>>
-        + for<'a> From<&'a PartitionRef<'a, Self::LevelDown>>;
+        + for<'a, 'b> SubAssign<&'b PartitionRef<'a, Self::LevelDown>>
+        + for<'a, 'b> From<&'b PartitionRef<'a, Self::LevelDown>>;
+
+    /// The decoded child of a prefix partition at this level, which is always
+    /// a `PartitionRef` one level down. Naming it through the level keeps
+    /// `PrefixRef` from being an infinitely recursive type.
+    type PrefixChild<'a>: Borrow<PartitionRef<'a, Self::LevelDown>>
+        + From<PartitionRef<'a, Self::LevelDown>>
+        + Into<PartitionRef<'a, Self::LevelDown>>
+        + Debug
+        + Clone;
 
-    type Value: num::PrimInt
+    type Value: Int
         + AsPrimitive<usize>
         + SplitSegment<Rest = <Self::LevelDown as Level>::Value>
         + TruncateFrom<usize>
@@ -79,6 +89,9 @@ pub trait Level: Sized + Clone + Copy {
     const BITS: usize;
     const MAX_LEN: usize = 1 << Self::BITS;
     const ALLOW_TREE: bool = Self::BITS > 8;
+    const ALLOW_ELIAS_FANO: bool = false;
+    const ALLOW_DELTA_VEC: bool = Self::BITS > 8;
+    const ALLOW_SPARSE_BITMAP: bool = false;
 }
 
 /// High is an internal type which is only exposed in docs due to it's usage in
@@ -88,9 +101,11 @@ pub struct High;
 
 impl Level for High {
     const DEBUG_NAME: &'static str = "High";
+    const LEVEL: PartitionLevel = PartitionLevel::High;
 
     type LevelDown = Mid;
     type Down = Partition<Self::LevelDown>;
+    type PrefixChild<'a> = PartitionRef<'a, Self::LevelDown>;
     type Value = u32;
     type ValueUnaligned = U32<BE>;
 
@@ -103,13 +118,16 @@ pub struct Mid;
 
 impl Level for Mid {
     const DEBUG_NAME: &'static str = "Mid";
+    const LEVEL: PartitionLevel = PartitionLevel::Mid;
 
     type LevelDown = Low;
     type Down = Partition<Self::LevelDown>;
+    type PrefixChild<'a> = PartitionRef<'a, Self::LevelDown>;
     type Value = u24;
     type ValueUnaligned = U24<BE>;
 
     const BITS: usize = 24;
+    const ALLOW_ELIAS_FANO: bool = true;
 }
 
 #[doc(hidden)]
@@ -118,13 +136,17 @@ pub struct Low;
 
 impl Level for Low {
     const DEBUG_NAME: &'static str = "Low";
+    const LEVEL: PartitionLevel = PartitionLevel::Low;
 
     type LevelDown = Block;
     type Down = Partition<Self::LevelDown>;
+    type PrefixChild<'a> = PartitionRef<'a, Self::LevelDown>;
     type Value = u16;
     type ValueUnaligned = U16<BE>;
 
     const BITS: usize = 16;
+    const ALLOW_ELIAS_FANO: bool = true;
+    const ALLOW_SPARSE_BITMAP: bool = true;
 }
 
 #[doc(hidden)]
@@ -133,9 +155,11 @@ pub struct Block;
 
 impl Level for Block {
     const DEBUG_NAME: &'static str = "Block";
+    const LEVEL: PartitionLevel = PartitionLevel::Block;
 
     type LevelDown = Never;
     type Down = Never;
+    type PrefixChild<'a> = PartitionRef<'a, Self::LevelDown>;
     type Value = u8;
     type ValueUnaligned = u8;
 
@@ -14,12 +14,17 @@
 
 pub mod codec;
 pub mod cow;
+pub mod lazy;
 pub mod level;
+pub mod pack;
+pub mod patch;
 pub mod splinter;
+pub mod splinter_mut;
 pub mod splinter_ops;
 pub mod splinter_ref;
 pub mod splinter_ref_ops;
 pub mod traits;
+pub(crate) mod u24;
 
 #[doc(hidden)]
 pub mod count;
@@ -34,13 +39,19 @@ mod util;
 #[doc(inline)]
 pub use cow::CowSplinter;
 #[doc(inline)]
+pub use lazy::LazySplinterRef;
+#[doc(inline)]
+pub use patch::SplinterPatch;
+#[doc(inline)]
 pub use splinter::Splinter;
 #[doc(inline)]
+pub use splinter_mut::SplinterMut;
+#[doc(inline)]
 pub use splinter_ref::SplinterRef;
 
 #[doc(inline)]
 pub use crate::{
-    codec::{DecodeErr, Encodable},
+    codec::{DecodeErr, Encodable, EncodeErr},
     traits::{Cut, Optimizable, PartitionRead, PartitionWrite},
 };
 
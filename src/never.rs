@@ -4,7 +4,7 @@ use bytes::BufMut;
 
 use crate::{
     Encodable, PartitionRead, PartitionWrite,
-    codec::{encoder::Encoder, partition_ref::PartitionRef},
+    codec::{PartitionLevel, encoder::Encoder, features::Features, partition_ref::PartitionRef},
     level::Level,
     partition::Partition,
     traits::{Complement, Cut, DefaultFull, Optimizable},
@@ -36,6 +36,10 @@ impl Encodable for Never {
     fn encode<B: BufMut>(&self, _encoder: &mut Encoder<B>) {
         unreachable!("Never::encode")
     }
+
+    fn encoded_features(&self) -> Features {
+        unreachable!("Never::encoded_features")
+    }
 }
 
 impl<L: Level> PartitionWrite<L> for Never {
@@ -104,9 +108,12 @@ impl Optimizable for Never {
 
 impl Level for Never {
     const DEBUG_NAME: &'static str = "Never";
+    // partitions are never decoded below the block level
+    const LEVEL: PartitionLevel = PartitionLevel::Block;
 
     type LevelDown = Never;
     type Down = Never;
+    type PrefixChild<'a> = PartitionRef<'a, Self::LevelDown>;
     type Value = u8;
     type ValueUnaligned = u8;
 
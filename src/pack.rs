@@ -0,0 +1,813 @@
+//! A container format which stores many encoded splinters in a single
+//! buffer, indexed by key.
+//!
+//! A pack stores its splinters back to back, optionally padded to start on
+//! 8-byte boundaries (see [`PackWriter::with_aligned_bitmaps`]), followed by
+//! a directory which maps each key to the location of its splinter:
+//!
+//! ```text
+//! [splinters...][keys][entries][footer]
+//! ```
+//!
+//! - `keys` stores every key back to back, in ascending order.
+//! - `entries` stores the location of each key and its splinter, in the same
+//!   order.
+//! - `footer` stores the offset of `keys`, the number of entries, a
+//!   CRC-64/NVME checksum and [`PACK_MAGIC`].
+//!
+//! The checksum covers the directory (the keys, entries, offset and number of
+//! entries), while each splinter is covered by its own checksum. Opening a
+//! pack only reads the directory, and splinters are returned as zero-copy
+//! [`SplinterRef`] views into the pack buffer, which may be a memory mapped
+//! file.
+//!
+//! # Examples
+//!
+//! ```
+//! use bytes::BytesMut;
+//! use splinter_rs::{
+//!     PartitionRead, Splinter,
+//!     pack::{PackReader, PackWriter},
+//! };
+//!
+//! let mut writer = PackWriter::new(BytesMut::new());
+//! writer.push(b"apple", &Splinter::from_iter([1, 2, 3]));
+//! writer.push(b"banana", &Splinter::from_iter(1000..2000));
+//! let buf = writer.finish().freeze();
+//!
+//! let pack = PackReader::from_bytes(buf).unwrap();
+//! assert_eq!(pack.len(), 2);
+//! let apple = pack.get(b"apple").unwrap().unwrap();
+//! assert_eq!(apple.iter().collect::<Vec<_>>(), [1, 2, 3]);
+//! assert!(pack.get(b"cherry").unwrap().is_none());
+//! ```
+
+use std::{collections::BTreeMap, fmt::Debug, ops::Deref};
+
+use bytes::BufMut;
+use itertools::{EitherOrBoth, Itertools};
+use zerocopy::{FromBytes, Immutable, IntoBytes, KnownLayout, LittleEndian, U32, U64, Unaligned};
+
+use crate::{
+    Encodable, Optimizable, SplinterRef,
+    codec::{DecodeErr, checksum::Checksum, encoder::Encoder, features::Features},
+};
+
+/// The last four bytes of an encoded pack.
+pub const PACK_MAGIC: [u8; 4] = [0x59, 0x11, 0xA7, 0x9C];
+
+/// The location of a key and its splinter within a pack.
+#[derive(FromBytes, IntoBytes, Immutable, Unaligned, KnownLayout)]
+#[repr(C)]
+struct Entry {
+    /// the offset of the splinter from the start of the pack
+    offset: U64<LittleEndian>,
+    /// the length of the splinter
+    len: U32<LittleEndian>,
+    /// the offset of the key from the start of the keys
+    key_offset: U32<LittleEndian>,
+    /// the length of the key
+    key_len: U32<LittleEndian>,
+}
+
+/// The footer at the end of a pack.
+#[derive(FromBytes, IntoBytes, Immutable, Unaligned, KnownLayout)]
+#[repr(C)]
+struct PackFooter {
+    /// the offset of the keys from the start of the pack
+    directory: U64<LittleEndian>,
+    /// the number of entries in the directory
+    count: U32<LittleEndian>,
+    /// the checksum of the directory, excluding the checksum and magic
+    checksum: U64<LittleEndian>,
+    magic: [u8; 4],
+}
+
+impl PackFooter {
+    /// The number of footer bytes covered by the checksum.
+    const CHECKSUM_OFFSET: usize = size_of::<U64<LittleEndian>>() + size_of::<U32<LittleEndian>>();
+}
+
+/// Writes splinters into a pack.
+///
+/// Splinters are written to the buffer as they are pushed, while the
+/// directory is kept in memory until [`PackWriter::finish`] is called.
+pub struct PackWriter<B: BufMut> {
+    buf: B,
+    bytes_written: usize,
+    /// the location of each splinter, sorted by key
+    entries: BTreeMap<Box<[u8]>, (usize, usize)>,
+    /// the optional features pushed splinters may use
+    features: Features,
+    /// the checksum of each pushed splinter
+    checksum: Checksum,
+    /// whether splinters start on 8-byte boundaries and align their bitmaps
+    align_bitmaps: bool,
+}
+
+impl<B: BufMut> PackWriter<B> {
+    /// Create a `PackWriter` which encodes splinters like [`Encoder::new`],
+    /// so that they can be decoded by every V2 reader.
+    pub fn new(buf: B) -> Self {
+        Self::with_features(buf, Features::EMPTY)
+    }
+
+    /// Create a `PackWriter` which encodes splinters like
+    /// [`Encoder::with_features`], allowing them to use the provided optional
+    /// features.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{
+    ///     Optimizable, Splinter,
+    ///     codec::{checksum::Checksum, features::Features},
+    ///     pack::{PackReader, PackWriter},
+    /// };
+    ///
+    /// let mut splinter = Splinter::from_iter((0..65536).filter(|v| v % 1000 != 0));
+    /// splinter.optimize_with(Features::SUPPORTED);
+    ///
+    /// let mut writer =
+    ///     PackWriter::with_features(vec![], Features::SUPPORTED).with_checksum(Checksum::None);
+    /// writer.push(b"a", &splinter);
+    /// let pack = PackReader::from_bytes(writer.finish()).unwrap();
+    ///
+    /// let splinter_ref = pack.get(b"a").unwrap().unwrap();
+    /// assert!(splinter_ref.features().contains(Features::INVERTED_PARTITIONS));
+    /// assert_eq!(splinter_ref.checksum(), Checksum::None);
+    /// ```
+    pub fn with_features(buf: B, features: Features) -> Self {
+        Self {
+            buf,
+            bytes_written: 0,
+            entries: BTreeMap::new(),
+            features,
+            checksum: Checksum::default(),
+            align_bitmaps: false,
+        }
+    }
+
+    /// Configure the checksum of every pushed splinter, like
+    /// [`Encoder::with_checksum`].
+    ///
+    /// # Panics
+    ///
+    /// Panics if the checksum's cargo feature is disabled, see
+    /// [`Checksum::is_enabled`].
+    pub fn with_checksum(mut self, checksum: Checksum) -> Self {
+        assert!(
+            checksum.is_enabled(),
+            "invalid pack usage: the {checksum:?} checksum is disabled by the crate's cargo features"
+        );
+        self.checksum = checksum;
+        self
+    }
+
+    /// Start every splinter on an 8-byte boundary, and encode pushed
+    /// splinters with [`Encoder::with_aligned_bitmaps`]. When the pack is
+    /// loaded into a buffer which is itself 8-byte aligned, such as a memory
+    /// mapped file, the splinters returned by [`PackReader::get`] read their
+    /// bitmaps as u64 words.
+    ///
+    /// Splinters copied by [`PackWriter::push_encoded`] also start on an
+    /// 8-byte boundary, but keep their own layout, so their bitmaps are only
+    /// aligned if they were encoded with aligned bitmaps.
+    ///
+    /// # Panics
+    ///
+    /// Panics if any splinter has already been pushed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{
+    ///     PartitionRead, Splinter,
+    ///     pack::{PackReader, PackWriter},
+    /// };
+    ///
+    /// let mut writer = PackWriter::new(vec![]).with_aligned_bitmaps();
+    /// writer.push(b"a", &Splinter::from_iter([1, 2, 3]));
+    /// writer.push(b"b", &Splinter::from_iter((0..4096).step_by(3)));
+    /// let pack = PackReader::from_bytes(writer.finish()).unwrap();
+    /// assert_eq!(pack.get(b"b").unwrap().unwrap().cardinality(), 1366);
+    /// ```
+    pub fn with_aligned_bitmaps(mut self) -> Self {
+        assert_eq!(
+            self.bytes_written, 0,
+            "invalid pack usage: alignment changed after writing"
+        );
+        self.align_bitmaps = true;
+        self
+    }
+
+    /// The number of splinters pushed so far.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries.len()
+    }
+
+    /// Returns true if no splinters have been pushed.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries.is_empty()
+    }
+
+    /// Encode a splinter into the pack under the provided key. Keys may be
+    /// pushed in any order.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key has already been pushed, or if the key or encoded
+    /// splinter is 4 GiB or larger.
+    pub fn push(&mut self, key: &[u8], splinter: &impl Encodable) {
+        self.check_key(key);
+        self.pad();
+        let mut encoder =
+            Encoder::with_features(&mut self.buf, self.features).with_checksum(self.checksum);
+        if self.align_bitmaps {
+            encoder = encoder.with_aligned_bitmaps();
+        }
+        splinter.encode(&mut encoder);
+        let len = encoder.bytes_written();
+        self.insert(key, len);
+    }
+
+    /// Copy an encoded splinter into the pack under the provided key, as is.
+    ///
+    /// Unlike [`PackWriter::push`], the splinter isn't re-encoded, so it
+    /// keeps its own features and checksum.
+    ///
+    /// # Panics
+    ///
+    /// Panics if the key has already been pushed, or if the key or encoded
+    /// splinter is 4 GiB or larger.
+    pub fn push_encoded(&mut self, key: &[u8], splinter: &SplinterRef<impl Deref<Target = [u8]>>) {
+        self.check_key(key);
+        self.pad();
+        let data = splinter.inner();
+        self.buf.put_slice(data);
+        self.insert(key, data.len());
+    }
+
+    fn check_key(&self, key: &[u8]) {
+        assert!(
+            u32::try_from(key.len()).is_ok(),
+            "invalid pack usage: key is too large"
+        );
+        assert!(
+            !self.entries.contains_key(key),
+            "invalid pack usage: duplicate key"
+        );
+    }
+
+    /// Pad the buffer so that the next splinter starts on an 8-byte boundary,
+    /// if this writer aligns bitmaps.
+    fn pad(&mut self) {
+        if self.align_bitmaps {
+            let padding =
+                self.bytes_written.next_multiple_of(size_of::<u64>()) - self.bytes_written;
+            self.buf.put_bytes(0, padding);
+            self.bytes_written += padding;
+        }
+    }
+
+    /// Record a splinter of length `len` which has just been written.
+    fn insert(&mut self, key: &[u8], len: usize) {
+        assert!(
+            u32::try_from(len).is_ok(),
+            "invalid pack usage: splinter is too large"
+        );
+        self.entries.insert(key.into(), (self.bytes_written, len));
+        self.bytes_written += len;
+    }
+
+    /// Write the directory and footer, and return the wrapped buffer.
+    pub fn finish(mut self) -> B {
+        let directory = self.bytes_written;
+        let mut hasher = Checksum::Crc64Nvme.hasher();
+
+        let mut key_offset = 0;
+        let mut entries = Vec::with_capacity(self.entries.len());
+        for (key, &(offset, len)) in &self.entries {
+            self.buf.put_slice(key);
+            hasher.write(key);
+            entries.push(Entry {
+                offset: (offset as u64).into(),
+                len: (len as u32).into(),
+                key_offset: u32::try_from(key_offset)
+                    .expect("invalid pack usage: keys are too large")
+                    .into(),
+                key_len: (key.len() as u32).into(),
+            });
+            key_offset += key.len();
+        }
+        self.buf.put_slice(entries.as_bytes());
+        hasher.write(entries.as_bytes());
+
+        let mut footer = PackFooter {
+            directory: (directory as u64).into(),
+            count: u32::try_from(entries.len())
+                .expect("invalid pack usage: too many splinters")
+                .into(),
+            checksum: 0.into(),
+            magic: PACK_MAGIC,
+        };
+        hasher.write(&footer.as_bytes()[..PackFooter::CHECKSUM_OFFSET]);
+        footer.checksum = hasher.sum().into();
+        self.buf.put_slice(footer.as_bytes());
+        self.buf
+    }
+}
+
+/// A zero-copy reader for a pack.
+///
+/// `PackReader` wraps any type that can be dereferenced to `[u8]`, such as
+/// `Bytes`, `Vec<u8>` or a memory mapped file.
+pub struct PackReader<B> {
+    data: B,
+    /// the offset of the keys from the start of the pack
+    directory: usize,
+    /// the offset of the entries from the start of the pack
+    entries: usize,
+    /// the offset of the footer from the start of the pack
+    footer: usize,
+}
+
+impl<B: Deref<Target = [u8]>> Debug for PackReader<B> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_struct("PackReader")
+            .field("len", &self.len())
+            .finish_non_exhaustive()
+    }
+}
+
+impl<B: Deref<Target = [u8]>> PackReader<B> {
+    /// Opens a pack, validating its directory.
+    ///
+    /// The splinters themselves are validated when they are retrieved.
+    ///
+    /// # Errors
+    ///
+    /// - [`DecodeErr::Length`]: The buffer is truncated, or the directory
+    ///   locates a key past the end of the address space
+    /// - [`DecodeErr::Magic`]: The buffer does not end with [`PACK_MAGIC`]
+    /// - [`DecodeErr::Checksum`]: The directory is corrupted
+    /// - [`DecodeErr::Validity`]: The directory is out of bounds or its keys
+    ///   are not sorted and unique
+    pub fn from_bytes(data: B) -> Result<Self, DecodeErr> {
+        let (rest, footer) = PackFooter::ref_from_suffix(&data)?;
+        if footer.magic != PACK_MAGIC {
+            return Err(DecodeErr::Magic);
+        }
+
+        let directory = usize::try_from(footer.directory.get()).map_err(|_| DecodeErr::Length)?;
+        let covered = rest.get(directory..).ok_or(DecodeErr::Length)?;
+        let mut hasher = Checksum::Crc64Nvme.hasher();
+        hasher.write(covered);
+        hasher.write(&footer.as_bytes()[..PackFooter::CHECKSUM_OFFSET]);
+        if hasher.sum() != footer.checksum.get() {
+            return Err(DecodeErr::Checksum);
+        }
+
+        let count = footer.count.get() as usize;
+        let (keys, entries) = <[Entry]>::ref_from_suffix_with_elems(covered, count)?;
+        let mut prev: Option<&[u8]> = None;
+        let mut key_end = 0;
+        for entry in entries {
+            // keys are stored back to back in ascending order
+            let key_offset = entry.key_offset.get() as usize;
+            let key_end_offset = key_offset
+                .checked_add(entry.key_len.get() as usize)
+                .ok_or(DecodeErr::Length)?;
+            let key = keys
+                .get(key_offset..key_end_offset)
+                .ok_or(DecodeErr::Validity)?;
+            if key_offset != key_end || prev.is_some_and(|prev| prev >= key) {
+                return Err(DecodeErr::Validity);
+            }
+            let offset = entry.offset.get();
+            let end = offset.checked_add(entry.len.get().into());
+            if end.is_none_or(|end| end > footer.directory.get()) {
+                return Err(DecodeErr::Validity);
+            }
+            prev = Some(key);
+            key_end = key_offset + key.len();
+        }
+        if key_end != keys.len() {
+            return Err(DecodeErr::Validity);
+        }
+
+        Ok(Self {
+            directory,
+            entries: directory + keys.len(),
+            footer: rest.len(),
+            data,
+        })
+    }
+
+    /// The number of splinters in the pack.
+    #[inline]
+    pub fn len(&self) -> usize {
+        self.entries().len()
+    }
+
+    /// Returns true if the pack contains no splinters.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.entries().is_empty()
+    }
+
+    /// Returns the splinter stored under the provided key, or `None` if the
+    /// pack doesn't contain the key.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error returned by [`SplinterRef::from_bytes`] if the
+    /// splinter is corrupted.
+    pub fn get(&self, key: &[u8]) -> Result<Option<SplinterRef<&[u8]>>, DecodeErr> {
+        let entries = self.entries();
+        match entries.binary_search_by(|entry| self.key(entry).cmp(key)) {
+            Ok(idx) => self.splinter(&entries[idx]).map(Some),
+            Err(_) => Ok(None),
+        }
+    }
+
+    /// Returns true if the pack contains the provided key.
+    pub fn contains_key(&self, key: &[u8]) -> bool {
+        self.entries()
+            .binary_search_by(|entry| self.key(entry).cmp(key))
+            .is_ok()
+    }
+
+    /// Returns an iterator over the keys in the pack, in ascending order.
+    pub fn keys(&self) -> impl ExactSizeIterator<Item = &[u8]> {
+        self.entries().iter().map(|entry| self.key(entry))
+    }
+
+    /// Returns an iterator over the keys and splinters in the pack, in
+    /// ascending key order.
+    ///
+    /// Each splinter is validated as it's yielded, see [`PackReader::get`].
+    pub fn iter(
+        &self,
+    ) -> impl ExactSizeIterator<Item = (&[u8], Result<SplinterRef<&[u8]>, DecodeErr>)> {
+        self.entries()
+            .iter()
+            .map(|entry| (self.key(entry), self.splinter(entry)))
+    }
+
+    /// Consumes the `PackReader` and returns the underlying data buffer.
+    #[inline]
+    pub fn into_inner(self) -> B {
+        self.data
+    }
+
+    #[inline]
+    fn entries(&self) -> &[Entry] {
+        <[Entry]>::ref_from_bytes(&self.data[self.entries..self.footer]).unwrap()
+    }
+
+    #[inline]
+    fn key(&self, entry: &Entry) -> &[u8] {
+        let start = self.directory + entry.key_offset.get() as usize;
+        &self.data[start..(start + entry.key_len.get() as usize)]
+    }
+
+    fn splinter(&self, entry: &Entry) -> Result<SplinterRef<&[u8]>, DecodeErr> {
+        let start = entry.offset.get() as usize;
+        SplinterRef::from_bytes(&self.data[start..(start + entry.len.get() as usize)])
+    }
+}
+
+/// Merges two packs into `writer`, and returns its buffer.
+///
+/// Splinters whose key only appears in one pack are copied as is with
+/// [`PackWriter::push_encoded`], while splinters whose key appears in both
+/// packs are replaced by their union, which is optimized for and encoded
+/// with the writer's features and checksum.
+///
+/// # Errors
+///
+/// Returns any error returned by [`PackReader::get`] if a splinter in either
+/// pack is corrupted.
+///
+/// # Panics
+///
+/// Panics if `writer` already contains one of the keys.
+///
+/// # Examples
+///
+/// ```
+/// use splinter_rs::{
+///     PartitionRead, Splinter,
+///     pack::{self, PackReader, PackWriter},
+/// };
+///
+/// let mut a = PackWriter::new(vec![]);
+/// a.push(b"a", &Splinter::from_iter([1, 2]));
+/// a.push(b"b", &Splinter::from_iter([3]));
+/// let a = PackReader::from_bytes(a.finish()).unwrap();
+///
+/// let mut b = PackWriter::new(vec![]);
+/// b.push(b"b", &Splinter::from_iter([4]));
+/// b.push(b"c", &Splinter::from_iter([5]));
+/// let b = PackReader::from_bytes(b.finish()).unwrap();
+///
+/// let merged = pack::merge(&a, &b, PackWriter::new(vec![])).unwrap();
+/// let merged = PackReader::from_bytes(merged).unwrap();
+/// assert_eq!(merged.keys().collect::<Vec<_>>(), [b"a", b"b", b"c"].map(|k| &k[..]));
+/// let union = merged.get(b"b").unwrap().unwrap();
+/// assert_eq!(union.iter().collect::<Vec<_>>(), [3, 4]);
+/// ```
+pub fn merge<B: BufMut>(
+    a: &PackReader<impl Deref<Target = [u8]>>,
+    b: &PackReader<impl Deref<Target = [u8]>>,
+    mut writer: PackWriter<B>,
+) -> Result<B, DecodeErr> {
+    let entries = a.iter().merge_join_by(b.iter(), |(a, _), (b, _)| a.cmp(b));
+    for entry in entries {
+        match entry {
+            EitherOrBoth::Left((key, splinter)) | EitherOrBoth::Right((key, splinter)) => {
+                writer.push_encoded(key, &splinter?);
+            }
+            EitherOrBoth::Both((key, a), (_, b)) => {
+                let mut union = a?.decode_to_splinter() | b?;
+                union.optimize_with(writer.features);
+                writer.push(key, &union);
+            }
+        }
+    }
+    Ok(writer.finish())
+}
+
+#[cfg(test)]
+mod tests {
+    use std::mem::offset_of;
+
+    use bytes::BytesMut;
+    use itertools::Itertools;
+    use zerocopy::IntoBytes;
+
+    use crate::{
+        Encodable, Optimizable, Splinter, assert_error,
+        codec::{
+            DecodeErr,
+            checksum::Checksum,
+            features::Features,
+            partition_ref::{NonRecursivePartitionRef, PartitionRef},
+        },
+        level::Level,
+        pack::{self, Entry, PACK_MAGIC, PackFooter, PackReader, PackWriter},
+        testutil::SetGen,
+        traits::PartitionRead,
+    };
+
+    fn mkpack(entries: &[(&str, Vec<u32>)]) -> Vec<u8> {
+        let mut writer = PackWriter::new(vec![]);
+        for (key, values) in entries {
+            writer.push(key.as_bytes(), &Splinter::from_iter(values.iter().copied()));
+        }
+        writer.finish()
+    }
+
+    #[test]
+    fn test_pack_roundtrip() {
+        let mut set_gen = SetGen::new(0xDEAD_BEEF);
+        let mut entries: Vec<(Vec<u8>, Vec<u32>)> = (0..100u32)
+            .map(|i| {
+                let key = format!("term-{}", i * 7919 % 100).into_bytes();
+                (key, set_gen.random(i as usize * 10))
+            })
+            .collect();
+        entries.push((vec![], vec![42]));
+
+        let mut writer = PackWriter::new(BytesMut::new());
+        for (key, values) in &entries {
+            writer.push(key, &Splinter::from_iter(values.iter().copied()));
+        }
+        assert_eq!(writer.len(), entries.len());
+        let buf = writer.finish().freeze();
+
+        let pack = PackReader::from_bytes(buf.clone()).unwrap();
+        assert_eq!(pack.len(), entries.len());
+        for (key, values) in &entries {
+            let splinter = pack.get(key).unwrap().unwrap();
+            assert_eq!(splinter.iter().collect_vec(), *values);
+            assert!(pack.contains_key(key));
+
+            // splinters are views into the pack buffer
+            let range = buf.as_ptr_range();
+            assert!(range.contains(&splinter.inner().as_ptr()));
+        }
+        assert!(pack.get(b"missing").unwrap().is_none());
+        assert!(!pack.contains_key(b"term-"));
+
+        entries.sort();
+        assert_eq!(
+            pack.keys().collect_vec(),
+            entries.iter().map(|(k, _)| &k[..]).collect_vec()
+        );
+        for ((key, splinter), (expected_key, values)) in pack.iter().zip(&entries) {
+            assert_eq!(key, expected_key);
+            assert_eq!(splinter.unwrap().iter().collect_vec(), *values);
+        }
+    }
+
+    #[test]
+    fn test_pack_empty() {
+        let buf = PackWriter::new(vec![]).finish();
+        let pack = PackReader::from_bytes(&buf[..]).unwrap();
+        assert!(pack.is_empty());
+        assert!(pack.get(b"").unwrap().is_none());
+        assert_eq!(pack.iter().count(), 0);
+    }
+
+    #[test]
+    #[should_panic(expected = "duplicate key")]
+    fn test_pack_duplicate_key() {
+        let mut writer = PackWriter::new(vec![]);
+        writer.push(b"a", &Splinter::EMPTY);
+        writer.push(b"a", &Splinter::EMPTY);
+    }
+
+    #[test]
+    fn test_pack_corruption() {
+        let buf = mkpack(&[("a", vec![1, 2, 3]), ("b", vec![4, 5, 6])]);
+
+        assert_error!(PackReader::from_bytes(&buf[..3]), DecodeErr::Length);
+        assert_error!(
+            PackReader::from_bytes(&buf[..(buf.len() - 1)]),
+            DecodeErr::Magic
+        );
+
+        // the directory is covered by the checksum
+        let splinter_len = Splinter::from_iter([1, 2, 3]).encoded_size();
+        let mut corrupted = buf.clone();
+        corrupted[2 * splinter_len] ^= 1;
+        assert_error!(PackReader::from_bytes(corrupted), DecodeErr::Checksum);
+
+        // the splinters are covered by their own checksums
+        let mut corrupted = buf.clone();
+        corrupted[splinter_len] ^= 1;
+        let pack = PackReader::from_bytes(corrupted).unwrap();
+        assert!(pack.get(b"a").unwrap().is_some());
+        assert_error!(pack.get(b"b"), DecodeErr::Checksum);
+
+        assert!(buf.ends_with(&PACK_MAGIC));
+
+        // keys must be sorted and unique
+        let footer = buf.len() - size_of::<PackFooter>();
+        let checksum = footer + PackFooter::CHECKSUM_OFFSET;
+        for keys in [b"ba", b"aa"] {
+            let mut invalid = buf.clone();
+            invalid[(2 * splinter_len)..(2 * splinter_len + 2)].copy_from_slice(keys);
+            let sum = Checksum::Crc64Nvme.compute(&invalid[(2 * splinter_len)..checksum]);
+            invalid[checksum..(checksum + 8)].copy_from_slice(&sum.to_le_bytes());
+            assert_error!(PackReader::from_bytes(invalid), DecodeErr::Validity);
+        }
+
+        // keys which end past the address space are rejected without
+        // overflowing
+        let mut invalid = buf.clone();
+        let key_offset = 2 * splinter_len + 2 + offset_of!(Entry, key_offset);
+        invalid[key_offset..(key_offset + 8)].fill(0xFF);
+        let sum = Checksum::Crc64Nvme.compute(&invalid[(2 * splinter_len)..checksum]);
+        invalid[checksum..(checksum + 8)].copy_from_slice(&sum.to_le_bytes());
+        #[cfg(target_pointer_width = "64")]
+        assert_error!(PackReader::from_bytes(invalid), DecodeErr::Validity);
+        #[cfg(not(target_pointer_width = "64"))]
+        assert_error!(PackReader::from_bytes(invalid), DecodeErr::Length);
+    }
+
+    #[test]
+    fn test_pack_aligned_bitmaps() {
+        let mut set_gen = SetGen::new(0xDEAD_BEEF);
+        let mut splinter = Splinter::from_iter(set_gen.distributed(4, 8, 8, 64));
+        splinter.optimize();
+
+        for align in [false, true] {
+            let mut writer = PackWriter::new(vec![]);
+            if align {
+                writer = writer.with_aligned_bitmaps();
+            }
+            // the first splinter's odd length misaligns the ones after it
+            writer.push(b"a", &Splinter::from_iter([1, 2, 3]));
+            writer.push(b"b", &splinter);
+            writer.push_encoded(b"c", &splinter.encode_to_splinter_ref());
+            let buf = writer.finish();
+
+            // load the pack into an 8-byte aligned buffer, like a memory
+            // mapped file
+            let mut words = vec![0u64; buf.len().div_ceil(8)];
+            let aligned_buf = &mut words.as_mut_bytes()[..buf.len()];
+            aligned_buf.copy_from_slice(&buf);
+            let pack = PackReader::from_bytes(&*aligned_buf).unwrap();
+
+            let pushed = pack.get(b"b").unwrap().unwrap();
+            assert_eq!(pushed, splinter);
+            let (aligned, bitmaps) = count_bitmaps(&pushed.load_unchecked());
+            assert!(bitmaps > 0);
+            if align {
+                assert_eq!(aligned, bitmaps);
+            } else {
+                assert!(aligned < bitmaps);
+            }
+
+            // copied splinters start on an 8-byte boundary, but keep their
+            // own layout
+            let copied = pack.get(b"c").unwrap().unwrap();
+            assert_eq!(copied, splinter);
+            assert_eq!(
+                copied.inner().as_ptr().align_offset(8) == 0,
+                align,
+                "copied splinter alignment"
+            );
+        }
+    }
+
+    /// Returns the number of bitmaps in `partition` which are read as u64
+    /// words, and the total number of bitmaps.
+    fn count_bitmaps<L: Level>(partition: &PartitionRef<'_, L>) -> (usize, usize) {
+        match partition {
+            PartitionRef::NonRecursive(NonRecursivePartitionRef::Bitmap { bitmap }) => {
+                (usize::from(bitmap.is_aligned()), 1)
+            }
+            PartitionRef::Tree(tree) => tree
+                .children()
+                .map(|child| count_bitmaps(&child))
+                .fold((0, 0), |(a, b), (c, d)| (a + c, b + d)),
+            PartitionRef::Prefix(prefix) => count_bitmaps(prefix.child()),
+            _ => (0, 0),
+        }
+    }
+
+    #[test]
+    fn test_pack_merge() {
+        let a = mkpack(&[("a", vec![1, 2]), ("b", vec![3]), ("d", vec![])]);
+        let b = mkpack(&[("b", vec![4, 5]), ("c", vec![6])]);
+        let a = PackReader::from_bytes(a).unwrap();
+        let b = PackReader::from_bytes(b).unwrap();
+
+        let merged = pack::merge(&a, &b, PackWriter::new(vec![])).unwrap();
+        let merged = PackReader::from_bytes(merged).unwrap();
+        let entries = merged
+            .iter()
+            .map(|(key, splinter)| (key.to_vec(), splinter.unwrap().iter().collect_vec()))
+            .collect_vec();
+        assert_eq!(
+            entries,
+            [
+                (b"a".to_vec(), vec![1, 2]),
+                (b"b".to_vec(), vec![3, 4, 5]),
+                (b"c".to_vec(), vec![6]),
+                (b"d".to_vec(), vec![]),
+            ]
+        );
+
+        // merging with an empty pack copies every splinter as is
+        let empty = PackReader::from_bytes(PackWriter::new(vec![]).finish()).unwrap();
+        assert_eq!(
+            pack::merge(&a, &empty, PackWriter::new(vec![])).unwrap(),
+            a.into_inner()
+        );
+    }
+
+    #[test]
+    fn test_pack_merge_encoding() {
+        let values = (0..65536).filter(|v| v % 1000 != 0).collect_vec();
+        let mut splinter = Splinter::from_iter(values.iter().copied());
+        splinter.optimize_with(Features::SUPPORTED);
+
+        let mut a =
+            PackWriter::with_features(vec![], Features::SUPPORTED).with_checksum(Checksum::None);
+        a.push(b"a", &splinter);
+        a.push(b"b", &Splinter::from_iter([70000]));
+        let a = PackReader::from_bytes(a.finish()).unwrap();
+        let b = PackReader::from_bytes(mkpack(&[("b", values.clone())])).unwrap();
+
+        // the splinter which is only in one pack keeps its encoding, while
+        // the union is encoded by the writer
+        let merged = pack::merge(&a, &b, PackWriter::new(vec![])).unwrap();
+        let merged = PackReader::from_bytes(merged).unwrap();
+        let copied = merged.get(b"a").unwrap().unwrap();
+        assert_eq!(copied.inner(), a.get(b"a").unwrap().unwrap().inner());
+        assert_eq!(copied.checksum(), Checksum::None);
+        assert!(copied.features().contains(Features::INVERTED_PARTITIONS));
+        let union = merged.get(b"b").unwrap().unwrap();
+        assert_eq!(union.checksum(), Checksum::Crc64Nvme);
+        assert_eq!(union.features(), Features::EMPTY);
+        assert_eq!(union.cardinality(), values.len() + 1);
+
+        let merged = pack::merge(
+            &a,
+            &b,
+            PackWriter::with_features(vec![], Features::SUPPORTED),
+        )
+        .unwrap();
+        let merged = PackReader::from_bytes(merged).unwrap();
+        let union = merged.get(b"b").unwrap().unwrap();
+        assert_eq!(union.checksum(), Checksum::Crc64Nvme);
+        assert!(union.features().contains(Features::INVERTED_PARTITIONS));
+    }
+}
@@ -1,26 +1,34 @@
 use std::{
+    borrow::Cow,
     fmt::{self, Debug},
     ops::{RangeBounds, RangeInclusive},
 };
 
 use bytes::BufMut;
-use itertools::Itertools;
-use num::traits::{AsPrimitive, Bounded};
+use num::traits::{AsPrimitive, Bounded, ConstZero};
 
 use crate::{
     MultiIter,
-    codec::{Encodable, encoder::Encoder},
+    codec::{Encodable, encoder::Encoder, features::Features},
+    count::count_unique_sorted,
     level::Level,
     partition::{
-        bitmap::BitmapPartition, run::RunPartition, tree::TreePartition, vec::VecPartition,
+        bitmap::BitmapPartition, delta_vec::DeltaVecPartition, elias_fano::EliasFanoPartition,
+        inverted::InvertedPartition, run::RunPartition, sparse_bitmap::SparseBitmapPartition,
+        sparse_bitmap::WORD_BITS, tree::TreePartition, vec::VecPartition,
     },
     partition_kind::PartitionKind,
-    traits::{DefaultFull, Optimizable, PartitionRead, PartitionWrite, TruncateFrom},
+    segment::SplitSegment,
+    traits::{Complement, DefaultFull, Optimizable, PartitionRead, PartitionWrite, TruncateFrom},
     util::{IteratorExt, RangeExt},
 };
 
 pub mod bitmap;
+pub mod delta_vec;
+pub mod elias_fano;
+pub mod inverted;
 pub mod run;
+pub mod sparse_bitmap;
 pub mod tree;
 pub mod vec;
 
@@ -31,6 +39,41 @@ pub enum Partition<L: Level> {
     Vec(VecPartition<L>),
     Run(RunPartition<L>),
     Tree(TreePartition<L>),
+    /// A tree with a single child, which is encoded without a tree index
+    Prefix(TreePartition<L>),
+    Inverted(Box<InvertedPartition<Partition<L>>>),
+    EliasFano(EliasFanoPartition<L>),
+    DeltaVec(DeltaVecPartition<L>),
+    SparseBitmap(SparseBitmapPartition<L>),
+}
+
+/// Encoded sizes (including the kind byte) of the partition kinds whose size
+/// depends on how a partition is currently stored, as passed to
+/// [`Partition::cheapest_kind`]. Kinds which shouldn't be considered are left
+/// at `usize::MAX`.
+#[derive(Debug, Clone, Copy)]
+pub(crate) struct KindSizes {
+    pub tree: usize,
+    pub prefix: usize,
+    pub run: usize,
+    pub inverted_run: usize,
+    pub elias_fano: usize,
+    pub delta_vec: usize,
+    pub sparse_bitmap: usize,
+}
+
+impl Default for KindSizes {
+    fn default() -> Self {
+        Self {
+            tree: usize::MAX,
+            prefix: usize::MAX,
+            run: usize::MAX,
+            inverted_run: usize::MAX,
+            elias_fano: usize::MAX,
+            delta_vec: usize::MAX,
+            sparse_bitmap: usize::MAX,
+        }
+    }
 }
 
 impl<L: Level> Partition<L> {
@@ -43,6 +86,11 @@ impl<L: Level> Partition<L> {
             Partition::Vec(_) => PartitionKind::Vec,
             Partition::Run(_) => PartitionKind::Run,
             Partition::Tree(_) => PartitionKind::Tree,
+            Partition::Prefix(_) => PartitionKind::Prefix,
+            Partition::Inverted(p) => p.kind(),
+            Partition::EliasFano(_) => PartitionKind::EliasFano,
+            Partition::DeltaVec(_) => PartitionKind::DeltaVec,
+            Partition::SparseBitmap(_) => PartitionKind::SparseBitmap,
         }
     }
 
@@ -52,7 +100,7 @@ impl<L: Level> Partition<L> {
         }
 
         assert!(
-            L::ALLOW_TREE || kind != PartitionKind::Tree,
+            L::ALLOW_TREE || !matches!(kind, PartitionKind::Tree | PartitionKind::Prefix),
             "BUG: Tree partitioning is not allowed at this level"
         );
 
@@ -65,7 +113,10 @@ impl<L: Level> Partition<L> {
                 debug_assert_eq!(self.cardinality(), L::MAX_LEN, "Partition is not full");
                 Partition::Full
             }
-            PartitionKind::Bitmap => Partition::Bitmap(self.iter().collect()),
+            PartitionKind::Bitmap => Partition::Bitmap(match &self {
+                Partition::SparseBitmap(partition) => partition.to_bitmap(),
+                other => other.iter().collect(),
+            }),
             PartitionKind::Vec => {
                 Partition::Vec(VecPartition::from_sorted_unique_unchecked(self.iter()))
             }
@@ -73,76 +124,303 @@ impl<L: Level> Partition<L> {
                 // optimize full partition conversion as we convert into run
                 // partitions when removing values from a full splinter
                 Partition::Full => RunPartition::full(),
+                Partition::Inverted(partition) => partition.to_run(),
                 other => RunPartition::from_sorted_unique_unchecked(other.iter()),
             }),
-            PartitionKind::Tree => Partition::Tree(match &self {
+            PartitionKind::Tree => Partition::Tree(match &mut *self {
                 Partition::Full | Partition::Tree(_) => {
                     // Full should never be optimized to Tree due cardinality check
                     // Tree should never be optimized into Tree due to early exit
                     unreachable!("BUG: invalid tree conversion")
                 }
-                Partition::Bitmap(partition) => partition.into(),
-                Partition::Vec(partition) => partition.into(),
-                Partition::Run(partition) => partition.into(),
+                Partition::Prefix(partition) => std::mem::take(partition),
+                Partition::Bitmap(partition) => (&*partition).into(),
+                Partition::Vec(partition) => (&*partition).into(),
+                Partition::Run(partition) => (&*partition).into(),
+                Partition::Inverted(partition) => (&partition.to_run()).into(),
+                Partition::EliasFano(partition) => {
+                    (&VecPartition::from_sorted_unique_unchecked(partition.iter())).into()
+                }
+                Partition::DeltaVec(partition) => {
+                    (&VecPartition::from_sorted_unique_unchecked(partition.iter())).into()
+                }
+                Partition::SparseBitmap(partition) => (&partition.to_bitmap()).into(),
             }),
+            PartitionKind::Prefix => {
+                debug_assert!(self.segments() <= 1, "Partition has multiple segments");
+                Partition::Prefix(match &mut *self {
+                    Partition::Tree(partition) => std::mem::take(partition),
+                    other => other.to_tree().into_owned(),
+                })
+            }
+            PartitionKind::EliasFano => {
+                assert!(
+                    L::ALLOW_ELIAS_FANO,
+                    "BUG: Elias-Fano partitions are not allowed at this level"
+                );
+                Partition::EliasFano(EliasFanoPartition::from_sorted_unique_unchecked(
+                    self.cardinality(),
+                    self.iter(),
+                ))
+            }
+            PartitionKind::DeltaVec => {
+                assert!(
+                    L::ALLOW_DELTA_VEC,
+                    "BUG: delta-vec partitions are not allowed at this level"
+                );
+                Partition::DeltaVec(DeltaVecPartition::from_sorted_unique_unchecked(self.iter()))
+            }
+            PartitionKind::SparseBitmap => {
+                assert!(
+                    L::ALLOW_SPARSE_BITMAP,
+                    "BUG: sparse bitmap partitions are not allowed at this level"
+                );
+                Partition::SparseBitmap(match &self {
+                    Partition::Bitmap(partition) => SparseBitmapPartition::from_bitmap(partition),
+                    other => SparseBitmapPartition::from_sorted_unique_unchecked(other.iter()),
+                })
+            }
+            PartitionKind::InvertedVec | PartitionKind::InvertedRun => {
+                // store the complement using the non-inverted kind
+                let mut complement = std::mem::take(self);
+                complement.complement();
+                complement.switch_kind(kind.invert());
+                Partition::Inverted(Box::new(InvertedPartition::new(complement)))
+            }
         }
     }
 
+    /// Returns this partition as a `TreePartition`, converting it if needed.
+    pub(crate) fn to_tree(&self) -> Cow<'_, TreePartition<L>> {
+        Cow::Owned(match self {
+            Partition::Tree(partition) | Partition::Prefix(partition) => {
+                return Cow::Borrowed(partition);
+            }
+            Partition::Full => (&RunPartition::full()).into(),
+            Partition::Bitmap(partition) => partition.into(),
+            Partition::Vec(partition) => partition.into(),
+            Partition::Run(partition) => partition.into(),
+            Partition::Inverted(partition) => (&partition.to_run()).into(),
+            Partition::EliasFano(partition) => {
+                (&VecPartition::from_sorted_unique_unchecked(partition.iter())).into()
+            }
+            Partition::DeltaVec(partition) => {
+                (&VecPartition::from_sorted_unique_unchecked(partition.iter())).into()
+            }
+            Partition::SparseBitmap(partition) => (&partition.to_bitmap()).into(),
+        })
+    }
+
     fn segments(&self) -> usize {
         match self {
             Partition::Full => 256,
             Partition::Vec(p) => p.segments(),
             Partition::Bitmap(p) => p.segments(),
             Partition::Run(p) => p.segments(),
-            Partition::Tree(p) => p.segments(),
+            Partition::Tree(p) | Partition::Prefix(p) => p.segments(),
+            Partition::Inverted(p) => p.to_run().segments(),
+            Partition::EliasFano(p) => p.segments(),
+            Partition::DeltaVec(p) => p.segments(),
+            Partition::SparseBitmap(p) => p.segments(),
         }
     }
 
-    fn count_runs(&self) -> usize {
+    pub(crate) fn count_runs(&self) -> usize {
         match self {
             Partition::Full => 1,
             Partition::Bitmap(p) => p.count_runs(),
             Partition::Vec(p) => p.count_runs(),
             Partition::Run(p) => p.count_runs(),
-            Partition::Tree(p) => p.count_runs(),
+            Partition::Tree(p) | Partition::Prefix(p) => p.count_runs(),
+            Partition::Inverted(p) => p.count_runs(),
+            Partition::EliasFano(p) => p.count_runs(),
+            Partition::DeltaVec(p) => p.count_runs(),
+            Partition::SparseBitmap(p) => p.count_runs(),
+        }
+    }
+
+    /// Count the nonzero 64 bit words in this partition's bitmap
+    pub(crate) fn count_words(&self) -> usize {
+        match self {
+            Partition::Bitmap(p) => p.count_words(),
+            Partition::SparseBitmap(p) => p.count_words(),
+            other => count_unique_sorted(other.iter().map(|v| v.as_() / WORD_BITS)),
         }
     }
 
     #[inline]
     pub(crate) fn optimize_fast(&mut self) {
-        self.switch_kind(self.optimize_kind(true));
+        let (kind, _) = self.optimize_kind(true, Features::SUPPORTED);
+        self.switch_kind(kind);
     }
 
-    fn optimize_kind(&self, fast: bool) -> PartitionKind {
+    /// Pick the kind this partition should be stored as among the kinds
+    /// which only need the provided optional features, along with its
+    /// encoded size (including the kind byte). Unless the partition is
+    /// already a tree and `fast` is false, the size of a tree is estimated.
+    fn optimize_kind(&self, fast: bool, features: Features) -> (PartitionKind, usize) {
         let cardinality = self.cardinality();
 
         if cardinality == L::MAX_LEN {
-            return PartitionKind::Full;
+            return (PartitionKind::Full, 1);
         }
 
         if cardinality == 0 {
             if L::ALLOW_TREE {
-                return PartitionKind::Tree;
+                return (PartitionKind::Tree, 1);
             } else {
-                return PartitionKind::Vec;
+                return (PartitionKind::Vec, 1);
             }
         }
 
+        let segments = if L::ALLOW_TREE { self.segments() } else { 0 };
+        let tree = if !fast && let Partition::Tree(tree) | Partition::Prefix(tree) = self {
+            // if we are already a tree, then we should only stay a tree
+            // if we are the smallest option
+            tree.encoded_size_with(features) + 1
+        } else if L::ALLOW_TREE {
+            // switch to tree if this level prefers it and the
+            // estimated size is the smallest option
+            TreePartition::<L>::estimate_encoded_size(segments, cardinality, features) + 1
+        } else {
+            // otherwise we don't want to be a tree
+            usize::MAX
+        };
+
+        let prefix = if segments != 1 {
+            // only trees with a single child can be path-compressed
+            usize::MAX
+        } else if !fast && let Partition::Tree(tree) | Partition::Prefix(tree) = self {
+            tree.prefix_encoded_size_with(features) + 1
+        } else if fast && !matches!(self, Partition::Prefix(_)) {
+            // if we are optimizing fast, avoid switching to prefix partitions
+            // so that unoptimized splinters don't need optional features
+            usize::MAX
+        } else {
+            TreePartition::<L>::estimate_prefix_encoded_size(cardinality, features) + 1
+        };
+
+        let run = if let Partition::Run(run) = self {
+            // if we are already a run partition, make sure we stay there
+            // until a more optimal choice presents itself
+            run.encoded_size() + 1
+        } else if fast {
+            // if we are optimizing fast, avoid switching to run
+            // partitions as counting runs can be slow
+            usize::MAX
+        } else {
+            RunPartition::<L>::encoded_size(self.count_runs()) + 1
+        };
+
+        let inverted_run = if let Partition::Inverted(inverted) = self
+            && inverted.kind() == PartitionKind::InvertedRun
+        {
+            // same hysteresis as non-inverted run partitions
+            inverted.encoded_size() + 1
+        } else if fast || cardinality <= L::MAX_LEN / 2 {
+            usize::MAX
+        } else {
+            RunPartition::<L>::encoded_size(self.count_complement_runs()) + 1
+        };
+
+        let elias_fano = if fast && !matches!(self, Partition::EliasFano(_)) {
+            // if we are optimizing fast, avoid switching to Elias-Fano
+            // partitions as they have to be rebuilt when they are modified
+            usize::MAX
+        } else {
+            Self::elias_fano_size(cardinality)
+        };
+
+        let delta_vec = if let Partition::DeltaVec(delta_vec) = self {
+            delta_vec.encoded_size() + 1
+        } else if fast || !L::ALLOW_DELTA_VEC {
+            // like Elias-Fano partitions, delta-vec partitions have to be
+            // rebuilt when they are modified
+            usize::MAX
+        } else {
+            let gap_bytes = DeltaVecPartition::<L>::gap_bytes(self.iter());
+            DeltaVecPartition::<L>::encoded_size(cardinality, gap_bytes) + 1
+        };
+
+        let sparse_bitmap = if let Partition::SparseBitmap(sparse_bitmap) = self {
+            sparse_bitmap.encoded_size() + 1
+        } else if fast || !L::ALLOW_SPARSE_BITMAP {
+            // sparse bitmaps also have to be rebuilt when they are modified
+            usize::MAX
+        } else {
+            Self::sparse_bitmap_size(self.count_words())
+        };
+
+        Self::cheapest_kind(
+            cardinality,
+            KindSizes {
+                tree,
+                prefix,
+                run,
+                inverted_run,
+                elias_fano,
+                delta_vec,
+                sparse_bitmap,
+            },
+            features,
+        )
+    }
+
+    /// The encoded size of this partition as an Elias-Fano partition
+    /// (including the kind byte), or `usize::MAX` if this level doesn't
+    /// support Elias-Fano partitions.
+    pub(crate) fn elias_fano_size(cardinality: usize) -> usize {
+        if L::ALLOW_ELIAS_FANO {
+            EliasFanoPartition::<L>::encoded_size(cardinality) + 1
+        } else {
+            usize::MAX
+        }
+    }
+
+    /// The encoded size of a sparse bitmap partition storing `words` nonzero
+    /// words (including the kind byte), or `usize::MAX` if this level doesn't
+    /// support sparse bitmap partitions.
+    pub(crate) fn sparse_bitmap_size(words: usize) -> usize {
+        if L::ALLOW_SPARSE_BITMAP {
+            SparseBitmapPartition::<L>::encoded_size(words) + 1
+        } else {
+            usize::MAX
+        }
+    }
+
+    /// The largest encoded size (including the kind byte) of any non-tree
+    /// partition at this level, ignoring Elias-Fano, delta-vec and sparse
+    /// bitmap partitions.
+    pub(crate) fn max_leaf_encoded_size() -> usize {
+        debug_assert!(!L::ALLOW_ELIAS_FANO && !L::ALLOW_DELTA_VEC && !L::ALLOW_SPARSE_BITMAP);
+        let vec = VecPartition::<L>::encoded_size(L::MAX_LEN);
+        let run = RunPartition::<L>::encoded_size(L::MAX_LEN.div_ceil(2));
+        vec.max(run).max(BitmapPartition::<L>::ENCODED_SIZE) + 1
+    }
+
+    /// Pick the kind with the smallest encoded size (including the kind byte)
+    /// for a partition which is neither empty nor full, among the kinds which
+    /// only need the provided optional features. The sizes of kinds which
+    /// depend on how the partition is currently stored are provided by the
+    /// caller.
+    pub(crate) fn cheapest_kind(
+        cardinality: usize,
+        sizes: KindSizes,
+        features: Features,
+    ) -> (PartitionKind, usize) {
+        debug_assert!(cardinality > 0 && cardinality < L::MAX_LEN);
+        let KindSizes {
+            tree,
+            prefix,
+            run,
+            inverted_run,
+            elias_fano,
+            delta_vec,
+            sparse_bitmap,
+        } = sizes;
+
         let choices = [
-            (PartitionKind::Tree, {
-                if !fast && let Partition::Tree(tree) = self {
-                    // if we are already a tree, then we should only stay a tree
-                    // if we are the smallest option
-                    tree.encoded_size() + 1
-                } else if L::ALLOW_TREE {
-                    // switch to tree if this level prefers it and the
-                    // estimated size is the smallest option
-                    TreePartition::<L>::estimate_encoded_size(self.segments(), cardinality) + 1
-                } else {
-                    // otherwise we don't want to be a tree
-                    usize::MAX
-                }
-            }),
+            (PartitionKind::Tree, tree),
             (
                 PartitionKind::Vec,
                 VecPartition::<L>::encoded_size(cardinality) + 1,
@@ -151,27 +429,63 @@ impl<L: Level> Partition<L> {
                 PartitionKind::Bitmap,
                 BitmapPartition::<L>::ENCODED_SIZE + 1,
             ),
+            (PartitionKind::Run, run),
+            // only partitions which are more than half full are inverted, as
+            // otherwise the complement is larger than the partition
             (
-                PartitionKind::Run,
-                if let Partition::Run(run) = self {
-                    // if we are already a run partition, make sure we stay there
-                    // until a more optimal choice presents itself
-                    run.encoded_size() + 1
-                } else if fast {
-                    // if we are optimizing fast, avoid switching to run
-                    // partitions as counting runs can be slow
-                    usize::MAX
+                PartitionKind::InvertedVec,
+                if cardinality > L::MAX_LEN / 2 {
+                    VecPartition::<L>::encoded_size(L::MAX_LEN - cardinality) + 1
                 } else {
-                    RunPartition::<L>::encoded_size(self.count_runs()) + 1
+                    usize::MAX
                 },
             ),
+            (PartitionKind::InvertedRun, inverted_run),
+            (PartitionKind::EliasFano, elias_fano),
+            (PartitionKind::DeltaVec, delta_vec),
+            (PartitionKind::SparseBitmap, sparse_bitmap),
+            // prefix partitions need an optional feature, so they lose ties
+            (PartitionKind::Prefix, prefix),
         ];
 
-        if let Some(idx) = choices.iter().position_min_by_key(|(_, s)| *s) {
-            return choices[idx].0;
+        // ties are broken in favor of the earlier choice
+        choices
+            .into_iter()
+            .filter(|(kind, _)| features.contains(kind.features()))
+            .min_by_key(|(_, s)| *s)
+            .expect("Vec and Bitmap partitions don't need optional features")
+    }
+
+    /// Returns the kind which is encoded in place of this partition when its
+    /// kind needs an optional feature the encoder doesn't support, using the
+    /// cheapest kind which only needs the provided features. Like
+    /// [`Partition::optimize_with`], the size of a tree is estimated rather
+    /// than computed exactly, so the returned size is only exact for
+    /// non-tree kinds.
+    fn fallback_kind(&self, features: Features) -> (PartitionKind, usize) {
+        self.optimize_kind(false, features)
+    }
+
+    /// Returns the partition which is encoded in place of this one when its
+    /// kind needs an optional feature the encoder doesn't support, see
+    /// [`Partition::fallback_kind`].
+    fn fallback(&self, features: Features) -> Self {
+        let (kind, _) = self.fallback_kind(features);
+        let mut fallback = self.clone();
+        fallback.switch_kind(kind);
+        if let Partition::Tree(tree) | Partition::Prefix(tree) = &mut fallback {
+            tree.optimize_children(features);
         }
+        fallback
+    }
 
-        self.kind()
+    /// Count the runs in the complement of this partition
+    pub(crate) fn count_complement_runs(&self) -> usize {
+        // every run splits one of the complement's runs, except for runs
+        // touching either end of the partition
+        let at_start = self.contains(L::Value::ZERO) as usize;
+        let at_end = self.contains(L::Value::max_value()) as usize;
+        self.count_runs() + 1 - at_start - at_end
     }
 
     /// Insert a value into the partition without optimizing the partition's
@@ -185,6 +499,35 @@ impl<L: Level> Partition<L> {
             Partition::Vec(partition) => partition.insert(value),
             Partition::Run(partition) => partition.insert(value),
             Partition::Tree(partition) => partition.insert(value),
+            Partition::Prefix(partition) => {
+                if partition
+                    .only_child()
+                    .is_some_and(|(segment, _)| segment != value.segment())
+                {
+                    // a second child turns the partition back into a tree
+                    self.switch_kind(PartitionKind::Tree);
+                    self.raw_insert(value)
+                } else {
+                    partition.insert(value)
+                }
+            }
+            Partition::Inverted(partition) => partition.insert(value),
+            Partition::EliasFano(_) | Partition::DeltaVec(_) => {
+                if self.contains(value) {
+                    return false;
+                }
+                // materialize immutable partitions as Vec partitions
+                self.switch_kind(PartitionKind::Vec);
+                self.raw_insert(value)
+            }
+            Partition::SparseBitmap(_) => {
+                if self.contains(value) {
+                    return false;
+                }
+                // materialize sparse bitmaps as Bitmap partitions
+                self.switch_kind(PartitionKind::Bitmap);
+                self.raw_insert(value)
+            }
         }
     }
 
@@ -200,7 +543,24 @@ impl<L: Level> Partition<L> {
             Partition::Bitmap(partition) => partition.remove(value),
             Partition::Vec(partition) => partition.remove(value),
             Partition::Run(partition) => partition.remove(value),
-            Partition::Tree(partition) => partition.remove(value),
+            Partition::Tree(partition) | Partition::Prefix(partition) => partition.remove(value),
+            Partition::Inverted(partition) => partition.remove(value),
+            Partition::EliasFano(_) | Partition::DeltaVec(_) => {
+                if !self.contains(value) {
+                    return false;
+                }
+                // materialize immutable partitions as Vec partitions
+                self.switch_kind(PartitionKind::Vec);
+                self.raw_remove(value)
+            }
+            Partition::SparseBitmap(_) => {
+                if !self.contains(value) {
+                    return false;
+                }
+                // materialize sparse bitmaps as Bitmap partitions
+                self.switch_kind(PartitionKind::Bitmap);
+                self.raw_remove(value)
+            }
         }
     }
 }
@@ -213,40 +573,145 @@ impl<L: Level> DefaultFull for Partition<L> {
 }
 
 impl<L: Level> Optimizable for Partition<L> {
+    #[inline]
     fn optimize(&mut self) {
+        self.optimize_with(Features::EMPTY);
+    }
+
+    fn optimize_with(&mut self, features: Features) {
         // we need to optimize children first to ensure that optimize_kind
         // chooses the best result
-        if let Partition::Tree(tree) = self {
-            tree.optimize_children();
+        if let Partition::Tree(tree) | Partition::Prefix(tree) = self {
+            tree.optimize_children(features);
         }
 
-        let kind = self.optimize_kind(false);
+        let (kind, _) = self.optimize_kind(false, features);
         if self.kind() != kind {
+            let was_tree = matches!(self, Partition::Tree(_) | Partition::Prefix(_));
             self.switch_kind(kind);
 
             // if we switched to a tree, make sure we fully optimize children
-            if let Partition::Tree(tree) = self {
-                tree.optimize_children();
+            if !was_tree && let Partition::Tree(tree) | Partition::Prefix(tree) = self {
+                tree.optimize_children(features);
             }
         }
     }
+
+    fn canonicalize(&mut self) {
+        self.canonicalize_with(Features::EMPTY);
+    }
+
+    fn canonicalize_with(&mut self, features: Features) {
+        let cardinality = self.cardinality();
+        if cardinality == L::MAX_LEN {
+            *self = Partition::Full;
+            return;
+        }
+        if cardinality == 0 {
+            // empty partitions always encode as PartitionKind::Empty
+            return;
+        }
+
+        // unlike optimize_kind, every size is computed exactly from the
+        // values, so the choice doesn't depend on the current kind
+        let tree = L::ALLOW_TREE.then(|| {
+            let mut tree = self.to_tree().into_owned();
+            tree.canonicalize_children(features);
+            tree
+        });
+        let tree_size = tree
+            .as_ref()
+            .map_or(usize::MAX, |t| t.encoded_size_with(features) + 1);
+        let prefix_size = match &tree {
+            Some(t) if t.segments() == 1 => t.prefix_encoded_size_with(features) + 1,
+            _ => usize::MAX,
+        };
+        let run = RunPartition::<L>::encoded_size(self.count_runs()) + 1;
+        let inverted_run = if cardinality > L::MAX_LEN / 2 {
+            RunPartition::<L>::encoded_size(self.count_complement_runs()) + 1
+        } else {
+            usize::MAX
+        };
+        let delta_vec = if L::ALLOW_DELTA_VEC {
+            let gap_bytes = DeltaVecPartition::<L>::gap_bytes(self.iter());
+            DeltaVecPartition::<L>::encoded_size(cardinality, gap_bytes) + 1
+        } else {
+            usize::MAX
+        };
+        let sparse_bitmap = if L::ALLOW_SPARSE_BITMAP {
+            Self::sparse_bitmap_size(self.count_words())
+        } else {
+            usize::MAX
+        };
+
+        let (kind, _) = Self::cheapest_kind(
+            cardinality,
+            KindSizes {
+                tree: tree_size,
+                prefix: prefix_size,
+                run,
+                inverted_run,
+                elias_fano: Self::elias_fano_size(cardinality),
+                delta_vec,
+                sparse_bitmap,
+            },
+            features,
+        );
+        match (kind, tree) {
+            (PartitionKind::Tree, Some(tree)) => *self = Partition::Tree(tree),
+            (PartitionKind::Prefix, Some(tree)) => *self = Partition::Prefix(tree),
+            _ => self.switch_kind(kind),
+        }
+    }
 }
 
 impl<L: Level> Encodable for Partition<L> {
     fn encoded_size(&self) -> usize {
+        self.encoded_size_with(Features::EMPTY)
+    }
+
+    fn encoded_size_with(&self, features: Features) -> usize {
         if self.is_empty() {
             // PartitionKind::Empty
-            1
-        } else {
-            let inner_size = match self {
-                Partition::Full => 0,
-                Partition::Bitmap(partition) => partition.encoded_size(),
-                Partition::Vec(partition) => partition.encoded_size(),
-                Partition::Run(partition) => partition.encoded_size(),
-                Partition::Tree(partition) => partition.encoded_size(),
+            return 1;
+        }
+        if !features.contains(self.kind().features()) {
+            return match self {
+                // unsupported prefix partitions fall back to trees
+                Partition::Prefix(partition) => partition.encoded_size_with(features) + 1,
+                _ => match self.fallback_kind(features) {
+                    // only trees need to be built to know their size
+                    (PartitionKind::Tree | PartitionKind::Prefix, _) => {
+                        self.fallback(features).encoded_size_with(features)
+                    }
+                    (_, size) => size,
+                },
             };
-            // inner + PartitionKind
-            inner_size + 1
+        }
+        let inner_size = match self {
+            Partition::Full => 0,
+            Partition::Bitmap(partition) => partition.encoded_size(),
+            Partition::Vec(partition) => partition.encoded_size(),
+            Partition::Run(partition) => partition.encoded_size(),
+            Partition::Tree(partition) => partition.encoded_size_with(features),
+            Partition::Prefix(partition) => partition.prefix_encoded_size_with(features),
+            Partition::Inverted(partition) => partition.encoded_size(),
+            Partition::EliasFano(partition) => partition.encoded_size(),
+            Partition::DeltaVec(partition) => partition.encoded_size(),
+            Partition::SparseBitmap(partition) => partition.encoded_size(),
+        };
+        // inner + PartitionKind
+        inner_size + 1
+    }
+
+    fn max_encoded_size(&self) -> usize {
+        match self {
+            // prefix partitions are encoded as trees by default, and every
+            // other kind is sized exactly
+            Partition::Tree(partition) | Partition::Prefix(partition) if !partition.is_empty() => {
+                partition.max_encoded_size() + 1
+            }
+            _ => self.encoded_size(),
         }
     }
 
@@ -274,7 +739,84 @@ impl<L: Level> Encodable for Partition<L> {
                     partition.encode(encoder);
                     encoder.put_kind(PartitionKind::Tree);
                 }
+                Partition::Prefix(partition) => {
+                    if partition.only_child().is_some()
+                        && encoder.supports(Features::PREFIX_PARTITIONS)
+                    {
+                        partition.encode_prefix(encoder);
+                        encoder.put_kind(PartitionKind::Prefix);
+                    } else {
+                        // fall back to a tree partition, which every reader
+                        // can decode
+                        partition.encode(encoder);
+                        encoder.put_kind(PartitionKind::Tree);
+                    }
+                }
+                Partition::Inverted(partition) => {
+                    if partition.inner().is_empty() {
+                        encoder.put_kind(PartitionKind::Full);
+                    } else if encoder.supports(Features::INVERTED_PARTITIONS) {
+                        partition.encode(encoder);
+                        encoder.put_kind(partition.kind());
+                    } else {
+                        self.fallback(encoder.features()).encode(encoder);
+                    }
+                }
+                Partition::EliasFano(partition) => {
+                    if encoder.supports(Features::ELIAS_FANO_PARTITIONS) {
+                        partition.encode(encoder);
+                        encoder.put_kind(PartitionKind::EliasFano);
+                    } else {
+                        self.fallback(encoder.features()).encode(encoder);
+                    }
+                }
+                Partition::DeltaVec(partition) => {
+                    if encoder.supports(Features::DELTA_VEC_PARTITIONS) {
+                        partition.encode(encoder);
+                        encoder.put_kind(PartitionKind::DeltaVec);
+                    } else {
+                        self.fallback(encoder.features()).encode(encoder);
+                    }
+                }
+                Partition::SparseBitmap(partition) => {
+                    if encoder.supports(Features::SPARSE_BITMAP_PARTITIONS) {
+                        partition.encode(encoder);
+                        encoder.put_kind(PartitionKind::SparseBitmap);
+                    } else {
+                        self.fallback(encoder.features()).encode(encoder);
+                    }
+                }
+            }
+        }
+    }
+
+    fn encoded_features(&self) -> Features {
+        self.encoded_features_with(Features::SUPPORTED)
+    }
+
+    fn encoded_features_with(&self, features: Features) -> Features {
+        match self {
+            _ if self.is_empty() => Features::EMPTY,
+            Partition::Prefix(partition) if !features.contains(Features::PREFIX_PARTITIONS) => {
+                partition.encoded_features_with(features)
+            }
+            _ if !features.contains(self.kind().features()) => match self.fallback_kind(features) {
+                (PartitionKind::Tree | PartitionKind::Prefix, _) => {
+                    self.fallback(features).encoded_features_with(features)
+                }
+                (kind, _) => kind.features(),
+            },
+            Partition::Prefix(partition) if partition.only_child().is_some() => {
+                partition.encoded_features_with(features) | Features::PREFIX_PARTITIONS
+            }
+            Partition::Tree(partition) | Partition::Prefix(partition) => {
+                partition.encoded_features_with(features)
             }
+            Partition::Inverted(partition) => partition.encoded_features(),
+            Partition::EliasFano(_) => Features::ELIAS_FANO_PARTITIONS,
+            Partition::DeltaVec(_) => Features::DELTA_VEC_PARTITIONS,
+            Partition::SparseBitmap(_) => Features::SPARSE_BITMAP_PARTITIONS,
+            _ => Features::EMPTY,
         }
     }
 }
@@ -293,6 +835,11 @@ impl<L: Level> Debug for Partition<L> {
             Partition::Vec(partition) => partition.fmt(f),
             Partition::Run(partition) => partition.fmt(f),
             Partition::Tree(partition) => partition.fmt(f),
+            Partition::Prefix(partition) => f.debug_tuple("Prefix").field(partition).finish(),
+            Partition::Inverted(partition) => partition.fmt(f),
+            Partition::EliasFano(partition) => partition.fmt(f),
+            Partition::DeltaVec(partition) => partition.fmt(f),
+            Partition::SparseBitmap(partition) => partition.fmt(f),
         }
     }
 }
@@ -304,7 +851,11 @@ impl<L: Level> PartitionRead<L> for Partition<L> {
             Partition::Bitmap(partition) => partition.cardinality(),
             Partition::Vec(partition) => partition.cardinality(),
             Partition::Run(partition) => partition.cardinality(),
-            Partition::Tree(partition) => partition.cardinality(),
+            Partition::Tree(partition) | Partition::Prefix(partition) => partition.cardinality(),
+            Partition::Inverted(partition) => partition.cardinality(),
+            Partition::EliasFano(partition) => partition.cardinality(),
+            Partition::DeltaVec(partition) => partition.cardinality(),
+            Partition::SparseBitmap(partition) => partition.cardinality(),
         }
     }
 
@@ -314,7 +865,11 @@ impl<L: Level> PartitionRead<L> for Partition<L> {
             Partition::Bitmap(partition) => partition.is_empty(),
             Partition::Vec(partition) => partition.is_empty(),
             Partition::Run(partition) => partition.is_empty(),
-            Partition::Tree(partition) => partition.is_empty(),
+            Partition::Tree(partition) | Partition::Prefix(partition) => partition.is_empty(),
+            Partition::Inverted(partition) => partition.is_empty(),
+            Partition::EliasFano(partition) => partition.is_empty(),
+            Partition::DeltaVec(partition) => partition.is_empty(),
+            Partition::SparseBitmap(partition) => partition.is_empty(),
         }
     }
 
@@ -324,7 +879,11 @@ impl<L: Level> PartitionRead<L> for Partition<L> {
             Partition::Bitmap(partition) => partition.contains(value),
             Partition::Vec(partition) => partition.contains(value),
             Partition::Run(partition) => partition.contains(value),
-            Partition::Tree(partition) => partition.contains(value),
+            Partition::Tree(partition) | Partition::Prefix(partition) => partition.contains(value),
+            Partition::Inverted(partition) => partition.contains(value),
+            Partition::EliasFano(partition) => partition.contains(value),
+            Partition::DeltaVec(partition) => partition.contains(value),
+            Partition::SparseBitmap(partition) => partition.contains(value),
         }
     }
 
@@ -334,7 +893,11 @@ impl<L: Level> PartitionRead<L> for Partition<L> {
             Partition::Bitmap(partition) => partition.position(value),
             Partition::Vec(partition) => partition.position(value),
             Partition::Run(partition) => partition.position(value),
-            Partition::Tree(partition) => partition.position(value),
+            Partition::Tree(partition) | Partition::Prefix(partition) => partition.position(value),
+            Partition::Inverted(partition) => partition.position(value),
+            Partition::EliasFano(partition) => partition.position(value),
+            Partition::DeltaVec(partition) => partition.position(value),
+            Partition::SparseBitmap(partition) => partition.position(value),
         }
     }
 
@@ -344,7 +907,11 @@ impl<L: Level> PartitionRead<L> for Partition<L> {
             Partition::Bitmap(p) => p.rank(value),
             Partition::Vec(p) => p.rank(value),
             Partition::Run(p) => p.rank(value),
-            Partition::Tree(p) => p.rank(value),
+            Partition::Tree(p) | Partition::Prefix(p) => p.rank(value),
+            Partition::Inverted(p) => p.rank(value),
+            Partition::EliasFano(p) => p.rank(value),
+            Partition::DeltaVec(p) => p.rank(value),
+            Partition::SparseBitmap(p) => p.rank(value),
         }
     }
 
@@ -354,7 +921,11 @@ impl<L: Level> PartitionRead<L> for Partition<L> {
             Partition::Bitmap(p) => p.select(idx),
             Partition::Vec(p) => p.select(idx),
             Partition::Run(p) => p.select(idx),
-            Partition::Tree(p) => p.select(idx),
+            Partition::Tree(p) | Partition::Prefix(p) => p.select(idx),
+            Partition::Inverted(p) => p.select(idx),
+            Partition::EliasFano(p) => p.select(idx),
+            Partition::DeltaVec(p) => p.select(idx),
+            Partition::SparseBitmap(p) => p.select(idx),
         }
     }
 
@@ -364,7 +935,11 @@ impl<L: Level> PartitionRead<L> for Partition<L> {
             Partition::Bitmap(p) => p.last(),
             Partition::Vec(p) => p.last(),
             Partition::Run(p) => p.last(),
-            Partition::Tree(p) => p.last(),
+            Partition::Tree(p) | Partition::Prefix(p) => p.last(),
+            Partition::Inverted(p) => p.last(),
+            Partition::EliasFano(p) => p.last(),
+            Partition::DeltaVec(p) => p.last(),
+            Partition::SparseBitmap(p) => p.last(),
         }
     }
 
@@ -378,7 +953,15 @@ impl<L: Level> PartitionRead<L> for Partition<L> {
             Partition::Bitmap(p) => Iter::Bitmap(p.iter()),
             Partition::Vec(p) => Iter::Vec(p.iter()),
             Partition::Run(p) => Iter::Run(p.iter()),
-            Partition::Tree(p) => Iter::Tree(p.iter()),
+            Partition::Tree(p) | Partition::Prefix(p) => Iter::Tree(p.iter()),
+            Partition::Inverted(p) => {
+                // boxed to break the recursion between Partition and its complement
+                let iter: Box<dyn Iterator<Item = L::Value> + '_> = Box::new(p.iter());
+                Iter::Inverted(iter)
+            }
+            Partition::EliasFano(p) => Iter::EliasFano(p.iter()),
+            Partition::DeltaVec(p) => Iter::DeltaVec(p.iter()),
+            Partition::SparseBitmap(p) => Iter::SparseBitmap(p.iter()),
         }
     }
 
@@ -388,7 +971,11 @@ impl<L: Level> PartitionRead<L> for Partition<L> {
             Partition::Bitmap(p) => p.contains_all(values),
             Partition::Vec(p) => p.contains_all(values),
             Partition::Run(p) => p.contains_all(values),
-            Partition::Tree(p) => p.contains_all(values),
+            Partition::Tree(p) | Partition::Prefix(p) => p.contains_all(values),
+            Partition::Inverted(p) => p.contains_all(values),
+            Partition::EliasFano(p) => p.contains_all(values),
+            Partition::DeltaVec(p) => p.contains_all(values),
+            Partition::SparseBitmap(p) => p.contains_all(values),
         }
     }
 
@@ -401,7 +988,11 @@ impl<L: Level> PartitionRead<L> for Partition<L> {
             Partition::Bitmap(p) => p.contains_any(values),
             Partition::Vec(p) => p.contains_any(values),
             Partition::Run(p) => p.contains_any(values),
-            Partition::Tree(p) => p.contains_any(values),
+            Partition::Tree(p) | Partition::Prefix(p) => p.contains_any(values),
+            Partition::Inverted(p) => p.contains_any(values),
+            Partition::EliasFano(p) => p.contains_any(values),
+            Partition::DeltaVec(p) => p.contains_any(values),
+            Partition::SparseBitmap(p) => p.contains_any(values),
         }
     }
 }
@@ -432,7 +1023,18 @@ impl<L: Level> PartitionWrite<L> for Partition<L> {
             Partition::Bitmap(partition) => partition.remove_range(values),
             Partition::Vec(partition) => partition.remove_range(values),
             Partition::Run(partition) => partition.remove_range(values),
-            Partition::Tree(partition) => partition.remove_range(values),
+            Partition::Tree(partition) | Partition::Prefix(partition) => {
+                partition.remove_range(values)
+            }
+            Partition::Inverted(partition) => partition.remove_range(values),
+            Partition::EliasFano(_) | Partition::DeltaVec(_) => {
+                self.switch_kind(PartitionKind::Vec);
+                self.remove_range(values)
+            }
+            Partition::SparseBitmap(_) => {
+                self.switch_kind(PartitionKind::Bitmap);
+                self.remove_range(values)
+            }
         }
         self.optimize_fast();
     }
@@ -459,20 +1061,46 @@ impl<L: Level> Extend<L::Value> for Partition<L> {
             Partition::Bitmap(partition) => partition.extend(iter),
             Partition::Vec(partition) => partition.extend(iter),
             Partition::Run(partition) => partition.extend(iter),
-            Partition::Tree(partition) => partition.extend(iter),
+            Partition::Tree(partition) | Partition::Prefix(partition) => partition.extend(iter),
+            Partition::Inverted(partition) => {
+                for value in iter {
+                    partition.insert(value);
+                }
+            }
+            Partition::EliasFano(_) | Partition::DeltaVec(_) => {
+                self.switch_kind(PartitionKind::Vec);
+                self.extend(iter)
+            }
+            Partition::SparseBitmap(_) => {
+                self.switch_kind(PartitionKind::Bitmap);
+                self.extend(iter)
+            }
         }
         self.optimize_fast();
     }
 }
 
-MultiIter!(Iter, Full, Bitmap, Vec, Run, Tree);
+MultiIter!(
+    Iter,
+    Full,
+    Bitmap,
+    Vec,
+    Run,
+    Tree,
+    Inverted,
+    EliasFano,
+    DeltaVec,
+    SparseBitmap
+);
 
 #[cfg(test)]
 mod tests {
+    use bytes::BytesMut;
     use itertools::Itertools;
 
     use crate::{
         PartitionRead, PartitionWrite,
+        codec::{Encodable, encoder::Encoder, features::Features, partition_ref::PartitionRef},
         level::{Block, High, Level, Low},
         partition::Partition,
         partition_kind::PartitionKind,
@@ -504,6 +1132,12 @@ mod tests {
             PartitionKind::Vec,
             PartitionKind::Run,
             PartitionKind::Tree,
+            PartitionKind::Prefix,
+            PartitionKind::InvertedVec,
+            PartitionKind::InvertedRun,
+            PartitionKind::EliasFano,
+            PartitionKind::DeltaVec,
+            PartitionKind::SparseBitmap,
         ];
         let sets = &[
             vec![],
@@ -544,4 +1178,25 @@ mod tests {
         // this should not panic
         let _ = p.segments();
     }
+
+    #[test]
+    fn test_encode_fallback() {
+        // a single run falls back to a Run partition rather than a Vec
+        let values = (0..200).collect_vec();
+        for kind in [
+            PartitionKind::EliasFano,
+            PartitionKind::DeltaVec,
+            PartitionKind::SparseBitmap,
+            PartitionKind::InvertedRun,
+        ] {
+            let mut partition = Partition::<Low>::from_iter(values.iter().copied());
+            partition.switch_kind(kind);
+            let mut encoder = Encoder::with_features(BytesMut::new(), Features::EMPTY);
+            partition.encode(&mut encoder);
+            let buf = encoder.into_inner();
+            let partition_ref = PartitionRef::<Low>::from_suffix(&buf).unwrap();
+            assert_eq!(partition_ref.kind(), PartitionKind::Run, "kind {kind:?}");
+            test_partition_read(&partition_ref, &values);
+        }
+    }
 }
@@ -7,6 +7,7 @@ use std::{
 use bitvec::{
     bitbox,
     boxed::BitBox,
+    field::BitField,
     order::{BitOrder, Lsb0},
     slice::BitSlice,
     store::BitStore,
@@ -45,11 +46,59 @@ impl<L: Level> BitmapPartition<L> {
         count_bitmap_runs(&self.bitmap)
     }
 
+    /// Count the nonzero u64 words in the bitmap
+    #[inline]
+    pub fn count_words(&self) -> usize {
+        self.bitmap
+            .as_raw_slice()
+            .iter()
+            .filter(|&&word| word != 0)
+            .count()
+    }
+
     #[inline]
     pub(crate) fn as_bitbox(&self) -> &BitBox<u64, Lsb0> {
         &self.bitmap
     }
 
+    /// Decodes a bitmap from its encoded bytes. Each u64 word is loaded from
+    /// eight little-endian bytes so the result doesn't depend on the target's
+    /// byte order.
+    pub(crate) fn from_encoded(bits: &BitSlice<u8, Lsb0>) -> Self {
+        assert_eq!(
+            bits.len(),
+            L::MAX_LEN,
+            "BUG: encoded bitmap has the wrong length"
+        );
+        let words = bits
+            .chunks_exact(u64::BITS as usize)
+            .map(|word| word.load_le::<u64>())
+            .collect::<Box<[u64]>>();
+        let bitmap = BitBox::from_boxed_slice(words);
+        let cardinality = bitmap.count_ones();
+        Self {
+            bitmap,
+            cardinality,
+            _marker: PhantomData,
+        }
+    }
+
+    /// Copies a bitmap which was read from an aligned buffer as u64 words.
+    pub(crate) fn from_words(bits: &BitSlice<u64, Lsb0>) -> Self {
+        assert_eq!(
+            bits.len(),
+            L::MAX_LEN,
+            "BUG: encoded bitmap has the wrong length"
+        );
+        let bitmap = BitBox::from_bitslice(bits);
+        let cardinality = bitmap.count_ones();
+        Self {
+            bitmap,
+            cardinality,
+            _marker: PhantomData,
+        }
+    }
+
     /// Count the number of segments in the bitmap
     pub(crate) fn segments(&self) -> usize {
         let mut count = 0;
@@ -400,11 +449,13 @@ impl<L: Level> Extend<L::Value> for BitmapPartition<L> {
 mod test {
     use std::collections::{BTreeSet, HashSet};
 
+    use bitvec::slice::BitSlice;
     use hegel::generators;
     use itertools::Itertools;
     use proptest::proptest;
 
     use crate::{
+        codec::Encodable,
         count::count_runs_sorted,
         level::{Block, Low},
         partition::{Partition, bitmap::BitmapPartition},
@@ -660,4 +711,51 @@ mod test {
         assert_eq!(partition.iter().collect_vec(), expected);
         assert_eq!(partition.cardinality(), expected.len());
     }
+
+    /// Models the u64 words a big-endian target such as s390x sees when it
+    /// reinterprets encoded bytes in place: the first byte of each word is
+    /// its most significant byte.
+    fn big_endian_native_words(bytes: &[u8]) -> Vec<u64> {
+        bytes
+            .chunks_exact(8)
+            .map(|word| {
+                word.iter()
+                    .fold(0, |acc, &byte| (acc << 8) | u64::from(byte))
+            })
+            .collect()
+    }
+
+    proptest! {
+        #[test]
+        fn test_bitmap_encoding_is_endian_independent(values: HashSet<u16>) {
+            let partition = BitmapPartition::<Low>::from_iter(values.iter().copied());
+
+            // the format stores bit i in bit i % 8 of byte i / 8
+            let mut expected = vec![0u8; BitmapPartition::<Low>::ENCODED_SIZE];
+            for &value in &values {
+                expected[usize::from(value / 8)] |= 1 << (value % 8);
+            }
+            assert_eq!(partition.encode_to_bytes(), expected);
+
+            // reading the encoded words in place on a big-endian target
+            // reverses the bytes of every word, scrambling the bitmap
+            let native = big_endian_native_words(&expected);
+            let scrambled = BitmapPartition::<Low>::from_words(BitSlice::from_slice(&native));
+            let expected_scrambled = values
+                .iter()
+                .map(|&value| {
+                    let byte = (value % 64) / 8;
+                    value - byte * 8 + (7 - byte) * 8
+                })
+                .sorted()
+                .collect_vec();
+            assert_eq!(scrambled.iter().collect_vec(), expected_scrambled);
+
+            // so bitmaps are decoded from their bytes, which restores the
+            // same words no matter how the target orders them in memory
+            let decoded = BitmapPartition::<Low>::from_encoded(BitSlice::from_slice(&expected));
+            assert_eq!(decoded.as_bitbox().as_raw_slice(), partition.as_bitbox().as_raw_slice());
+            assert_eq!(decoded.cardinality(), values.len());
+        }
+    }
 }
@@ -0,0 +1,352 @@
+use std::{fmt::Debug, marker::PhantomData, mem::size_of, ops::RangeBounds};
+
+use bytes::BufMut;
+use num::traits::AsPrimitive;
+use zerocopy::IntoBytes;
+
+use crate::{
+    codec::{Encodable, delta_vec_ref::DeltaVecRef, encoder::Encoder},
+    count::{count_runs_sorted, count_unique_sorted},
+    level::Level,
+    segment::SplitSegment,
+    traits::{PartitionRead, TruncateFrom},
+};
+
+/// The number of values stored in each block of a delta-vec partition. The
+/// first value of every block is stored in the skip index.
+pub(crate) const SKIP_INTERVAL: usize = 64;
+
+/// Returns the number of blocks needed to store `cardinality` values.
+#[inline]
+pub(crate) const fn num_blocks(cardinality: usize) -> usize {
+    cardinality.div_ceil(SKIP_INTERVAL)
+}
+
+/// Returns the number of bytes needed to store `value` as a LEB128 varint.
+#[inline]
+pub(crate) const fn varint_len(value: usize) -> usize {
+    // every byte stores 7 bits of the value
+    ((usize::BITS - (value | 1).leading_zeros()) as usize).div_ceil(7)
+}
+
+/// Appends `value` to `buf` as a LEB128 varint.
+fn put_varint(buf: &mut Vec<u8>, mut value: usize) {
+    while value >= 0x80 {
+        buf.push(value as u8 | 0x80);
+        value >>= 7;
+    }
+    buf.push(value as u8);
+}
+
+/// Decodes a LEB128 varint from the start of `data`, returning the value and
+/// the number of bytes it used. Returns `None` if the varint is truncated or
+/// too large.
+#[inline]
+pub(crate) fn get_varint(data: &[u8]) -> Option<(usize, usize)> {
+    let mut value = 0;
+    for (i, &byte) in data.iter().enumerate() {
+        let shift = i * 7;
+        if shift >= usize::BITS as usize {
+            return None;
+        }
+        value |= ((byte & 0x7F) as usize) << shift;
+        if byte & 0x80 == 0 {
+            return Some((value, i + 1));
+        }
+    }
+    None
+}
+
+/// A `DeltaVecPartition` stores its values in blocks of [`SKIP_INTERVAL`]
+/// values. The first value of each block is stored in a skip index, while the
+/// remaining values are stored as varint encoded gaps from the previous value.
+/// This is much smaller than a Vec partition for clustered values, while
+/// lookups only need to binary search the skip index and decode a single
+/// block.
+///
+/// Encoding order: `[gaps][block first values][block end offsets][length]`,
+/// where each gap stores `value - previous - 1`, and the end offsets locate
+/// the last gap of each block.
+///
+/// The partition is stored in its encoded form and can't be modified in
+/// place. `Partition` converts it into a Vec partition before writing.
+#[derive(Clone, Eq)]
+pub struct DeltaVecPartition<L: Level> {
+    cardinality: usize,
+    /// the encoded gaps and skip index
+    data: Box<[u8]>,
+    _marker: PhantomData<L>,
+}
+
+impl<L: Level> DeltaVecPartition<L> {
+    #[inline]
+    pub const fn encoded_size(cardinality: usize, gap_bytes: usize) -> usize {
+        // gaps + skip index + length
+        let vsize = size_of::<L::ValueUnaligned>();
+        gap_bytes + (2 * num_blocks(cardinality) * vsize) + vsize
+    }
+
+    /// Returns the number of bytes needed to store the gaps between a sorted
+    /// iter of unique values.
+    pub fn gap_bytes(values: impl Iterator<Item = L::Value>) -> usize {
+        let mut prev = 0;
+        let mut bytes = 0;
+        for (idx, value) in values.enumerate() {
+            let value: usize = value.as_();
+            if !idx.is_multiple_of(SKIP_INTERVAL) {
+                bytes += varint_len(value - prev - 1);
+            }
+            prev = value;
+        }
+        bytes
+    }
+
+    /// Construct a `DeltaVecPartition` from a sorted iter of unique values
+    /// SAFETY: undefined behavior if the iter is not sorted or contains duplicates
+    pub fn from_sorted_unique_unchecked(values: impl Iterator<Item = L::Value>) -> Self {
+        let mut gaps = Vec::new();
+        let mut firsts = Vec::new();
+        let mut ends = Vec::new();
+        let mut cardinality: usize = 0;
+        let mut prev = 0;
+        for value in values {
+            let raw: usize = value.as_();
+            if cardinality.is_multiple_of(SKIP_INTERVAL) {
+                if cardinality > 0 {
+                    ends.push(gaps.len());
+                }
+                firsts.push(value);
+            } else {
+                put_varint(&mut gaps, raw - prev - 1);
+            }
+            prev = raw;
+            cardinality += 1;
+        }
+        if cardinality > 0 {
+            ends.push(gaps.len());
+        }
+
+        let mut data = gaps;
+        for value in firsts {
+            data.extend_from_slice(L::ValueUnaligned::from(value).as_bytes());
+        }
+        for end in ends {
+            assert!(
+                end < L::MAX_LEN,
+                "delta-vec gaps too large to encode offsets at this level"
+            );
+            let end = L::ValueUnaligned::from(L::Value::truncate_from(end));
+            data.extend_from_slice(end.as_bytes());
+        }
+
+        Self {
+            cardinality,
+            data: data.into_boxed_slice(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns a view of the encoded gaps and skip index
+    #[inline]
+    pub fn as_encoded(&self) -> DeltaVecRef<'_, L> {
+        DeltaVecRef::from_parts(self.cardinality, &self.data)
+    }
+
+    #[inline]
+    pub fn count_runs(&self) -> usize {
+        count_runs_sorted(self.iter())
+    }
+
+    #[inline]
+    pub fn segments(&self) -> usize {
+        count_unique_sorted(self.iter().map(|v| v.segment()))
+    }
+}
+
+impl<L: Level> Debug for DeltaVecPartition<L> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "DeltaVecPartition<{}>({})",
+            L::DEBUG_NAME,
+            self.cardinality
+        )
+    }
+}
+
+impl<L: Level> Encodable for DeltaVecPartition<L> {
+    #[inline]
+    fn encoded_size(&self) -> usize {
+        // the encoded data already contains the skip index
+        self.data.len() + size_of::<L::ValueUnaligned>()
+    }
+
+    fn encode<B: BufMut>(&self, encoder: &mut Encoder<B>) {
+        encoder.put_delta_vec_partition::<L>(&self.data, self.cardinality);
+    }
+}
+
+impl<L: Level> PartitionRead<L> for DeltaVecPartition<L> {
+    #[inline]
+    fn cardinality(&self) -> usize {
+        self.cardinality
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.cardinality == 0
+    }
+
+    fn contains(&self, value: L::Value) -> bool {
+        self.as_encoded().contains(value)
+    }
+
+    fn position(&self, value: L::Value) -> Option<usize> {
+        self.as_encoded().position(value)
+    }
+
+    fn rank(&self, value: L::Value) -> usize {
+        self.as_encoded().rank(value)
+    }
+
+    fn select(&self, idx: usize) -> Option<L::Value> {
+        self.as_encoded().select(idx)
+    }
+
+    fn last(&self) -> Option<L::Value> {
+        self.as_encoded().last()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = L::Value> {
+        self.as_encoded().into_iter()
+    }
+
+    fn contains_all<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        self.as_encoded().contains_all(values)
+    }
+
+    fn contains_any<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        self.as_encoded().contains_any(values)
+    }
+}
+
+impl<L: Level> PartialEq for DeltaVecPartition<L> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        // the encoding is fully determined by the partition's values
+        self.cardinality == other.cardinality && self.data == other.data
+    }
+}
+
+impl<'a, L: Level> PartialEq<DeltaVecRef<'a, L>> for DeltaVecPartition<L> {
+    #[inline]
+    fn eq(&self, other: &DeltaVecRef<'a, L>) -> bool {
+        self.as_encoded() == *other
+    }
+}
+
+impl<'a, L: Level> From<&DeltaVecRef<'a, L>> for DeltaVecPartition<L> {
+    fn from(value: &DeltaVecRef<'a, L>) -> Self {
+        Self {
+            cardinality: value.cardinality(),
+            data: value.to_bytes(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use itertools::Itertools;
+    use proptest::proptest;
+
+    use crate::{
+        Encodable,
+        level::{High, Level, Low},
+        partition::delta_vec::{DeltaVecPartition, get_varint, put_varint, varint_len},
+        testutil::{LevelSetGen, test_partition_read},
+        traits::TruncateFrom,
+    };
+
+    #[test]
+    fn test_varint() {
+        for value in [
+            0,
+            1,
+            127,
+            128,
+            300,
+            16383,
+            16384,
+            u32::MAX as usize,
+            usize::MAX,
+        ] {
+            let mut buf = vec![];
+            put_varint(&mut buf, value);
+            assert_eq!(buf.len(), varint_len(value), "varint_len({value})");
+            buf.push(0xFF);
+            assert_eq!(get_varint(&buf), Some((value, buf.len() - 1)));
+        }
+
+        // truncated varints can't be decoded
+        assert_eq!(get_varint(&[0x80, 0x80]), None);
+        assert_eq!(get_varint(&[]), None);
+    }
+
+    #[test]
+    fn test_delta_vec_size() {
+        // consecutive values only need a single byte per gap
+        let partition = DeltaVecPartition::<Low>::from_sorted_unique_unchecked(0..1000);
+        let gap_bytes = DeltaVecPartition::<Low>::gap_bytes(0..1000);
+        assert_eq!(gap_bytes, 1000 - 16);
+        assert_eq!(
+            partition.encoded_size(),
+            DeltaVecPartition::<Low>::encoded_size(1000, gap_bytes)
+        );
+    }
+
+    #[test]
+    fn test_delta_vec_read() {
+        let mut setgen = LevelSetGen::<High>::new(0xDEADBEEF);
+        let sets = &[
+            vec![0],
+            vec![High::MAX_LEN - 1],
+            vec![0, High::MAX_LEN - 1],
+            (0..4096).collect_vec(),
+            (0..High::MAX_LEN).step_by(1 << 20).collect_vec(),
+        ];
+        let sets = sets
+            .iter()
+            .map(|set| {
+                set.iter()
+                    .map(|&v| <High as Level>::Value::truncate_from(v))
+                    .collect_vec()
+            })
+            .chain([
+                setgen.random(63),
+                setgen.random(64),
+                setgen.random(65),
+                setgen.random(4096),
+                setgen.runs(4096, 0.5),
+            ]);
+
+        for set in sets {
+            let partition =
+                DeltaVecPartition::<High>::from_sorted_unique_unchecked(set.iter().copied());
+            test_partition_read(&partition, &set);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_delta_vec_read_proptest(set: HashSet<u16>) {
+            let expected = set.iter().copied().sorted().collect_vec();
+            let partition = DeltaVecPartition::<Low>::from_sorted_unique_unchecked(
+                expected.iter().copied(),
+            );
+            test_partition_read(&partition, &expected);
+        }
+    }
+}
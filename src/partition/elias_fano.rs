@@ -0,0 +1,356 @@
+use std::{fmt::Debug, marker::PhantomData, ops::RangeBounds};
+
+use bitvec::{bitvec, field::BitField, order::Lsb0, slice::BitSlice};
+use bytes::BufMut;
+use either::Either;
+use itertools::Itertools;
+use num::traits::AsPrimitive;
+use zerocopy::{IntoBytes, LittleEndian, U32};
+
+use crate::{
+    codec::{Encodable, elias_fano_ref::EliasFanoRef, encoder::Encoder},
+    count::{count_runs_sorted, count_unique_sorted},
+    level::Level,
+    segment::SplitSegment,
+    traits::PartitionRead,
+};
+
+/// The number of set (or unset) bits in the upper bit array between two
+/// entries of the select index.
+pub(crate) const SELECT_INTERVAL: usize = 256;
+
+/// The encoded position of a bit in the upper bit array.
+pub(crate) type SelectSample = U32<LittleEndian>;
+
+/// The shape of an Elias-Fano encoded partition, which only depends on the
+/// partition's cardinality.
+///
+/// Each value is split into its `low_bits` least significant bits, which are
+/// packed into the lower bit array, and its remaining high bits, which are
+/// stored in the upper bit array in unary: the `i`th value sets bit
+/// `(value >> low_bits) + i`.
+///
+/// The select index stores the position of every [`SELECT_INTERVAL`]th set
+/// and unset bit of the upper bit array (skipping the first one), so lookups
+/// only scan the bits following the closest sample.
+///
+/// Encoding order: `[lower bits][upper bits][one samples][zero samples][length]`,
+/// where both bit arrays are padded to a whole number of bytes.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+pub(crate) struct EliasFanoLayout {
+    pub low_bits: usize,
+    pub lower_bits: usize,
+    pub upper_bits: usize,
+    pub one_samples: usize,
+    pub zero_samples: usize,
+}
+
+impl EliasFanoLayout {
+    pub const fn new<L: Level>(cardinality: usize) -> Self {
+        let buckets = match L::MAX_LEN.checked_div(cardinality) {
+            Some(buckets) => buckets,
+            None => L::MAX_LEN,
+        };
+        let low_bits = buckets.ilog2() as usize;
+        let zeros = L::MAX_LEN >> low_bits;
+        Self {
+            low_bits,
+            lower_bits: cardinality * low_bits,
+            // one bit per value plus one terminating bit per bucket
+            upper_bits: cardinality + zeros,
+            one_samples: num_samples(cardinality),
+            zero_samples: num_samples(zeros),
+        }
+    }
+
+    #[inline]
+    pub const fn lower_bytes(&self) -> usize {
+        self.lower_bits.div_ceil(8)
+    }
+
+    #[inline]
+    pub const fn upper_bytes(&self) -> usize {
+        self.upper_bits.div_ceil(8)
+    }
+
+    /// The number of bytes needed to store both bit arrays and the select index.
+    #[inline]
+    pub const fn bytes(&self) -> usize {
+        self.lower_bytes()
+            + self.upper_bytes()
+            + (self.one_samples + self.zero_samples) * size_of::<SelectSample>()
+    }
+}
+
+/// Returns the number of select index entries needed for `count` set (or
+/// unset) bits.
+#[inline]
+const fn num_samples(count: usize) -> usize {
+    count.saturating_sub(1) / SELECT_INTERVAL
+}
+
+/// Returns the positions of the set bits (or unset bits if `bit` is false) in
+/// `upper` which are stored in the select index.
+pub(crate) fn select_samples(
+    upper: &BitSlice<u8, Lsb0>,
+    bit: bool,
+) -> impl Iterator<Item = usize> + '_ {
+    let positions = if bit {
+        Either::Left(upper.iter_ones())
+    } else {
+        Either::Right(upper.iter_zeros())
+    };
+    positions.skip(SELECT_INTERVAL).step_by(SELECT_INTERVAL)
+}
+
+/// An `EliasFanoPartition` stores its values using Elias-Fano encoding, which
+/// needs roughly `2 + log2(MAX_LEN / cardinality)` bits per value. This is
+/// smaller than both Vec and Bitmap partitions for mid-density partitions.
+///
+/// The partition is stored in its encoded form and can't be modified in
+/// place. `Partition` converts it into a Vec partition before writing.
+#[derive(Clone, Eq)]
+pub struct EliasFanoPartition<L: Level> {
+    cardinality: usize,
+    /// the encoded lower and upper bit arrays and select index
+    data: Box<[u8]>,
+    _marker: PhantomData<L>,
+}
+
+impl<L: Level> EliasFanoPartition<L> {
+    #[inline]
+    pub const fn encoded_size(cardinality: usize) -> usize {
+        // bits + select index + length
+        EliasFanoLayout::new::<L>(cardinality).bytes() + size_of::<L::ValueUnaligned>()
+    }
+
+    /// Construct an `EliasFanoPartition` from a sorted iter of `cardinality`
+    /// unique values
+    /// SAFETY: undefined behavior if the iter is not sorted, contains
+    /// duplicates, or doesn't yield exactly `cardinality` values
+    pub fn from_sorted_unique_unchecked(
+        cardinality: usize,
+        values: impl Iterator<Item = L::Value>,
+    ) -> Self {
+        let layout = EliasFanoLayout::new::<L>(cardinality);
+        let upper_start = layout.lower_bytes() * 8;
+        let low_mask = (1 << layout.low_bits) - 1;
+
+        let mut bits = bitvec![u8, Lsb0; 0; (layout.lower_bytes() + layout.upper_bytes()) * 8];
+        for (idx, value) in values.enumerate() {
+            let value: usize = value.as_();
+            if layout.low_bits > 0 {
+                let lower = idx * layout.low_bits;
+                bits[lower..lower + layout.low_bits].store_le(value & low_mask);
+            }
+            bits.set(upper_start + (value >> layout.low_bits) + idx, true);
+        }
+
+        let upper = &bits[upper_start..upper_start + layout.upper_bits];
+        let samples = select_samples(upper, true)
+            .chain(select_samples(upper, false))
+            .map(|pos| SelectSample::new(pos as u32))
+            .collect_vec();
+        let mut data = bits.into_vec();
+        data.extend_from_slice(samples.as_bytes());
+        debug_assert_eq!(data.len(), layout.bytes());
+
+        Self {
+            cardinality,
+            data: data.into_boxed_slice(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns a view of the encoded bit arrays
+    #[inline]
+    pub fn as_encoded(&self) -> EliasFanoRef<'_, L> {
+        EliasFanoRef::from_parts(self.cardinality, &self.data)
+    }
+
+    #[inline]
+    pub fn count_runs(&self) -> usize {
+        count_runs_sorted(self.iter())
+    }
+
+    #[inline]
+    pub fn segments(&self) -> usize {
+        count_unique_sorted(self.iter().map(|v| v.segment()))
+    }
+}
+
+impl<L: Level> Debug for EliasFanoPartition<L> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "EliasFanoPartition<{}>({})",
+            L::DEBUG_NAME,
+            self.cardinality
+        )
+    }
+}
+
+impl<L: Level> Encodable for EliasFanoPartition<L> {
+    #[inline]
+    fn encoded_size(&self) -> usize {
+        Self::encoded_size(self.cardinality)
+    }
+
+    fn encode<B: BufMut>(&self, encoder: &mut Encoder<B>) {
+        encoder.put_elias_fano_partition::<L>(&self.data, self.cardinality);
+    }
+}
+
+impl<L: Level> PartitionRead<L> for EliasFanoPartition<L> {
+    #[inline]
+    fn cardinality(&self) -> usize {
+        self.cardinality
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.cardinality == 0
+    }
+
+    fn contains(&self, value: L::Value) -> bool {
+        self.as_encoded().contains(value)
+    }
+
+    fn position(&self, value: L::Value) -> Option<usize> {
+        self.as_encoded().position(value)
+    }
+
+    fn rank(&self, value: L::Value) -> usize {
+        self.as_encoded().rank(value)
+    }
+
+    fn select(&self, idx: usize) -> Option<L::Value> {
+        self.as_encoded().select(idx)
+    }
+
+    fn last(&self) -> Option<L::Value> {
+        self.as_encoded().last()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = L::Value> {
+        self.as_encoded().into_iter()
+    }
+
+    fn contains_all<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        self.as_encoded().contains_all(values)
+    }
+
+    fn contains_any<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        self.as_encoded().contains_any(values)
+    }
+}
+
+impl<L: Level> PartialEq for EliasFanoPartition<L> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        // the encoding is fully determined by the partition's values
+        self.cardinality == other.cardinality && self.data == other.data
+    }
+}
+
+impl<'a, L: Level> PartialEq<EliasFanoRef<'a, L>> for EliasFanoPartition<L> {
+    #[inline]
+    fn eq(&self, other: &EliasFanoRef<'a, L>) -> bool {
+        self.as_encoded() == *other
+    }
+}
+
+impl<'a, L: Level> From<&EliasFanoRef<'a, L>> for EliasFanoPartition<L> {
+    fn from(value: &EliasFanoRef<'a, L>) -> Self {
+        Self {
+            cardinality: value.cardinality(),
+            data: value.to_bytes(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use itertools::Itertools;
+    use proptest::proptest;
+
+    use crate::{
+        level::{Level, Low, Mid},
+        partition::elias_fano::{EliasFanoLayout, EliasFanoPartition},
+        testutil::{LevelSetGen, test_partition_read},
+        traits::{PartitionRead, TruncateFrom},
+    };
+
+    #[test]
+    fn test_elias_fano_layout() {
+        // 4096 values in 65536 slots use 4 low bits and 2 upper bits each
+        let layout = EliasFanoLayout::new::<Low>(4096);
+        assert_eq!(layout.low_bits, 4);
+        // along with a sample for every 256th set and unset bit but the first
+        assert_eq!((layout.one_samples, layout.zero_samples), (15, 15));
+        assert_eq!(layout.bytes(), 4096 * 6 / 8 + 30 * 4);
+
+        // a single value stores all of its bits in the lower bit array
+        let layout = EliasFanoLayout::new::<Low>(1);
+        assert_eq!(layout.low_bits, 16);
+        assert_eq!(layout.upper_bits, 2);
+
+        // more than half full partitions don't store any low bits
+        let layout = EliasFanoLayout::new::<Low>(Low::MAX_LEN);
+        assert_eq!(layout.low_bits, 0);
+        assert_eq!(layout.upper_bits, 2 * Low::MAX_LEN);
+    }
+
+    #[test]
+    fn test_elias_fano_read() {
+        let mut setgen = LevelSetGen::<Mid>::new(0xDEADBEEF);
+        let sets = &[
+            vec![0],
+            vec![Mid::MAX_LEN - 1],
+            (0..4096).collect_vec(),
+            (0..Mid::MAX_LEN).step_by(4099).collect_vec(),
+        ];
+        let sets = sets
+            .iter()
+            .map(|set| {
+                set.iter()
+                    .map(|&v| <Mid as Level>::Value::truncate_from(v))
+                    .collect_vec()
+            })
+            .chain([
+                setgen.random(16),
+                setgen.random(4096),
+                setgen.runs(4096, 0.5),
+                // large enough for lookups to rely on the select index
+                setgen.random(1 << 18),
+                setgen.runs(1 << 18, 0.01),
+            ]);
+
+        for set in sets {
+            let partition = EliasFanoPartition::<Mid>::from_sorted_unique_unchecked(
+                set.len(),
+                set.iter().copied(),
+            );
+            test_partition_read(&partition, &set);
+            for (idx, &value) in set.iter().enumerate() {
+                assert_eq!(partition.select(idx), Some(value));
+                assert_eq!(partition.rank(value), idx + 1);
+            }
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_elias_fano_read_proptest(set: HashSet<u16>) {
+            let expected = set.iter().copied().sorted().collect_vec();
+            let partition = EliasFanoPartition::<Low>::from_sorted_unique_unchecked(
+                expected.len(),
+                expected.iter().copied(),
+            );
+            test_partition_read(&partition, &expected);
+        }
+    }
+}
@@ -0,0 +1,303 @@
+use std::ops::RangeBounds;
+
+use num::traits::AsPrimitive;
+use range_set_blaze::SortedDisjoint;
+
+use crate::{
+    codec::{
+        Encodable, encoder::Encoder, features::Features, partition_ref::NonRecursivePartitionRef,
+    },
+    level::Level,
+    partition::{
+        Partition,
+        run::{MergeRuns, RunPartition},
+    },
+    partition_kind::PartitionKind,
+    traits::{Complement, PartitionRead, PartitionWrite, TruncateFrom},
+    util::{IteratorExt, RangeExt, RangeIter},
+};
+
+/// An `InvertedPartition` stores the complement of its values.
+///
+/// Partitions which contain more than half of their possible values are
+/// stored inverted, such that they only grow until they reach half of their
+/// max storage and then start shrinking again. The complement is always stored
+/// as either a Vec or a Run partition.
+#[derive(Debug, Clone, PartialEq, Eq)]
+pub struct InvertedPartition<P> {
+    complement: P,
+}
+
+impl<P> InvertedPartition<P> {
+    #[inline]
+    pub fn new(complement: P) -> Self {
+        Self { complement }
+    }
+
+    /// Returns the partition storing every value *not* in this partition.
+    #[inline]
+    pub fn inner(&self) -> &P {
+        &self.complement
+    }
+
+    #[inline]
+    pub fn inner_mut(&mut self) -> &mut P {
+        &mut self.complement
+    }
+
+    #[inline]
+    pub fn into_inner(self) -> P {
+        self.complement
+    }
+}
+
+impl<L: Level> InvertedPartition<Partition<L>> {
+    pub fn kind(&self) -> PartitionKind {
+        self.complement.kind().invert()
+    }
+
+    /// Materializes this partition as a `RunPartition`, which costs
+    /// O(complement) rather than O(cardinality).
+    pub fn to_run(&self) -> RunPartition<L> {
+        let mut runs = match &self.complement {
+            Partition::Run(runs) => runs.clone(),
+            other => RunPartition::from_sorted_unique_unchecked(other.iter()),
+        };
+        runs.complement();
+        runs
+    }
+
+    #[inline]
+    pub fn count_runs(&self) -> usize {
+        self.complement.count_complement_runs()
+    }
+}
+
+impl<'a, L: Level> InvertedPartition<NonRecursivePartitionRef<'a, L>> {
+    /// Materializes this partition as a `RunPartition`, which costs
+    /// O(complement) rather than O(cardinality).
+    pub fn to_run(&self) -> RunPartition<L> {
+        let mut runs = match &self.complement {
+            NonRecursivePartitionRef::Run { runs } => RunPartition::from(runs),
+            other => RunPartition::from_sorted_unique_unchecked(other.iter()),
+        };
+        runs.complement();
+        runs
+    }
+}
+
+impl<L: Level> Encodable for InvertedPartition<Partition<L>> {
+    fn encoded_size(&self) -> usize {
+        match &self.complement {
+            // an inverted partition without a complement is full
+            p if p.is_empty() => 0,
+            Partition::Vec(p) => p.encoded_size(),
+            Partition::Run(p) => p.encoded_size(),
+            p => unreachable!("BUG: invalid inverted partition kind {:?}", p.kind()),
+        }
+    }
+
+    fn encode<B: bytes::BufMut>(&self, encoder: &mut Encoder<B>) {
+        match &self.complement {
+            p if p.is_empty() => (),
+            Partition::Vec(p) => p.encode(encoder),
+            Partition::Run(p) => p.encode(encoder),
+            p => unreachable!("BUG: invalid inverted partition kind {:?}", p.kind()),
+        }
+    }
+
+    fn encoded_features(&self) -> Features {
+        if self.complement.is_empty() {
+            Features::EMPTY
+        } else {
+            Features::INVERTED_PARTITIONS
+        }
+    }
+}
+
+impl<L: Level, P: PartitionRead<L>> PartitionRead<L> for InvertedPartition<P> {
+    fn cardinality(&self) -> usize {
+        L::MAX_LEN - self.complement.cardinality()
+    }
+
+    fn is_empty(&self) -> bool {
+        self.complement.cardinality() == L::MAX_LEN
+    }
+
+    fn contains(&self, value: L::Value) -> bool {
+        !self.complement.contains(value)
+    }
+
+    fn position(&self, value: L::Value) -> Option<usize> {
+        self.contains(value)
+            .then(|| value.as_() - self.complement.rank(value))
+    }
+
+    fn rank(&self, value: L::Value) -> usize {
+        value.as_() + 1 - self.complement.rank(value)
+    }
+
+    fn select(&self, idx: usize) -> Option<L::Value> {
+        if idx >= self.cardinality() {
+            return None;
+        }
+
+        // binary search for the first value with rank > idx, which must be
+        // contained by this partition
+        let (mut lo, mut hi) = (idx, L::MAX_LEN - 1);
+        while lo < hi {
+            let mid = lo + (hi - lo) / 2;
+            if self.rank(L::Value::truncate_from(mid)) > idx {
+                hi = mid;
+            } else {
+                lo = mid + 1;
+            }
+        }
+        Some(L::Value::truncate_from(lo))
+    }
+
+    fn last(&self) -> Option<L::Value> {
+        self.cardinality()
+            .checked_sub(1)
+            .and_then(|idx| self.select(idx))
+    }
+
+    fn iter(&self) -> impl Iterator<Item = L::Value> {
+        MergeRuns::new(self.complement.iter())
+            .complement()
+            .flat_map(RangeIter::new)
+            .with_size_hint(self.cardinality())
+    }
+
+    fn contains_all<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        // empty ranges are trivially contained
+        RangeExt::is_empty(&values) || !self.complement.contains_any(values)
+    }
+
+    fn contains_any<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        // empty ranges have no intersection
+        !RangeExt::is_empty(&values) && !self.complement.contains_all(values)
+    }
+}
+
+impl<L: Level> PartitionWrite<L> for InvertedPartition<Partition<L>> {
+    #[inline]
+    fn insert(&mut self, value: L::Value) -> bool {
+        self.complement.raw_remove(value)
+    }
+
+    #[inline]
+    fn remove(&mut self, value: L::Value) -> bool {
+        self.complement.raw_insert(value)
+    }
+
+    fn remove_range<R: RangeBounds<L::Value>>(&mut self, values: R) {
+        if let Some(range) = values.try_into_inclusive() {
+            // the complement must be stored as runs to absorb ranges cheaply
+            self.complement.switch_kind(PartitionKind::Run);
+            if let Partition::Run(runs) = &mut self.complement {
+                *runs |= &RunPartition::from(range);
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use std::collections::{BTreeSet, HashSet};
+
+    use itertools::Itertools;
+    use proptest::proptest;
+
+    use crate::{
+        codec::partition_ref::PartitionRef,
+        level::{Block, Low},
+        testutil::{mkpartition, test_partition_read},
+        traits::Optimizable,
+    };
+
+    use super::*;
+
+    fn complement_of<L: Level>(missing: &[L::Value]) -> Vec<L::Value> {
+        let missing = missing.iter().copied().collect::<BTreeSet<_>>();
+        (0..L::MAX_LEN)
+            .map(L::Value::truncate_from)
+            .filter(|v| !missing.contains(v))
+            .collect_vec()
+    }
+
+    #[test]
+    fn test_inverted_optimize() {
+        let set = complement_of::<Block>(&[3, 100, 101, 255]);
+        let mut partition = Partition::<Block>::from_iter(set.iter().copied());
+        partition.optimize_with(Features::SUPPORTED);
+        assert_eq!(partition.kind(), PartitionKind::InvertedVec);
+        // 4 values + length + kind
+        assert_eq!(partition.encoded_size_with(Features::SUPPORTED), 6);
+        test_partition_read(&partition, &set);
+
+        let set = complement_of::<Low>(&(1000..2000).collect_vec());
+        let mut partition = Partition::<Low>::from_iter(set.iter().copied());
+        partition.optimize_with(Features::SUPPORTED);
+        assert_eq!(partition.kind(), PartitionKind::InvertedRun);
+        test_partition_read(&partition, &set);
+    }
+
+    #[test]
+    fn test_inverted_bitops_match_model() {
+        let lhs_values = complement_of::<Low>(&[1, 2, 3, 255, 256, 1024]);
+        let rhs_values = [2u16, 4, 255, 300, 1024, 4096];
+
+        let lhs_model = lhs_values.iter().copied().collect::<BTreeSet<_>>();
+        let rhs_model = rhs_values.into_iter().collect::<BTreeSet<_>>();
+
+        let lhs = mkpartition::<Low>(PartitionKind::InvertedVec, &lhs_values);
+        let rhs = mkpartition::<Low>(PartitionKind::Vec, &rhs_values);
+
+        for (a, b, a_model, b_model) in [
+            (&lhs, &rhs, &lhs_model, &rhs_model),
+            (&rhs, &lhs, &rhs_model, &lhs_model),
+        ] {
+            let mut union = a.clone();
+            union |= b;
+            itertools::assert_equal(union.iter(), a_model.union(b_model).copied());
+
+            let mut intersection = a.clone();
+            intersection &= b;
+            itertools::assert_equal(intersection.iter(), a_model.intersection(b_model).copied());
+
+            let mut xor = a.clone();
+            xor ^= b;
+            itertools::assert_equal(xor.iter(), a_model.symmetric_difference(b_model).copied());
+
+            let mut difference = a.clone();
+            difference -= b;
+            itertools::assert_equal(difference.iter(), a_model.difference(b_model).copied());
+        }
+
+        let mut complement = lhs.clone();
+        complement.complement();
+        itertools::assert_equal(complement.iter(), [1, 2, 3, 255, 256, 1024]);
+    }
+
+    proptest! {
+        #[test]
+        fn test_inverted_encode_decode_proptest(missing: HashSet<u8>) {
+            let missing = missing.into_iter().sorted().collect_vec();
+            let set = complement_of::<Block>(&missing);
+
+            for kind in [PartitionKind::InvertedVec, PartitionKind::InvertedRun] {
+                let partition = mkpartition::<Block>(kind, &set);
+                assert_eq!(partition.cardinality(), set.len());
+
+                let buf = partition.encode_to_bytes();
+                assert_eq!(partition.encoded_size(), buf.len());
+
+                let partition_ref = PartitionRef::<Block>::from_suffix(&buf).unwrap();
+                partition_ref.validate().unwrap();
+                assert_eq!(partition, partition_ref);
+                itertools::assert_equal(partition_ref.into_iter(), set.iter().copied());
+            }
+        }
+    }
+}
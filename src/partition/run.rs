@@ -7,11 +7,7 @@ use std::{
 
 use bytes::BufMut;
 use itertools::{FoldWhile, Itertools};
-use num::{
-    PrimInt,
-    cast::AsPrimitive,
-    traits::{ConstOne, ConstZero},
-};
+use num::{cast::AsPrimitive, traits::ConstZero};
 use range_set_blaze::{Integer, RangeSetBlaze, SortedDisjoint, SortedStarts};
 
 use crate::{
@@ -21,7 +17,7 @@ use crate::{
     level::Level,
     partition::Partition,
     segment::{Segment, SplitSegment},
-    traits::{Complement, Cut, PartitionRead, TruncateFrom},
+    traits::{Complement, Cut, Int, PartitionRead, TruncateFrom},
     util::{IteratorExt, RangeExt},
 };
 
@@ -39,7 +35,7 @@ pub(crate) trait Run<T> {
 
 impl<T> Run<T> for RangeInclusive<T>
 where
-    T: PrimInt + AsPrimitive<usize> + TruncateFrom<usize> + ConstOne,
+    T: Int + AsPrimitive<usize> + TruncateFrom<usize>,
 {
     #[inline]
     fn len(&self) -> usize {
@@ -98,7 +94,7 @@ where
 
 pub(crate) fn run_position<T, I>(iter: I, value: T) -> Option<usize>
 where
-    T: PrimInt + AsPrimitive<usize> + TruncateFrom<usize> + ConstOne,
+    T: Int + AsPrimitive<usize> + TruncateFrom<usize>,
     I: IntoIterator<Item = RangeInclusive<T>>,
 {
     let mut found = false;
@@ -122,7 +118,7 @@ where
 
 pub(crate) fn run_rank<T, I>(iter: I, value: T) -> usize
 where
-    T: PrimInt + AsPrimitive<usize> + TruncateFrom<usize> + ConstOne,
+    T: Int + AsPrimitive<usize> + TruncateFrom<usize>,
     I: IntoIterator<Item = RangeInclusive<T>>,
 {
     iter.into_iter()
@@ -140,7 +136,7 @@ where
 
 pub(crate) fn run_select<T, I>(iter: I, mut n: usize) -> Option<T>
 where
-    T: PrimInt + AsPrimitive<usize> + TruncateFrom<usize> + ConstOne,
+    T: Int + AsPrimitive<usize> + TruncateFrom<usize>,
     I: IntoIterator<Item = RangeInclusive<T>>,
 {
     for run in iter.into_iter() {
@@ -456,7 +452,7 @@ pub(crate) struct MergeRuns<I, T> {
 
 impl<I, T> MergeRuns<I, T>
 where
-    T: PrimInt + ConstOne,
+    T: Int,
     I: Iterator<Item = T>,
 {
     pub(crate) fn new(mut inner: I) -> Self {
@@ -467,14 +463,14 @@ where
 
 impl<I, T> FusedIterator for MergeRuns<I, T>
 where
-    T: PrimInt + ConstOne,
+    T: Int,
     I: Iterator<Item = T>,
 {
 }
 
 impl<I, T> Iterator for MergeRuns<I, T>
 where
-    T: PrimInt + ConstOne,
+    T: Int,
     I: Iterator<Item = T>,
 {
     type Item = RangeInclusive<T>;
@@ -497,14 +493,14 @@ where
 
 impl<I, T> SortedStarts<T> for MergeRuns<I, T>
 where
-    T: PrimInt + ConstOne + range_set_blaze::Integer,
+    T: Int + range_set_blaze::Integer,
     I: Iterator<Item = T>,
 {
 }
 
 impl<I, T> SortedDisjoint<T> for MergeRuns<I, T>
 where
-    T: PrimInt + ConstOne + range_set_blaze::Integer,
+    T: Int + range_set_blaze::Integer,
     I: Iterator<Item = T>,
 {
 }
@@ -0,0 +1,306 @@
+use std::{fmt::Debug, iter, marker::PhantomData, mem::size_of, ops::RangeBounds};
+
+use bitvec::{bitbox, order::Lsb0};
+use bytes::BufMut;
+use num::traits::AsPrimitive;
+
+use crate::{
+    codec::{Encodable, encoder::Encoder, sparse_bitmap_ref::SparseBitmapRef},
+    count::{count_runs_sorted, count_unique_sorted},
+    level::Level,
+    partition::bitmap::BitmapPartition,
+    segment::SplitSegment,
+    traits::PartitionRead,
+};
+
+/// The number of bits in each word of a sparse bitmap partition.
+pub(crate) const WORD_BITS: usize = u64::BITS as usize;
+
+/// Returns the values stored in a sequence of `(word index, word)` pairs.
+pub(crate) fn word_values(
+    words: impl Iterator<Item = (usize, u64)>,
+) -> impl Iterator<Item = usize> {
+    words.flat_map(|(word, mut bits)| {
+        iter::from_fn(move || {
+            (bits != 0).then(|| {
+                let bit = bits.trailing_zeros() as usize;
+                bits &= bits - 1;
+                word * WORD_BITS + bit
+            })
+        })
+    })
+}
+
+/// A `SparseBitmapPartition` stores a bitmap without its zero words. A mask
+/// with one bit per word records which words are stored, so a value is found
+/// by counting the set bits in the mask before its word. This is much smaller
+/// than a Bitmap partition when most words are zero, and smaller than a Vec
+/// partition when the values are clustered within their words.
+///
+/// Encoding order: `[words][mask]`, where every stored word is a nonzero
+/// little-endian u64, and the mask stores bit `i` in bit `i % 8` of byte
+/// `i / 8` like a Bitmap partition.
+///
+/// The partition is stored in its encoded form and can't be modified in
+/// place. `Partition` converts it into a Bitmap partition before writing.
+#[derive(Clone, Eq)]
+pub struct SparseBitmapPartition<L: Level> {
+    cardinality: usize,
+    /// the encoded words and mask
+    data: Box<[u8]>,
+    _marker: PhantomData<L>,
+}
+
+impl<L: Level> SparseBitmapPartition<L> {
+    /// The number of words in the bitmap, including zero words
+    pub const WORDS: usize = L::MAX_LEN / WORD_BITS;
+
+    /// The encoded size of the word mask
+    pub const MASK_SIZE: usize = Self::WORDS.div_ceil(8);
+
+    #[inline]
+    pub const fn encoded_size(words: usize) -> usize {
+        // words + mask
+        words * size_of::<u64>() + Self::MASK_SIZE
+    }
+
+    /// Construct a `SparseBitmapPartition` from a sorted iter of unique values
+    /// SAFETY: undefined behavior if the iter is not sorted or contains duplicates
+    pub fn from_sorted_unique_unchecked(values: impl Iterator<Item = L::Value>) -> Self {
+        let mut words: Vec<(usize, u64)> = Vec::new();
+        for value in values {
+            let value: usize = value.as_();
+            let (word, bit) = (value / WORD_BITS, value % WORD_BITS);
+            match words.last_mut() {
+                Some((last, bits)) if *last == word => *bits |= 1 << bit,
+                _ => words.push((word, 1 << bit)),
+            }
+        }
+        Self::from_words(words.into_iter())
+    }
+
+    /// Construct a `SparseBitmapPartition` from the words of a Bitmap
+    /// partition.
+    pub fn from_bitmap(bitmap: &BitmapPartition<L>) -> Self {
+        let words = bitmap.as_bitbox().as_raw_slice().iter().copied();
+        Self::from_words(words.enumerate().filter(|&(_, bits)| bits != 0))
+    }
+
+    /// Construct a `SparseBitmapPartition` from a sorted iter of nonzero
+    /// words and their indexes.
+    fn from_words(words: impl Iterator<Item = (usize, u64)>) -> Self {
+        let mut data = Vec::new();
+        let mut mask = vec![0u8; Self::MASK_SIZE];
+        let mut cardinality = 0;
+        for (word, bits) in words {
+            debug_assert_ne!(bits, 0, "BUG: sparse bitmap words must be nonzero");
+            data.extend_from_slice(&bits.to_le_bytes());
+            mask[word / 8] |= 1 << (word % 8);
+            cardinality += bits.count_ones() as usize;
+        }
+        data.extend_from_slice(&mask);
+
+        Self {
+            cardinality,
+            data: data.into_boxed_slice(),
+            _marker: PhantomData,
+        }
+    }
+
+    /// Returns a view of the encoded words and mask
+    #[inline]
+    pub fn as_encoded(&self) -> SparseBitmapRef<'_, L> {
+        SparseBitmapRef::from_parts(&self.data)
+    }
+
+    /// Expands this partition into a Bitmap partition
+    pub fn to_bitmap(&self) -> BitmapPartition<L> {
+        let mut bitmap = bitbox![u64, Lsb0; 0; L::MAX_LEN];
+        let raw = bitmap.as_raw_mut_slice();
+        for (word, bits) in self.as_encoded().words() {
+            raw[word] = bits;
+        }
+        BitmapPartition::from(bitmap.as_bitslice())
+    }
+
+    /// The number of nonzero words stored by this partition
+    #[inline]
+    pub fn count_words(&self) -> usize {
+        self.as_encoded().count_words()
+    }
+
+    #[inline]
+    pub fn count_runs(&self) -> usize {
+        count_runs_sorted(self.iter())
+    }
+
+    #[inline]
+    pub fn segments(&self) -> usize {
+        count_unique_sorted(self.iter().map(|v| v.segment()))
+    }
+}
+
+impl<L: Level> Debug for SparseBitmapPartition<L> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        write!(
+            f,
+            "SparseBitmapPartition<{}>({})",
+            L::DEBUG_NAME,
+            self.cardinality
+        )
+    }
+}
+
+impl<L: Level> Encodable for SparseBitmapPartition<L> {
+    #[inline]
+    fn encoded_size(&self) -> usize {
+        self.data.len()
+    }
+
+    fn encode<B: BufMut>(&self, encoder: &mut Encoder<B>) {
+        encoder.put_sparse_bitmap_partition(&self.data);
+    }
+}
+
+impl<L: Level> PartitionRead<L> for SparseBitmapPartition<L> {
+    #[inline]
+    fn cardinality(&self) -> usize {
+        self.cardinality
+    }
+
+    #[inline]
+    fn is_empty(&self) -> bool {
+        self.cardinality == 0
+    }
+
+    fn contains(&self, value: L::Value) -> bool {
+        self.as_encoded().contains(value)
+    }
+
+    fn position(&self, value: L::Value) -> Option<usize> {
+        self.as_encoded().position(value)
+    }
+
+    fn rank(&self, value: L::Value) -> usize {
+        self.as_encoded().rank(value)
+    }
+
+    fn select(&self, idx: usize) -> Option<L::Value> {
+        self.as_encoded().select(idx)
+    }
+
+    fn last(&self) -> Option<L::Value> {
+        self.as_encoded().last()
+    }
+
+    fn iter(&self) -> impl Iterator<Item = L::Value> {
+        self.as_encoded().into_iter()
+    }
+
+    fn contains_all<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        self.as_encoded().contains_all(values)
+    }
+
+    fn contains_any<R: RangeBounds<L::Value>>(&self, values: R) -> bool {
+        self.as_encoded().contains_any(values)
+    }
+}
+
+impl<L: Level> PartialEq for SparseBitmapPartition<L> {
+    #[inline]
+    fn eq(&self, other: &Self) -> bool {
+        // the encoding is fully determined by the partition's values
+        self.data == other.data
+    }
+}
+
+impl<'a, L: Level> PartialEq<SparseBitmapRef<'a, L>> for SparseBitmapPartition<L> {
+    #[inline]
+    fn eq(&self, other: &SparseBitmapRef<'a, L>) -> bool {
+        self.as_encoded() == *other
+    }
+}
+
+impl<'a, L: Level> From<&SparseBitmapRef<'a, L>> for SparseBitmapPartition<L> {
+    fn from(value: &SparseBitmapRef<'a, L>) -> Self {
+        Self {
+            cardinality: value.cardinality(),
+            data: value.to_bytes(),
+            _marker: PhantomData,
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use std::collections::HashSet;
+
+    use itertools::Itertools;
+    use proptest::proptest;
+
+    use crate::{
+        Encodable,
+        level::Low,
+        partition::{bitmap::BitmapPartition, sparse_bitmap::SparseBitmapPartition},
+        testutil::{LevelSetGen, test_partition_read},
+        traits::PartitionRead,
+    };
+
+    #[test]
+    fn test_sparse_bitmap_size() {
+        // values in the same word share a single stored word
+        let partition = SparseBitmapPartition::<Low>::from_sorted_unique_unchecked(
+            (0..64).chain(1000..1030).chain([65535]),
+        );
+        assert_eq!(partition.count_words(), 4);
+        assert_eq!(partition.cardinality(), 95);
+        assert_eq!(
+            partition.encoded_size(),
+            SparseBitmapPartition::<Low>::encoded_size(4)
+        );
+        assert_eq!(SparseBitmapPartition::<Low>::encoded_size(0), 128);
+    }
+
+    #[test]
+    fn test_sparse_bitmap_read() {
+        let mut setgen = LevelSetGen::<Low>::new(0xDEADBEEF);
+        let sets = [
+            vec![0],
+            vec![63],
+            vec![64],
+            vec![u16::MAX],
+            vec![0, u16::MAX],
+            (0..=u16::MAX).collect_vec(),
+            (0..=u16::MAX).step_by(63).collect_vec(),
+            setgen.random(64),
+            setgen.random(4096),
+            setgen.runs(4096, 0.01),
+            setgen.runs(4096, 0.5),
+        ];
+
+        for set in sets {
+            let partition =
+                SparseBitmapPartition::<Low>::from_sorted_unique_unchecked(set.iter().copied());
+            test_partition_read(&partition, &set);
+
+            // converting to and from bitmaps preserves the encoding
+            let bitmap = partition.to_bitmap();
+            assert_eq!(
+                bitmap,
+                BitmapPartition::<Low>::from_iter(set.iter().copied())
+            );
+            assert_eq!(SparseBitmapPartition::from_bitmap(&bitmap), partition);
+        }
+    }
+
+    proptest! {
+        #[test]
+        fn test_sparse_bitmap_read_proptest(set: HashSet<u16>) {
+            let expected = set.iter().copied().sorted().collect_vec();
+            let partition = SparseBitmapPartition::<Low>::from_sorted_unique_unchecked(
+                expected.iter().copied(),
+            );
+            test_partition_read(&partition, &expected);
+        }
+    }
+}
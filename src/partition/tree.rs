@@ -13,6 +13,8 @@ use crate::{
     codec::{
         Encodable,
         encoder::Encoder,
+        features::Features,
+        prefix_ref::PrefixRef,
         tree_ref::{TreeIndexBuilder, TreeRef},
     },
     count::count_runs_sorted,
@@ -20,6 +22,7 @@ use crate::{
     partition::{
         Partition,
         bitmap::BitmapPartition,
+        elias_fano::EliasFanoPartition,
         run::{Run, RunPartition},
         vec::VecPartition,
     },
@@ -40,44 +43,130 @@ impl<L: Level> TreePartition<L> {
         self.children.len()
     }
 
+    /// Iterate over the children of this partition, in segment order
+    pub fn iter_children(&self) -> impl Iterator<Item = (Segment, &L::Down)> {
+        self.children
+            .iter()
+            .map(|(&segment, child)| (segment, child))
+    }
+
+    /// Returns the segment and child of a tree with exactly one child, which
+    /// can be encoded as a prefix partition.
+    pub fn only_child(&self) -> Option<(Segment, &L::Down)> {
+        if self.children.len() == 1 {
+            self.iter_children().next()
+        } else {
+            None
+        }
+    }
+
+    /// The encoded size of this tree as a prefix partition if it has a single
+    /// child, or as a regular tree otherwise, when encoded with the provided
+    /// optional features.
+    pub fn prefix_encoded_size_with(&self, features: Features) -> usize {
+        match self.only_child() {
+            // child + segment
+            Some((_, child)) => child.encoded_size_with(features) + 1,
+            None => self.encoded_size_with(features),
+        }
+    }
+
+    /// Encodes this tree as a prefix partition: the only child followed by
+    /// its segment. The caller is responsible for writing the kind.
+    pub fn encode_prefix<B: BufMut>(&self, encoder: &mut Encoder<B>) {
+        let (segment, child) = self
+            .only_child()
+            .expect("BUG: prefix partitions must have exactly one child");
+        child.encode(encoder);
+        encoder.put_slice(&[segment]);
+    }
+
     #[inline]
     pub fn count_runs(&self) -> usize {
         count_runs_sorted(self.iter())
     }
 
-    pub fn optimize_children(&mut self) {
+    pub fn optimize_children(&mut self, features: Features) {
+        for child in self.children.values_mut() {
+            child.optimize_with(features);
+        }
+    }
+
+    pub fn canonicalize_children(&mut self, features: Features) {
         for child in self.children.values_mut() {
-            child.optimize();
+            child.canonicalize_with(features);
         }
     }
 
+    /// Modifies the children of this partition in place, removing any which
+    /// were emptied by `f`.
+    pub(crate) fn update_children(&mut self, f: impl FnOnce(&mut BTreeMap<Segment, L::Down>)) {
+        f(&mut self.children);
+        self.children.retain(|_, child| !child.is_empty());
+        self.refresh_cardinality();
+    }
+
     fn refresh_cardinality(&mut self) {
         self.cardinality = self.children.values().map(|c| c.cardinality()).sum();
     }
 
     /// estimate the encoded size of a `TreePartition` based on the number of
-    /// segments
-    pub fn estimate_encoded_size(segments: usize, cardinality: usize) -> usize {
+    /// segments, when its children may use the provided optional features
+    pub fn estimate_encoded_size(segments: usize, cardinality: usize, features: Features) -> usize {
         let index_size = TreeIndexBuilder::<L>::encoded_size(segments);
 
-        let avg_cardinality_per_segment = cardinality as f64 / segments as f64;
-        let per_segment_est =
-            VecPartition::<L::LevelDown>::encoded_size(avg_cardinality_per_segment as usize)
+        let avg_cardinality_per_segment = (cardinality as f64 / segments as f64) as usize;
+        let mut per_segment_est =
+            VecPartition::<L::LevelDown>::encoded_size(avg_cardinality_per_segment)
                 .min(BitmapPartition::<L::LevelDown>::ENCODED_SIZE);
+        if L::LevelDown::ALLOW_ELIAS_FANO && features.contains(Features::ELIAS_FANO_PARTITIONS) {
+            per_segment_est = per_segment_est.min(
+                EliasFanoPartition::<L::LevelDown>::encoded_size(avg_cardinality_per_segment),
+            );
+        }
 
         // we add segments to account for the extra PartitionKind byte for each child
         index_size + segments + (per_segment_est * segments)
     }
+
+    /// estimate the encoded size of a `TreePartition` with a single segment
+    /// when encoded as a prefix partition
+    pub fn estimate_prefix_encoded_size(cardinality: usize, features: Features) -> usize {
+        // the tree index is replaced by the child's segment
+        Self::estimate_encoded_size(1, cardinality, features)
+            - TreeIndexBuilder::<L>::encoded_size(1)
+            + 1
+    }
 }
 
 impl<L: Level> Encodable for TreePartition<L> {
     fn encoded_size(&self) -> usize {
+        self.encoded_size_with(Features::EMPTY)
+    }
+
+    fn encoded_size_with(&self, features: Features) -> usize {
         let index_size = TreeIndexBuilder::<L>::encoded_size(self.children.len());
-        let values: usize = self.children.values().map(|c| c.encoded_size()).sum();
+        let values: usize = self
+            .children
+            .values()
+            .map(|c| c.encoded_size_with(features))
+            .sum();
         // values + index
         values + index_size
     }
 
+    fn max_encoded_size(&self) -> usize {
+        let index_size = TreeIndexBuilder::<L>::encoded_size(self.children.len());
+        let values: usize = if <L::LevelDown as Level>::ALLOW_TREE {
+            self.children.values().map(|c| c.max_encoded_size()).sum()
+        } else {
+            // the bottom level makes up most of the partitions, so rather
+            // than visiting every child assume each one is as large as possible
+            self.children.len() * Partition::<L::LevelDown>::max_leaf_encoded_size()
+        };
+        values + index_size
+    }
+
     fn encode<B: BufMut>(&self, encoder: &mut Encoder<B>) {
         let mut index = TreeIndexBuilder::<L>::new(self.children.len());
         for (&segment, child) in self.children.iter() {
@@ -86,6 +175,16 @@ impl<L: Level> Encodable for TreePartition<L> {
         }
         encoder.put_tree_index(index);
     }
+
+    fn encoded_features(&self) -> Features {
+        self.encoded_features_with(Features::SUPPORTED)
+    }
+
+    fn encoded_features_with(&self, features: Features) -> Features {
+        self.children.values().fold(Features::EMPTY, |used, child| {
+            used | child.encoded_features_with(features)
+        })
+    }
 }
 
 impl<L: Level> Debug for TreePartition<L> {
@@ -622,6 +721,13 @@ impl<L: Level> From<&TreeRef<'_, L>> for TreePartition<L> {
     }
 }
 
+impl<L: Level> From<&PrefixRef<'_, L>> for TreePartition<L> {
+    fn from(value: &PrefixRef<'_, L>) -> Self {
+        let child = L::Down::from(value.child());
+        Self::from(BTreeMap::from([(value.segment(), child)]))
+    }
+}
+
 #[cfg(test)]
 mod test {
     use std::collections::HashSet;
@@ -6,6 +6,7 @@ use std::{
 
 use bytes::BufMut;
 use itertools::{EitherOrBoth, Itertools};
+use num::traits::AsPrimitive;
 use range_set_blaze::SortedDisjoint;
 
 use crate::{
@@ -15,7 +16,7 @@ use crate::{
     partition::{Partition, run::MergeRuns},
     segment::SplitSegment,
     traits::{Complement, Cut, PartitionRead, PartitionWrite},
-    util::{RangeExt, RangeIter, find_next_sorted},
+    util::{RangeExt, find_next_sorted},
 };
 
 #[derive(Clone, Eq)]
@@ -127,11 +128,11 @@ impl<L: Level> PartitionRead<L> for VecPartition<L> {
                 Err(_) => return false, // range.start() not in partition
             };
 
-            // Check if all values in the range are present by comparing iterators
-            let range_end = *range.end();
-            let expected = RangeIter::new(range);
-            let actual = self.values[start_idx..].iter().copied();
-            itertools::equal(expected, actual.take_while(|&v| v <= range_end))
+            // values are sorted and unique, so the range is fully present iff
+            // `end` sits exactly `end - start` slots later
+            let (start, end) = (*range.start(), *range.end());
+            let offset: usize = (end - start).as_();
+            self.values.get(start_idx + offset) == Some(&end)
         } else {
             // empty range is trivially contained
             true
@@ -1,13 +1,18 @@
 use zerocopy::{KnownLayout, TryFromBytes};
 
-use crate::partition::Partition;
+use crate::{codec::features::Features, partition::Partition};
 
 use crate::level::Level;
 
-/// `PartitionKind` is a one byte bitfield which currently only uses the first
-/// three bits (LE). The remaining bits are reserved for future expansion.
+/// `PartitionKind` is a one byte bitfield. The first three bits (LE) store the
+/// partition storage kind, while the fourth bit marks partitions which store
+/// the complement of their values. The fifth bit marks compressed variants of
+/// trees and bitmaps: path-compressed trees store a single child, while sparse
+/// bitmaps only store their nonzero words. The remaining bits are reserved for
+/// future expansion.
 #[derive(Debug, Clone, Copy, PartialEq, Eq, Default, TryFromBytes, KnownLayout)]
 #[repr(u8)]
+#[non_exhaustive]
 pub enum PartitionKind {
     #[default]
     Empty = 0b000,
@@ -16,10 +21,52 @@ pub enum PartitionKind {
     Vec = 0b011,
     Run = 0b100,
     Tree = 0b101,
+    EliasFano = 0b110,
+    DeltaVec = 0b111,
+
+    // inverted kinds set the fourth bit
+    InvertedVec = 0b1011,
+    InvertedRun = 0b1100,
+
+    // compressed kinds set the fifth bit
+    SparseBitmap = 0b1_0010,
+    Prefix = 0b1_0101,
 }
 
 impl PartitionKind {
-    pub fn build<L: Level>(self) -> Partition<L> {
+    const INVERTED: u8 = 0b1000;
+
+    #[inline]
+    pub const fn is_inverted(self) -> bool {
+        self as u8 & Self::INVERTED != 0
+    }
+
+    /// Returns the optional format features needed to decode this kind.
+    #[inline]
+    pub(crate) const fn features(self) -> Features {
+        match self {
+            PartitionKind::EliasFano => Features::ELIAS_FANO_PARTITIONS,
+            PartitionKind::DeltaVec => Features::DELTA_VEC_PARTITIONS,
+            PartitionKind::Prefix => Features::PREFIX_PARTITIONS,
+            PartitionKind::SparseBitmap => Features::SPARSE_BITMAP_PARTITIONS,
+            kind if kind.is_inverted() => Features::INVERTED_PARTITIONS,
+            _ => Features::EMPTY,
+        }
+    }
+
+    /// Toggles the inverted bit of this kind. Only Vec and Run partitions
+    /// support inversion.
+    pub(crate) fn invert(self) -> Self {
+        match self {
+            PartitionKind::Vec => PartitionKind::InvertedVec,
+            PartitionKind::Run => PartitionKind::InvertedRun,
+            PartitionKind::InvertedVec => PartitionKind::Vec,
+            PartitionKind::InvertedRun => PartitionKind::Run,
+            kind => unreachable!("BUG: {kind:?} partitions can't be inverted"),
+        }
+    }
+
+    pub(crate) fn build<L: Level>(self) -> Partition<L> {
         match self {
             PartitionKind::Empty => Partition::EMPTY,
             PartitionKind::Full => Partition::Full,
@@ -27,6 +74,17 @@ impl PartitionKind {
             PartitionKind::Vec => Partition::Vec(Default::default()),
             PartitionKind::Run => Partition::Run(Default::default()),
             PartitionKind::Tree => Partition::Tree(Default::default()),
+            PartitionKind::Prefix => Partition::Prefix(Default::default()),
+            PartitionKind::InvertedVec | PartitionKind::InvertedRun => {
+                // an empty inverted partition would need to store every
+                // possible value, so build the partition via `switch_kind`
+                unreachable!("BUG: inverted partitions can't be built empty")
+            }
+            PartitionKind::EliasFano | PartitionKind::DeltaVec | PartitionKind::SparseBitmap => {
+                // Elias-Fano, delta-vec and sparse bitmap partitions are
+                // immutable, so build the partition via `switch_kind`
+                unreachable!("BUG: {self:?} partitions can't be built empty")
+            }
         }
     }
 }
@@ -4,9 +4,12 @@ use itertools::{EitherOrBoth, Itertools};
 
 use crate::{
     PartitionRead, PartitionWrite,
-    codec::partition_ref::{NonRecursivePartitionRef, PartitionRef},
+    codec::{
+        bitmap_ref::with_bits,
+        partition_ref::{NonRecursivePartitionRef, PartitionRef},
+    },
     level::Level,
-    partition::Partition,
+    partition::{Partition, inverted::InvertedPartition, tree::TreePartition},
     partition_kind::PartitionKind,
     traits::{Complement, Cut},
 };
@@ -21,7 +24,11 @@ impl<L: Level> PartialEq for Partition<L> {
             (Bitmap(a), Bitmap(b)) => a == b,
             (Vec(a), Vec(b)) => a == b,
             (Run(a), Run(b)) => a == b,
-            (Tree(a), Tree(b)) => a == b,
+            (Tree(a) | Prefix(a), Tree(b) | Prefix(b)) => a == b,
+            (Inverted(a), Inverted(b)) => a == b,
+            (EliasFano(a), EliasFano(b)) => a == b,
+            (DeltaVec(a), DeltaVec(b)) => a == b,
+            (SparseBitmap(a), SparseBitmap(b)) => a == b,
 
             // otherwise fall back to logical ops
             (a, b) => itertools::equal(a.iter(), b.iter()),
@@ -37,10 +44,17 @@ impl<L: Level> PartialEq<PartitionRef<'_, L>> for Partition<L> {
         match (self, other) {
             // use fast physical ops if both partitions share storage
             (Partition::Full, NonRecursive(Full)) => true,
-            (Partition::Bitmap(a), NonRecursive(Bitmap { bitmap })) => a == bitmap,
+            (Partition::Bitmap(a), NonRecursive(Bitmap { bitmap })) => {
+                with_bits!(bitmap, |bits| *a == bits)
+            }
             (Partition::Vec(a), NonRecursive(Vec { values })) => a == values,
             (Partition::Run(a), NonRecursive(Run { runs })) => a == runs,
-            (Partition::Tree(a), Tree(b)) => a == b,
+            (Partition::Tree(a) | Partition::Prefix(a), Tree(b)) => a == b,
+            (Partition::Tree(a) | Partition::Prefix(a), Prefix(b)) => *a == TreePartition::from(b),
+            (Partition::Inverted(a), Inverted(b)) => *a.inner() == NonRecursive(b.inner().clone()),
+            (Partition::EliasFano(a), NonRecursive(EliasFano { values })) => a == values,
+            (Partition::DeltaVec(a), NonRecursive(DeltaVec { values })) => a == values,
+            (Partition::SparseBitmap(a), NonRecursive(SparseBitmap { bitmap })) => a == bitmap,
 
             // otherwise fall back to logical ops
             (a, b) => itertools::equal(a.iter(), b.iter()),
@@ -61,11 +75,18 @@ impl<L: Level> BitOrAssign<&Partition<L>> for Partition<L> {
             (_, b) if b.is_empty() => (),
             (a, b) if a.is_empty() => *a = b.clone(),
 
+            // materialize inverted partitions as runs
+            (a @ Inverted(_), b) => {
+                a.switch_kind(PartitionKind::Run);
+                a.bitor_assign(b);
+            }
+            (a, Inverted(b)) => a.bitor_assign(&Partition::Run(b.to_run())),
+
             // use fast physical ops if both partitions share storage
             (Bitmap(a), Bitmap(b)) => a.bitor_assign(b),
             (Vec(a), Vec(b)) => a.bitor_assign(b),
             (Run(a), Run(b)) => a.bitor_assign(b),
-            (Tree(a), Tree(b)) => a.bitor_assign(b),
+            (Tree(a) | Prefix(a), Tree(b) | Prefix(b)) => a.bitor_assign(b),
 
             // otherwise fall back to logical ops
             (a, b) => {
@@ -93,11 +114,23 @@ impl<L: Level> BitOrAssign<&PartitionRef<'_, L>> for Partition<L> {
             (_, NonRecursive(Empty)) => (),
             (a, b) if a.is_empty() => *a = b.into(),
 
+            // materialize inverted partitions as runs
+            (a @ Partition::Inverted(_), b) => {
+                a.switch_kind(PartitionKind::Run);
+                a.bitor_assign(b);
+            }
+            (a, Inverted(b)) => a.bitor_assign(&Partition::Run(b.to_run())),
+
             // use fast physical ops if both partitions share storage
-            (Partition::Bitmap(a), NonRecursive(Bitmap { bitmap })) => a.bitor_assign(*bitmap),
+            (Partition::Bitmap(a), NonRecursive(Bitmap { bitmap })) => {
+                with_bits!(bitmap, |bits| a.bitor_assign(bits))
+            }
             (Partition::Vec(a), NonRecursive(Vec { values })) => a.bitor_assign(*values),
             (Partition::Run(a), NonRecursive(Run { runs })) => a.bitor_assign(runs),
-            (Partition::Tree(a), Tree(tree)) => a.bitor_assign(tree),
+            (Partition::Tree(a) | Partition::Prefix(a), Tree(tree)) => a.bitor_assign(tree),
+            (Partition::Tree(a) | Partition::Prefix(a), Prefix(prefix)) => {
+                a.bitor_assign(&TreePartition::from(prefix))
+            }
 
             // otherwise fall back to logical ops
             (a, b) => {
@@ -123,11 +156,18 @@ impl<L: Level> BitAndAssign<&Partition<L>> for Partition<L> {
             // special case empty
             (a, b) if a.is_empty() || b.is_empty() => *a = Partition::EMPTY,
 
+            // materialize inverted partitions as runs
+            (a @ Inverted(_), b) => {
+                a.switch_kind(PartitionKind::Run);
+                a.bitand_assign(b);
+            }
+            (a, Inverted(b)) => a.bitand_assign(&Partition::Run(b.to_run())),
+
             // use fast physical ops if both partitions share storage
             (Bitmap(a), Bitmap(b)) => a.bitand_assign(b),
             (Vec(a), Vec(b)) => a.bitand_assign(b),
             (Run(a), Run(b)) => a.bitand_assign(b),
-            (Tree(a), Tree(b)) => a.bitand_assign(b),
+            (Tree(a) | Prefix(a), Tree(b) | Prefix(b)) => a.bitand_assign(b),
 
             // otherwise fall back to logical ops
             (a, b) => {
@@ -161,11 +201,23 @@ impl<L: Level> BitAndAssign<&PartitionRef<'_, L>> for Partition<L> {
             (a, NonRecursive(Empty)) => *a = Partition::EMPTY,
             (a, _) if a.is_empty() => (),
 
+            // materialize inverted partitions as runs
+            (a @ Partition::Inverted(_), b) => {
+                a.switch_kind(PartitionKind::Run);
+                a.bitand_assign(b);
+            }
+            (a, Inverted(b)) => a.bitand_assign(&Partition::Run(b.to_run())),
+
             // use fast physical ops if both partitions share storage
-            (Partition::Bitmap(a), NonRecursive(Bitmap { bitmap })) => a.bitand_assign(*bitmap),
+            (Partition::Bitmap(a), NonRecursive(Bitmap { bitmap })) => {
+                with_bits!(bitmap, |bits| a.bitand_assign(bits))
+            }
             (Partition::Vec(a), NonRecursive(Vec { values })) => a.bitand_assign(*values),
             (Partition::Run(a), NonRecursive(Run { runs })) => a.bitand_assign(runs),
-            (Partition::Tree(a), Tree(tree)) => a.bitand_assign(tree),
+            (Partition::Tree(a) | Partition::Prefix(a), Tree(tree)) => a.bitand_assign(tree),
+            (Partition::Tree(a) | Partition::Prefix(a), Prefix(prefix)) => {
+                a.bitand_assign(&TreePartition::from(prefix))
+            }
 
             // otherwise fall back to logical ops
             (a, b) => {
@@ -201,11 +253,18 @@ impl<L: Level> BitXorAssign<&Partition<L>> for Partition<L> {
             (_, b) if b.is_empty() => (),
             (a, b) if a.is_empty() => *a = b.clone(),
 
+            // materialize inverted partitions as runs
+            (a @ Inverted(_), b) => {
+                a.switch_kind(PartitionKind::Run);
+                a.bitxor_assign(b);
+            }
+            (a, Inverted(b)) => a.bitxor_assign(&Partition::Run(b.to_run())),
+
             // use fast physical ops if both partitions share storage
             (Bitmap(a), Bitmap(b)) => a.bitxor_assign(b),
             (Vec(a), Vec(b)) => a.bitxor_assign(b),
             (Run(a), Run(b)) => a.bitxor_assign(b),
-            (Tree(a), Tree(b)) => a.bitxor_assign(b),
+            (Tree(a) | Prefix(a), Tree(b) | Prefix(b)) => a.bitxor_assign(b),
 
             // otherwise fall back to logical ops
             (a, b) => {
@@ -242,11 +301,23 @@ impl<L: Level> BitXorAssign<&PartitionRef<'_, L>> for Partition<L> {
             (_, NonRecursive(Empty)) => (),
             (a, b) if a.is_empty() => *a = b.into(),
 
+            // materialize inverted partitions as runs
+            (a @ Partition::Inverted(_), b) => {
+                a.switch_kind(PartitionKind::Run);
+                a.bitxor_assign(b);
+            }
+            (a, Inverted(b)) => a.bitxor_assign(&Partition::Run(b.to_run())),
+
             // use fast physical ops if both partitions share storage
-            (Partition::Bitmap(a), NonRecursive(Bitmap { bitmap })) => a.bitxor_assign(*bitmap),
+            (Partition::Bitmap(a), NonRecursive(Bitmap { bitmap })) => {
+                with_bits!(bitmap, |bits| a.bitxor_assign(bits))
+            }
             (Partition::Vec(a), NonRecursive(Vec { values })) => a.bitxor_assign(*values),
             (Partition::Run(a), NonRecursive(Run { runs })) => a.bitxor_assign(runs),
-            (Partition::Tree(a), Tree(tree)) => a.bitxor_assign(tree),
+            (Partition::Tree(a) | Partition::Prefix(a), Tree(tree)) => a.bitxor_assign(tree),
+            (Partition::Tree(a) | Partition::Prefix(a), Prefix(prefix)) => {
+                a.bitxor_assign(&TreePartition::from(prefix))
+            }
 
             // otherwise fall back to logical ops
             (a, b) => {
@@ -281,11 +352,18 @@ impl<L: Level> SubAssign<&Partition<L>> for Partition<L> {
             // special case empty
             (a, b) if a.is_empty() || b.is_empty() => (),
 
+            // materialize inverted partitions as runs
+            (a @ Inverted(_), b) => {
+                a.switch_kind(PartitionKind::Run);
+                a.sub_assign(b);
+            }
+            (a, Inverted(b)) => a.sub_assign(&Partition::Run(b.to_run())),
+
             // use fast physical ops if both partitions share storage
             (Bitmap(a), Bitmap(b)) => a.sub_assign(b),
             (Vec(a), Vec(b)) => a.sub_assign(b),
             (Run(a), Run(b)) => a.sub_assign(b),
-            (Tree(a), Tree(b)) => a.sub_assign(b),
+            (Tree(a) | Prefix(a), Tree(b) | Prefix(b)) => a.sub_assign(b),
 
             // otherwise fall back to logical ops
             (a, b) => {
@@ -316,11 +394,23 @@ impl<L: Level> SubAssign<&PartitionRef<'_, L>> for Partition<L> {
             // special case empty
             (a, b) if a.is_empty() || b.is_empty() => (),
 
+            // materialize inverted partitions as runs
+            (a @ Partition::Inverted(_), b) => {
+                a.switch_kind(PartitionKind::Run);
+                a.sub_assign(b);
+            }
+            (a, Inverted(b)) => a.sub_assign(&Partition::Run(b.to_run())),
+
             // use fast physical ops if both partitions share storage
-            (Partition::Bitmap(a), NonRecursive(Bitmap { bitmap })) => a.sub_assign(*bitmap),
+            (Partition::Bitmap(a), NonRecursive(Bitmap { bitmap })) => {
+                with_bits!(bitmap, |bits| a.sub_assign(bits))
+            }
             (Partition::Vec(a), NonRecursive(Vec { values })) => a.sub_assign(*values),
             (Partition::Run(a), NonRecursive(Run { runs })) => a.sub_assign(runs),
-            (Partition::Tree(a), Tree(tree)) => a.sub_assign(tree),
+            (Partition::Tree(a) | Partition::Prefix(a), Tree(tree)) => a.sub_assign(tree),
+            (Partition::Tree(a) | Partition::Prefix(a), Prefix(prefix)) => {
+                a.sub_assign(&TreePartition::from(prefix))
+            }
 
             // otherwise fall back to logical ops
             (a, b) => {
@@ -348,10 +438,17 @@ impl<L: Level> Cut for Partition<L> {
             // special case full
             (a, Full) => std::mem::take(a),
 
+            // materialize inverted partitions as runs
+            (a @ Inverted(_), b) => {
+                a.switch_kind(PartitionKind::Run);
+                a.cut(b)
+            }
+            (a, Inverted(b)) => a.cut(&Partition::Run(b.to_run())),
+
             // use fast physical ops if both partitions share storage
             (Bitmap(a), Bitmap(b)) => a.cut(b),
             (Run(a), Run(b)) => a.cut(b),
-            (Tree(a), Tree(b)) => a.cut(b),
+            (Tree(a) | Prefix(a), Tree(b) | Prefix(b)) => a.cut(b),
 
             // fallback to general optimized logical ops
             (Vec(a), b) => a.cut(b),
@@ -394,10 +491,22 @@ impl<L: Level> Cut<PartitionRef<'_, L>> for Partition<L> {
             // special case empty
             (a, NonRecursive(Full)) => std::mem::take(a),
 
+            // materialize inverted partitions as runs
+            (a @ Partition::Inverted(_), b) => {
+                a.switch_kind(PartitionKind::Run);
+                a.cut(b)
+            }
+            (a, Inverted(b)) => a.cut(&Partition::Run(b.to_run())),
+
             // use fast physical ops if both partitions share storage
-            (Partition::Bitmap(a), NonRecursive(Bitmap { bitmap })) => a.cut(bitmap),
+            (Partition::Bitmap(a), NonRecursive(Bitmap { bitmap })) => {
+                with_bits!(bitmap, |bits| a.cut(&bits))
+            }
             (Partition::Run(a), NonRecursive(Run { runs })) => a.cut(runs),
-            (Partition::Tree(a), Tree(b)) => a.cut(b),
+            (Partition::Tree(a) | Partition::Prefix(a), Tree(b)) => a.cut(b),
+            (Partition::Tree(a) | Partition::Prefix(a), Prefix(b)) => {
+                a.cut(&TreePartition::from(b))
+            }
 
             // fallback to general optimized logical ops
             (Partition::Vec(a), b) => a.cut(b),
@@ -441,16 +550,32 @@ impl<L: Level> Complement for Partition<L> {
             Vec(p) => {
                 let complement_cardinality = L::MAX_LEN.saturating_sub(p.cardinality());
                 if complement_cardinality > L::MAX_LEN / 2 {
-                    // if the complement is more than half the universe, switch
-                    // to a run partition before complementing
-                    self.switch_kind(PartitionKind::Run);
-                    self.complement();
+                    // if the complement is more than half the universe, keep
+                    // the current values and mark the partition as inverted
+                    let values = Vec(std::mem::take(p));
+                    *self = Inverted(Box::new(InvertedPartition::new(values)));
                 } else {
                     p.complement();
                 }
             }
             Run(p) => p.complement(),
-            Tree(p) => p.complement(),
+            Tree(p) | Prefix(p) => p.complement(),
+            Inverted(p) => {
+                let complement = std::mem::take(p.inner_mut());
+                *self = complement;
+            }
+            EliasFano(_) | DeltaVec(_) => {
+                // materialize immutable partitions as Vec partitions
+                self.switch_kind(PartitionKind::Vec);
+                self.complement();
+                return;
+            }
+            SparseBitmap(_) => {
+                // the complement of a sparse bitmap is usually dense
+                self.switch_kind(PartitionKind::Bitmap);
+                self.complement();
+                return;
+            }
         }
 
         self.optimize_fast();
@@ -463,6 +588,8 @@ impl<L: Level> From<&PartitionRef<'_, L>> for Partition<L> {
         match value {
             NonRecursive(p) => p.into(),
             Tree(t) => Partition::Tree(t.into()),
+            Prefix(p) => Partition::Prefix(p.into()),
+            Inverted(p) => Partition::Inverted(Box::new(InvertedPartition::new(p.inner().into()))),
         }
     }
 }
@@ -494,7 +621,7 @@ mod tests {
     fn test_complement_small_vec() {
         let mut partition = Partition::<High>::Vec(VecPartition::from_iter([1u32]));
         partition.complement();
-        assert_matches!(partition, Partition::Run(_));
+        assert_matches!(partition, Partition::Inverted(_));
         assert_eq!(partition.cardinality(), High::MAX_LEN - 1);
     }
 
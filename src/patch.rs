@@ -0,0 +1,352 @@
+//! Compact patches between two splinters.
+//!
+//! A [`SplinterPatch`] records the values which were added to and removed
+//! from a splinter. When two splinters only differ by a few values, their
+//! patch is much smaller than either splinter, which makes patches a cheap
+//! way to replicate changes.
+//!
+//! Patches are encoded by the same [`Encoder`] as splinters, so the added and
+//! removed values are stored as regular partitions and protected by a regular
+//! footer and checksum:
+//!
+//! ```text
+//! [added partition][removed partition][added length][footer]
+//! ```
+//!
+//! The added length is a little-endian u32 which locates the end of the added
+//! partition. The footer always records [`Features::PATCH`], so patches are
+//! never mistaken for splinters.
+
+use std::ops::Deref;
+
+use bytes::BufMut;
+use zerocopy::{FromBytes, IntoBytes, LittleEndian, U32};
+
+use crate::{
+    CowSplinter, Encodable, Optimizable, PartitionRead, PartitionWrite, Splinter,
+    codec::{
+        DecodeErr, checksum::Checksum, encoder::Encoder, features::Features, footer::Footer,
+        partition_ref::PartitionRef,
+    },
+    level::High,
+};
+
+/// The number of bytes in the added partition.
+type AddedLength = U32<LittleEndian>;
+
+/// The values added to and removed from a splinter, created by
+/// [`Splinter::diff`].
+///
+/// # Examples
+///
+/// ```
+/// use splinter_rs::{CowSplinter, Encodable, Splinter, SplinterPatch};
+///
+/// let old = Splinter::from_iter(0..10_000);
+/// let new = Splinter::from_iter((1..10_000).chain([20_000]));
+///
+/// let patch = Splinter::diff(&old, &new);
+/// let bytes = patch.encode_to_bytes();
+/// assert!(bytes.len() < new.encoded_size());
+///
+/// // apply the patch to a copy of the old splinter
+/// let patch = SplinterPatch::from_bytes(&bytes).unwrap();
+/// let mut replica = CowSplinter::from_ref(old.encode_to_splinter_ref());
+/// replica.apply_patch(&patch);
+/// assert_eq!(replica, new);
+/// ```
+#[derive(Clone, PartialEq, Eq, Default, Debug)]
+pub struct SplinterPatch {
+    added: Splinter,
+    removed: Splinter,
+}
+
+impl SplinterPatch {
+    /// The values which the patch adds.
+    #[inline]
+    pub fn added(&self) -> &Splinter {
+        &self.added
+    }
+
+    /// The values which the patch removes.
+    #[inline]
+    pub fn removed(&self) -> &Splinter {
+        &self.removed
+    }
+
+    /// Returns true if the patch doesn't change any values.
+    #[inline]
+    pub fn is_empty(&self) -> bool {
+        self.added.is_empty() && self.removed.is_empty()
+    }
+
+    /// Decodes a patch encoded by [`SplinterPatch::encode`](Encodable::encode).
+    ///
+    /// Patches are usually received from other nodes, so both partitions are
+    /// always fully validated, as if by [`SplinterRef::from_bytes_validated`].
+    ///
+    /// # Errors
+    ///
+    /// - [`DecodeErr::Length`]: Not enough bytes in the buffer
+    /// - [`DecodeErr::Magic`]: Invalid magic bytes
+    /// - [`DecodeErr::Checksum`]: Data corruption detected
+    /// - [`DecodeErr::Validity`]: Invalid internal structure
+    /// - [`DecodeErr::NotPatch`]: The buffer contains a splinter rather than a
+    ///   patch
+    /// - [`DecodeErr::UnsupportedFeature`]: Data uses optional features this
+    ///   version doesn't support
+    ///
+    /// [`SplinterRef::from_bytes_validated`]: crate::SplinterRef::from_bytes_validated
+    pub fn from_bytes(data: &[u8]) -> Result<Self, DecodeErr> {
+        let (partitions, footer) = Footer::split(data)?;
+        if !footer.features.contains(Features::PATCH) {
+            return Err(DecodeErr::NotPatch);
+        }
+        let (partitions, added_len) = AddedLength::read_from_suffix(partitions)?;
+        let (added, removed) = partitions
+            .split_at_checked(added_len.get() as usize)
+            .ok_or(DecodeErr::Length)?;
+        Ok(Self {
+            added: decode_partition(added)?,
+            removed: decode_partition(removed)?,
+        })
+    }
+}
+
+/// Decodes and validates a single partition stored in a patch.
+fn decode_partition(data: &[u8]) -> Result<Splinter, DecodeErr> {
+    let root = PartitionRef::<High>::from_suffix(data)?;
+    root.validate()?;
+    Ok(Splinter::new((&root).into()))
+}
+
+impl Encodable for SplinterPatch {
+    fn encoded_size(&self) -> usize {
+        self.encoded_size_with(Features::PATCH)
+    }
+
+    fn encoded_size_with(&self, features: Features) -> usize {
+        let footer = Footer::new(Checksum::default(), self.encoded_features_with(features));
+        self.added.inner().encoded_size_with(features)
+            + self.removed.inner().encoded_size_with(features)
+            + size_of::<AddedLength>()
+            + footer.encoded_size()
+    }
+
+    fn max_encoded_size(&self) -> usize {
+        let footer = Footer::new(Checksum::default(), Features::PATCH);
+        self.added.inner().max_encoded_size()
+            + self.removed.inner().max_encoded_size()
+            + size_of::<AddedLength>()
+            + footer.encoded_size()
+    }
+
+    /// # Panics
+    ///
+    /// Panics if the encoder doesn't support [`Features::PATCH`].
+    fn encode<B: BufMut>(&self, encoder: &mut Encoder<B>) {
+        assert!(
+            encoder.supports(Features::PATCH),
+            "invalid encoder usage: patches require Features::PATCH"
+        );
+        let start = encoder.bytes_written();
+        self.added.inner().encode(encoder);
+        let added_len =
+            u32::try_from(encoder.bytes_written() - start).expect("encoded patch is too large");
+        self.removed.inner().encode(encoder);
+        encoder.put_slice(AddedLength::new(added_len).as_bytes());
+        encoder.use_features(Features::PATCH);
+        encoder.write_footer();
+    }
+
+    fn encoded_features(&self) -> Features {
+        self.added.encoded_features() | self.removed.encoded_features() | Features::PATCH
+    }
+
+    fn encoded_features_with(&self, features: Features) -> Features {
+        self.added.encoded_features_with(features)
+            | self.removed.encoded_features_with(features)
+            | Features::PATCH
+    }
+
+    fn required_features(&self) -> Features {
+        Features::PATCH
+    }
+}
+
+impl Splinter {
+    /// Returns the patch which transforms `old` into `new`.
+    ///
+    /// The patch only stores the values which differ between the two
+    /// splinters, optimized for size.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{PartitionRead, Splinter};
+    ///
+    /// let old = Splinter::from_iter([1, 2, 3]);
+    /// let new = Splinter::from_iter([2, 3, 4]);
+    ///
+    /// let patch = Splinter::diff(&old, &new);
+    /// assert_eq!(patch.added().iter().collect::<Vec<_>>(), [4]);
+    /// assert_eq!(patch.removed().iter().collect::<Vec<_>>(), [1]);
+    /// ```
+    pub fn diff(old: &Splinter, new: &Splinter) -> SplinterPatch {
+        let mut added = new - old;
+        let mut removed = old - new;
+        added.optimize();
+        removed.optimize();
+        SplinterPatch { added, removed }
+    }
+
+    /// Applies a patch to this splinter, removing the patch's removed values
+    /// and then inserting its added values.
+    ///
+    /// Applying a patch is idempotent, and applying the patch returned by
+    /// [`Splinter::diff`] to `old` always produces `new`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::Splinter;
+    ///
+    /// let old = Splinter::from_iter([1, 2, 3]);
+    /// let new = Splinter::from_iter([2, 3, 4]);
+    ///
+    /// let mut splinter = old.clone();
+    /// splinter.apply_patch(&Splinter::diff(&old, &new));
+    /// assert_eq!(splinter, new);
+    /// ```
+    pub fn apply_patch(&mut self, patch: &SplinterPatch) {
+        *self -= &patch.removed;
+        *self |= &patch.added;
+    }
+}
+
+impl<B: Deref<Target = [u8]>> CowSplinter<B> {
+    /// Applies a patch to this splinter, see [`Splinter::apply_patch`].
+    ///
+    /// Splinters which have not been decoded yet are edited value by value,
+    /// so small patches only decode the parts of the splinter they modify.
+    pub fn apply_patch(&mut self, patch: &SplinterPatch) {
+        if let CowSplinter::Owned(splinter) = self {
+            return splinter.apply_patch(patch);
+        }
+        for value in patch.removed.iter() {
+            self.remove(value);
+        }
+        for value in patch.added.iter() {
+            self.insert(value);
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use itertools::Itertools;
+    use proptest::{collection::vec, proptest};
+
+    use crate::{
+        CowSplinter, Encodable, PartitionRead, Splinter, SplinterPatch, SplinterRef, assert_error,
+        codec::{DecodeErr, encoder::Encoder, features::Features},
+        testutil::SetGen,
+    };
+
+    #[test]
+    fn test_patch_roundtrip() {
+        let mut set_gen = SetGen::new(0xDEAD_BEEF);
+        let old = set_gen.random(4096);
+        let mut new = old.clone();
+        new.retain(|v| v % 7 != 0);
+        new.extend(set_gen.random(16));
+
+        let old = Splinter::from_iter(old);
+        let new = Splinter::from_iter(new);
+        let patch = Splinter::diff(&old, &new);
+        assert!(!patch.is_empty());
+
+        let encoded = patch.encode_to_bytes();
+        assert_eq!(encoded.len(), patch.encoded_size());
+        assert!(encoded.len() <= patch.max_encoded_size());
+        let decoded = SplinterPatch::from_bytes(&encoded).unwrap();
+        assert_eq!(decoded, patch);
+
+        let mut splinter = old.clone();
+        splinter.apply_patch(&decoded);
+        assert_eq!(splinter, new);
+        // patches are idempotent
+        splinter.apply_patch(&decoded);
+        assert_eq!(splinter, new);
+
+        let mut cow = CowSplinter::from_ref(old.encode_to_splinter_ref());
+        cow.apply_patch(&decoded);
+        assert_eq!(cow, new);
+
+        // an empty patch
+        let patch = Splinter::diff(&new, &new);
+        assert!(patch.is_empty());
+        let decoded = SplinterPatch::from_bytes(&patch.encode_to_bytes()).unwrap();
+        assert!(decoded.is_empty());
+    }
+
+    #[test]
+    fn test_patch_is_not_a_splinter() {
+        let old = Splinter::from_iter(0..100);
+        let new = Splinter::from_iter(50..150);
+        let patch = Splinter::diff(&old, &new).encode_to_bytes();
+
+        assert_error!(SplinterRef::from_bytes(patch.clone()), DecodeErr::Patch);
+        assert_error!(CowSplinter::from_bytes(patch.clone()), DecodeErr::Patch);
+        assert_error!(
+            SplinterPatch::from_bytes(&new.encode_to_bytes()),
+            DecodeErr::NotPatch
+        );
+
+        // the patch is covered by the checksum
+        let mut corrupted = patch.to_vec();
+        corrupted[0] ^= 1;
+        assert_error!(SplinterPatch::from_bytes(&corrupted), DecodeErr::Checksum);
+
+        // patches can record their length like splinters
+        let mut encoder = Encoder::with_features(Vec::new(), Features::PATCH).with_encoded_length();
+        Splinter::diff(&old, &new).encode(&mut encoder);
+        let encoded = encoder.into_inner();
+        assert_eq!(encoded.len(), patch.len() + 4);
+        assert_eq!(
+            SplinterPatch::from_bytes(&encoded).unwrap(),
+            Splinter::diff(&old, &new)
+        );
+    }
+
+    #[test]
+    #[should_panic(expected = "patches require Features::PATCH")]
+    fn test_patch_requires_feature() {
+        let patch = Splinter::diff(&Splinter::EMPTY, &Splinter::from_iter([1]));
+        let mut encoder = Encoder::with_features(Vec::new(), Features::EMPTY);
+        patch.encode(&mut encoder);
+    }
+
+    proptest! {
+        #[test]
+        fn test_patch_proptest(
+            old in vec(0u32..(1 << 20), 0..512),
+            new in vec(0u32..(1 << 20), 0..512),
+        ) {
+            let old = Splinter::from_iter(old);
+            let new = Splinter::from_iter(new);
+            let patch = Splinter::diff(&old, &new);
+            let encoded = patch.encode_to_bytes();
+            assert_eq!(encoded.len(), patch.encoded_size());
+            let patch = SplinterPatch::from_bytes(&encoded).unwrap();
+
+            let mut splinter = old.clone();
+            splinter.apply_patch(&patch);
+            assert_eq!(&splinter, &new);
+
+            let mut cow = CowSplinter::from_ref(old.encode_to_splinter_ref());
+            cow.apply_patch(&patch);
+            assert_eq!(cow.iter().collect_vec(), new.iter().collect_vec());
+        }
+    }
+}
@@ -1,7 +1,8 @@
 use std::iter::FusedIterator;
 
 use num::traits::AsPrimitive;
-use u24::u24;
+
+use crate::u24::u24;
 
 pub type Segment = u8;
 
@@ -74,7 +75,7 @@ macro_rules! impl_split {
 
 impl_split!(
     (u32, u24, 0xFF000000),
-    (u24, u16, u24!(0xFF0000)),
+    (u24, u16, u24::truncating_from_u32(0xFF0000)),
     (u16, u8, 0xFF00)
 );
 
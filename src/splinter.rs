@@ -1,10 +1,10 @@
 use std::{fmt::Debug, ops::RangeBounds};
 
-use bytes::Bytes;
+use bytes::{BufMut, Bytes, BytesMut};
 
 use crate::{
     Encodable, Optimizable, SplinterRef,
-    codec::{encoder::Encoder, footer::Footer},
+    codec::{DecodeErr, checksum::Checksum, encoder::Encoder, features::Features, footer::Footer},
     level::High,
     partition::Partition,
     traits::{PartitionRead, PartitionWrite},
@@ -85,6 +85,117 @@ impl Splinter {
         SplinterRef { data: self.encode_to_bytes() }
     }
 
+    /// Encodes this splinter using its canonical layout.
+    ///
+    /// [`Optimizable::optimize`] keeps a partition's current representation
+    /// until a smaller one presents itself, so equal splinters can encode to
+    /// different bytes depending on how they were built. The canonical
+    /// encoding always picks the smallest representation of every partition,
+    /// so two splinters encode to the same canonical bytes if and only if
+    /// they contain the same values. This makes the output suitable for
+    /// content hashing and deduplication.
+    ///
+    /// The canonical encoding is written in the V2 format by the default
+    /// [`Encoder::new`]. Use [`Splinter::encode_canonical_with`] to pick
+    /// among partition kinds which depend on optional features.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Splinter, PartitionWrite, Optimizable};
+    ///
+    /// let mut a = Splinter::from_iter(0..1024);
+    /// a.optimize();
+    /// a.remove_range(16..1024);
+    /// a.optimize();
+    ///
+    /// let b = Splinter::from_iter(0..16);
+    /// assert_eq!(a.encode_canonical(), b.encode_canonical());
+    /// ```
+    pub fn encode_canonical(&self) -> Bytes {
+        self.encode_canonical_with(Features::EMPTY)
+    }
+
+    /// Encodes this splinter using its canonical layout for the provided
+    /// optional features, with [`Encoder::with_features`].
+    ///
+    /// Every feature set has its own canonical layout: splinters encode to
+    /// the same canonical bytes if and only if they contain the same values
+    /// and are encoded with the same features. Readers must support the
+    /// provided features.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Encodable, Splinter, SplinterRef, codec::features::Features};
+    ///
+    /// let splinter = Splinter::from_iter((0..65536).filter(|v| v % 1000 != 0));
+    /// let canonical = splinter.encode_canonical_with(Features::SUPPORTED);
+    /// assert!(canonical.len() < splinter.encode_canonical().len());
+    ///
+    /// let splinter_ref = SplinterRef::from_bytes(canonical).unwrap();
+    /// assert!(splinter_ref.is_canonical());
+    /// assert_eq!(splinter_ref, splinter);
+    /// ```
+    pub fn encode_canonical_with(&self, features: Features) -> Bytes {
+        let mut canonical = self.clone();
+        canonical.canonicalize_with(features);
+        let size = canonical.encoded_size_with(features);
+        let mut encoder = Encoder::with_features(BytesMut::with_capacity(size), features);
+        canonical.encode(&mut encoder);
+        encoder.into_inner().freeze()
+    }
+
+    /// Decodes a bitmap serialized in the [Roaring portable format].
+    ///
+    /// Each Roaring container is converted directly into the equivalent
+    /// Splinter partition. The returned `Splinter` is not optimized, so call
+    /// [`Optimizable::optimize`] before encoding it.
+    ///
+    /// # Errors
+    ///
+    /// - [`DecodeErr::Length`]: Not enough bytes in the buffer
+    /// - [`DecodeErr::Magic`]: Invalid Roaring cookie
+    /// - [`DecodeErr::Validity`]: Invalid internal structure, or trailing bytes
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Splinter, PartitionRead};
+    ///
+    /// let mut buf = Vec::new();
+    /// Splinter::from_iter([1, 2, 100_000]).encode_roaring(&mut buf);
+    ///
+    /// let splinter = Splinter::from_roaring_bytes(&buf).unwrap();
+    /// assert_eq!(splinter.iter().collect::<Vec<_>>(), [1, 2, 100_000]);
+    /// ```
+    ///
+    /// [Roaring portable format]: https://github.com/RoaringBitmap/RoaringFormatSpec
+    pub fn from_roaring_bytes(data: &[u8]) -> Result<Self, DecodeErr> {
+        crate::codec::roaring::decode(data).map(Self)
+    }
+
+    /// Encodes this splinter in the [Roaring portable format], which can be
+    /// read by any Roaring implementation.
+    ///
+    /// Each Low partition is written as the equivalent Roaring container.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::Splinter;
+    ///
+    /// let mut buf = Vec::new();
+    /// Splinter::from(0..1000).encode_roaring(&mut buf);
+    /// // a header followed by a single run container
+    /// assert_eq!(buf.len(), 15);
+    /// ```
+    ///
+    /// [Roaring portable format]: https://github.com/RoaringBitmap/RoaringFormatSpec
+    pub fn encode_roaring<B: BufMut>(&self, buf: &mut B) {
+        crate::codec::roaring::encode(&self.0, buf)
+    }
+
     #[inline(always)]
     pub(crate) fn new(inner: Partition<High>) -> Self {
         Self(inner)
@@ -424,13 +535,30 @@ impl PartitionWrite<High> for Splinter {
 
 impl Encodable for Splinter {
     fn encoded_size(&self) -> usize {
-        self.0.encoded_size() + std::mem::size_of::<Footer>()
+        self.encoded_size_with(Features::EMPTY)
+    }
+
+    fn encoded_size_with(&self, features: Features) -> usize {
+        let footer = Footer::new(Checksum::default(), self.0.encoded_features_with(features));
+        self.0.encoded_size_with(features) + footer.encoded_size()
+    }
+
+    fn max_encoded_size(&self) -> usize {
+        self.0.max_encoded_size() + Footer::SIZE
     }
 
     fn encode<B: bytes::BufMut>(&self, encoder: &mut Encoder<B>) {
         self.0.encode(encoder);
         encoder.write_footer();
     }
+
+    fn encoded_features(&self) -> Features {
+        self.0.encoded_features()
+    }
+
+    fn encoded_features_with(&self, features: Features) -> Features {
+        self.0.encoded_features_with(features)
+    }
 }
 
 impl Optimizable for Splinter {
@@ -438,6 +566,21 @@ impl Optimizable for Splinter {
     fn optimize(&mut self) {
         self.0.optimize();
     }
+
+    #[inline]
+    fn optimize_with(&mut self, features: Features) {
+        self.0.optimize_with(features);
+    }
+
+    #[inline]
+    fn canonicalize(&mut self) {
+        self.0.canonicalize();
+    }
+
+    #[inline]
+    fn canonicalize_with(&mut self, features: Features) {
+        self.0.canonicalize_with(features);
+    }
 }
 
 impl Extend<u32> for Splinter {
@@ -453,9 +596,13 @@ mod tests {
 
     use super::*;
     use crate::{
-        codec::Encodable,
-        level::{Level, Low},
-        testutil::{SetGen, mksplinter, ratio_to_marks, test_partition_read, test_partition_write},
+        codec::{Encodable, checksum::Checksum, encoder::Encoder},
+        level::{High, Level, Low},
+        partition_kind::PartitionKind,
+        testutil::{
+            SetGen, encode_with_features, mkpartition, mkpartition_buf, mksplinter,
+            mksplinter_manual, ratio_to_marks, test_partition_read, test_partition_write,
+        },
         traits::Optimizable,
     };
     use itertools::{Itertools, assert_equal};
@@ -544,6 +691,107 @@ mod tests {
         assert_eq!(splinter.cardinality(), (u32::MAX as usize) - count);
     }
 
+    #[test]
+    fn test_encode_canonical() {
+        let mut set_gen = SetGen::new(0xDEAD_BEEF);
+        let sets = [
+            vec![],
+            vec![0],
+            (0..16).collect_vec(),
+            (0..4096).collect_vec(),
+            set_gen.random(1024),
+            set_gen.distributed(4, 8, 8, 8),
+            set_gen.dense(1, 1, 2, 256),
+        ];
+
+        for (set, features) in sets
+            .into_iter()
+            .cartesian_product([Features::EMPTY, Features::SUPPORTED])
+        {
+            let expected = Splinter::from_iter(set.iter().copied()).encode_canonical_with(features);
+            let expected_ref = SplinterRef::from_bytes(expected.clone()).unwrap();
+            assert!(expected_ref.is_canonical());
+            assert!(features.contains(expected_ref.encoded_features()));
+            if features == Features::EMPTY {
+                assert_eq!(
+                    Splinter::from_iter(set.iter().copied()).encode_canonical(),
+                    expected
+                );
+            }
+
+            // the canonical encoding uses the default footer, so a different
+            // checksum or an encoded length isn't canonical
+            let mut canonical = Splinter::from_iter(set.iter().copied());
+            canonical.canonicalize_with(features);
+            for checksum in Checksum::enabled().filter(|&c| c != Checksum::default()) {
+                let mut encoder =
+                    Encoder::with_features(BytesMut::new(), features).with_checksum(checksum);
+                canonical.encode(&mut encoder);
+                let splinter_ref = SplinterRef::from_bytes(encoder.into_inner().freeze()).unwrap();
+                assert!(!splinter_ref.is_canonical(), "checksum {checksum:?}");
+            }
+            let mut encoder =
+                Encoder::with_features(BytesMut::new(), features).with_encoded_length();
+            canonical.encode(&mut encoder);
+            let splinter_ref = SplinterRef::from_bytes(encoder.into_inner().freeze()).unwrap();
+            assert!(!splinter_ref.is_canonical());
+
+            // the canonical encoding is never larger than the optimized one
+            let mut optimized = Splinter::from_iter(set.iter().copied());
+            optimized.optimize_with(features);
+            assert!(expected.len() <= optimized.encoded_size_with(features));
+
+            // build the same set starting from different partition kinds
+            for kind in [PartitionKind::Vec, PartitionKind::Run, PartitionKind::Tree] {
+                let splinter = Splinter::new(mkpartition(kind, &set));
+                assert_eq!(
+                    splinter.encode_canonical_with(features),
+                    expected,
+                    "kind {kind:?}"
+                );
+            }
+
+            // arrive at the same set after removing a large range from a full
+            // splinter
+            let mut splinter = Splinter::FULL;
+            splinter.remove_range(..);
+            splinter.extend(set.iter().copied());
+            splinter.optimize();
+            assert_eq!(splinter.encode_canonical_with(features), expected);
+
+            // and via the encoded form
+            let splinter_ref =
+                SplinterRef::from_bytes(encode_with_features(&optimized, features)).unwrap();
+            assert_eq!(
+                splinter_ref
+                    .decode_to_splinter()
+                    .encode_canonical_with(features),
+                expected
+            );
+            assert_eq!(splinter_ref, expected_ref);
+        }
+
+        // each feature set has its own canonical encoding
+        let splinter = Splinter::from_iter((0..65536).filter(|v| v % 1000 != 0));
+        let canonical = splinter.encode_canonical_with(Features::SUPPORTED);
+        assert_ne!(canonical, splinter.encode_canonical());
+        assert!(SplinterRef::from_bytes(canonical).unwrap().is_canonical());
+
+        // a prefix partition which fell back to a tree isn't canonical
+        let mut splinter = Splinter::from_iter(0..16);
+        splinter.canonicalize_with(Features::SUPPORTED);
+        let mut encoder = Encoder::with_features(BytesMut::new(), Features::EMPTY);
+        splinter.encode(&mut encoder);
+        let splinter_ref = SplinterRef::from_bytes(encoder.into_inner().freeze()).unwrap();
+        assert!(!splinter_ref.is_canonical());
+
+        // a Vec partition storing a single run isn't canonical
+        let buf = mkpartition_buf::<High>(PartitionKind::Vec, &[0, 1, 2, 3]);
+        let splinter_ref = SplinterRef::from_bytes(mksplinter_manual(&buf)).unwrap();
+        assert!(!splinter_ref.is_canonical());
+        assert_equal(splinter_ref.iter(), 0..4);
+    }
+
     proptest! {
         #[test]
         fn test_splinter_read_proptest(set in hash_set(0u32..16384, 0..1024)) {
@@ -589,6 +837,27 @@ mod tests {
             assert_eq!(a, b);
         }
 
+        #[test]
+        fn test_encode_canonical_proptest(
+            set in hash_set(0u32..16384, 0..1024),
+            removed in hash_set(0u32..16384, 0..256),
+        ) {
+            // insert some extra values, optimize, and then remove them again
+            let mut splinter = Splinter::from_iter(set.iter().chain(&removed).copied());
+            splinter.optimize();
+            for value in removed.difference(&set) {
+                splinter.remove(*value);
+            }
+            splinter.optimize();
+
+            for features in [Features::EMPTY, Features::SUPPORTED] {
+                let expected =
+                    Splinter::from_iter(set.iter().copied()).encode_canonical_with(features);
+                assert_eq!(splinter.encode_canonical_with(features), expected);
+                assert!(SplinterRef::from_bytes(expected).unwrap().is_canonical());
+            }
+        }
+
         #[test]
         fn test_splinter_remove_range_proptest(set in hash_set(0u32..16384, 0..1024)) {
             let expected = set.iter().copied().sorted().collect_vec();
@@ -751,6 +1020,44 @@ mod tests {
         }
     }
 
+    #[test]
+    fn test_default_encoded_sizes() {
+        // optimized splinters written by the default encoder must be exactly
+        // as large as before optional partition kinds were added
+        let mut set_gen = SetGen::new(0xDEAD_BEEF);
+        let cases = [
+            (
+                (0..2000u32)
+                    .map(|i| i.wrapping_mul(2654435761) >> 8)
+                    .collect_vec(),
+                6027,
+            ),
+            ((0..65536).filter(|v| v % 1000 != 0).collect_vec(), 299),
+            (set_gen.distributed(1, 1, 1, 128), 72),
+            (set_gen.distributed(4, 8, 8, 2), 1402),
+            (set_gen.distributed(256, 16, 1, 1), 15374),
+            (set_gen.dense(1, 32, 16, 8), 2393),
+            (set_gen.random(1024), 4113),
+            (set_gen.random_max(4096, 65536), 5666),
+            (set_gen.random_max(61440, 65536), 8225),
+        ];
+
+        for (set, expected) in cases {
+            let mut splinter = Splinter::from_iter(set.iter().copied());
+            splinter.optimize();
+            assert_eq!(splinter.encoded_features(), Features::EMPTY);
+            assert_eq!(splinter.encoded_size(), expected);
+            assert_eq!(splinter.encode_to_bytes().len(), expected);
+
+            // optimizing for every feature and falling back to the default
+            // kinds doesn't beat optimizing for the default encoder
+            let mut splinter = Splinter::from_iter(set.iter().copied());
+            splinter.optimize_with(Features::SUPPORTED);
+            assert!(splinter.encoded_size() >= expected);
+            assert_eq!(splinter.encode_to_bytes().len(), splinter.encoded_size());
+        }
+    }
+
     #[test]
     fn test_expected_compression() {
         fn to_roaring(set: impl Iterator<Item = u32>) -> Vec<u8> {
@@ -766,6 +1073,8 @@ mod tests {
             baseline: usize,
             //        (actual, expected)
             splinter: (usize, usize),
+            // encoded with every optional feature enabled
+            splinter_all: (usize, usize),
             roaring: (usize, usize),
 
             splinter_lz4: usize,
@@ -778,10 +1087,12 @@ mod tests {
                             set: Vec<u32>,
                             expected_set_size: usize,
                             expected_splinter: usize,
+                            expected_splinter_all: usize,
                             expected_roaring: usize| {
             assert_eq!(set.len(), expected_set_size, "Set size mismatch");
 
             let mut splinter = Splinter::from_iter(set.clone());
+            let mut splinter_all = splinter.clone();
             splinter.optimize();
             itertools::assert_equal(splinter.iter(), set.iter().copied());
 
@@ -796,6 +1107,17 @@ mod tests {
                 "actual encoded size does not match declared encoded size"
             );
 
+            splinter_all.optimize_with(Features::SUPPORTED);
+            itertools::assert_equal(splinter_all.iter(), set.iter().copied());
+            let expected_size = splinter_all.encoded_size_with(Features::SUPPORTED);
+            let splinter_all = encode_with_features(&splinter_all, Features::SUPPORTED);
+
+            assert_eq!(
+                splinter_all.len(),
+                expected_size,
+                "actual encoded size does not match declared encoded size"
+            );
+
             let roaring = to_roaring(set.iter().copied());
 
             let splinter_lz4 = lz4::block::compress(&splinter, None, false).unwrap();
@@ -815,6 +1137,7 @@ mod tests {
                 name: name.to_owned(),
                 baseline: set.len() * std::mem::size_of::<u32>(),
                 splinter: (splinter.len(), expected_splinter),
+                splinter_all: (splinter_all.len(), expected_splinter_all),
                 roaring: (roaring.len(), expected_roaring),
 
                 splinter_lz4: splinter_lz4.len(),
@@ -825,130 +1148,134 @@ mod tests {
         let mut set_gen = SetGen::new(0xDEAD_BEEF);
 
         // empty splinter
-        run_test("empty", vec![], 0, 13, 8);
+        run_test("empty", vec![], 0, 13, 13, 8);
 
         // 1 element in set
         let set = set_gen.distributed(1, 1, 1, 1);
-        run_test("1 element", set, 1, 21, 18);
+        run_test("1 element", set, 1, 21, 21, 18);
 
         // 1 fully dense block
         let set = set_gen.distributed(1, 1, 1, 256);
-        run_test("1 dense block", set, 256, 25, 15);
+        run_test("1 dense block", set, 256, 25, 23, 15);
 
         // 1 half full block
         let set = set_gen.distributed(1, 1, 1, 128);
-        run_test("1 half full block", set, 128, 72, 255);
+        run_test("1 half full block", set, 128, 72, 55, 255);
 
         // 1 sparse block
         let set = set_gen.distributed(1, 1, 1, 16);
-        run_test("1 sparse block", set, 16, 57, 48);
+        run_test("1 sparse block", set, 16, 57, 40, 48);
 
         // 8 half full blocks
         let set = set_gen.distributed(1, 1, 8, 128);
-        run_test("8 half full blocks", set, 1024, 338, 2003);
+        run_test("8 half full blocks", set, 1024, 338, 326, 2003);
 
         // 8 sparse blocks
         let set = set_gen.distributed(1, 1, 8, 2);
-        run_test("8 sparse blocks", set, 16, 67, 48);
+        run_test("8 sparse blocks", set, 16, 67, 51, 48);
 
         // 64 half full blocks
         let set = set_gen.distributed(4, 4, 4, 128);
-        run_test("64 half full blocks", set, 8192, 2634, 16452);
+        run_test("64 half full blocks", set, 8192, 2634, 2634, 16452);
 
         // 64 sparse blocks
         let set = set_gen.distributed(4, 4, 4, 2);
-        run_test("64 sparse blocks", set, 128, 450, 392);
+        run_test("64 sparse blocks", set, 128, 450, 280, 392);
 
         // 256 half full blocks
         let set = set_gen.distributed(4, 8, 8, 128);
-        run_test("256 half full blocks", set, 32768, 10074, 65580);
+        run_test("256 half full blocks", set, 32768, 10074, 10074, 65580);
 
         // 256 sparse blocks
         let set = set_gen.distributed(4, 8, 8, 2);
-        run_test("256 sparse blocks", set, 512, 1402, 1288);
+        run_test("256 sparse blocks", set, 512, 1402, 940, 1288);
 
         // 512 half full blocks
         let set = set_gen.distributed(8, 8, 8, 128);
-        run_test("512 half full blocks", set, 65536, 20134, 130810);
+        run_test("512 half full blocks", set, 65536, 20134, 20134, 130810);
 
         // 512 sparse blocks
         let set = set_gen.distributed(8, 8, 8, 2);
-        run_test("512 sparse blocks", set, 1024, 2790, 2568);
+        run_test("512 sparse blocks", set, 1024, 2790, 1878, 2568);
 
         // the rest of the compression tests use 4k elements
         let elements = 4096;
 
         // fully dense splinter
         let set = set_gen.distributed(1, 1, 16, 256);
-        run_test("fully dense", set, elements, 87, 63);
+        run_test("fully dense", set, elements, 87, 75, 63);
 
         // 128 elements per block; dense partitions
         let set = set_gen.distributed(1, 1, 32, 128);
-        run_test("128/block; dense", set, elements, 1250, 8208);
+        run_test("128/block; dense", set, elements, 1250, 1173, 8208);
 
         // 32 elements per block; dense partitions
         let set = set_gen.distributed(1, 1, 128, 32);
-        run_test("32/block; dense", set, elements, 4802, 8208);
+        run_test("32/block; dense", set, elements, 4802, 3215, 8208);
 
         // 16 element per block; dense low partitions
         let set = set_gen.distributed(1, 1, 256, 16);
-        run_test("16/block; dense", set, elements, 5666, 8208);
+        run_test("16/block; dense", set, elements, 5666, 3215, 8208);
 
         // 128 elements per block; sparse mid partitions
         let set = set_gen.distributed(1, 32, 1, 128);
-        run_test("128/block; sparse mid", set, elements, 1529, 8282);
+        run_test("128/block; sparse mid", set, elements, 1529, 1364, 8282);
 
         // 128 elements per block; sparse high partitions
         let set = set_gen.distributed(32, 1, 1, 128);
-        run_test("128/block; sparse high", set, elements, 1870, 8224);
+        run_test("128/block; sparse high", set, elements, 1870, 1490, 8224);
 
         // 1 element per block; sparse mid partitions
         let set = set_gen.distributed(1, 256, 16, 1);
-        run_test("1/block; sparse mid", set, elements, 10521, 10248);
+        run_test("1/block; sparse mid", set, elements, 10521, 7310, 10248);
 
         // 1 element per block; sparse high partitions
         let set = set_gen.distributed(256, 16, 1, 1);
-        run_test("1/block; sparse high", set, elements, 15374, 40968);
+        run_test("1/block; sparse high", set, elements, 15374, 13159, 40968);
 
         // 1/block; spread low
         let set = set_gen.dense(1, 16, 256, 1);
-        run_test("1/block; spread low", set, elements, 8377, 8328);
+        run_test("1/block; spread low", set, elements, 8377, 5300, 8328);
 
         // each partition is dense
         let set = set_gen.dense(8, 8, 8, 8);
-        run_test("dense throughout", set, elements, 2790, 2700);
+        run_test("dense throughout", set, elements, 2790, 2790, 2700);
 
         // the lowest partitions are dense
         let set = set_gen.dense(1, 1, 64, 64);
-        run_test("dense low", set, elements, 291, 267);
+        run_test("dense low", set, elements, 291, 279, 267);
 
         // the mid and low partitions are dense
         let set = set_gen.dense(1, 32, 16, 8);
-        run_test("dense mid/low", set, elements, 2393, 2376);
+        run_test("dense mid/low", set, elements, 2393, 2388, 2376);
 
         let random_cases = [
             // random sets drawing from the enire u32 range
-            (32, High::MAX_LEN, 145, 328),
-            (256, High::MAX_LEN, 1041, 2544),
-            (1024, High::MAX_LEN, 4113, 10168),
-            (4096, High::MAX_LEN, 15374, 40056),
-            (16384, High::MAX_LEN, 52238, 148656),
-            (65536, High::MAX_LEN, 199694, 461288),
+            (32, High::MAX_LEN, 145, 145, 328),
+            (256, High::MAX_LEN, 1041, 1029, 2544),
+            (1024, High::MAX_LEN, 4113, 3793, 10168),
+            (4096, High::MAX_LEN, 15374, 13120, 40056),
+            (16384, High::MAX_LEN, 52238, 44181, 148656),
+            (65536, High::MAX_LEN, 199694, 151565, 461288),
             // random sets with values < 65536
-            (32, 65536, 99, 80),
-            (256, 65536, 547, 528),
-            (1024, 65536, 2083, 2064),
-            (4096, 65536, 5666, 8208),
-            (65536, 65536, 25, 15),
+            (32, 65536, 99, 75, 80),
+            (256, 65536, 547, 343, 528),
+            (1024, 65536, 2083, 1071, 2064),
+            (4096, 65536, 5666, 3215, 8208),
+            (65536, 65536, 25, 21, 15),
             // small sets with values < 1024
-            (8, 1024, 49, 32),
-            (16, 1024, 67, 48),
-            (32, 1024, 94, 80),
-            (64, 1024, 126, 144),
-            (128, 1024, 183, 272),
+            (8, 1024, 49, 39, 32),
+            (16, 1024, 67, 45, 48),
+            (32, 1024, 94, 59, 80),
+            (64, 1024, 126, 90, 144),
+            (128, 1024, 183, 157, 272),
+            // nearly full sets with values < 65536
+            (61440, 65536, 8225, 8213, 8208),
+            (65280, 65536, 1063, 535, 1039),
         ];
 
-        for (count, max, expected_splinter, expected_roaring) in random_cases {
+        for (count, max, expected_splinter, expected_splinter_all, expected_roaring) in random_cases
+        {
             let name = if max == High::MAX_LEN {
                 format!("random/{count}")
             } else {
@@ -959,6 +1286,7 @@ mod tests {
                 set_gen.random_max(count, max),
                 count,
                 expected_splinter,
+                expected_splinter_all,
                 expected_roaring,
             );
         }
@@ -986,6 +1314,18 @@ mod tests {
                 }
             );
 
+            let diff = report.splinter_all.0 as f64 / report.splinter.0 as f64;
+            let ok_status = if report.splinter_all.0 != report.splinter_all.1 {
+                fail_test = true;
+                "FAIL".into()
+            } else {
+                ratio_to_marks(diff)
+            };
+            println!(
+                "{:30} {:12} {:6} {:10} {:>10.2} {:>10}",
+                "", "Splinter all", report.splinter_all.0, report.splinter_all.1, diff, ok_status
+            );
+
             let diff = report.roaring.0 as f64 / report.splinter.0 as f64;
             let ok_status = if report.roaring.0 != report.roaring.1 {
                 fail_test = true;
@@ -0,0 +1,481 @@
+//! In-place edits of encoded splinters.
+//!
+//! Some edits fit within the existing encoding of a splinter. Setting or
+//! clearing a bit in a Bitmap partition, or in one of the nonzero words of a
+//! sparse bitmap partition, doesn't change its size, and neither does growing
+//! or shrinking a Run partition's run by one value. After patching
+//! the partition, only the cumulative cardinalities of its parent trees and
+//! the footer checksum need to be rewritten. [`SplinterMut`] performs these
+//! edits directly on an encoded buffer, and returns
+//! [`EditErr::NeedsReencode`] when an edit doesn't fit.
+
+use std::fmt::Debug;
+
+use thiserror::Error;
+
+use crate::{
+    PartitionRead, SplinterRef,
+    codec::{DecodeErr, footer::Footer},
+};
+
+/// The error returned by [`SplinterMut`] when an edit doesn't fit within the
+/// existing encoding, for example inserting a value into a Vec partition or
+/// splitting a run in two.
+///
+/// The splinter is left unchanged. Decode it using
+/// [`SplinterRef::decode_to_splinter`], apply the edit, and encode it again.
+#[derive(Debug, Error, Clone, Copy, PartialEq, Eq)]
+#[error("edit doesn't fit the encoded splinter, it must be re-encoded")]
+pub struct NeedsReencode;
+
+/// The error returned by [`SplinterMut`] edits.
+#[derive(Debug, Error)]
+#[non_exhaustive]
+pub enum EditErr {
+    /// The edit doesn't fit within the existing encoding.
+    #[error(transparent)]
+    NeedsReencode(#[from] NeedsReencode),
+
+    /// A partition read by the edit is malformed. The buffer is left
+    /// unchanged.
+    #[error(transparent)]
+    Decode(#[from] DecodeErr),
+}
+
+/// A mutable view of an encoded splinter which supports in-place edits.
+///
+/// Edits which fit the existing encoding patch the buffer, rewrite the
+/// cumulative cardinalities of the enclosing tree partitions, and then
+/// recompute the footer checksum. Every other edit returns
+/// [`EditErr::NeedsReencode`] without modifying the buffer.
+///
+/// Only the footer and checksum are verified when a `SplinterMut` is
+/// created. Each edit validates the partitions it reads, which are the
+/// indexes of the trees on the path to the edited value and the partition
+/// storing it, so its cost doesn't depend on the size of the splinter.
+///
+/// Edits never change the size of the buffer, so the resulting encoding may
+/// be larger than the encoding of an optimized [`Splinter`](crate::Splinter).
+///
+/// # Examples
+///
+/// ```
+/// use splinter_rs::{Encodable, Optimizable, PartitionRead, Splinter, SplinterMut};
+///
+/// let mut splinter = Splinter::from_iter((0..1024).step_by(2));
+/// splinter.optimize();
+/// let mut bytes = splinter.encode_to_bytes().to_vec();
+///
+/// let mut splinter_mut = SplinterMut::from_bytes(&mut bytes).unwrap();
+/// assert!(splinter_mut.insert(1).unwrap());
+/// assert!(splinter_mut.remove(2).unwrap());
+/// assert!(!splinter_mut.remove(2).unwrap());
+///
+/// let splinter_ref = splinter_mut.splinter_ref();
+/// assert_eq!(splinter_ref.cardinality(), 512);
+/// assert!(splinter_ref.contains(1));
+/// assert!(!splinter_ref.contains(2));
+///
+/// // a new block doesn't fit in the encoded splinter
+/// assert!(splinter_mut.insert(1 << 20).is_err());
+/// ```
+pub struct SplinterMut<'a> {
+    data: &'a mut [u8],
+}
+
+impl<'a> SplinterMut<'a> {
+    /// Creates a `SplinterMut` from an encoded splinter, performing the same
+    /// checks as [`SplinterRef::from_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// Returns any error returned by [`SplinterRef::from_bytes`].
+    pub fn from_bytes(data: &'a mut [u8]) -> Result<Self, DecodeErr> {
+        SplinterRef::from_bytes(&*data)?;
+        Ok(Self { data })
+    }
+
+    /// Returns a read-only view of the splinter.
+    #[inline]
+    pub fn splinter_ref(&self) -> SplinterRef<&[u8]> {
+        SplinterRef { data: self.data }
+    }
+
+    /// Consumes the `SplinterMut` and returns the underlying buffer.
+    #[inline]
+    pub fn into_inner(self) -> &'a mut [u8] {
+        self.data
+    }
+
+    /// Inserts `value` in place, returning `Ok(true)` if the value was added
+    /// and `Ok(false)` if it was already present.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EditErr::NeedsReencode`] if the value can't be inserted
+    /// without changing the size of the encoding, or [`EditErr::Decode`] if
+    /// a partition on the path to the value is malformed.
+    pub fn insert(&mut self, value: u32) -> Result<bool, EditErr> {
+        self.edit(value, true)
+    }
+
+    /// Removes `value` in place, returning `Ok(true)` if the value was
+    /// removed and `Ok(false)` if it wasn't present.
+    ///
+    /// # Errors
+    ///
+    /// Returns [`EditErr::NeedsReencode`] if the value can't be removed
+    /// without changing the size of the encoding, or [`EditErr::Decode`] if
+    /// a partition on the path to the value is malformed.
+    pub fn remove(&mut self, value: u32) -> Result<bool, EditErr> {
+        self.edit(value, false)
+    }
+
+    fn edit(&mut self, value: u32, insert: bool) -> Result<bool, EditErr> {
+        let mut patches = Patches::new(self.data);
+        let splinter_ref = self.splinter_ref();
+        let root = splinter_ref.load_unchecked();
+        // patches are planned from the tree indexes, which the checksum
+        // doesn't vouch for
+        root.validate_path(value)?;
+        if root.contains(value) == insert {
+            return Ok(false);
+        }
+        root.plan_edit(value, insert, &mut patches)?;
+
+        patches.apply(self.data);
+        Footer::update_checksum(self.data);
+        Ok(true)
+    }
+}
+
+impl<'a> From<SplinterRef<&'a mut [u8]>> for SplinterMut<'a> {
+    /// Converts a `SplinterRef` into a `SplinterMut`, which has already
+    /// passed the checks performed by [`SplinterMut::from_bytes`].
+    fn from(value: SplinterRef<&'a mut [u8]>) -> Self {
+        Self { data: value.data }
+    }
+}
+
+impl Debug for SplinterMut<'_> {
+    fn fmt(&self, f: &mut std::fmt::Formatter<'_>) -> std::fmt::Result {
+        f.debug_tuple("SplinterMut")
+            .field(&self.splinter_ref().load_unchecked())
+            .finish()
+    }
+}
+
+/// A single in-place edit of an encoded splinter.
+#[derive(Debug, Clone, Copy, PartialEq, Eq)]
+enum Patch {
+    /// toggles the bits of `mask` in the byte at `offset`
+    Toggle { offset: usize, mask: u8 },
+    /// adds `delta` to the big-endian integer stored at `offset..offset+width`
+    Add {
+        offset: usize,
+        width: usize,
+        delta: i64,
+    },
+}
+
+/// The patches needed to apply an edit, which are planned while reading the
+/// buffer and applied once planning succeeds. Patches locate bytes by their
+/// offset from the start of the buffer.
+pub(crate) struct Patches {
+    base: usize,
+    patches: Vec<Patch>,
+}
+
+impl Patches {
+    fn new(data: &[u8]) -> Self {
+        Self {
+            base: data.as_ptr().addr(),
+            patches: Vec::new(),
+        }
+    }
+
+    /// Returns the offset of `bytes` from the start of the buffer.
+    fn offset_of(&self, bytes: &[u8]) -> usize {
+        bytes.as_ptr().addr() - self.base
+    }
+
+    /// Toggles bit `idx` of the bitmap stored in `bitmap`, which must point
+    /// into the buffer.
+    pub(crate) fn toggle_bit(&mut self, bitmap: &[u8], idx: usize) {
+        let offset = self.offset_of(bitmap) + idx / 8;
+        self.patches
+            .push(Patch::Toggle { offset, mask: 1 << (idx % 8) });
+    }
+
+    /// Adds `delta` to the big-endian integer stored in `value`, which must
+    /// point into the buffer.
+    pub(crate) fn add(&mut self, value: &[u8], delta: i64) {
+        let offset = self.offset_of(value);
+        self.patches
+            .push(Patch::Add { offset, width: value.len(), delta });
+    }
+
+    fn apply(&self, data: &mut [u8]) {
+        for &patch in &self.patches {
+            match patch {
+                Patch::Toggle { offset, mask } => data[offset] ^= mask,
+                Patch::Add { offset, width, delta } => {
+                    let bytes = &mut data[offset..offset + width];
+                    let value = bytes.iter().fold(0u64, |acc, &b| (acc << 8) | u64::from(b));
+                    let value = value
+                        .checked_add_signed(delta)
+                        .expect("BUG: patched value out of range");
+                    debug_assert!(value >> (8 * width) == 0, "BUG: patched value overflow");
+                    bytes.copy_from_slice(&value.to_be_bytes()[(8 - width)..]);
+                }
+            }
+        }
+    }
+}
+
+#[cfg(test)]
+mod test {
+    use assert_matches::assert_matches;
+    use itertools::Itertools;
+    use proptest::{collection::vec, proptest};
+
+    use crate::{
+        Encodable, Optimizable, PartitionRead, PartitionWrite, Splinter, SplinterMut, SplinterRef,
+        codec::{
+            DecodeErr, checksum::Checksum, encoder::Encoder, features::Features,
+            partition_ref::PartitionRef,
+        },
+        level::High,
+        partition_kind::PartitionKind,
+        splinter_mut::EditErr,
+        testutil::{encode_with_features, mkpartition_buf, mksplinter_manual},
+    };
+
+    /// Encodes `values` as a splinter, optimizing it if `optimize` is true.
+    /// The encoder supports every optional feature, so that the optimized
+    /// kinds are kept.
+    fn encode(values: impl IntoIterator<Item = u32>, optimize: bool) -> Vec<u8> {
+        let mut splinter = Splinter::from_iter(values);
+        if optimize {
+            splinter.optimize_with(Features::SUPPORTED);
+        }
+        encode_with_features(&splinter, Features::SUPPORTED).to_vec()
+    }
+
+    #[test]
+    fn test_splinter_mut_bitmap() {
+        // every other value in the first 16 blocks, stored as bitmaps
+        let mut bytes = encode((0..4096).step_by(2), true);
+        let mut splinter_mut = SplinterMut::from_bytes(&mut bytes).unwrap();
+        let mut expected = Splinter::from_iter((0..4096).step_by(2));
+
+        for value in [1, 3, 4095, 2048, 2049, 0, 4094] {
+            let insert = !expected.contains(value);
+            let changed = if insert {
+                expected.insert(value);
+                splinter_mut.insert(value)
+            } else {
+                expected.remove(value);
+                splinter_mut.remove(value)
+            };
+            assert!(changed.unwrap(), "value {value}");
+            assert_eq!(splinter_mut.splinter_ref(), expected);
+        }
+
+        // no-ops succeed without changing the buffer
+        let before = splinter_mut.splinter_ref().inner().to_vec();
+        assert!(!splinter_mut.insert(1).unwrap());
+        assert!(!splinter_mut.remove(5000).unwrap());
+        assert_eq!(*splinter_mut.splinter_ref().inner(), &before[..]);
+
+        // the checksum and cardinalities are rewritten
+        let bytes = splinter_mut.into_inner();
+        let splinter_ref = SplinterRef::from_bytes_validated(&*bytes).unwrap();
+        assert_eq!(splinter_ref.cardinality(), expected.cardinality());
+        assert_eq!(
+            splinter_ref.iter().collect_vec(),
+            expected.iter().collect_vec()
+        );
+    }
+
+    #[test]
+    fn test_splinter_mut_runs() {
+        let runs = (10..20).chain(30..40).chain(1000..2000);
+        let mut bytes = encode(runs.clone(), true);
+        let mut splinter_mut = SplinterMut::from_bytes(&mut bytes).unwrap();
+
+        // runs can grow and shrink at either end
+        assert!(splinter_mut.insert(20).unwrap());
+        assert!(splinter_mut.insert(9).unwrap());
+        assert!(splinter_mut.remove(1999).unwrap());
+        assert!(splinter_mut.remove(1000).unwrap());
+
+        // but splitting, merging, adding or dropping runs doesn't fit
+        assert_matches!(splinter_mut.remove(1500), Err(EditErr::NeedsReencode(_)));
+        assert_matches!(splinter_mut.insert(25), Err(EditErr::NeedsReencode(_)));
+        assert!(splinter_mut.insert(29).unwrap());
+        assert!(splinter_mut.insert(21).unwrap());
+        assert!(splinter_mut.insert(22).unwrap());
+        assert!(splinter_mut.insert(28).unwrap());
+        assert!(splinter_mut.insert(23).unwrap());
+        assert!(splinter_mut.insert(27).unwrap());
+        assert!(splinter_mut.insert(24).unwrap());
+        assert!(splinter_mut.insert(26).unwrap());
+        assert_matches!(splinter_mut.insert(25), Err(EditErr::NeedsReencode(_)));
+
+        let expected = Splinter::from_iter((9..25).chain(26..40).chain(1001..1999));
+        let bytes = splinter_mut.into_inner();
+        let splinter_ref = SplinterRef::from_bytes_validated(&*bytes).unwrap();
+        assert_eq!(splinter_ref, expected);
+    }
+
+    #[test]
+    fn test_splinter_mut_sparse_bitmap() {
+        // clustered values in every eighth word, plus a word with one value
+        let values = (0..65536)
+            .step_by(3)
+            .filter(|v| (v / 64) % 8 == 0)
+            .chain([4161]);
+        let mut bytes = encode(values.clone(), true);
+        let mut splinter_mut = SplinterMut::from_bytes(&mut bytes).unwrap();
+        let mut expected = Splinter::from_iter(values);
+
+        // bits in stored words can be toggled
+        assert!(splinter_mut.insert(1).unwrap());
+        assert!(splinter_mut.remove(3).unwrap());
+        expected.insert(1);
+        expected.remove(3);
+        assert_eq!(splinter_mut.splinter_ref(), expected);
+
+        // but words can't be added or removed
+        assert_matches!(splinter_mut.insert(64), Err(EditErr::NeedsReencode(_)));
+        assert_matches!(splinter_mut.remove(4161), Err(EditErr::NeedsReencode(_)));
+
+        let bytes = splinter_mut.into_inner();
+        let splinter_ref = SplinterRef::from_bytes_validated(&*bytes).unwrap();
+        assert_eq!(splinter_ref, expected);
+    }
+
+    #[test]
+    fn test_splinter_mut_needs_reencode() {
+        // Vec partitions can't grow or shrink in place
+        let mut bytes = encode([1, 5, 9], true);
+        let original = bytes.clone();
+        let mut splinter_mut = SplinterMut::from_bytes(&mut bytes).unwrap();
+        assert_matches!(splinter_mut.insert(3), Err(EditErr::NeedsReencode(_)));
+        assert_matches!(splinter_mut.remove(5), Err(EditErr::NeedsReencode(_)));
+        assert!(!splinter_mut.insert(5).unwrap());
+        assert_eq!(bytes, original);
+
+        // tree children can't be added or removed
+        let mut bytes = encode((0..4096).step_by(2).chain([1 << 20]), true);
+        let mut splinter_mut = SplinterMut::from_bytes(&mut bytes).unwrap();
+        assert_matches!(splinter_mut.insert(2 << 20), Err(EditErr::NeedsReencode(_)));
+        assert_matches!(splinter_mut.remove(1 << 20), Err(EditErr::NeedsReencode(_)));
+
+        // empty and full splinters
+        let mut bytes = Splinter::EMPTY.encode_to_bytes().to_vec();
+        let mut splinter_mut = SplinterMut::from_bytes(&mut bytes).unwrap();
+        assert_matches!(splinter_mut.insert(1), Err(EditErr::NeedsReencode(_)));
+        assert!(!splinter_mut.remove(1).unwrap());
+        let mut bytes = Splinter::FULL.encode_to_bytes().to_vec();
+        let mut splinter_mut = SplinterMut::from_bytes(&mut bytes).unwrap();
+        assert!(!splinter_mut.insert(1).unwrap());
+        assert_matches!(splinter_mut.remove(1), Err(EditErr::NeedsReencode(_)));
+    }
+
+    #[test]
+    fn test_splinter_mut_checksums() {
+        for checksum in Checksum::enabled() {
+            let mut splinter = Splinter::from_iter(0..1000);
+            splinter.optimize();
+            let mut encoder = Encoder::new(Vec::new()).with_checksum(checksum);
+            splinter.encode(&mut encoder);
+            let mut bytes = encoder.into_inner();
+
+            let mut splinter_mut = SplinterMut::from_bytes(&mut bytes).unwrap();
+            assert!(splinter_mut.remove(999).unwrap());
+            let splinter_ref = SplinterRef::from_bytes(&bytes[..]).unwrap();
+            assert_eq!(splinter_ref, Splinter::from_iter(0..999));
+        }
+    }
+
+    #[test]
+    fn test_splinter_mut_corrupted() {
+        let mut buf = mkpartition_buf::<High>(PartitionKind::Tree, &[1, 2]);
+        // point the child offset past the start of the buffer, which the
+        // checksum doesn't detect
+        buf[13] = 0x20;
+        let mut corrupted = mksplinter_manual(&buf).to_vec();
+        let original = corrupted.clone();
+
+        // the tree index is only validated by edits
+        let mut splinter_mut = SplinterMut::from_bytes(&mut corrupted).unwrap();
+        assert_matches!(
+            splinter_mut.insert(3),
+            Err(EditErr::Decode(DecodeErr::Length))
+        );
+        assert_eq!(corrupted, original);
+    }
+
+    #[test]
+    fn test_splinter_mut_skips_other_children() {
+        let values = (0..100).chain((1 << 24)..(1 << 24 | 100)).collect_vec();
+        let mut buf = mkpartition_buf::<High>(PartitionKind::Tree, &values);
+        let PartitionRef::Tree(tree) = PartitionRef::<High>::from_suffix(&buf).unwrap() else {
+            panic!("expected a tree root");
+        };
+        // corrupt the kind of the first child, which the checksum doesn't
+        // detect
+        let end = tree.children_len() - tree.child_end_offset(0);
+        buf[end - 1] = 0xFF;
+        let mut corrupted = mksplinter_manual(&buf).to_vec();
+
+        // edits only validate the children they read
+        let mut splinter_mut = SplinterMut::from_bytes(&mut corrupted).unwrap();
+        assert!(splinter_mut.remove(1 << 24 | 99).unwrap());
+        assert_matches!(splinter_mut.remove(99), Err(EditErr::Decode(_)));
+        let splinter_ref = splinter_mut.splinter_ref();
+        assert_eq!(splinter_ref.cardinality(), 199);
+        assert!(!splinter_ref.contains(1 << 24 | 99));
+    }
+
+    proptest! {
+        #[test]
+        fn test_splinter_mut_proptest(
+            values in vec(0u32..16384, 0..1024),
+            edits in vec((0u32..16384, proptest::bool::ANY), 0..64),
+            optimize: bool,
+        ) {
+            let mut expected = Splinter::from_iter(values.iter().copied());
+            let mut bytes = encode(values, optimize);
+            let mut splinter_mut = SplinterMut::from_bytes(&mut bytes).unwrap();
+
+            for (value, insert) in edits {
+                let before = splinter_mut.splinter_ref().inner().to_vec();
+                let result = if insert {
+                    splinter_mut.insert(value)
+                } else {
+                    splinter_mut.remove(value)
+                };
+                match result {
+                    Ok(changed) => {
+                        let expected_changed = if insert {
+                            expected.insert(value)
+                        } else {
+                            expected.remove(value)
+                        };
+                        assert_eq!(changed, expected_changed);
+                    }
+                    Err(EditErr::NeedsReencode(_)) => {
+                        assert_eq!(*splinter_mut.splinter_ref().inner(), &before[..]);
+                    }
+                    Err(err) => panic!("unexpected error: {err}"),
+                }
+                assert_eq!(splinter_mut.splinter_ref(), expected);
+            }
+
+            SplinterRef::from_bytes_validated(splinter_mut.into_inner()).unwrap();
+        }
+    }
+}
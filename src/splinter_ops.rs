@@ -17,6 +17,7 @@ impl<B: Deref<Target = [u8]>> PartialEq<CowSplinter<B>> for Splinter {
         match other {
             CowSplinter::Ref(splinter_ref) => self.eq(splinter_ref),
             CowSplinter::Owned(splinter) => self.eq(splinter),
+            CowSplinter::Patched(patched) => patched.eq_values(self),
         }
     }
 }
@@ -44,6 +45,7 @@ impl<B: Deref<Target = [u8]>> Cut<CowSplinter<B>> for Splinter {
         match rhs {
             CowSplinter::Ref(splinter_ref) => self.cut(splinter_ref),
             CowSplinter::Owned(splinter) => self.cut(splinter),
+            CowSplinter::Patched(patched) => Self::new(self.inner_mut().cut(patched)),
         }
     }
 }
@@ -123,6 +125,11 @@ macro_rules! binary_bitop {
                 match rhs {
                     CowSplinter::Ref(inner) => $BitOp::$bitop(self, inner),
                     CowSplinter::Owned(inner) => $BitOp::$bitop(self, inner),
+                    CowSplinter::Patched(inner) => {
+                        let mut out = self.clone();
+                        $bitassign(out.inner_mut(), inner);
+                        out
+                    }
                 }
             }
         }
@@ -154,6 +161,9 @@ macro_rules! unary_bitassign {
                 match rhs {
                     CowSplinter::Ref(splinter_ref) => $BitOpAssign::$bitassign(self, splinter_ref),
                     CowSplinter::Owned(splinter) => $BitOpAssign::$bitassign(self, splinter),
+                    CowSplinter::Patched(patched) => {
+                        $BitOpAssign::$bitassign(self.inner_mut(), &patched)
+                    }
                 }
             }
         }
@@ -162,6 +172,9 @@ macro_rules! unary_bitassign {
                 match rhs {
                     CowSplinter::Ref(splinter_ref) => $BitOpAssign::$bitassign(self, splinter_ref),
                     CowSplinter::Owned(splinter) => $BitOpAssign::$bitassign(self, splinter),
+                    CowSplinter::Patched(patched) => {
+                        $BitOpAssign::$bitassign(self.inner_mut(), patched)
+                    }
                 }
             }
         }
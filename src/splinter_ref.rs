@@ -1,20 +1,19 @@
 use std::{
     fmt::Debug,
+    iter::FusedIterator,
     ops::{Deref, RangeBounds},
 };
 
 use bytes::Bytes;
-use zerocopy::FromBytes;
 
 use crate::{
     Splinter,
     codec::{
-        DecodeErr, Encodable,
-        encoder::Encoder,
-        footer::{Footer, SPLINTER_V2_MAGIC},
+        DecodeErr, DecodeLimit, DecodeOptions, Encodable, LocatedDecodeErr, checksum::Checksum,
+        compressed, encoder::Encoder, features::Features, footer::Footer, legacy_v1,
         partition_ref::PartitionRef,
     },
-    level::High,
+    level::{Block, High, Level},
     traits::PartitionRead,
 };
 
@@ -154,17 +153,262 @@ impl SplinterRef<Bytes> {
     pub fn encode_to_bytes(&self) -> Bytes {
         self.data.clone()
     }
+
+    /// Splits the last splinter from the end of a buffer of concatenated
+    /// splinters, returning the remaining bytes and a `SplinterRef` which
+    /// shares the buffer. See [`SplinterRef::split_last`] for details.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bytes::BytesMut;
+    /// use splinter_rs::{Encodable, Splinter, SplinterRef, codec::encoder::Encoder};
+    ///
+    /// let mut buf = BytesMut::new();
+    /// for values in [0..10, 1000..2000] {
+    ///     let mut encoder = Encoder::new(&mut buf).with_encoded_length();
+    ///     Splinter::from_iter(values).encode(&mut encoder);
+    /// }
+    ///
+    /// let (rest, last) = SplinterRef::split_last_bytes(buf.freeze()).unwrap();
+    /// assert_eq!(last, Splinter::from_iter(1000..2000));
+    /// let (rest, first) = SplinterRef::split_last_bytes(rest).unwrap();
+    /// assert_eq!(first, Splinter::from_iter(0..10));
+    /// assert!(rest.is_empty());
+    /// ```
+    pub fn split_last_bytes(mut data: Bytes) -> Result<(Bytes, Self), DecodeErr> {
+        let (rest, _) = Footer::split_last(&data)?;
+        let splinter = data.split_off(rest.len());
+        Ok((data, Self::from_bytes(splinter)?))
+    }
+
+    /// Creates a `SplinterRef` from bytes which may contain a splinter
+    /// wrapped in a compressed envelope, such as the output of
+    /// [`Encodable::encode_compressed`].
+    ///
+    /// Compressed splinters are decompressed into a new buffer, while
+    /// uncompressed splinters are used in place exactly like
+    /// [`SplinterRef::from_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// Returns any error returned by [`compressed::decompress`] or
+    /// [`SplinterRef::from_bytes`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Encodable, PartitionRead, Splinter, SplinterRef};
+    ///
+    /// let splinter = Splinter::from_iter((0..1_000_000).step_by(256));
+    /// let bytes = splinter.encode_compressed();
+    ///
+    /// let splinter_ref = SplinterRef::from_compressed_bytes(bytes).unwrap();
+    /// assert_eq!(splinter_ref.cardinality(), splinter.cardinality());
+    /// ```
+    #[cfg(feature = "lz4")]
+    pub fn from_compressed_bytes(data: Bytes) -> Result<Self, DecodeErr> {
+        if compressed::is_compressed(&data) {
+            Self::from_bytes(compressed::decompress(&data)?)
+        } else {
+            Self::from_bytes(data)
+        }
+    }
+}
+
+impl<'a> SplinterRef<&'a [u8]> {
+    /// Splits the last splinter from the end of a buffer of concatenated
+    /// splinters, returning the remaining bytes and a `SplinterRef` to the
+    /// last splinter.
+    ///
+    /// Encoded splinters are decoded from their end, so a buffer of
+    /// concatenated splinters can only be split if every splinter records
+    /// its length, see [`Encoder::with_encoded_length`]. The last splinter is
+    /// validated in the same way as [`SplinterRef::from_bytes`].
+    ///
+    /// # Errors
+    ///
+    /// Returns [`DecodeErr::MissingLength`] if the last splinter doesn't
+    /// record its length, as well as any error returned by
+    /// [`SplinterRef::from_bytes`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bytes::BytesMut;
+    /// use splinter_rs::{Encodable, Splinter, SplinterRef, codec::encoder::Encoder};
+    ///
+    /// let mut buf = BytesMut::new();
+    /// for values in [0..10, 1000..2000] {
+    ///     let mut encoder = Encoder::new(&mut buf).with_encoded_length();
+    ///     Splinter::from_iter(values).encode(&mut encoder);
+    /// }
+    ///
+    /// let (rest, last) = SplinterRef::split_last(&buf).unwrap();
+    /// assert_eq!(last, Splinter::from_iter(1000..2000));
+    /// let (rest, first) = SplinterRef::split_last(rest).unwrap();
+    /// assert_eq!(first, Splinter::from_iter(0..10));
+    /// assert!(rest.is_empty());
+    /// ```
+    pub fn split_last(data: &'a [u8]) -> Result<(&'a [u8], Self), DecodeErr> {
+        let (rest, splinter) = Footer::split_last(data)?;
+        Ok((rest, Self::from_bytes(splinter)?))
+    }
+
+    /// Lazily splits a buffer of concatenated splinters into its splinters,
+    /// starting from the last splinter written. Every splinter must record
+    /// its length, see [`SplinterRef::split_last`].
+    ///
+    /// Splinters are decoded from their end, so they can only be split from
+    /// the back of the buffer. Each step costs the same as
+    /// [`SplinterRef::split_last`], and the iterator stops after the first
+    /// error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bytes::BytesMut;
+    /// use splinter_rs::{Encodable, PartitionRead, Splinter, SplinterRef, codec::encoder::Encoder};
+    ///
+    /// let mut buf = BytesMut::new();
+    /// for i in 0..4 {
+    ///     let mut encoder = Encoder::new(&mut buf).with_encoded_length();
+    ///     Splinter::from_iter(0..i).encode(&mut encoder);
+    /// }
+    ///
+    /// let mut splinters = SplinterRef::rsplit(&buf);
+    /// assert_eq!(splinters.next().unwrap().unwrap().cardinality(), 3);
+    /// assert_eq!(splinters.next().unwrap().unwrap().cardinality(), 2);
+    ///
+    /// // the first two splinters haven't been split yet
+    /// let rest = SplinterRef::split_all_vec(splinters.remainder()).unwrap();
+    /// assert_eq!(rest.len(), 2);
+    /// ```
+    pub fn rsplit(data: &'a [u8]) -> RSplit<'a> {
+        RSplit { data }
+    }
+
+    /// Eagerly splits a buffer of concatenated splinters into all of its
+    /// splinters, in the order they were written. Every splinter must record
+    /// its length, see [`SplinterRef::split_last`].
+    ///
+    /// Splinters can only be split from the back of the buffer, so this
+    /// collects every splinter into a `Vec` before reversing it, allocating
+    /// space for one `SplinterRef` per splinter. An error in any splinter is
+    /// reported before any splinter is returned. Use [`SplinterRef::rsplit`]
+    /// to split the buffer lazily and without allocating instead.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use bytes::BytesMut;
+    /// use splinter_rs::{Encodable, PartitionRead, Splinter, SplinterRef, codec::encoder::Encoder};
+    ///
+    /// let mut buf = BytesMut::new();
+    /// for i in 0..4 {
+    ///     let mut encoder = Encoder::new(&mut buf).with_encoded_length();
+    ///     Splinter::from_iter(0..i).encode(&mut encoder);
+    /// }
+    ///
+    /// let splinters = SplinterRef::split_all_vec(&buf).unwrap();
+    /// let cardinalities: Vec<_> = splinters.iter().map(|s| s.cardinality()).collect();
+    /// assert_eq!(cardinalities, [0, 1, 2, 3]);
+    /// ```
+    pub fn split_all_vec(data: &'a [u8]) -> Result<Vec<Self>, DecodeErr> {
+        let mut splinters = Self::rsplit(data).collect::<Result<Vec<_>, _>>()?;
+        splinters.reverse();
+        Ok(splinters)
+    }
+}
+
+/// An iterator over the splinters in a buffer of concatenated splinters,
+/// from the last splinter to the first. See [`SplinterRef::rsplit`].
+#[derive(Debug, Clone)]
+pub struct RSplit<'a> {
+    data: &'a [u8],
+}
+
+impl<'a> RSplit<'a> {
+    /// Returns the bytes which haven't been split yet.
+    pub fn remainder(&self) -> &'a [u8] {
+        self.data
+    }
+}
+
+impl<'a> Iterator for RSplit<'a> {
+    type Item = Result<SplinterRef<&'a [u8]>, DecodeErr>;
+
+    fn next(&mut self) -> Option<Self::Item> {
+        if self.data.is_empty() {
+            return None;
+        }
+        match SplinterRef::split_last(self.data) {
+            Ok((rest, splinter)) => {
+                self.data = rest;
+                Some(Ok(splinter))
+            }
+            Err(err) => {
+                // the remaining bytes can't be split any further
+                self.data = &[];
+                Some(Err(err))
+            }
+        }
+    }
 }
 
+impl FusedIterator for RSplit<'_> {}
+
 impl<B: Deref<Target = [u8]>> Encodable for SplinterRef<B> {
     #[inline]
     fn encoded_size(&self) -> usize {
-        self.data.len()
+        self.encoded_size_with(Features::EMPTY)
+    }
+
+    fn encoded_size_with(&self, features: Features) -> usize {
+        let (partitions, footer) = Footer::split_unchecked(&self.data);
+        let used = footer.features.difference(Features::ENCODED_LENGTH);
+        if features.contains(used) {
+            partitions.len() + Footer::new(Checksum::default(), used).encoded_size()
+        } else {
+            self.decode_to_splinter().encoded_size_with(features)
+        }
     }
 
     #[inline]
     fn encode<T: bytes::BufMut>(&self, encoder: &mut Encoder<T>) {
-        encoder.write_splinter(&self.data);
+        let (partitions, footer) = Footer::split_unchecked(&self.data);
+        // the encoded length is stored in the footer rather than the partitions
+        let features = footer.features.difference(Features::ENCODED_LENGTH);
+        let misaligned = encoder.aligns_bitmaps()
+            && !self
+                .load_unchecked()
+                .bitmaps_aligned(&self.data, encoder.position());
+        if !encoder.supports(features) || misaligned {
+            // re-encode the splinter using the encoder's feature set and
+            // alignment
+            self.decode_to_splinter().encode(encoder);
+        } else if encoder.checksum() != footer.checksum
+            || encoder.encodes_length() != footer.features.contains(Features::ENCODED_LENGTH)
+        {
+            encoder.write_partitions(partitions, features);
+            encoder.write_footer();
+        } else {
+            encoder.write_splinter(&self.data);
+        }
+    }
+
+    #[inline]
+    fn encoded_features(&self) -> Features {
+        self.features()
+    }
+
+    fn encoded_features_with(&self, features: Features) -> Features {
+        let used = self.features().difference(Features::ENCODED_LENGTH);
+        if features.contains(used) {
+            used
+        } else {
+            self.decode_to_splinter().encoded_features_with(features)
+        }
     }
 }
 
@@ -205,8 +449,9 @@ impl<B: Deref<Target = [u8]>> SplinterRef<B> {
     ///
     /// IMPORTANT: This method *does not* recursively verify the entire
     /// splinter, opting instead to rely on the checksum to detect any
-    /// corruption. Do not use Splinter with untrusted data as it's trivial to
-    /// construct a Splinter which will cause your program to panic at runtime.
+    /// corruption. It's trivial to construct a Splinter with a valid checksum
+    /// which will cause your program to panic at runtime. Use
+    /// [`SplinterRef::from_bytes_validated`] when decoding untrusted data.
     ///
     /// Returns an error if the data is corrupted or in an invalid format.
     ///
@@ -216,7 +461,14 @@ impl<B: Deref<Target = [u8]>> SplinterRef<B> {
     /// - [`DecodeErr::Magic`]: Invalid magic bytes
     /// - [`DecodeErr::Checksum`]: Data corruption detected
     /// - [`DecodeErr::Validity`]: Invalid internal structure
-    /// - [`DecodeErr::SplinterV1`]: Data is from incompatible v1 format
+    /// - [`DecodeErr::SplinterV1`]: Data is from incompatible v1 format, see
+    ///   [`CowSplinter::from_bytes_any_version`](crate::CowSplinter::from_bytes_any_version)
+    /// - [`DecodeErr::UnsupportedFeature`]: Data uses optional features this
+    ///   version doesn't support
+    /// - [`DecodeErr::Compressed`]: Data is a compressed splinter, see
+    ///   `SplinterRef::from_compressed_bytes` (requires the `lz4` feature)
+    /// - [`DecodeErr::Patch`]: Data is a patch, see
+    ///   [`SplinterPatch::from_bytes`](crate::SplinterPatch::from_bytes)
     ///
     /// # Examples
     ///
@@ -236,31 +488,249 @@ impl<B: Deref<Target = [u8]>> SplinterRef<B> {
     /// ```
     /// use splinter_rs::{SplinterRef, codec::DecodeErr};
     ///
-    /// let invalid_bytes = vec![0u8; 5]; // Too short
+    /// let invalid_bytes = vec![0u8; 3]; // Too short
     /// let result = SplinterRef::from_bytes(invalid_bytes);
     /// assert!(matches!(result.unwrap_err(), DecodeErr::Length));
     /// ```
     pub fn from_bytes(data: B) -> Result<Self, DecodeErr> {
-        pub(crate) const SPLINTER_V1_MAGIC: [u8; 4] = [0xDA, 0xAE, 0x12, 0xDF];
-        if data.len() >= 4
-            && data.starts_with(&SPLINTER_V1_MAGIC)
-            && !data.ends_with(&SPLINTER_V2_MAGIC)
-        {
-            return Err(DecodeErr::SplinterV1);
+        Self::from_bytes_with(data, DecodeOptions::DEFAULT).map_err(DecodeErr::from)
+    }
+
+    /// Creates a `SplinterRef` from raw bytes, using the provided
+    /// [`DecodeOptions`] to control how the data is verified and to limit the
+    /// size of the splinters which are accepted.
+    ///
+    /// The limits are checked before this method returns, so any splinter it
+    /// returns can be safely decoded using [`SplinterRef::decode_to_splinter`]
+    /// without exceeding them.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error returned by [`SplinterRef::from_bytes`] or
+    /// [`SplinterRef::validate_deep`], as well as
+    /// [`DecodeErr::LimitExceeded`] if the splinter exceeds one of the limits.
+    /// Errors caused by a malformed partition are located like
+    /// [`SplinterRef::validate_deep_located`], and convert into a
+    /// [`DecodeErr`] using `?`.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{
+    ///     Splinter, SplinterRef, PartitionRead, Encodable,
+    ///     codec::{DecodeErr, DecodeLimit, DecodeOptions, LocatedDecodeErr},
+    /// };
+    ///
+    /// let bytes = Splinter::from_iter(0..1000).encode_to_bytes();
+    ///
+    /// let splinter_ref = SplinterRef::from_bytes_with(bytes.clone(), DecodeOptions::TRUSTED).unwrap();
+    /// assert_eq!(splinter_ref.cardinality(), 1000);
+    ///
+    /// let options = DecodeOptions::UNTRUSTED.with_max_len(8);
+    /// assert!(matches!(
+    ///     SplinterRef::from_bytes_with(bytes, options),
+    ///     Err(LocatedDecodeErr {
+    ///         err: DecodeErr::LimitExceeded { limit: DecodeLimit::Length },
+    ///         location: None,
+    ///         ..
+    ///     })
+    /// ));
+    /// ```
+    pub fn from_bytes_with(data: B, options: DecodeOptions) -> Result<Self, LocatedDecodeErr> {
+        if data.len() > options.max_len() {
+            return Err(DecodeErr::LimitExceeded { limit: DecodeLimit::Length }.into());
+        }
+        if legacy_v1::is_v1(&data) {
+            return Err(DecodeErr::SplinterV1.into());
+        }
+        if compressed::is_compressed(&data) {
+            return Err(DecodeErr::Compressed.into());
         }
 
-        if data.len() < Footer::SIZE {
-            return Err(DecodeErr::Length);
+        let (partitions, footer) = if options.verifies_checksum() {
+            Footer::split(&data)?
+        } else {
+            Footer::split_unverified(&data)?
+        };
+        if footer.features.contains(Features::PATCH) {
+            return Err(DecodeErr::Patch.into());
+        }
+        let root = if options.validates_deep() {
+            PartitionRef::<High>::validate_suffix(partitions)?
+        } else {
+            PartitionRef::<High>::from_suffix(partitions)?
+        };
+        // tree partitions never have more than 256 children
+        if options.max_fan_out() < Block::MAX_LEN {
+            root.check_fan_out(options.max_fan_out())?;
+        }
+        // the cardinality is only trustworthy once the partitions are
+        // validated, which setting a limit implies
+        if options.max_cardinality() < usize::MAX && root.cardinality() > options.max_cardinality()
+        {
+            return Err(DecodeErr::LimitExceeded { limit: DecodeLimit::Cardinality }.into());
         }
-        let (partitions, footer) = data.split_at(data.len() - Footer::SIZE);
-        Footer::ref_from_bytes(footer)?.validate(partitions)?;
-        PartitionRef::<High>::from_suffix(partitions)?;
         Ok(Self { data })
     }
 
+    /// Creates a `SplinterRef` from raw bytes, recursively validating the
+    /// entire splinter.
+    ///
+    /// In addition to the checks performed by [`SplinterRef::from_bytes`],
+    /// this method walks every partition at every level and verifies:
+    /// - Tree offsets are in bounds and children don't overlap
+    /// - Tree cumulative cardinalities match the cardinalities of each child
+    /// - Tree segments are unique and match the number of children
+    /// - Vec partitions are sorted and unique
+    /// - Run partitions are sorted and non-overlapping
+    ///
+    /// This is the preferred constructor for data received from untrusted
+    /// sources. It costs a full scan of the serialized splinter, after which
+    /// every read operation on the returned `SplinterRef` is panic free.
+    ///
+    /// # Errors
+    ///
+    /// Returns any error returned by [`SplinterRef::from_bytes`], as well as
+    /// [`DecodeErr::Length`] or [`DecodeErr::Validity`] if a nested partition
+    /// is malformed. Use [`SplinterRef::from_bytes_with`] and
+    /// [`DecodeOptions::UNTRUSTED`] to also learn the location of that
+    /// partition.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Splinter, SplinterRef, PartitionRead, Encodable};
+    ///
+    /// let bytes = Splinter::from_iter([1, 1024, 1 << 20]).encode_to_bytes();
+    ///
+    /// let splinter_ref = SplinterRef::from_bytes_validated(bytes).unwrap();
+    /// assert_eq!(splinter_ref.cardinality(), 3);
+    /// ```
+    pub fn from_bytes_validated(data: B) -> Result<Self, DecodeErr> {
+        Ok(Self::from_bytes_with(data, DecodeOptions::UNTRUSTED)?)
+    }
+
+    /// Recursively validates every partition in this splinter.
+    ///
+    /// See [`SplinterRef::from_bytes_validated`] for the list of checks
+    /// performed.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Splinter, PartitionWrite};
+    ///
+    /// let mut splinter = Splinter::EMPTY;
+    /// splinter.insert(42);
+    /// let splinter_ref = splinter.encode_to_splinter_ref();
+    ///
+    /// assert!(splinter_ref.validate_deep().is_ok());
+    /// ```
+    pub fn validate_deep(&self) -> Result<(), DecodeErr> {
+        let (partitions, _) = Footer::split_unchecked(&self.data);
+        PartitionRef::<High>::from_suffix(partitions)?.validate()
+    }
+
+    /// Recursively validates every partition in this splinter like
+    /// [`SplinterRef::validate_deep`], but reports the location of the first
+    /// malformed partition along with the error.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::Splinter;
+    ///
+    /// let splinter_ref = Splinter::from_iter([1, 2, 3]).encode_to_splinter_ref();
+    ///
+    /// match splinter_ref.validate_deep_located() {
+    ///     Ok(()) => {}
+    ///     Err(err) => match err.location {
+    ///         Some(location) => panic!("{:?} at offset {}", err.err, location.offset),
+    ///         None => panic!("{:?}", err.err),
+    ///     },
+    /// }
+    /// ```
+    pub fn validate_deep_located(&self) -> Result<(), LocatedDecodeErr> {
+        let (partitions, _) = Footer::split_unchecked(&self.data);
+        PartitionRef::<High>::validate_suffix(partitions).map(|_| ())
+    }
+
+    /// Returns the optional format features used by this splinter.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Splinter, codec::features::Features};
+    ///
+    /// let splinter_ref = Splinter::from_iter([1, 2, 3]).encode_to_splinter_ref();
+    /// assert_eq!(splinter_ref.features(), Features::EMPTY);
+    /// ```
+    pub fn features(&self) -> Features {
+        Footer::split_unchecked(&self.data).1.features
+    }
+
+    /// Returns the checksum algorithm stored in this splinter's footer.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Splinter, codec::checksum::Checksum};
+    ///
+    /// let splinter_ref = Splinter::from_iter([1, 2, 3]).encode_to_splinter_ref();
+    /// assert_eq!(splinter_ref.checksum(), Checksum::Crc64Nvme);
+    /// ```
+    pub fn checksum(&self) -> Checksum {
+        Footer::split_unchecked(&self.data).1.checksum
+    }
+
+    /// Returns true if this splinter was encoded by
+    /// [`Splinter::encode_canonical`] or [`Splinter::encode_canonical_with`].
+    ///
+    /// Byte equality between canonical splinters implies set equality, so
+    /// comparing two canonical `SplinterRef`s encoded with the same features
+    /// only needs to compare their bytes.
+    ///
+    /// The whole buffer is compared, footer included, so a splinter is only
+    /// canonical if it uses the default [`Checksum::Crc64Nvme`] checksum,
+    /// doesn't record its encoded length, and every partition uses the
+    /// smallest kind available with the optional features recorded in its
+    /// footer.
+    ///
+    /// This check decodes and re-encodes the whole splinter, so it costs O(n)
+    /// time and allocates a copy of the encoding. Keep it off hot paths:
+    /// check once when the bytes are received and remember the result. The
+    /// `PartialEq` impls never call it.
+    ///
+    /// No [`DecodeOptions`] limits are applied here. The decoded splinter's
+    /// size is only bounded by the limits checked when this `SplinterRef` was
+    /// created, so callers checking untrusted bytes must create it using
+    /// [`SplinterRef::from_bytes_with`] with [`DecodeOptions::with_max_len`]
+    /// and [`DecodeOptions::with_max_cardinality`] set first.
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Splinter, SplinterRef};
+    ///
+    /// let splinter = Splinter::from_iter([1, 2, 3]);
+    /// let splinter_ref = SplinterRef::from_bytes(splinter.encode_canonical()).unwrap();
+    /// assert!(splinter_ref.is_canonical());
+    /// ```
+    pub fn is_canonical(&self) -> bool {
+        let (_, footer) = Footer::split_unchecked(&self.data);
+        if footer.checksum != Checksum::default()
+            || footer.features.contains(Features::ENCODED_LENGTH)
+        {
+            return false;
+        }
+        self.decode_to_splinter()
+            .encode_canonical_with(footer.features)[..]
+            == self.data[..]
+    }
+
     pub(crate) fn load_unchecked(&self) -> PartitionRef<'_, High> {
-        let without_footer = &self.data[..(self.data.len() - Footer::SIZE)];
-        PartitionRef::from_suffix(without_footer).unwrap()
+        let (partitions, _) = Footer::split_unchecked(&self.data);
+        PartitionRef::from_suffix(partitions).unwrap()
     }
 }
 
@@ -308,11 +778,22 @@ impl<B: Deref<Target = [u8]>> PartitionRead<High> for SplinterRef<B> {
 
 #[cfg(test)]
 mod test {
+    use assert_matches::assert_matches;
+    use bytes::BytesMut;
+    use itertools::Itertools;
     use proptest::{collection::vec, prop_assume, proptest};
 
     use crate::{
-        Optimizable, PartitionRead, Splinter,
-        testutil::{SetGen, mksplinter},
+        Encodable, Optimizable, PartitionRead, Splinter, SplinterRef, assert_error,
+        codec::{
+            DecodeErr, checksum::Checksum, encoder::Encoder, features::Features, footer::Footer,
+        },
+        level::High,
+        partition_kind::PartitionKind,
+        testutil::{
+            SetGen, encode_with_checksum, encode_with_features, mkpartition_buf, mksplinter,
+            mksplinter_manual_with_features, mksplinter_manual_with_footer, test_partition_read,
+        },
     };
 
     #[test]
@@ -338,6 +819,16 @@ mod test {
     }
 
     proptest! {
+        #[test]
+        fn test_validate_deep_proptest(
+            values in vec(0u32..16384, 0..1024),
+        ) {
+            let mut splinter = Splinter::from_iter(values);
+            SplinterRef::from_bytes_validated(splinter.encode_to_bytes()).unwrap();
+            splinter.optimize();
+            SplinterRef::from_bytes_validated(splinter.encode_to_bytes()).unwrap();
+        }
+
         #[test]
         fn test_splinter_ref_proptest(set in vec(0u32..16384, 0..1024)) {
             let splinter = mksplinter(&set).encode_to_splinter_ref();
@@ -490,4 +981,91 @@ mod test {
         assert_eq!(splinter.rank(val), splinter_ref.rank(val));
         assert_eq!(splinter.position(val), splinter_ref.position(val));
     }
+
+    #[test]
+    fn test_concatenated_splinters() {
+        let mut set_gen = SetGen::new(0xDEAD_BEEF);
+        let sets = [
+            vec![],
+            vec![1, 2, 3],
+            set_gen.distributed(4, 8, 8, 64),
+            (0..65536).filter(|v| v % 1000 != 0).collect_vec(),
+        ];
+
+        let mut buf = BytesMut::new();
+        for (set, checksum) in sets.iter().zip(Checksum::enabled().cycle()) {
+            let mut splinter = Splinter::from_iter(set.iter().copied());
+            splinter.optimize();
+            let mut encoder = Encoder::new(&mut buf)
+                .with_checksum(checksum)
+                .with_encoded_length();
+            splinter.encode(&mut encoder);
+        }
+
+        let splinters = SplinterRef::split_all_vec(&buf).unwrap();
+        assert_eq!(splinters.len(), sets.len());
+        let rsplit = SplinterRef::rsplit(&buf).map(Result::unwrap).collect_vec();
+        assert!(itertools::equal(rsplit.iter().rev(), &splinters));
+        for (splinter_ref, set) in splinters.into_iter().zip(&sets) {
+            assert!(splinter_ref.features().contains(Features::ENCODED_LENGTH));
+            splinter_ref.validate_deep().unwrap();
+            test_partition_read(&splinter_ref, set);
+
+            // re-encoding only records the length if requested
+            let encoded = splinter_ref.decode_to_splinter().encode_to_bytes();
+            assert_eq!(
+                encode_with_checksum(&splinter_ref, Checksum::default()),
+                encoded
+            );
+            let mut encoder = Encoder::new(BytesMut::new()).with_encoded_length();
+            SplinterRef::from_bytes(encoded)
+                .unwrap()
+                .encode(&mut encoder);
+            let delimited = encoder.into_inner().freeze();
+            assert_eq!(SplinterRef::split_last(&delimited).unwrap().0, b"");
+        }
+
+        // splitting from the back shares the underlying buffer
+        let buf = buf.freeze();
+        let (rest, last) = SplinterRef::split_last_bytes(buf.clone()).unwrap();
+        test_partition_read(&last, &sets[3]);
+        assert_eq!(rest.len() + last.inner().len(), buf.len());
+
+        // splinters which don't record their length can't be split
+        let mut undelimited = buf.to_vec();
+        undelimited.extend_from_slice(&Splinter::from_iter([1, 2, 3]).encode_to_bytes());
+        assert_error!(
+            SplinterRef::split_last(&undelimited),
+            DecodeErr::MissingLength
+        );
+        let features = encode_with_features(&Splinter::from_iter(0..10), Features::SUPPORTED);
+        assert_error!(SplinterRef::split_last(&features), DecodeErr::MissingLength);
+
+        // splinters can follow arbitrary data
+        let partition = mkpartition_buf::<High>(PartitionKind::Vec, &[1, 2, 3]);
+        let splinter = mksplinter_manual_with_features(&partition, Features::ENCODED_LENGTH);
+        let buf = [&b"prefix"[..], &splinter].concat();
+        let (rest, splinter_ref) = SplinterRef::split_last(&buf).unwrap();
+        assert_eq!(rest, b"prefix");
+        assert_eq!(splinter_ref.iter().collect_vec(), [1, 2, 3]);
+        assert_matches!(
+            SplinterRef::split_all_vec(&buf).map(|_| ()),
+            Err(DecodeErr::Magic)
+        );
+
+        // splitting lazily yields the splinters before the error
+        let mut rsplit = SplinterRef::rsplit(&buf);
+        assert_eq!(rsplit.next().unwrap().unwrap(), splinter_ref);
+        assert_eq!(rsplit.remainder(), b"prefix");
+        assert_error!(rsplit.next().unwrap(), DecodeErr::Magic);
+        assert!(rsplit.next().is_none());
+
+        // the recorded length must match the splinter
+        let footer = Footer::new(Checksum::None, Features::ENCODED_LENGTH);
+        let mut buf = mksplinter_manual_with_footer(&partition, footer).to_vec();
+        SplinterRef::from_bytes(&buf[..]).unwrap();
+        buf[partition.len()] += 1;
+        assert_error!(SplinterRef::from_bytes(&buf[..]), DecodeErr::Length);
+        assert_error!(SplinterRef::split_last(&buf), DecodeErr::Length);
+    }
 }
@@ -20,7 +20,9 @@ where
     B2: Deref<Target = [u8]>,
 {
     fn eq(&self, other: &SplinterRef<B2>) -> bool {
-        self.load_unchecked() == other.load_unchecked()
+        // identical encodings always contain the same values, and canonical
+        // encodings are identical whenever their values are
+        self.data[..] == other.data[..] || self.load_unchecked() == other.load_unchecked()
     }
 }
 
@@ -7,8 +7,8 @@ use std::{
 use bytes::{BufMut, Bytes, BytesMut};
 use itertools::{Itertools, assert_equal};
 use num::{
-    CheckedAdd, Saturating,
-    traits::{Bounded, ConstOne, ConstZero},
+    CheckedAdd,
+    traits::{AsPrimitive, Bounded, ConstOne, ConstZero},
 };
 use rand::{
     RngExt, SeedableRng,
@@ -20,7 +20,13 @@ use zerocopy::IntoBytes;
 
 use crate::{
     CowSplinter, PartitionRead, PartitionWrite, SplinterRef,
-    codec::{Encodable, footer::Footer},
+    codec::{
+        Encodable,
+        checksum::Checksum,
+        encoder::Encoder,
+        features::Features,
+        footer::{EncodedFeatures, EncodedLength, Footer},
+    },
     level::{High, Level},
     partition::Partition,
     partition::run::MergeRuns,
@@ -194,7 +200,7 @@ where
     }
 
     if let (Some(&start), Some(&end)) = (expected.first(), expected.last()) {
-        let mid = start.saturating_add(end) / L::Value::truncate_from(2);
+        let mid = L::Value::truncate_from((start.as_() + end.as_()) / 2);
 
         let starts = [
             Bound::Unbounded,
@@ -338,21 +344,53 @@ where
     ));
 }
 
+/// computes the default [`Checksum::Crc64Nvme`] checksum of data
 pub fn mkchecksum(data: &[u8]) -> u64 {
-    let mut c = crc64fast_nvme::Digest::new();
-    c.write(data);
-    c.sum64()
+    Checksum::default().compute(data)
 }
 
 /// appends a valid Splinter Footer to data and returns it as Bytes
 pub fn mksplinter_manual(data: &[u8]) -> Bytes {
-    let mut buf = BytesMut::with_capacity(data.len() + Footer::SIZE);
+    mksplinter_manual_with_footer(data, Footer::new(Checksum::default(), Features::EMPTY))
+}
+
+/// appends the encoded features and a valid Splinter Footer to data and
+/// returns it as Bytes
+pub fn mksplinter_manual_with_features(data: &[u8], features: Features) -> Bytes {
+    mksplinter_manual_with_footer(data, Footer::new(Checksum::default(), features))
+}
+
+/// appends the provided Splinter Footer to data and returns it as Bytes
+pub fn mksplinter_manual_with_footer(data: &[u8], footer: Footer) -> Bytes {
+    let mut buf = BytesMut::with_capacity(data.len() + footer.encoded_size());
     buf.put_slice(data);
-    buf.put_slice(Footer::from_checksum(mkchecksum(data)).as_bytes());
+    if footer.features.contains(Features::ENCODED_LENGTH) {
+        let length = data.len() + footer.encoded_size();
+        buf.put_slice(EncodedLength::new(length as u32).as_bytes());
+    }
+    if !footer.features.is_empty() {
+        buf.put_slice(EncodedFeatures::new(footer.features.bits()).as_bytes());
+    }
+    let checksum = footer.checksum.compute(&buf).to_le_bytes();
+    buf.put_slice(&checksum[..footer.checksum.size()]);
+    buf.put_slice(&footer.magic());
     buf.freeze()
 }
 
 pub fn mkpartition<L: Level>(kind: PartitionKind, values: &[L::Value]) -> Partition<L> {
+    if kind.is_inverted()
+        || matches!(
+            kind,
+            PartitionKind::EliasFano | PartitionKind::DeltaVec | PartitionKind::SparseBitmap
+        )
+    {
+        // inverted, Elias-Fano, delta-vec and sparse bitmap partitions can't
+        // be built empty
+        let mut p = mkpartition(PartitionKind::Run, values);
+        p.switch_kind(kind);
+        return p;
+    }
+
     let mut p = kind.build();
     for &v in values {
         p.raw_insert(v);
@@ -360,9 +398,23 @@ pub fn mkpartition<L: Level>(kind: PartitionKind, values: &[L::Value]) -> Partit
     p
 }
 
+/// encodes value with an encoder which may use the provided optional features
+pub fn encode_with_features(value: &impl Encodable, features: Features) -> Bytes {
+    let mut encoder = Encoder::with_features(BytesMut::new(), features);
+    value.encode(&mut encoder);
+    encoder.into_inner().freeze()
+}
+
+/// encodes value with a default encoder which uses the provided checksum
+pub fn encode_with_checksum(value: &impl Encodable, checksum: Checksum) -> Bytes {
+    let mut encoder = Encoder::new(BytesMut::new()).with_checksum(checksum);
+    value.encode(&mut encoder);
+    encoder.into_inner().freeze()
+}
+
 pub fn mkpartition_buf<L: Level>(kind: PartitionKind, values: &[L::Value]) -> BytesMut {
-    mkpartition::<L>(kind, values)
-        .encode_to_bytes()
+    let partition = mkpartition::<L>(kind, values);
+    encode_with_features(&partition, Features::SUPPORTED)
         .try_into_mut()
         .unwrap()
 }
@@ -378,7 +430,9 @@ pub fn mksplinter_buf(values: &[u32]) -> BytesMut {
 #[macro_export]
 macro_rules! assert_error {
     ($expr:expr, $err:path$(, $($rest:tt),+)?) => {
-        assert_matches::assert_matches!(($expr).expect_err("expected an error"), $err $(, $($rest),+)?)
+        assert_matches::assert_matches!(
+            $crate::codec::DecodeErr::from(($expr).expect_err("expected an error")),
+            $err $(, $($rest),+)?)
     };
 }
 
@@ -1,8 +1,11 @@
-use std::ops::RangeBounds;
+use std::ops::{Add, RangeBounds, Sub};
 
-use crate::level::Level;
-use num::cast::AsPrimitive;
-use u24::u24;
+use crate::{codec::features::Features, level::Level, u24::u24};
+use num::{
+    Bounded, CheckedAdd, Saturating, ToPrimitive,
+    cast::AsPrimitive,
+    traits::{ConstOne, ConstZero},
+};
 
 pub trait PartitionRead<L: Level> {
     /// the total number of values accessible via this partition.
@@ -62,6 +65,38 @@ pub trait PartitionWrite<L: Level> {
     fn remove_range<R: RangeBounds<L::Value>>(&mut self, values: R);
 }
 
+/// The integer operations which partitions need from their value type. This
+/// is a small subset of [`num::PrimInt`], so that `u24` only needs to
+/// implement the operations this crate uses.
+#[doc(hidden)]
+pub trait Int:
+    Copy
+    + Ord
+    + Bounded
+    + ConstZero
+    + ConstOne
+    + Add<Output = Self>
+    + Sub<Output = Self>
+    + CheckedAdd
+    + Saturating
+    + ToPrimitive
+{
+}
+
+impl<T> Int for T where
+    T: Copy
+        + Ord
+        + Bounded
+        + ConstZero
+        + ConstOne
+        + Add<Output = Self>
+        + Sub<Output = Self>
+        + CheckedAdd
+        + Saturating
+        + ToPrimitive
+{
+}
+
 #[doc(hidden)]
 pub trait TruncateFrom<T> {
     fn truncate_from(other: T) -> Self;
@@ -83,7 +118,62 @@ impl_truncate_from_usize!(u32, u24, u16, u8);
 
 pub trait Optimizable {
     /// Optimize memory usage. Should be run after batch inserts or before serialization.
+    ///
+    /// Only partition kinds which every V2 reader can decode are considered,
+    /// matching the default [`Encoder::new`]. Use [`Self::optimize_with`] to
+    /// consider partition kinds which depend on optional features.
+    ///
+    /// [`Encoder::new`]: crate::codec::encoder::Encoder::new
     fn optimize(&mut self);
+
+    /// Like [`Self::optimize`], but also considers the partition kinds which
+    /// depend on the provided optional features. Encode the result with
+    /// [`Encoder::with_features`] using the same features; other encoders
+    /// fall back to kinds they support, which may be larger than optimizing
+    /// for them in the first place.
+    ///
+    /// The default implementation calls [`Self::optimize`].
+    ///
+    /// # Examples
+    ///
+    /// ```
+    /// use splinter_rs::{Encodable, Optimizable, Splinter, codec::features::Features};
+    ///
+    /// let mut splinter = Splinter::from_iter((0..65536).filter(|v| v % 1000 != 0));
+    /// splinter.optimize_with(Features::SUPPORTED);
+    /// assert!(splinter.encoded_features().contains(Features::INVERTED_PARTITIONS));
+    /// ```
+    ///
+    /// [`Encoder::with_features`]: crate::codec::encoder::Encoder::with_features
+    fn optimize_with(&mut self, features: Features) {
+        let _ = features;
+        self.optimize();
+    }
+
+    /// Convert into the smallest representation of this set, regardless of
+    /// how it was built or previously optimized. Unlike [`Self::optimize`],
+    /// which prefers to keep the current representation until a better one
+    /// presents itself, equal sets always end up with identical layouts.
+    ///
+    /// Only partition kinds which every V2 reader can decode are considered,
+    /// like [`Self::optimize`].
+    ///
+    /// The default implementation calls [`Self::optimize`], which is only
+    /// canonical for types with a single possible layout.
+    fn canonicalize(&mut self) {
+        self.optimize();
+    }
+
+    /// Like [`Self::canonicalize`], but also considers the partition kinds
+    /// which depend on the provided optional features. Each feature set has
+    /// its own canonical layout, so equal sets only end up with identical
+    /// layouts when they are canonicalized with the same features.
+    ///
+    /// The default implementation calls [`Self::canonicalize`].
+    fn canonicalize_with(&mut self, features: Features) {
+        let _ = features;
+        self.canonicalize();
+    }
 }
 
 pub trait Cut<Rhs = Self> {
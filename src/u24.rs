@@ -0,0 +1,488 @@
+//! An unsigned 24-bit integer, used as the value type of [`crate::level::Mid`].
+//!
+//! [`u24`] stores its value in a `u32` and [`U24`] stores its bytes in a
+//! `[u8; 3]`. Converting between the two is done by value, so neither type
+//! depends on the target's byte order. The `u24` crate this replaces asserts
+//! a little-endian target at compile time, which rules out s390x.
+
+use std::{
+    fmt::{self, Debug, Display},
+    marker::PhantomData,
+    ops::{Add, BitAnd, BitOr, Mul, Shl, Shr, Sub},
+};
+
+use num::{
+    Bounded, CheckedAdd, One, Saturating, ToPrimitive, Zero,
+    traits::{AsPrimitive, ConstOne, ConstZero},
+};
+use zerocopy::{ByteOrder, FromBytes, Immutable, IntoBytes, KnownLayout, Order, Unaligned};
+
+/// An unsigned 24-bit integer.
+#[derive(Clone, Copy, Default, PartialEq, Eq, PartialOrd, Ord, Hash)]
+#[allow(non_camel_case_types)]
+pub struct u24(u32);
+
+impl u24 {
+    pub const MIN: u24 = u24(0);
+    pub const MAX: u24 = u24(Self::MASK);
+    pub const BITS: u32 = 24;
+
+    const MASK: u32 = 0x00FF_FFFF;
+
+    /// Creates a `u24` from a `u32`, discarding the most significant byte.
+    #[inline]
+    pub const fn truncating_from_u32(v: u32) -> Self {
+        Self(v & Self::MASK)
+    }
+
+    /// Creates a `u24` from a `u32`, returning `None` if it doesn't fit.
+    #[inline]
+    pub const fn checked_from_u32(v: u32) -> Option<Self> {
+        if v > Self::MASK { None } else { Some(Self(v)) }
+    }
+
+    #[inline]
+    pub const fn into_u32(self) -> u32 {
+        self.0
+    }
+
+    #[inline]
+    pub const fn from_le_bytes(b: [u8; 3]) -> Self {
+        Self(u32::from_le_bytes([b[0], b[1], b[2], 0]))
+    }
+
+    #[inline]
+    pub const fn from_be_bytes(b: [u8; 3]) -> Self {
+        Self(u32::from_be_bytes([0, b[0], b[1], b[2]]))
+    }
+
+    #[inline]
+    pub const fn to_le_bytes(self) -> [u8; 3] {
+        let [b0, b1, b2, _] = self.0.to_le_bytes();
+        [b0, b1, b2]
+    }
+
+    #[inline]
+    pub const fn to_be_bytes(self) -> [u8; 3] {
+        let [_, b0, b1, b2] = self.0.to_be_bytes();
+        [b0, b1, b2]
+    }
+
+    /// Narrows an arithmetic result back into a `u24`. Like the primitive
+    /// integers, overflow panics in debug builds and wraps in release builds.
+    #[inline(always)]
+    fn must_from_u32(v: u32) -> Self {
+        debug_assert!(v <= Self::MASK, "attempt to overflow a u24");
+        Self::truncating_from_u32(v)
+    }
+}
+
+impl Debug for u24 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.0, f)
+    }
+}
+
+impl Display for u24 {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.0, f)
+    }
+}
+
+macro_rules! impl_bin_op {
+    ($(($op:ident, $meth:ident, $op_fn:ident)),*) => {
+        $(
+            impl $op for u24 {
+                type Output = Self;
+
+                #[inline(always)]
+                fn $meth(self, rhs: Self) -> Self {
+                    Self::must_from_u32(self.0.$op_fn(rhs.0))
+                }
+            }
+        )*
+    };
+}
+
+impl_bin_op!(
+    (Add, add, wrapping_add),
+    (Sub, sub, wrapping_sub),
+    (Mul, mul, wrapping_mul),
+    (BitAnd, bitand, bitand),
+    (BitOr, bitor, bitor)
+);
+
+impl Shl<usize> for u24 {
+    type Output = Self;
+
+    #[inline(always)]
+    fn shl(self, rhs: usize) -> Self {
+        debug_assert!(
+            rhs < Self::BITS as usize,
+            "attempt to shift left with overflow"
+        );
+        Self::truncating_from_u32(self.0 << rhs)
+    }
+}
+
+impl Shr<usize> for u24 {
+    type Output = Self;
+
+    #[inline(always)]
+    fn shr(self, rhs: usize) -> Self {
+        debug_assert!(
+            rhs < Self::BITS as usize,
+            "attempt to shift right with overflow"
+        );
+        Self(self.0 >> rhs)
+    }
+}
+
+impl Zero for u24 {
+    fn zero() -> Self {
+        Self::ZERO
+    }
+
+    fn is_zero(&self) -> bool {
+        *self == Self::ZERO
+    }
+}
+
+impl ConstZero for u24 {
+    const ZERO: Self = Self(0);
+}
+
+impl One for u24 {
+    fn one() -> Self {
+        Self::ONE
+    }
+}
+
+impl ConstOne for u24 {
+    const ONE: Self = Self(1);
+}
+
+impl Bounded for u24 {
+    fn min_value() -> Self {
+        Self::MIN
+    }
+
+    fn max_value() -> Self {
+        Self::MAX
+    }
+}
+
+impl ToPrimitive for u24 {
+    fn to_i64(&self) -> Option<i64> {
+        Some(self.0.into())
+    }
+
+    fn to_u64(&self) -> Option<u64> {
+        Some(self.0.into())
+    }
+}
+
+macro_rules! impl_checked_op {
+    ($(($trait:ident, $meth:ident)),*) => {
+        $(
+            impl $trait for u24 {
+                #[inline]
+                fn $meth(&self, rhs: &Self) -> Option<Self> {
+                    self.0.$meth(rhs.0).and_then(Self::checked_from_u32)
+                }
+            }
+        )*
+    };
+}
+
+impl_checked_op!((CheckedAdd, checked_add));
+
+impl Saturating for u24 {
+    fn saturating_add(self, rhs: Self) -> Self {
+        self.checked_add(&rhs).unwrap_or(Self::MAX)
+    }
+
+    fn saturating_sub(self, rhs: Self) -> Self {
+        Self(self.0.saturating_sub(rhs.0))
+    }
+}
+
+macro_rules! impl_as {
+    ($($ty:ty),*) => {
+        $(
+            impl AsPrimitive<$ty> for u24 {
+                #[inline(always)]
+                fn as_(self) -> $ty {
+                    self.0 as $ty
+                }
+            }
+
+            impl AsPrimitive<u24> for $ty {
+                #[inline(always)]
+                fn as_(self) -> u24 {
+                    u24::truncating_from_u32(self as u32)
+                }
+            }
+        )*
+    };
+}
+
+impl_as!(usize, u32, u16, u8);
+
+impl range_set_blaze::Integer for u24 {
+    type SafeLen = u32;
+
+    fn checked_add_one(self) -> Option<Self> {
+        self.checked_add(&Self::ONE)
+    }
+
+    fn add_one(self) -> Self {
+        self + Self::ONE
+    }
+
+    fn sub_one(self) -> Self {
+        self - Self::ONE
+    }
+
+    fn assign_sub_one(&mut self) {
+        *self = self.sub_one();
+    }
+
+    fn range_next(range: &mut std::ops::RangeInclusive<Self>) -> Option<Self> {
+        let (start, end) = (*range.start(), *range.end());
+        if start < end {
+            *range = start.add_one()..=end;
+        } else if start == end {
+            *range = Self::exhausted_range();
+        } else {
+            return None;
+        }
+        Some(start)
+    }
+
+    fn range_next_back(range: &mut std::ops::RangeInclusive<Self>) -> Option<Self> {
+        let (start, end) = (*range.start(), *range.end());
+        if start < end {
+            *range = start..=end.sub_one();
+        } else if start == end {
+            *range = Self::exhausted_range();
+        } else {
+            return None;
+        }
+        Some(end)
+    }
+
+    fn min_value() -> Self {
+        Self::MIN
+    }
+
+    fn max_value() -> Self {
+        Self::MAX
+    }
+
+    fn safe_len(range: &std::ops::RangeInclusive<Self>) -> u32 {
+        let (start, end) = (*range.start(), *range.end());
+        if start <= end { end.0 - start.0 + 1 } else { 0 }
+    }
+
+    fn f64_to_safe_len_lossy(f: f64) -> u32 {
+        f as u32
+    }
+
+    fn safe_len_to_f64_lossy(len: u32) -> f64 {
+        len.into()
+    }
+
+    fn inclusive_end_from_start(self, b: u32) -> Self {
+        debug_assert!(b > 0 && b <= Self::MASK + 1, "b must be in range 1..=2**24");
+        Self::truncating_from_u32(self.0.wrapping_add(b - 1))
+    }
+
+    fn start_from_inclusive_end(self, b: u32) -> Self {
+        debug_assert!(b > 0 && b <= Self::MASK + 1, "b must be in range 1..=2**24");
+        Self::truncating_from_u32(self.0.wrapping_sub(b - 1))
+    }
+}
+
+/// A [`u24`] stored as three bytes in the byte order `O`, usable in unaligned
+/// zerocopy structs.
+#[derive(FromBytes, IntoBytes, Immutable, KnownLayout, Unaligned, Clone, Copy, PartialEq, Eq)]
+#[repr(transparent)]
+pub struct U24<O>([u8; 3], PhantomData<O>);
+
+impl<O: ByteOrder> U24<O> {
+    #[inline]
+    pub const fn new(value: u24) -> Self {
+        let bytes = match O::ORDER {
+            Order::BigEndian => value.to_be_bytes(),
+            Order::LittleEndian => value.to_le_bytes(),
+        };
+        Self(bytes, PhantomData)
+    }
+
+    #[inline]
+    pub const fn get(self) -> u24 {
+        match O::ORDER {
+            Order::BigEndian => u24::from_be_bytes(self.0),
+            Order::LittleEndian => u24::from_le_bytes(self.0),
+        }
+    }
+}
+
+impl<O: ByteOrder> From<u24> for U24<O> {
+    fn from(value: u24) -> Self {
+        Self::new(value)
+    }
+}
+
+impl<O: ByteOrder> From<U24<O>> for u24 {
+    fn from(value: U24<O>) -> Self {
+        value.get()
+    }
+}
+
+impl<O: ByteOrder> PartialOrd for U24<O> {
+    fn partial_cmp(&self, other: &Self) -> Option<std::cmp::Ordering> {
+        Some(self.cmp(other))
+    }
+}
+
+impl<O: ByteOrder> Ord for U24<O> {
+    fn cmp(&self, other: &Self) -> std::cmp::Ordering {
+        self.get().cmp(&other.get())
+    }
+}
+
+impl<O: ByteOrder> Debug for U24<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Debug::fmt(&self.get(), f)
+    }
+}
+
+impl<O: ByteOrder> Display for U24<O> {
+    fn fmt(&self, f: &mut fmt::Formatter<'_>) -> fmt::Result {
+        Display::fmt(&self.get(), f)
+    }
+}
+
+#[cfg(test)]
+mod tests {
+    use proptest::proptest;
+    use range_set_blaze::{Integer, RangeSetBlaze};
+    use zerocopy::{BE, FromBytes, IntoBytes, LE};
+
+    use super::*;
+
+    fn n(n: u32) -> u24 {
+        u24::checked_from_u32(n).unwrap()
+    }
+
+    #[test]
+    fn test_u24_bytes() {
+        let v = u24::truncating_from_u32(0x0012_3456);
+        assert_eq!(v.to_be_bytes(), [0x12, 0x34, 0x56]);
+        assert_eq!(v.to_le_bytes(), [0x56, 0x34, 0x12]);
+        assert_eq!(u24::from_be_bytes([0x12, 0x34, 0x56]), v);
+        assert_eq!(u24::from_le_bytes([0x56, 0x34, 0x12]), v);
+
+        assert_eq!(U24::<BE>::new(v).as_bytes(), &[0x12, 0x34, 0x56]);
+        assert_eq!(U24::<LE>::new(v).as_bytes(), &[0x56, 0x34, 0x12]);
+        assert_eq!(U24::<BE>::new(v).get(), v);
+        assert_eq!(U24::<LE>::new(v).get(), v);
+
+        // unaligned values are read straight from the encoded bytes
+        let bytes = [0xFF, 0x12, 0x34, 0x56];
+        assert_eq!(U24::<BE>::ref_from_bytes(&bytes[1..]).unwrap().get(), v);
+        assert_eq!(
+            U24::<LE>::ref_from_bytes(&bytes[1..]).unwrap().get(),
+            u24::truncating_from_u32(0x0056_3412)
+        );
+
+        for v in [u24::MIN, u24::ONE, n(0x80_0000), u24::MAX] {
+            assert_eq!(u24::from_be_bytes(v.to_be_bytes()), v);
+            assert_eq!(u24::from_le_bytes(v.to_le_bytes()), v);
+            assert_eq!(<u24 as From<_>>::from(U24::<BE>::from(v)), v);
+            assert_eq!(<u24 as From<_>>::from(U24::<LE>::from(v)), v);
+        }
+    }
+
+    #[test]
+    fn test_u24_arithmetic() {
+        assert_eq!(n(0xFF_FFFE) + u24::ONE, u24::MAX);
+        assert_eq!(u24::MAX - n(0xFF_FFFE), u24::ONE);
+        assert_eq!(n(0x1000) * n(0x0FFF), n(0xFF_F000));
+        assert_eq!(n(0x80_0001) << 1, n(0x2));
+        assert_eq!(u24::MAX >> 23, u24::ONE);
+
+        assert_eq!(u24::MAX.checked_add(&u24::ONE), None);
+        assert_eq!(u24::MAX.saturating_add(u24::ONE), u24::MAX);
+        assert_eq!(u24::ZERO.saturating_sub(u24::ONE), u24::ZERO);
+
+        assert_eq!(u24::checked_from_u32(0x100_0000), None);
+        assert_eq!(u24::truncating_from_u32(0x0123_4567).into_u32(), 0x23_4567);
+        assert_eq!(u24::MAX.to_u64(), Some(0xFF_FFFF));
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "attempt to overflow a u24")]
+    fn test_u24_add_overflow() {
+        let _ = u24::MAX + u24::ONE;
+    }
+
+    #[test]
+    #[cfg(debug_assertions)]
+    #[should_panic(expected = "attempt to overflow a u24")]
+    fn test_u24_sub_overflow() {
+        let _ = u24::ZERO - u24::ONE;
+    }
+
+    #[test]
+    #[cfg(not(debug_assertions))]
+    fn test_u24_overflow_wraps() {
+        assert_eq!(u24::MAX + u24::ONE, u24::ZERO);
+        assert_eq!(u24::ZERO - u24::ONE, u24::MAX);
+    }
+
+    #[test]
+    fn test_u24_range_set() {
+        assert_eq!(u24::MAX.checked_add_one(), None);
+        assert_eq!(u24::ZERO.checked_add_one(), Some(u24::ONE));
+        assert_eq!(<u24 as Integer>::max_value(), u24::MAX);
+        assert_eq!(u24::safe_len(&(u24::MIN..=u24::MAX)), 1 << 24);
+        assert_eq!(u24::safe_len(&(u24::ONE..=u24::ZERO)), 0);
+        assert_eq!(n(10).inclusive_end_from_start(5), n(14));
+        assert_eq!(n(14).start_from_inclusive_end(5), n(10));
+        assert_eq!(u24::ZERO.inclusive_end_from_start(1 << 24), u24::MAX);
+
+        let mut range = n(0xFF_FFFD)..=u24::MAX;
+        assert_eq!(u24::range_next(&mut range), Some(n(0xFF_FFFD)));
+        assert_eq!(u24::range_next_back(&mut range), Some(u24::MAX));
+        assert_eq!(u24::range_next(&mut range), Some(n(0xFF_FFFE)));
+        assert_eq!(u24::range_next(&mut range), None);
+        assert_eq!(u24::range_next_back(&mut range), None);
+
+        let set = RangeSetBlaze::from_iter([u24::ZERO..=n(9), n(0xFF_FFF0)..=u24::MAX]);
+        assert_eq!(set.len(), 26);
+        let complement = !&set;
+        assert_eq!(
+            complement.ranges().collect::<Vec<_>>(),
+            [n(10)..=n(0xFF_FFEF)]
+        );
+        assert_eq!(complement.len(), (1 << 24) - 26);
+    }
+
+    proptest! {
+        #[test]
+        fn test_u24_matches_u32(a in 0u32..(1 << 24), b in 0u32..(1 << 24)) {
+            let (x, y) = (n(a), n(b));
+            assert_eq!(x.cmp(&y), a.cmp(&b));
+            assert_eq!(U24::<BE>::new(x).cmp(&U24::new(y)), a.cmp(&b));
+            assert_eq!(U24::<LE>::new(x).cmp(&U24::new(y)), a.cmp(&b));
+            let checked = |r: Option<u32>| r.and_then(u24::checked_from_u32);
+            assert_eq!(x.checked_add(&y), checked(a.checked_add(b)));
+            assert_eq!((x & y).into_u32(), a & b);
+            assert_eq!((x | y).into_u32(), a | b);
+        }
+    }
+}
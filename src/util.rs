@@ -3,12 +3,10 @@ use std::{
     ops::{Bound, RangeBounds, RangeInclusive},
 };
 
-use num::{
-    PrimInt,
-    traits::{ConstOne, ConstZero},
-};
 use range_set_blaze::Integer;
 
+use crate::traits::Int;
+
 #[doc(hidden)]
 #[macro_export]
 macro_rules! MultiIter {
@@ -66,7 +64,7 @@ pub trait RangeExt<T> {
     fn try_into_inclusive(self) -> Option<RangeInclusive<T>>;
 }
 
-impl<R: RangeBounds<T>, T: PrimInt + ConstOne + ConstZero> RangeExt<T> for R {
+impl<R: RangeBounds<T>, T: Int> RangeExt<T> for R {
     fn is_empty(&self) -> bool {
         use Bound::*;
         match (self.start_bound(), self.end_bound()) {